@@ -3,6 +3,8 @@ use std::{
     ffi::{CStr, CString},
     os::raw::{c_char, c_int},
 };
+#[cfg(unix)]
+use std::{io, os::fd::RawFd, sync::Arc};
 
 /// # Safety
 /// This function is unsafe because it dereferences raw pointers.
@@ -62,3 +64,36 @@ pub unsafe extern "C" fn clash_free_string(s: *mut c_char) {
         CString::from_raw(s);
     }
 }
+
+/// Called with the fd of every outbound socket before it connects, returns
+/// 0 once the socket is exempt from the host's VPN, e.g. after
+/// `VpnService.protect()` on Android
+#[cfg(unix)]
+pub type ClashProtectSocket = extern "C" fn(fd: c_int) -> c_int;
+
+#[cfg(unix)]
+struct FfiProtector(ClashProtectSocket);
+
+#[cfg(unix)]
+impl clash_lib::SocketProtector for FfiProtector {
+    fn protect(&self, fd: RawFd) -> io::Result<()> {
+        match (self.0)(fd) {
+            0 => Ok(()),
+            rv => Err(io::Error::other(format!(
+                "failed to protect socket {fd}: {rv}"
+            ))),
+        }
+    }
+}
+
+/// Sets the callback protecting outbound sockets, or unsets it with NULL.
+/// Call it before `clash_start`, so that no socket is created unprotected.
+#[cfg(unix)]
+#[unsafe(no_mangle)]
+pub extern "C" fn clash_set_socket_protector(protect: Option<ClashProtectSocket>) {
+    clash_lib::set_socket_protector(
+        protect.map(|f| {
+            Arc::new(FfiProtector(f)) as Arc<dyn clash_lib::SocketProtector>
+        }),
+    );
+}
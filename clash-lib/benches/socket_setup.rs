@@ -0,0 +1,72 @@
+//! Latency of the outbound TCP socket setup in `new_tcp_stream`: socket
+//! creation, option application, bind and connect, against a loopback
+//! listener.
+//!
+//! ```sh
+//! cargo bench -p clash-lib --features bench --bench socket_setup
+//! ```
+//!
+//! Cases the current user can't run (e.g. `SO_MARK` without
+//! `CAP_NET_ADMIN`) are skipped with a note.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use clash_lib::bench::{ConnectOptions, get_interface_by_ip, new_tcp_stream};
+use criterion::{Criterion, criterion_group, criterion_main};
+use tokio::{net::TcpListener, runtime::Runtime};
+
+/// Spawns a listener that accepts and immediately drops connections, so the
+/// TIME_WAIT state lands on its side rather than exhausting client ports.
+fn spawn_listener(rt: &Runtime) -> SocketAddr {
+    rt.block_on(async {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+        addr
+    })
+}
+
+fn bench_new_tcp_stream(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let target = spawn_listener(&rt);
+    let loopback = get_interface_by_ip(Ipv4Addr::LOCALHOST.into());
+
+    let baseline = ConnectOptions::default()
+        .freebind(false)
+        .nodelay(false)
+        .keepalive(false);
+    let mut cases = vec![
+        ("baseline", baseline),
+        ("default", ConnectOptions::default()),
+        ("dscp", ConnectOptions::default().dscp(Some(0x2e))),
+        ("so_mark", ConnectOptions::default().so_mark(Some(0xff))),
+        ("freebind", ConnectOptions::default().freebind(true)),
+    ];
+    match &loopback {
+        Some(iface) => cases.push((
+            "bind_interface",
+            ConnectOptions::default().iface(Some(iface)),
+        )),
+        None => eprintln!("skipping bind_interface: no loopback interface found"),
+    }
+
+    let mut group = c.benchmark_group("new_tcp_stream");
+    for (name, opts) in &cases {
+        if let Err(e) = rt.block_on(new_tcp_stream(target, opts)) {
+            eprintln!("skipping {name}: {e}");
+            continue;
+        }
+        group.bench_function(*name, |b| {
+            b.to_async(&rt)
+                .iter(|| async { new_tcp_stream(target, opts).await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_new_tcp_stream);
+criterion_main!(benches);
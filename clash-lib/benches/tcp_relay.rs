@@ -0,0 +1,153 @@
+//! Throughput of relaying a TCP connection over loopback, with the epoll
+//! based `splice` relay against the io_uring one.
+//!
+//! ```sh
+//! cargo bench -p clash-lib --features bench,io_uring --bench tcp_relay
+//! ```
+//!
+//! Linux only. The io_uring case is skipped with a note if the kernel lacks
+//! io_uring, or it's blocked e.g. by seccomp.
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{future::Future, io, pin::Pin, sync::Arc, time::Duration};
+
+    use clash_lib::bench::{
+        TrackCopy, uring_bidirectional, zero_copy_bidirectional,
+    };
+    use criterion::{Criterion, Throughput};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        runtime::Runtime,
+    };
+
+    const CHUNK: usize = 1024 * 1024;
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    struct Noop;
+
+    impl TrackCopy for Noop {
+        fn track(&self, _: usize) {}
+    }
+
+    type Relay = Box<
+        dyn FnOnce(
+                TcpStream,
+                TcpStream,
+            )
+                -> Pin<Box<dyn Future<Output = io::Result<()>> + Send>>
+            + Send,
+    >;
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (connected, accepted) = tokio::join!(
+            TcpStream::connect(listener.local_addr().unwrap()),
+            listener.accept()
+        );
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    /// Connects a client to a sink through `relay`, the sink reads and drops
+    /// whatever it receives.
+    fn spawn_relay(rt: &Runtime, relay: Relay) -> TcpStream {
+        rt.block_on(async {
+            let (client, a) = pair().await;
+            let (b, mut sink) = pair().await;
+            tokio::spawn(relay(a, b));
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 64 * 1024];
+                while sink.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+            });
+            client
+        })
+    }
+
+    fn splice() -> Relay {
+        Box::new(|mut a, mut b| {
+            Box::pin(async move {
+                zero_copy_bidirectional(
+                    &mut a,
+                    &mut b,
+                    Arc::new(Noop),
+                    Arc::new(Noop),
+                    TIMEOUT,
+                    TIMEOUT,
+                )
+                .await
+                .map(|_| ())
+                .map_err(|e| io::Error::other(e.to_string()))
+            })
+        })
+    }
+
+    fn uring() -> Relay {
+        Box::new(|mut a, mut b| {
+            Box::pin(async move {
+                match uring_bidirectional(
+                    &mut a,
+                    &mut b,
+                    Arc::new(Noop),
+                    Arc::new(Noop),
+                    TIMEOUT,
+                    TIMEOUT,
+                )
+                .await
+                {
+                    Some(rv) => {
+                        rv.map(|_| ()).map_err(|e| io::Error::other(e.to_string()))
+                    }
+                    None => Err(io::Error::other("io_uring is not available")),
+                }
+            })
+        })
+    }
+
+    /// Whether a relay over io_uring can be set up on this kernel.
+    fn uring_supported(rt: &Runtime) -> bool {
+        rt.block_on(async {
+            let (mut client, a) = pair().await;
+            let (b, mut server) = pair().await;
+            let relay = tokio::spawn(uring()(a, b));
+            client.write_all(b"ping").await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            let _ =
+                tokio::time::timeout(TIMEOUT, server.read_to_end(&mut buf)).await;
+            drop((client, server));
+            relay.await.unwrap().is_ok() && buf == b"ping"
+        })
+    }
+
+    pub fn bench_tcp_relay(c: &mut Criterion) {
+        let rt = Runtime::new().unwrap();
+        let buf = vec![0x5au8; CHUNK];
+
+        let mut group = c.benchmark_group("tcp_relay");
+        group.throughput(Throughput::Bytes(CHUNK as u64));
+        let mut client = spawn_relay(&rt, splice());
+        group.bench_function("splice", |b| {
+            b.iter(|| rt.block_on(client.write_all(&buf)).unwrap())
+        });
+        if uring_supported(&rt) {
+            let mut client = spawn_relay(&rt, uring());
+            group.bench_function("io_uring", |b| {
+                b.iter(|| rt.block_on(client.write_all(&buf)).unwrap())
+            });
+        } else {
+            eprintln!("skipping io_uring: not available");
+        }
+        group.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion::criterion_group!(benches, linux::bench_tcp_relay);
+#[cfg(target_os = "linux")]
+criterion::criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("tcp_relay only runs on Linux");
+}
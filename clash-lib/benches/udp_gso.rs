@@ -0,0 +1,77 @@
+//! Throughput of sending runs of equally sized datagrams, the shape of QUIC
+//! and most UDP relay traffic, one `sendto` per datagram against a single
+//! `UDP_SEGMENT` send, over loopback.
+//!
+//! ```sh
+//! cargo bench -p clash-lib --features bench --bench udp_gso
+//! ```
+//!
+//! Linux only. Skipped with a note if the kernel lacks `UDP_SEGMENT`.
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use clash_lib::bench::{gso_supported, send_segments};
+    use criterion::{Criterion, Throughput};
+    use tokio::{net::UdpSocket, runtime::Runtime};
+
+    const SEGMENT_SIZE: usize = 1200;
+    /// as many as fit in one `UDP_SEGMENT` send
+    const SEGMENTS: usize = 54;
+
+    /// Spawns a socket that reads and drops whatever it receives, so the
+    /// sends aren't slowed by a full receive buffer.
+    fn spawn_sink(rt: &Runtime) -> SocketAddr {
+        rt.block_on(async {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let addr = socket.local_addr().unwrap();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 65536];
+                while socket.recv(&mut buf).await.is_ok() {}
+            });
+            addr
+        })
+    }
+
+    pub fn bench_udp_send(c: &mut Criterion) {
+        let rt = Runtime::new().unwrap();
+        let target = spawn_sink(&rt);
+        let socket = rt.block_on(async {
+            UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap()
+        });
+        let buf = vec![0x5au8; SEGMENT_SIZE * SEGMENTS];
+
+        let mut group = c.benchmark_group("udp_send");
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+        group.bench_function("per_packet", |b| {
+            b.to_async(&rt).iter(|| async {
+                for segment in buf.chunks(SEGMENT_SIZE) {
+                    socket.send_to(segment, target).await.unwrap();
+                }
+            })
+        });
+        if gso_supported(&socket) {
+            group.bench_function("gso", |b| {
+                b.to_async(&rt).iter(|| async {
+                    send_segments(&socket, target, &buf, SEGMENT_SIZE as u16)
+                        .await
+                        .unwrap();
+                })
+            });
+        } else {
+            eprintln!("skipping gso: UDP_SEGMENT unsupported");
+        }
+        group.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion::criterion_group!(benches, linux::bench_udp_send);
+#[cfg(target_os = "linux")]
+criterion::criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("udp_gso only runs on Linux");
+}
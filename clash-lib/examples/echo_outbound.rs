@@ -0,0 +1,100 @@
+//! Registers an `echo` outbound protocol, whose connections send back
+//! whatever is written to them, and routes everything through it.
+//!
+//! ```sh
+//! cargo run -p clash-lib --features plugin --example echo_outbound
+//! # in another shell, anything typed is echoed back
+//! nc -X 5 -x 127.0.0.1:7890 example.com 80
+//! ```
+//!
+//! A real transport would dial its server with `new_tcp_stream` or
+//! `new_udp_socket`, passing `&sess.into()` as the options.
+
+use std::{collections::HashMap, io, sync::Arc};
+
+use async_trait::async_trait;
+use clash_lib::{
+    Config, Options,
+    plugin::{
+        AnyOutboundHandler, BoxedChainedDatagram, BoxedChainedStream, ChainedStream,
+        ChainedStreamWrapper, ConnectorType, DialWithConnector, OutboundHandler,
+        OutboundType, Session, ThreadSafeDNSResolver, register_outbound,
+    },
+};
+use serde_yaml::Value;
+
+#[derive(Debug)]
+struct Echo {
+    name: String,
+}
+
+impl DialWithConnector for Echo {}
+
+#[async_trait]
+impl OutboundHandler for Echo {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn proto(&self) -> OutboundType {
+        OutboundType::Plugin
+    }
+
+    async fn support_udp(&self) -> bool {
+        false
+    }
+
+    async fn connect_stream(
+        &self,
+        _sess: &Session,
+        _resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<BoxedChainedStream> {
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        tokio::spawn(async move {
+            let (mut r, mut w) = tokio::io::split(server);
+            tokio::io::copy(&mut r, &mut w).await
+        });
+
+        let s = ChainedStreamWrapper::new(client);
+        s.append_to_chain(self.name()).await;
+        Ok(Box::new(s))
+    }
+
+    async fn connect_datagram(
+        &self,
+        _sess: &Session,
+        _resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<BoxedChainedDatagram> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "echo doesn't support UDP",
+        ))
+    }
+
+    async fn support_connector(&self) -> ConnectorType {
+        ConnectorType::None
+    }
+}
+
+const CONFIG: &str = r#"
+mixed-port: 7890
+proxies:
+  - name: echo
+    type: echo
+rules:
+  - MATCH,echo
+"#;
+
+fn main() -> clash_lib::Result<()> {
+    register_outbound("echo", |config: HashMap<String, Value>| {
+        let name = config["name"].as_str().unwrap_or("echo").to_owned();
+        Ok(Arc::new(Echo { name }) as AnyOutboundHandler)
+    })?;
+
+    clash_lib::start_scaffold(Options {
+        config: Config::Str(CONFIG.to_owned()),
+        cwd: None,
+        rt: None,
+        log_file: None,
+    })
+}
@@ -10,6 +10,7 @@ use axum::{
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
+use tracing::warn;
 
 use crate::{
     GlobalState,
@@ -18,6 +19,10 @@ use crate::{
         dispatcher,
         dns::ThreadSafeDNSResolver,
         inbound::manager::{InboundManager, Ports},
+        net::{
+            DEFAULT_OUTBOUND_INTERFACE, Interface, interface_selection,
+            ipv6_disabled, pinned_outbound_interface, select_outbound_interface,
+        },
     },
     config::{def, internal::config::BindAddress},
 };
@@ -41,6 +46,7 @@ pub fn routes(
             "/",
             get(get_configs).put(update_configs).patch(patch_configs),
         )
+        .route("/interface", get(get_interface).put(update_interface))
         .with_state(ConfigState {
             inbound_manager,
             dispatcher,
@@ -228,8 +234,66 @@ async fn patch_configs(
     }
 
     if let Some(ipv6) = payload.ipv6 {
-        state.dns_resolver.set_ipv6(ipv6);
+        if ipv6 && ipv6_disabled() {
+            warn!("ipv6 is disabled in the config, not enabling it for dns");
+        } else {
+            state.dns_resolver.set_ipv6(ipv6);
+        }
     }
 
     StatusCode::ACCEPTED.into_response()
 }
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct UpdateInterfaceRequest {
+    /// `None` when omitted, `Some(None)` when explicitly cleared
+    #[serde(default, deserialize_with = "present")]
+    interface: Option<Option<String>>,
+    interface_fallback: Option<bool>,
+}
+
+fn present<'de, D, T>(d: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(d).map(Some)
+}
+
+async fn get_interface() -> impl IntoResponse {
+    let (interface, fallback) = interface_selection();
+    let mut r = serde_json::Map::new();
+    r.insert(
+        "interface".to_owned(),
+        serde_json::json!(interface.map(|x| x.to_string())),
+    );
+    r.insert("interface-fallback".to_owned(), fallback.into());
+    r.insert(
+        "pinned".to_owned(),
+        pinned_outbound_interface().await.is_some().into(),
+    );
+    r.insert(
+        "selected".to_owned(),
+        serde_json::json!(*DEFAULT_OUTBOUND_INTERFACE.read().await),
+    );
+    axum::response::Json(r)
+}
+
+/// Selects the outbound interface again with the given `interface` and
+/// `interface-fallback`, without reloading outbounds, rules or listeners.
+/// Omitted fields keep their current value, `interface: null` clears it.
+async fn update_interface(
+    Json(req): Json<UpdateInterfaceRequest>,
+) -> impl IntoResponse {
+    let (current, current_fallback) = interface_selection();
+    let interface = match req.interface {
+        Some(iface) => iface.map(|x| x.parse::<Interface>().unwrap()),
+        None => current,
+    };
+    let fallback = req.interface_fallback.unwrap_or(current_fallback);
+
+    select_outbound_interface(interface.as_ref(), fallback).await;
+
+    StatusCode::ACCEPTED.into_response()
+}
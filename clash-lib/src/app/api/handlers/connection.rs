@@ -15,19 +15,33 @@ use tracing::{debug, warn};
 
 use crate::app::{
     api::{AppState, handlers::utils::is_request_websocket},
-    dispatcher::StatisticsManager,
+    dispatcher::{Dispatcher, StatisticsManager},
 };
 
 #[derive(Clone)]
 struct ConnectionState {
     statistics_manager: Arc<StatisticsManager>,
+    dispatcher: Arc<Dispatcher>,
 }
 
-pub fn routes(statistics_manager: Arc<StatisticsManager>) -> Router<Arc<AppState>> {
+pub fn routes(
+    statistics_manager: Arc<StatisticsManager>,
+    dispatcher: Arc<Dispatcher>,
+) -> Router<Arc<AppState>> {
     Router::new()
         .route("/", get(get_connections).delete(close_all_connection))
+        .route("/udp", get(get_udp_sessions))
         .route("/{id}", delete(close_connection))
-        .with_state(ConnectionState { statistics_manager })
+        .with_state(ConnectionState {
+            statistics_manager,
+            dispatcher,
+        })
+}
+
+async fn get_udp_sessions(
+    State(state): State<ConnectionState>,
+) -> impl IntoResponse {
+    Json(state.dispatcher.udp_session_stats())
 }
 
 #[derive(Deserialize)]
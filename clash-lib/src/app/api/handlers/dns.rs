@@ -15,7 +15,6 @@ use crate::app::{api::AppState, dns::ThreadSafeDNSResolver};
 
 #[derive(Clone)]
 struct DNSState {
-    #[allow(dead_code)]
     resolver: ThreadSafeDNSResolver,
 }
 
@@ -23,9 +22,30 @@ pub fn routes(resolver: ThreadSafeDNSResolver) -> Router<Arc<AppState>> {
     let state = DNSState { resolver };
     Router::new()
         .route("/query", get(query_dns))
+        .route("/block", get(block_stats))
+        .route("/cache", get(cache_entries).delete(clear_cache))
         .with_state(state)
 }
 
+async fn block_stats(State(state): State<DNSState>) -> impl IntoResponse {
+    match state.resolver.block_stats() {
+        Some(stats) => Json(stats).into_response(),
+        None => (StatusCode::NOT_FOUND, "DNS block is not enabled.").into_response(),
+    }
+}
+
+async fn cache_entries(State(state): State<DNSState>) -> impl IntoResponse {
+    match state.resolver.cache_entries() {
+        Some(entries) => Json(entries).into_response(),
+        None => (StatusCode::NOT_FOUND, "DNS cache is not enabled.").into_response(),
+    }
+}
+
+async fn clear_cache(State(state): State<DNSState>) -> impl IntoResponse {
+    state.resolver.clear_cache();
+    StatusCode::NO_CONTENT
+}
+
 #[derive(Deserialize)]
 struct DnsQuery {
     name: String,
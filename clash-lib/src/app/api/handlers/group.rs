@@ -46,7 +46,7 @@ async fn find_group_by_name(
     next: Next,
 ) -> Response {
     let outbound_manager = state.outbound_manager.clone();
-    match outbound_manager.get_outbound(&name) {
+    match outbound_manager.find_outbound(&name) {
         Some(proxy) => {
             req.extensions_mut().insert(proxy);
             next.run(req).await
@@ -5,6 +5,7 @@ pub mod group;
 pub mod hello;
 pub mod log;
 pub mod memory;
+pub mod mode;
 pub mod provider;
 pub mod proxy;
 pub mod restart;
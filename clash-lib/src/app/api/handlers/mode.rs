@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{api::AppState, dispatcher::Dispatcher},
+    config::def::RunMode,
+};
+
+#[derive(Clone)]
+struct ModeState {
+    dispatcher: Arc<Dispatcher>,
+}
+
+pub fn routes(dispatcher: Arc<Dispatcher>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(get_mode).post(set_mode))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/toggle", post(toggle))
+        .with_state(ModeState { dispatcher })
+}
+
+#[derive(Serialize)]
+struct GetModeResponse {
+    mode: RunMode,
+    paused: bool,
+}
+
+async fn get_mode(State(state): State<ModeState>) -> impl IntoResponse {
+    Json(GetModeResponse {
+        mode: state.dispatcher.get_mode().await,
+        paused: state.dispatcher.is_paused().await,
+    })
+}
+
+#[derive(Deserialize)]
+struct SetModeRequest {
+    mode: RunMode,
+}
+
+async fn set_mode(
+    State(state): State<ModeState>,
+    Json(req): Json<SetModeRequest>,
+) -> impl IntoResponse {
+    state.dispatcher.set_mode(req.mode).await;
+    get_mode(State(state)).await
+}
+
+async fn pause(State(state): State<ModeState>) -> impl IntoResponse {
+    state.dispatcher.pause().await;
+    get_mode(State(state)).await
+}
+
+async fn resume(State(state): State<ModeState>) -> impl IntoResponse {
+    state.dispatcher.resume().await;
+    get_mode(State(state)).await
+}
+
+async fn toggle(State(state): State<ModeState>) -> impl IntoResponse {
+    if state.dispatcher.is_paused().await {
+        state.dispatcher.resume().await;
+    } else {
+        state.dispatcher.pause().await;
+    }
+    get_mode(State(state)).await
+}
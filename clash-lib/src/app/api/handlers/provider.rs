@@ -15,6 +15,7 @@ use crate::{
         api::AppState, outbound::manager::ThreadSafeOutboundManager,
         remote_content_manager::providers::proxy_provider::ThreadSafeProxyProvider,
     },
+    common::utils::proxy_name_eq,
     proxy::AnyOutboundHandler,
 };
 #[derive(Clone)]
@@ -103,12 +104,14 @@ async fn get_provider(
     axum::response::Json(provider.as_map().await)
 }
 
+/// Re-fetches the provider and responds with the proxies the update added,
+/// removed and changed.
 async fn update_provider(
     Extension(provider): Extension<ThreadSafeProxyProvider>,
 ) -> impl IntoResponse {
     let provider = provider.read().await;
-    match provider.update().await {
-        Ok(_) => (StatusCode::ACCEPTED, "provider update started").into_response(),
+    match provider.update_and_diff().await {
+        Ok(diff) => axum::response::Json(diff).into_response(),
         Err(err) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!(
@@ -142,7 +145,11 @@ async fn find_proxy_provider_proxy_by_name(
 ) -> Response {
     let provider = provider.read().await;
     let proxies = provider.proxies().await;
-    let proxy = proxies.iter().find(|x| x.name() == proxy_name);
+    let proxy = proxies.iter().find(|x| x.name() == proxy_name).or_else(|| {
+        proxies
+            .iter()
+            .find(|x| proxy_name_eq(x.name(), &proxy_name))
+    });
 
     if let Some(proxy) = proxy {
         req.extensions_mut().insert(proxy.clone());
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
 use axum::{
     Json, Router,
@@ -14,8 +14,9 @@ use serde::Deserialize;
 
 use crate::{
     app::{
-        api::AppState, outbound::manager::ThreadSafeOutboundManager,
-        profile::ThreadSafeCacheFile,
+        api::AppState, dispatcher::StatisticsManager,
+        outbound::manager::ThreadSafeOutboundManager, profile::ThreadSafeCacheFile,
+        remote_content_manager::DEFAULT_UDP_PROBES,
     },
     proxy::AnyOutboundHandler,
 };
@@ -24,15 +25,18 @@ use crate::{
 pub struct ProxyState {
     outbound_manager: ThreadSafeOutboundManager,
     cache_store: ThreadSafeCacheFile,
+    statistics_manager: Arc<StatisticsManager>,
 }
 
 pub fn routes(
     outbound_manager: ThreadSafeOutboundManager,
     cache_store: ThreadSafeCacheFile,
+    statistics_manager: Arc<StatisticsManager>,
 ) -> Router<Arc<AppState>> {
     let state = ProxyState {
         outbound_manager,
         cache_store,
+        statistics_manager,
     };
     Router::new()
         .route("/", get(get_proxies))
@@ -41,6 +45,7 @@ pub fn routes(
             Router::new()
                 .route("/", get(get_proxy).put(update_proxy))
                 .route("/delay", get(get_proxy_delay))
+                .route("/udp-delay", get(get_proxy_udp_delay))
                 .route_layer(middleware::from_fn_with_state(
                     state.clone(),
                     find_proxy_by_name,
@@ -65,7 +70,7 @@ async fn find_proxy_by_name(
     next: Next,
 ) -> Response {
     let outbound_manager = state.outbound_manager.clone();
-    match outbound_manager.get_outbound(&name) {
+    match outbound_manager.find_outbound(&name) {
         Some(proxy) => {
             req.extensions_mut().insert(proxy);
             next.run(req).await
@@ -80,7 +85,12 @@ async fn get_proxy(
     State(state): State<ProxyState>,
 ) -> impl IntoResponse {
     let outbound_manager = state.outbound_manager.clone();
-    axum::response::Json(outbound_manager.get_proxy(&proxy).await)
+    let mut r = outbound_manager.get_proxy(&proxy).await;
+    r.insert(
+        "stats".to_owned(),
+        Box::new(state.statistics_manager.proxy_stats(proxy.name())),
+    );
+    axum::response::Json(r)
 }
 
 #[derive(Deserialize)]
@@ -181,3 +191,77 @@ async fn get_proxy_delay(
     r.insert("overall".to_owned(), overall.as_millis());
     (headers, axum::response::Json(r)).into_response()
 }
+
+#[derive(Deserialize)]
+struct UdpDelayRequest {
+    /// a DNS server to query through the proxy
+    server: SocketAddr,
+    timeout: u16,
+    count: Option<u16>,
+}
+async fn get_proxy_udp_delay(
+    State(state): State<ProxyState>,
+    Extension(proxy): Extension<AnyOutboundHandler>,
+    Query(q): Query<UdpDelayRequest>,
+) -> impl IntoResponse {
+    let n = proxy.name().to_owned();
+    let timeout = Duration::from_millis(q.timeout.into());
+    match state
+        .outbound_manager
+        .udp_test(
+            proxy,
+            q.server,
+            q.count.unwrap_or(DEFAULT_UDP_PROBES),
+            timeout,
+        )
+        .await
+    {
+        Ok(quality) => axum::response::Json(quality).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            format!("get udp delay for {n} failed with error: {err}"),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, body::Body, extract::Path, routing::get};
+    use http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use crate::common::utils::proxy_name_eq;
+
+    #[tokio::test]
+    async fn test_unicode_proxy_name_in_path() {
+        let app: Router = Router::new().nest(
+            "/proxies/{name}",
+            Router::new().route(
+                "/delay",
+                get(|Path(name): Path<String>| async move { name }),
+            ),
+        );
+
+        // 🇯🇵 Tokyo / 01, as GUIs encode it with encodeURIComponent
+        let res = app
+            .oneshot(
+                Request::get(
+                    "/proxies/%F0%9F%87%AF%F0%9F%87%B5%20Tokyo%20%2F%2001/delay",
+                )
+                .body(Body::empty())
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let name = std::str::from_utf8(&body).unwrap();
+        assert_eq!(name, "🇯🇵 Tokyo / 01");
+
+        assert!(proxy_name_eq(name, "🇯🇵 Tokyo / 01"));
+        assert!(proxy_name_eq("\u{2764}\u{FE0F} Home", "\u{2764} Home"));
+        assert!(!proxy_name_eq(name, "🇯🇵 Tokyo / 02"));
+    }
+}
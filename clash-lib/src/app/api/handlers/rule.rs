@@ -15,12 +15,26 @@ pub fn routes(router: ThreadSafeRouter) -> Router<Arc<AppState>> {
         .with_state(RuleState { router })
 }
 
+/// The rules in the order they're matched, each with how many sessions it
+/// matched since the config was loaded. Rules that never match, e.g.
+/// shadowed by a broader one above, stay at 0.
 async fn get_rules(State(state): State<RuleState>) -> impl IntoResponse {
     let rules = state.router.get_all_rules();
+    let hits = state.router.rule_hits();
     let mut r = HashMap::new();
     r.insert(
         "rules",
-        rules.iter().map(|r| r.as_map()).collect::<Vec<_>>(),
+        rules
+            .iter()
+            .zip(hits)
+            .enumerate()
+            .map(|(index, (r, hits))| {
+                let mut m = r.as_map();
+                m.insert("index".to_owned(), Box::new(index));
+                m.insert("hits".to_owned(), Box::new(hits));
+                m
+            })
+            .collect::<Vec<_>>(),
     );
     axum::response::Json(r)
 }
@@ -1,7 +1,8 @@
 use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
-    extract::{ConnectInfo, State, WebSocketUpgrade, ws::Message},
+    Json,
+    extract::{ConnectInfo, Path, State, WebSocketUpgrade, ws::Message},
     response::IntoResponse,
 };
 
@@ -42,3 +43,27 @@ pub async fn handle(
         }
     })
 }
+
+pub async fn get_interfaces(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    Json(state.statistics_manager.interfaces_snapshot())
+}
+
+pub async fn reset_interfaces(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    state.statistics_manager.reset_interface_traffic(None).await;
+    "interface traffic reset".into_response()
+}
+
+pub async fn reset_interface(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    state
+        .statistics_manager
+        .reset_interface_traffic(Some(&name))
+        .await;
+    format!("interface {name} traffic reset").into_response()
+}
@@ -3,7 +3,7 @@ use std::{net::SocketAddr, path::PathBuf, sync::Arc};
 use axum::{
     Router, middleware,
     response::Redirect,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 use http::{Method, header};
 use tokio::sync::{Mutex, broadcast::Sender};
@@ -91,14 +91,24 @@ pub fn get_api_runner(
             .route("/", get(handlers::hello::handle))
             .route("/logs", get(handlers::log::handle))
             .route("/traffic", get(handlers::traffic::handle))
+            .route(
+                "/traffic/interfaces",
+                get(handlers::traffic::get_interfaces)
+                    .delete(handlers::traffic::reset_interfaces),
+            )
+            .route(
+                "/traffic/interfaces/{name}",
+                delete(handlers::traffic::reset_interface),
+            )
             .route("/version", get(handlers::version::handle))
             .route("/memory", get(handlers::memory::handle))
             .route("/restart", post(handlers::restart::handle))
+            .nest("/mode", handlers::mode::routes(dispatcher.clone()))
             .nest(
                 "/configs",
                 handlers::config::routes(
                     inbound_manager,
-                    dispatcher,
+                    dispatcher.clone(),
                     global_state,
                     dns_resolver.clone(),
                 ),
@@ -107,7 +117,11 @@ pub fn get_api_runner(
             .nest("/group", handlers::group::routes(outbound_manager.clone()))
             .nest(
                 "/proxies",
-                handlers::proxy::routes(outbound_manager.clone(), cache_store),
+                handlers::proxy::routes(
+                    outbound_manager.clone(),
+                    cache_store,
+                    statistics_manager.clone(),
+                ),
             )
             .nest(
                 "/providers/proxies",
@@ -115,7 +129,7 @@ pub fn get_api_runner(
             )
             .nest(
                 "/connections",
-                handlers::connection::routes(statistics_manager),
+                handlers::connection::routes(statistics_manager, dispatcher),
             )
             .nest("/dns", handlers::dns::routes(dns_resolver))
             .route_layer(middlewares::auth::AuthMiddlewareLayer::new(
@@ -1,16 +1,27 @@
 use crate::{
     app::{
-        dispatcher::tracked::{TrackedDatagram, TrackedStream},
-        dns::ClashResolver,
+        dispatcher::{
+            BoxedChainedStream, sniffer,
+            tracked::{TrackedDatagram, TrackedStream},
+        },
+        dns::{ClashResolver, default_ip_version, with_ip_version},
+        net::{
+            OutboundInterface, bogon_policy, direct_preserve_source,
+            get_interface_by_ip, is_bogon, is_local_address, local_address_policy,
+            pinned_outbound_interface,
+        },
         outbound::manager::ThreadSafeOutboundManager,
         router::ThreadSafeRouter,
     },
-    common::io::copy_bidirectional,
+    common::io::{IdleReadWatchdog, copy_bidirectional},
     config::{
-        def::RunMode,
+        def::{BogonPolicy, LocalAddressPolicy, RunMode},
         internal::proxy::{PROXY_DIRECT, PROXY_GLOBAL},
     },
-    proxy::{AnyInboundDatagram, ClientStream, datagram::UdpPacket},
+    proxy::{
+        AnyInboundDatagram, AnyOutboundHandler, ClientStream, OutboundType,
+        datagram::UdpPacket,
+    },
     session::{Session, SocksAddr},
 };
 use futures::{SinkExt, StreamExt};
@@ -21,22 +32,39 @@ use std::{
     sync::Arc,
     time::{Duration, Instant},
 };
-use tokio::{io::AsyncWriteExt, sync::RwLock, task::JoinHandle};
-use tracing::{Instrument, debug, error, info, info_span, instrument, trace, warn};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+};
+use tracing::{
+    Instrument, Span, debug, error, field, info, info_span, instrument, trace, warn,
+};
 
-use crate::app::dns::ThreadSafeDNSResolver;
+use crate::app::{dispatcher::BoxedChainedDatagram, dns::ThreadSafeDNSResolver};
 
-use super::statistics_manager::Manager;
+use super::{
+    statistics_manager::Manager,
+    udp_sessions::{UdpSessionPermit, UdpSessionStats, UdpSessionTable},
+};
 
 const DEFAULT_BUFFER_SIZE: usize = 16 * 1024;
+/// How long an outbound that doesn't declare UDP support is given to set up
+/// a datagram before falling back
+const OPTIMISTIC_UDP_TIMEOUT: Duration = Duration::from_secs(3);
 
 pub struct Dispatcher {
     outbound_manager: ThreadSafeOutboundManager,
     router: ThreadSafeRouter,
     resolver: ThreadSafeDNSResolver,
     mode: Arc<RwLock<RunMode>>,
+    /// the mode to go back to on resume, set while proxying is paused
+    resume_mode: Mutex<Option<RunMode>>,
     manager: Arc<Manager>,
     tcp_buffer_size: usize,
+    udp_fallback: Option<String>,
+    block_quic: bool,
+    udp_sessions: Arc<UdpSessionTable>,
 }
 
 impl Debug for Dispatcher {
@@ -53,20 +81,33 @@ impl Dispatcher {
         mode: RunMode,
         statistics_manager: Arc<Manager>,
         tcp_buffer_size: Option<usize>,
+        udp_fallback: Option<String>,
+        block_quic: bool,
+        max_udp_sessions: Option<usize>,
     ) -> Self {
         Self {
             outbound_manager,
             router,
             resolver,
             mode: Arc::new(RwLock::new(mode)),
+            resume_mode: Mutex::new(None),
             manager: statistics_manager,
             tcp_buffer_size: tcp_buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE),
+            udp_fallback,
+            block_quic,
+            udp_sessions: UdpSessionTable::new(max_udp_sessions),
         }
     }
 
+    pub fn udp_session_stats(&self) -> UdpSessionStats {
+        self.udp_sessions.stats()
+    }
+
     pub async fn set_mode(&self, mode: RunMode) {
         info!("run mode switched to {}", mode);
 
+        let mut resume_mode = self.resume_mode.lock().await;
+        *resume_mode = None;
         *self.mode.write().await = mode;
     }
 
@@ -74,7 +115,51 @@ impl Dispatcher {
         *self.mode.read().await
     }
 
-    #[instrument(skip(self, sess, lhs))]
+    /// Sends everything direct until [`Self::resume`], e.g. while logging
+    /// into a captive portal.
+    pub async fn pause(&self) {
+        let mut resume_mode = self.resume_mode.lock().await;
+        if resume_mode.is_some() {
+            return;
+        }
+
+        let mut mode = self.mode.write().await;
+        info!(
+            "proxying paused, run mode switched from {} to direct",
+            *mode
+        );
+        *resume_mode = Some(*mode);
+        *mode = RunMode::Direct;
+    }
+
+    /// Restores the mode from before [`Self::pause`].
+    pub async fn resume(&self) {
+        if let Some(prev) = self.resume_mode.lock().await.take() {
+            info!("proxying resumed, run mode switched to {}", prev);
+            *self.mode.write().await = prev;
+        }
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.resume_mode.lock().await.is_some()
+    }
+
+    /// The span of this function covers the whole lifetime of a TCP
+    /// connection, its fields are filled in as the connection progresses so
+    /// that they are exported along with the span, e.g. to OpenTelemetry.
+    #[instrument(
+        skip(self, sess, lhs),
+        fields(
+            network = "tcp",
+            source = %sess.source,
+            destination = field::Empty,
+            outbound = field::Empty,
+            iface = field::Empty,
+            connect_ms = field::Empty,
+            upload = field::Empty,
+            download = field::Empty,
+        )
+    )]
     pub async fn dispatch_stream(
         &self,
         mut sess: Session,
@@ -91,14 +176,30 @@ impl Dispatcher {
 
         sess.destination = dest.clone();
 
+        let span = Span::current();
+        span.record("destination", field::display(&sess.destination));
+
         let mode = *self.mode.read().await;
-        let (outbound_name, rule) = match mode {
-            RunMode::Global => (PROXY_GLOBAL, None),
-            RunMode::Rule => self.router.match_route(&mut sess).await,
-            RunMode::Direct => (PROXY_DIRECT, None),
+        if matches!(mode, RunMode::Rule) && self.router.sniff_alpn() {
+            let (stream, hello) = sniffer::sniff_tls(lhs).await;
+            lhs = stream;
+            sess.alpn = hello.map(|h| h.alpn);
+        }
+        let (outbound_name, rule) = match address_action(&sess) {
+            AddressAction::Block(kind) => {
+                warn!("blocked connection to {} destination {}", kind, sess);
+                return;
+            }
+            AddressAction::Direct => (PROXY_DIRECT, None),
+            AddressAction::Route => match mode {
+                RunMode::Global => (PROXY_GLOBAL, None),
+                RunMode::Rule => self.router.match_route(&mut sess).await,
+                RunMode::Direct => (PROXY_DIRECT, None),
+            },
         };
 
         debug!("dispatching {} to {}[{}]", sess, outbound_name, mode);
+        span.record("outbound", outbound_name);
 
         let mgr = self.outbound_manager.clone();
         let handler = mgr.get_outbound(outbound_name).unwrap_or_else(|| {
@@ -106,13 +207,35 @@ impl Dispatcher {
             mgr.get_outbound(PROXY_DIRECT).unwrap()
         });
 
+        if sess.iface.is_none() {
+            sess.iface = match preserved_source_interface(&sess, &handler) {
+                Some(iface) => Some(iface),
+                None => pinned_outbound_interface().await,
+            };
+        }
+        if let Some(iface) = &sess.iface {
+            span.record("iface", iface.name.as_str());
+        }
+
+        let connect_start = Instant::now();
         match handler
-            .connect_stream(&sess, self.resolver.clone())
+            .connect_stream(
+                &sess,
+                with_ip_version(
+                    self.resolver.clone(),
+                    Some(sess.ip_version.unwrap_or_else(default_ip_version)),
+                ),
+            )
             .instrument(info_span!("connect_stream", outbound_name = outbound_name,))
             .await
         {
             Ok(rhs) => {
+                span.record(
+                    "connect_ms",
+                    connect_start.elapsed().as_millis() as u64,
+                );
                 debug!("remote connection established {}", sess);
+                let rhs = self.watch_idle_reads(rhs).await;
                 let rhs = TrackedStream::new(
                     rhs,
                     self.manager.clone(),
@@ -120,6 +243,7 @@ impl Dispatcher {
                     rule,
                 )
                 .await;
+                let tracker = rhs.tracker_info();
                 match copy_bidirectional(
                     lhs,
                     rhs,
@@ -134,6 +258,8 @@ impl Dispatcher {
                 .await
                 {
                     Ok((up, down)) => {
+                        span.record("upload", up);
+                        span.record("download", down);
                         debug!(
                             "connection {} closed with {} bytes up, {} bytes down",
                             sess, up, down
@@ -174,6 +300,7 @@ impl Dispatcher {
                                     "connection {} closed with error {} by remote",
                                     sess, err
                                 );
+                                self.manager.record_connection_error(&tracker);
                             }
                         },
                         crate::common::io::CopyBidirectionalError::Other(err) => {
@@ -202,6 +329,7 @@ impl Dispatcher {
                     "failed to establish remote connection {}, error: {}",
                     sess, err
                 );
+                self.manager.record_proxy_error(outbound_name);
                 if let Err(e) = lhs.shutdown().await {
                     warn!("error closing local connection {}: {}", sess, e)
                 }
@@ -209,6 +337,20 @@ impl Dispatcher {
         }
     }
 
+    /// Wraps `rhs` in an [`IdleReadWatchdog`] when a handler along its
+    /// chain, innermost first, has an idle read timeout.
+    async fn watch_idle_reads(&self, rhs: BoxedChainedStream) -> BoxedChainedStream {
+        let timeout = rhs.chain().names().await.iter().find_map(|name| {
+            self.outbound_manager
+                .get_outbound(name)
+                .and_then(|h| h.idle_read_timeout())
+        });
+        match timeout {
+            Some(timeout) => Box::new(IdleReadWatchdog::new(rhs, timeout)),
+            None => rhs,
+        }
+    }
+
     /// Dispatch a UDP packet to outbound handler
     /// returns the close sender
     #[instrument]
@@ -218,13 +360,21 @@ impl Dispatcher {
         sess: Session,
         udp_inbound: AnyInboundDatagram,
     ) -> tokio::sync::oneshot::Sender<u8> {
-        let outbound_handle_guard = TimeoutUdpSessionManager::new();
+        let outbound_handle_guard =
+            TimeoutUdpSessionManager::new(self.udp_sessions.clone());
+
+        let mut sess = sess;
+        if sess.iface.is_none() {
+            sess.iface = pinned_outbound_interface().await;
+        }
 
         let router = self.router.clone();
         let outbound_manager = self.outbound_manager.clone();
         let resolver = self.resolver.clone();
         let mode = self.mode.clone();
         let manager = self.manager.clone();
+        let udp_fallback = self.udp_fallback.clone();
+        let block_quic = self.block_quic;
 
         #[rustfmt::skip]
         /*
@@ -252,6 +402,11 @@ impl Dispatcher {
         let ss = sess.clone();
         let t1 = tokio::spawn(async move {
             while let Some(mut packet) = local_r.next().await {
+                if block_quic && packet.is_quic_initial() {
+                    debug!("dropped QUIC initial packet {}", packet);
+                    continue;
+                }
+
                 let mut sess = sess.clone();
 
                 let dest = match reverse_lookup(&resolver, &packet.dst_addr).await {
@@ -275,10 +430,17 @@ impl Dispatcher {
 
                 let mode = *mode.read().await;
 
-                let (outbound_name, rule) = match mode {
-                    RunMode::Global => (PROXY_GLOBAL, None),
-                    RunMode::Rule => router.match_route(&mut sess).await,
-                    RunMode::Direct => (PROXY_DIRECT, None),
+                let (outbound_name, rule) = match address_action(&sess) {
+                    AddressAction::Block(kind) => {
+                        warn!("blocked packet to {} destination {}", kind, sess);
+                        continue;
+                    }
+                    AddressAction::Direct => (PROXY_DIRECT, None),
+                    AddressAction::Route => match mode {
+                        RunMode::Global => (PROXY_GLOBAL, None),
+                        RunMode::Rule => router.match_route(&mut sess).await,
+                        RunMode::Direct => (PROXY_DIRECT, None),
+                    },
                 };
 
                 let outbound_name = outbound_name.to_string();
@@ -321,13 +483,26 @@ impl Dispatcher {
                 {
                     None => {
                         debug!("building {} outbound datagram connecting", sess);
-                        let outbound_datagram = match handler
-                            .connect_datagram(&sess, resolver.clone())
-                            .await
+                        let outbound_datagram = match connect_datagram(
+                            &mgr,
+                            &handler,
+                            &outbound_name,
+                            udp_fallback.as_deref(),
+                            &sess,
+                            with_ip_version(
+                                resolver.clone(),
+                                Some(
+                                    sess.ip_version
+                                        .unwrap_or_else(default_ip_version),
+                                ),
+                            ),
+                        )
+                        .await
                         {
                             Ok(v) => v,
                             Err(err) => {
                                 error!("failed to connect outbound: {}", err);
+                                manager.record_proxy_error(&outbound_name);
                                 continue;
                             }
                         };
@@ -372,7 +547,18 @@ impl Dispatcher {
                         // local -> remote
                         let w_handle = tokio::spawn(async move {
                             while let Some(packet) = remote_forwarder.recv().await {
-                                match remote_w.send(packet).await {
+                                // whatever else is queued goes out in the same
+                                // flush, which batches it where the outbound can
+                                let mut res = remote_w.feed(packet).await;
+                                while res.is_ok()
+                                    && let Ok(packet) = remote_forwarder.try_recv()
+                                {
+                                    res = remote_w.feed(packet).await;
+                                }
+                                if res.is_ok() {
+                                    res = remote_w.flush().await;
+                                }
+                                match res {
                                     Ok(_) => {}
                                     Err(err) => {
                                         warn!(
@@ -445,6 +631,111 @@ impl Dispatcher {
     }
 }
 
+/// How to route `sess` as far as its destination is concerned: `Allow`
+/// unless it's a bogon address.
+/// What to do with a session because of its destination address, before
+/// any rule is matched.
+enum AddressAction {
+    /// drop it, the kind of address is given for logging
+    Block(&'static str),
+    Direct,
+    Route,
+}
+
+fn address_action(sess: &Session) -> AddressAction {
+    let Some(ip) = sess.destination.ip() else {
+        return AddressAction::Route;
+    };
+    if is_bogon(&ip) {
+        return match bogon_policy() {
+            BogonPolicy::Block => AddressAction::Block("bogon"),
+            BogonPolicy::Direct => AddressAction::Direct,
+            BogonPolicy::Allow => AddressAction::Route,
+        };
+    }
+    if is_local_address(&ip) {
+        return match local_address_policy() {
+            LocalAddressPolicy::Block => AddressAction::Block("local"),
+            LocalAddressPolicy::Direct => AddressAction::Direct,
+            LocalAddressPolicy::Allow => AddressAction::Route,
+        };
+    }
+    AddressAction::Route
+}
+
+/// With `direct-preserve-source`, the interface owning the address `sess`
+/// arrived on, for a DIRECT connection to leave from that same address.
+fn preserved_source_interface(
+    sess: &Session,
+    handler: &AnyOutboundHandler,
+) -> Option<OutboundInterface> {
+    if !direct_preserve_source() || !matches!(handler.proto(), OutboundType::Direct)
+    {
+        return None;
+    }
+    let ip = sess.local_addr?.ip().to_canonical();
+    if ip.is_loopback() || ip.is_unspecified() {
+        return None;
+    }
+    let iface = get_interface_by_ip(ip);
+    if iface.is_none() {
+        debug!("no interface owns inbound address {} of {}", ip, sess);
+    }
+    iface
+}
+
+/// Connect a datagram through `handler`, whose currently active proxy is
+/// `proxy_name`.
+/// With a fallback configured, proxies that don't declare UDP support are
+/// tried optimistically, and the outcome is remembered so that proxies known
+/// not to work go to the fallback straight away, until the failure expires.
+/// Note that some protocols can only fail on the first packet sent, which is
+/// not detected here.
+async fn connect_datagram(
+    mgr: &ThreadSafeOutboundManager,
+    handler: &AnyOutboundHandler,
+    proxy_name: &str,
+    fallback: Option<&str>,
+    sess: &Session,
+    resolver: ThreadSafeDNSResolver,
+) -> std::io::Result<BoxedChainedDatagram> {
+    let Some(fallback) = fallback else {
+        return handler.connect_datagram(sess, resolver).await;
+    };
+    if handler.support_udp().await {
+        return handler.connect_datagram(sess, resolver).await;
+    }
+
+    if mgr.observed_udp(proxy_name).await != Some(false) {
+        match tokio::time::timeout(
+            OPTIMISTIC_UDP_TIMEOUT,
+            handler.connect_datagram(sess, resolver.clone()),
+        )
+        .await
+        {
+            Ok(Ok(d)) => {
+                mgr.report_udp(proxy_name, true).await;
+                return Ok(d);
+            }
+            Ok(Err(e)) => debug!("optimistic UDP via {} failed: {}", proxy_name, e),
+            Err(_) => debug!("optimistic UDP via {} timed out", proxy_name),
+        }
+        mgr.report_udp(proxy_name, false).await;
+    }
+
+    let fallback = mgr.get_outbound(fallback).unwrap_or_else(|| {
+        debug!("unknown udp fallback: {}, fallback to direct", fallback);
+        mgr.get_outbound(PROXY_DIRECT).unwrap()
+    });
+    debug!(
+        "{} doesn't support UDP, using {} for {}",
+        proxy_name,
+        fallback.name(),
+        sess
+    );
+    fallback.connect_datagram(sess, resolver).await
+}
+
 // helper function to resolve the destination address
 // if the destination is an IP address, check if it's a fake IP
 // or look for cached IP
@@ -493,6 +784,7 @@ type OutboundPacketSender = tokio::sync::mpsc::Sender<UdpPacket>; // outbound pa
 
 struct TimeoutUdpSessionManager {
     map: Arc<RwLock<OutboundHandleMap>>,
+    sessions: Arc<UdpSessionTable>,
 
     cleaner: Option<JoinHandle<()>>,
 }
@@ -507,7 +799,7 @@ impl Drop for TimeoutUdpSessionManager {
 }
 
 impl TimeoutUdpSessionManager {
-    fn new() -> Self {
+    fn new(sessions: Arc<UdpSessionTable>) -> Self {
         let map = Arc::new(RwLock::new(OutboundHandleMap::new()));
         let timeout = Duration::from_secs(10);
 
@@ -525,7 +817,7 @@ impl TimeoutUdpSessionManager {
                 let mut alived = 0;
                 let mut expired = 0;
                 g.0.retain(|k, x| {
-                    let (h1, h2, _, last) = x;
+                    let (h1, h2, _, last, _) = x;
                     let now = Instant::now();
                     let alive = now.duration_since(*last) < timeout;
                     if !alive {
@@ -547,6 +839,7 @@ impl TimeoutUdpSessionManager {
 
         Self {
             map,
+            sessions,
 
             cleaner: Some(cleaner),
         }
@@ -560,8 +853,19 @@ impl TimeoutUdpSessionManager {
         send_handle: JoinHandle<()>,
         sender: OutboundPacketSender,
     ) {
+        let permit = self.sessions.register(
+            format!("{src_addr} via {outbound_name}"),
+            [recv_handle.abort_handle(), send_handle.abort_handle()],
+        );
         let mut map = self.map.write().await;
-        map.insert(outbound_name, src_addr, recv_handle, send_handle, sender);
+        map.insert(
+            outbound_name,
+            src_addr,
+            recv_handle,
+            send_handle,
+            sender,
+            permit,
+        );
     }
 
     async fn get_outbound_sender_mut(
@@ -580,6 +884,7 @@ type OutboundHandleVal = (
     JoinHandle<()>,
     OutboundPacketSender,
     Instant,
+    UdpSessionPermit,
 );
 
 struct OutboundHandleMap(HashMap<OutboundHandleKey, OutboundHandleVal>);
@@ -596,10 +901,11 @@ impl OutboundHandleMap {
         recv_handle: JoinHandle<()>,
         send_handle: JoinHandle<()>,
         sender: OutboundPacketSender,
+        permit: UdpSessionPermit,
     ) {
         self.0.insert(
             (outbound_name.to_string(), src_addr),
-            (recv_handle, send_handle, sender, Instant::now()),
+            (recv_handle, send_handle, sender, Instant::now(), permit),
         );
     }
 
@@ -608,16 +914,26 @@ impl OutboundHandleMap {
         outbound_name: &str,
         src_addr: SocketAddr,
     ) -> Option<OutboundPacketSender> {
-        self.0.get_mut(&(outbound_name.to_owned(), src_addr)).map(
-            |(_, _, sender, last)| {
-                trace!(
-                    "updating last access time for outbound {:?}",
-                    (outbound_name, src_addr)
-                );
-                *last = Instant::now();
-                sender.clone()
-            },
-        )
+        let key = (outbound_name.to_owned(), src_addr);
+        // the session is gone if its relay tasks were aborted, i.e. it got
+        // evicted, so a new one has to be set up
+        if self
+            .0
+            .get(&key)
+            .is_some_and(|(_, _, sender, ..)| sender.is_closed())
+        {
+            self.0.remove(&key);
+            return None;
+        }
+        self.0.get_mut(&key).map(|(_, _, sender, last, permit)| {
+            trace!(
+                "updating last access time for outbound {:?}",
+                (outbound_name, src_addr)
+            );
+            *last = Instant::now();
+            permit.touch();
+            sender.clone()
+        })
     }
 }
 
@@ -633,3 +949,151 @@ impl Drop for OutboundHandleMap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io, sync::Arc};
+
+    use crate::{
+        app::{
+            dns::{MockClashResolver, ThreadSafeDNSResolver},
+            outbound::manager::{OutboundManager, ThreadSafeOutboundManager},
+            profile::ThreadSafeCacheFile,
+        },
+        config::internal::proxy::PROXY_DIRECT,
+        proxy::{
+            AnyOutboundHandler, OutboundType, direct,
+            mocks::MockDummyOutboundHandler,
+        },
+        session::Session,
+    };
+
+    use super::connect_datagram;
+
+    const FALLBACK: &str = "udp-fallback";
+
+    fn handler(name: &str, udp: bool) -> MockDummyOutboundHandler {
+        let mut h = MockDummyOutboundHandler::new();
+        h.expect_name().return_const(name.to_owned());
+        h.expect_proto().return_const(OutboundType::Socks5);
+        h.expect_support_udp().return_const(udp);
+        h
+    }
+
+    /// A fallback that fails with its name, to tell where a datagram went
+    fn fallback() -> AnyOutboundHandler {
+        let mut h = handler(FALLBACK, true);
+        h.expect_connect_datagram()
+            .returning(|_, _| Err(io::Error::other(FALLBACK)));
+        Arc::new(h)
+    }
+
+    async fn manager(
+        proxy: MockDummyOutboundHandler,
+    ) -> (
+        ThreadSafeOutboundManager,
+        AnyOutboundHandler,
+        tempfile::TempDir,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ThreadSafeCacheFile::new(
+            dir.path().join("cache.db").to_str().unwrap(),
+            false,
+            false,
+            false,
+        );
+        let proxy: AnyOutboundHandler = Arc::new(proxy);
+        let mgr = OutboundManager::new(
+            vec![
+                Arc::new(direct::Handler::new(PROXY_DIRECT)),
+                proxy.clone(),
+                fallback(),
+            ],
+            vec![],
+            HashMap::new(),
+            vec![],
+            None,
+            resolver(),
+            cache,
+            dir.path().to_string_lossy().into_owned(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        (Arc::new(mgr), proxy, dir)
+    }
+
+    fn resolver() -> ThreadSafeDNSResolver {
+        Arc::new(MockClashResolver::new())
+    }
+
+    #[tokio::test]
+    async fn test_optimistic_udp() {
+        let sess = Session::default();
+        let datagram = direct::Handler::new(PROXY_DIRECT)
+            .connect_datagram(&sess, resolver())
+            .await
+            .unwrap();
+        let mut proxy = handler("p", false);
+        proxy
+            .expect_connect_datagram()
+            .times(1)
+            .return_once(move |_, _| Ok(datagram));
+        let (mgr, proxy, _dir) = manager(proxy).await;
+
+        connect_datagram(&mgr, &proxy, "p", Some(FALLBACK), &sess, resolver())
+            .await
+            .expect("through the proxy");
+        assert_eq!(mgr.observed_udp("p").await, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_optimistic_udp_falls_back() {
+        let sess = Session::default();
+        let mut proxy = handler("p", false);
+        // only tried once, then known not to work
+        proxy
+            .expect_connect_datagram()
+            .times(1)
+            .returning(|_, _| Err(io::Error::other("no udp")));
+        let (mgr, proxy, _dir) = manager(proxy).await;
+
+        for _ in 0..2 {
+            let err = connect_datagram(
+                &mgr,
+                &proxy,
+                "p",
+                Some(FALLBACK),
+                &sess,
+                resolver(),
+            )
+            .await
+            .err()
+            .expect("through the fallback");
+            assert_eq!(err.to_string(), FALLBACK);
+            assert_eq!(mgr.observed_udp("p").await, Some(false));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_without_fallback() {
+        let sess = Session::default();
+        let mut proxy = handler("p", false);
+        proxy
+            .expect_connect_datagram()
+            .times(2)
+            .returning(|_, _| Err(io::Error::other("no udp")));
+        let (mgr, proxy, _dir) = manager(proxy).await;
+
+        // not remembered, the proxy is always tried
+        for _ in 0..2 {
+            let err = connect_datagram(&mgr, &proxy, "p", None, &sess, resolver())
+                .await
+                .err()
+                .expect("through the proxy");
+            assert_eq!(err.to_string(), "no udp");
+        }
+        assert_eq!(mgr.observed_udp("p").await, None);
+    }
+}
@@ -1,9 +1,13 @@
 mod dispatcher_impl;
+mod sniffer;
 mod statistics_manager;
 mod tracked;
+mod udp_sessions;
 
 pub use dispatcher_impl::Dispatcher;
-pub use statistics_manager::Manager as StatisticsManager;
+pub use statistics_manager::{
+    InterfaceTrafficSnapshot, Manager as StatisticsManager,
+};
 pub use tracked::{
     BoxedChainedDatagram, BoxedChainedStream, ChainedDatagram,
     ChainedDatagramWrapper, ChainedStream, ChainedStreamWrapper, TrackedStream,
@@ -0,0 +1,272 @@
+//! Peeks at the first bytes of a TCP connection for a TLS ClientHello, so
+//! that rules can match on what it offers.
+//!
+//! QUIC carries the same ClientHello, ALPN included, in its Initial packets,
+//! but those are encrypted and UDP is routed packet by packet, so they are
+//! not sniffed: UDP sessions have no ALPN, and `ALPN` rules never match
+//! them. HTTP/3 is better caught with `NETWORK,UDP` and `DST-PORT,443`.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+use crate::proxy::ClientStream;
+
+/// How long to wait for the client to speak first. Server-first protocols,
+/// e.g. SMTP or SSH, are routed without ALPN once this runs out.
+const SNIFF_TIMEOUT: Duration = Duration::from_millis(100);
+
+const RECORD_HEADER_LEN: usize = 5;
+const MAX_RECORD_LEN: usize = 16384;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ClientHello {
+    /// The protocols offered in the ALPN extension, in the client's order
+    pub alpn: Vec<String>,
+}
+
+/// Reads the first TLS record of `lhs` and parses it as a ClientHello.
+/// Returns the stream with the bytes read put back in front.
+pub async fn sniff_tls(
+    mut lhs: Box<dyn ClientStream>,
+) -> (Box<dyn ClientStream>, Option<ClientHello>) {
+    let mut buf = Vec::with_capacity(RECORD_HEADER_LEN + 512);
+    let read = tokio::time::timeout(SNIFF_TIMEOUT, async {
+        let mut chunk = [0u8; 2048];
+        loop {
+            match record_len(&buf) {
+                Some(len) if buf.len() >= len => return,
+                None if buf.len() >= RECORD_HEADER_LEN => return,
+                _ => {}
+            }
+            match lhs.read(&mut chunk).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    })
+    .await;
+    let hello = read.ok().and_then(|_| parse_client_hello(&buf));
+
+    if buf.is_empty() {
+        return (lhs, hello);
+    }
+    let stream = SniffedStream {
+        prefix: buf,
+        pos: 0,
+        inner: lhs,
+    };
+    (Box::new(stream), hello)
+}
+
+/// The total length of the TLS handshake record at the start of `buf`, or
+/// `None` if it doesn't start with one.
+fn record_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < RECORD_HEADER_LEN || buf[0] != 0x16 || buf[1] != 0x03 {
+        return None;
+    }
+    let len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+    (len <= MAX_RECORD_LEN).then_some(RECORD_HEADER_LEN + len)
+}
+
+/// Parses a TLS record holding a ClientHello. The ClientHello has to fit in
+/// the first record, which is the case for all common clients.
+pub fn parse_client_hello(buf: &[u8]) -> Option<ClientHello> {
+    let len = record_len(buf)?;
+    let mut r = Reader(buf.get(RECORD_HEADER_LEN..len)?);
+
+    // handshake header
+    if r.u8()? != 0x01 {
+        return None;
+    }
+    let mut r = Reader(r.take(r.u24()?)?);
+
+    // legacy_version, random
+    r.take(2 + 32)?;
+    // legacy_session_id
+    let n = r.u8()? as usize;
+    r.take(n)?;
+    // cipher_suites
+    let n = r.u16()? as usize;
+    r.take(n)?;
+    // legacy_compression_methods
+    let n = r.u8()? as usize;
+    r.take(n)?;
+
+    let mut hello = ClientHello::default();
+    if r.0.is_empty() {
+        return Some(hello);
+    }
+    let n = r.u16()? as usize;
+    let mut exts = Reader(r.take(n)?);
+    while !exts.0.is_empty() {
+        let typ = exts.u16()?;
+        let n = exts.u16()? as usize;
+        let mut ext = Reader(exts.take(n)?);
+        // application_layer_protocol_negotiation
+        if typ != 0x0010 {
+            continue;
+        }
+        let n = ext.u16()? as usize;
+        let mut protos = Reader(ext.take(n)?);
+        while !protos.0.is_empty() {
+            let n = protos.u8()? as usize;
+            let proto = protos.take(n)?;
+            hello.alpn.push(String::from_utf8_lossy(proto).into_owned());
+        }
+    }
+    Some(hello)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.take(3)
+            .map(|b| u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+}
+
+/// A stream that replays the sniffed bytes before reading from the
+/// connection again.
+struct SniffedStream {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: Box<dyn ClientStream>,
+}
+
+impl AsyncRead for SniffedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos < self.prefix.len() {
+            let n = buf.remaining().min(self.prefix.len() - self.pos);
+            buf.put_slice(&self.prefix[self.pos..self.pos + n]);
+            self.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SniffedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    fn client_hello(sni: &str, alpn: &[&str]) -> Vec<u8> {
+        let mut exts = vec![];
+
+        let mut names = vec![0u8];
+        names.extend((sni.len() as u16).to_be_bytes());
+        names.extend(sni.as_bytes());
+        exts.extend(0x0000u16.to_be_bytes());
+        exts.extend((names.len() as u16 + 2).to_be_bytes());
+        exts.extend((names.len() as u16).to_be_bytes());
+        exts.extend(names);
+
+        let mut protos = vec![];
+        for p in alpn {
+            protos.push(p.len() as u8);
+            protos.extend(p.as_bytes());
+        }
+        exts.extend(0x0010u16.to_be_bytes());
+        exts.extend((protos.len() as u16 + 2).to_be_bytes());
+        exts.extend((protos.len() as u16).to_be_bytes());
+        exts.extend(protos);
+
+        let mut body = vec![0x03, 0x03];
+        body.extend([0u8; 32]);
+        body.push(0);
+        body.extend([0x00, 0x02, 0x13, 0x01]);
+        body.extend([0x01, 0x00]);
+        body.extend((exts.len() as u16).to_be_bytes());
+        body.extend(exts);
+
+        let mut hs = vec![0x01];
+        hs.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        hs.extend(body);
+
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend((hs.len() as u16).to_be_bytes());
+        record.extend(hs);
+        record
+    }
+
+    #[test]
+    fn test_parse_client_hello() {
+        let hello =
+            parse_client_hello(&client_hello("example.com", &["h2", "http/1.1"]))
+                .unwrap();
+        assert_eq!(hello.alpn, vec!["h2", "http/1.1"]);
+
+        let record = client_hello("example.com", &["h2"]);
+        assert_eq!(parse_client_hello(&record[..record.len() - 1]), None);
+        assert_eq!(parse_client_hello(b"GET / HTTP/1.1\r\n\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sniff_replays_prefix() {
+        let record = client_hello("example.com", &["h2"]);
+        let (mut client, server) = tokio::io::duplex(4096);
+        client.write_all(&record).await.unwrap();
+        client.write_all(b"rest").await.unwrap();
+
+        let (mut stream, hello) = sniff_tls(Box::new(server)).await;
+        assert_eq!(hello.unwrap().alpn, vec!["h2"]);
+
+        let mut got = vec![0u8; record.len() + 4];
+        stream.read_exact(&mut got).await.unwrap();
+        assert_eq!(&got[..record.len()], &record[..]);
+        assert_eq!(&got[record.len()..], b"rest");
+    }
+}
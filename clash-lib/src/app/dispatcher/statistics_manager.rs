@@ -1,15 +1,19 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, atomic::Ordering},
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Weak, atomic::Ordering},
+    time::{Duration, Instant},
 };
 
 use chrono::Utc;
 use memory_stats::memory_stats;
 use portable_atomic::AtomicU64;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::{Mutex, RwLock, oneshot::Sender};
 
-use crate::session::Session;
+use crate::{
+    app::{net::DEFAULT_OUTBOUND_INTERFACE, profile::ThreadSafeCacheFile},
+    session::Session,
+};
 
 use super::tracked::Tracked;
 
@@ -21,6 +25,10 @@ impl ProxyChain {
         let mut chain = self.0.write().await;
         chain.push(s);
     }
+
+    pub async fn names(&self) -> Vec<String> {
+        self.0.read().await.clone()
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -46,6 +54,111 @@ pub struct TrackerInfo {
     pub proxy_chain_holder: ProxyChain,
     #[serde(skip)]
     pub session_holder: Session,
+    #[serde(skip)]
+    pub interface_traffic: Option<Arc<InterfaceTraffic>>,
+    #[serde(skip)]
+    pub proxy_stats: ProxyConnection,
+}
+
+/// Bytes relayed through an outbound interface.
+///
+/// This counts the payload of the connections bound to the interface, the
+/// overhead added by proxy protocols is not included.
+#[derive(Default)]
+pub struct InterfaceTraffic {
+    upload: AtomicU64,
+    download: AtomicU64,
+}
+
+impl InterfaceTraffic {
+    fn snapshot(&self) -> InterfaceTrafficSnapshot {
+        InterfaceTrafficSnapshot {
+            upload: self.upload.load(Ordering::Relaxed),
+            download: self.download.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct InterfaceTrafficSnapshot {
+    pub upload: u64,
+    pub download: u64,
+}
+
+/// Connection counters of an outbound proxy, as seen by the proxy chains of
+/// the tracked connections.
+#[derive(Default)]
+pub struct ProxyStats {
+    active: AtomicU64,
+    total: AtomicU64,
+    upload: AtomicU64,
+    download: AtomicU64,
+    errors: std::sync::Mutex<VecDeque<Instant>>,
+}
+
+/// How long an error counts as recent
+const PROXY_ERROR_WINDOW: Duration = Duration::from_secs(300);
+
+impl ProxyStats {
+    fn record_error(&self) {
+        let now = Instant::now();
+        let mut errors = self.errors.lock().unwrap();
+        Self::expire(&mut errors, now);
+        errors.push_back(now);
+    }
+
+    fn expire(errors: &mut VecDeque<Instant>, now: Instant) {
+        while errors
+            .front()
+            .is_some_and(|x| now.duration_since(*x) > PROXY_ERROR_WINDOW)
+        {
+            errors.pop_front();
+        }
+    }
+
+    fn snapshot(&self) -> ProxyStatsSnapshot {
+        let mut errors = self.errors.lock().unwrap();
+        Self::expire(&mut errors, Instant::now());
+        ProxyStatsSnapshot {
+            active_connections: self.active.load(Ordering::Relaxed),
+            total_connections: self.total.load(Ordering::Relaxed),
+            upload: self.upload.load(Ordering::Relaxed),
+            download: self.download.load(Ordering::Relaxed),
+            recent_errors: errors.len(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyStatsSnapshot {
+    pub active_connections: u64,
+    pub total_connections: u64,
+    pub upload: u64,
+    pub download: u64,
+    /// errors within the last 5 minutes
+    pub recent_errors: usize,
+}
+
+/// The proxies a tracked connection goes through, which it counts as active
+/// until dropped.
+#[derive(Default)]
+pub struct ProxyConnection(Vec<Arc<ProxyStats>>);
+
+impl ProxyConnection {
+    fn record_error(&self) {
+        for stats in &self.0 {
+            stats.record_error();
+        }
+    }
+}
+
+impl Drop for ProxyConnection {
+    fn drop(&mut self) {
+        for stats in &self.0 {
+            stats.active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -67,10 +180,21 @@ pub struct Manager {
     download_blip: AtomicU64,
     upload_total: AtomicU64,
     download_total: AtomicU64,
+    interfaces: std::sync::RwLock<HashMap<String, Arc<InterfaceTraffic>>>,
+    proxies: std::sync::RwLock<HashMap<String, Arc<ProxyStats>>>,
+    /// where the interface traffic is persisted, if enabled
+    cache_store: Option<ThreadSafeCacheFile>,
 }
 
+/// How often the interface traffic is written to the cache
+const INTERFACE_TRAFFIC_PERSIST_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Key of the traffic that isn't bound to a known interface, i.e. that
+/// follows the routing table of the system.
+const DEFAULT_INTERFACE_KEY: &str = "default";
+
 impl Manager {
-    pub fn new() -> Arc<Self> {
+    pub fn new(cache_store: Option<ThreadSafeCacheFile>) -> Arc<Self> {
         let v = Arc::new(Self {
             connections: Arc::new(Mutex::new(HashMap::new())),
             upload_temp: AtomicU64::new(0),
@@ -79,11 +203,17 @@ impl Manager {
             download_blip: AtomicU64::new(0),
             upload_total: AtomicU64::new(0),
             download_total: AtomicU64::new(0),
+            interfaces: Default::default(),
+            proxies: Default::default(),
+            cache_store,
         });
         let c = v.clone();
         tokio::spawn(async move {
             c.kick_off().await;
         });
+        if v.cache_store.is_some() {
+            tokio::spawn(Self::keep_interface_traffic(Arc::downgrade(&v)));
+        }
         v
     }
 
@@ -124,18 +254,156 @@ impl Manager {
         }
     }
 
-    pub fn push_uploaded(&self, n: usize) {
+    pub fn push_uploaded(&self, tracker: &TrackerInfo, n: usize) {
         self.upload_temp
             .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
         self.upload_total
             .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+        if let Some(iface) = &tracker.interface_traffic {
+            iface.upload.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        for proxy in &tracker.proxy_stats.0 {
+            proxy.upload.fetch_add(n as u64, Ordering::Relaxed);
+        }
     }
 
-    pub fn push_downloaded(&self, n: usize) {
+    pub fn push_downloaded(&self, tracker: &TrackerInfo, n: usize) {
         self.download_temp
             .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
         self.download_total
             .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+        if let Some(iface) = &tracker.interface_traffic {
+            iface.download.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        for proxy in &tracker.proxy_stats.0 {
+            proxy.download.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the meter of the interface the session goes out from.
+    pub async fn interface_traffic(&self, sess: &Session) -> Arc<InterfaceTraffic> {
+        let name = match &sess.iface {
+            Some(iface) => iface.name.clone(),
+            None => DEFAULT_OUTBOUND_INTERFACE
+                .read()
+                .await
+                .as_ref()
+                .map(|x| x.name.clone())
+                .unwrap_or_else(|| DEFAULT_INTERFACE_KEY.to_owned()),
+        };
+        self.interface_meter(&name)
+    }
+
+    fn interface_meter(&self, name: &str) -> Arc<InterfaceTraffic> {
+        if let Some(meter) = self.interfaces.read().unwrap().get(name) {
+            return meter.clone();
+        }
+        self.interfaces
+            .write()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    pub fn interfaces_snapshot(&self) -> HashMap<String, InterfaceTrafficSnapshot> {
+        self.interfaces
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, meter)| (name.clone(), meter.snapshot()))
+            .collect()
+    }
+
+    fn proxy_meter(&self, name: &str) -> Arc<ProxyStats> {
+        if let Some(meter) = self.proxies.read().unwrap().get(name) {
+            return meter.clone();
+        }
+        self.proxies
+            .write()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Counts a new connection through every proxy in `chain`.
+    pub async fn proxy_connection(&self, chain: &ProxyChain) -> ProxyConnection {
+        let names = chain.0.read().await;
+        ProxyConnection(
+            names
+                .iter()
+                .map(|name| {
+                    let meter = self.proxy_meter(name);
+                    meter.active.fetch_add(1, Ordering::Relaxed);
+                    meter.total.fetch_add(1, Ordering::Relaxed);
+                    meter
+                })
+                .collect(),
+        )
+    }
+
+    /// Records a failure to connect through `proxy`.
+    pub fn record_proxy_error(&self, proxy: &str) {
+        self.proxy_meter(proxy).record_error();
+    }
+
+    /// Records an error on an established connection, against all the
+    /// proxies it goes through.
+    pub fn record_connection_error(&self, tracker: &TrackerInfo) {
+        tracker.proxy_stats.record_error();
+    }
+
+    pub fn proxy_stats(&self, proxy: &str) -> ProxyStatsSnapshot {
+        self.proxies
+            .read()
+            .unwrap()
+            .get(proxy)
+            .map(|x| x.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Resets the traffic of the given interface, or all of them.
+    pub async fn reset_interface_traffic(&self, name: Option<&str>) {
+        for (iface, meter) in self.interfaces.read().unwrap().iter() {
+            if name.is_none_or(|x| x == iface) {
+                meter.upload.store(0, Ordering::Relaxed);
+                meter.download.store(0, Ordering::Relaxed);
+            }
+        }
+        self.persist_interface_traffic().await;
+    }
+
+    /// Restores the interface traffic from the cache and writes it back
+    /// periodically, until the manager is dropped, e.g. on reload.
+    async fn keep_interface_traffic(this: Weak<Self>) {
+        if let Some(this) = this.upgrade()
+            && let Some(store) = &this.cache_store
+        {
+            for (name, saved) in store.get_interface_traffic().await {
+                let meter = this.interface_meter(&name);
+                meter.upload.fetch_add(saved.upload, Ordering::Relaxed);
+                meter.download.fetch_add(saved.download, Ordering::Relaxed);
+            }
+        }
+
+        let mut ticker = tokio::time::interval(INTERFACE_TRAFFIC_PERSIST_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match this.upgrade() {
+                Some(this) => this.persist_interface_traffic().await,
+                None => break,
+            }
+        }
+    }
+
+    async fn persist_interface_traffic(&self) {
+        if let Some(store) = &self.cache_store {
+            store
+                .set_interface_traffic(self.interfaces_snapshot())
+                .await;
+        }
     }
 
     pub fn now(&self) -> (u64, u64) {
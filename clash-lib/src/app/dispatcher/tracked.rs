@@ -11,6 +11,7 @@ use tracing::debug;
 
 use crate::{
     app::router::RuleMatcher,
+    common::io::IdleReadWatchdog,
     proxy::{ProxyStream, datagram::UdpPacket},
     session::Session,
 };
@@ -71,6 +72,17 @@ where
     }
 }
 
+#[async_trait]
+impl ChainedStream for IdleReadWatchdog<BoxedChainedStream> {
+    fn chain(&self) -> &ProxyChain {
+        self.get_ref().chain()
+    }
+
+    async fn append_to_chain(&self, name: &str) {
+        self.get_ref().append_to_chain(name).await;
+    }
+}
+
 impl<T> AsyncRead for ChainedStreamWrapper<T>
 where
     T: AsyncRead + Unpin,
@@ -135,7 +147,6 @@ impl TrackedStream {
             manager: manager.clone(),
             tracker: Arc::new(TrackerInfo {
                 uuid,
-                session_holder: sess,
 
                 start_time: chrono::Utc::now(),
                 rule: rule
@@ -146,6 +157,9 @@ impl TrackedStream {
                     .map(|x| x.payload().to_owned())
                     .unwrap_or_default(),
                 proxy_chain_holder: chain.clone(),
+                interface_traffic: Some(manager.interface_traffic(&sess).await),
+                proxy_stats: manager.proxy_connection(&chain).await,
+                session_holder: sess,
                 ..Default::default()
             }),
             close_notify: rx,
@@ -215,7 +229,7 @@ impl ReadTracker {
     }
 
     fn push_downloaded(&self, download: usize) {
-        self.manager.push_downloaded(download);
+        self.manager.push_downloaded(&self.tracker, download);
         self.tracker
             .download_total
             .fetch_add(download as u64, std::sync::atomic::Ordering::Release);
@@ -234,7 +248,7 @@ impl WriteTracker {
     }
 
     fn push_uploaded(&self, upload: usize) {
-        self.manager.push_uploaded(upload);
+        self.manager.push_uploaded(&self.tracker, upload);
         self.tracker
             .upload_total
             .fetch_add(upload as u64, std::sync::atomic::Ordering::Release);
@@ -270,7 +284,7 @@ impl AsyncRead for TrackedStream {
 
         let v = Pin::new(self.inner.as_mut()).poll_read(cx, buf);
         let download = buf.filled().len();
-        self.manager.push_downloaded(download);
+        self.manager.push_downloaded(&self.tracker, download);
         self.tracker
             .download_total
             .fetch_add(download as u64, std::sync::atomic::Ordering::Release);
@@ -300,7 +314,7 @@ impl AsyncWrite for TrackedStream {
             Poll::Ready(Ok(n)) => n,
             _ => return v,
         };
-        self.manager.push_uploaded(upload);
+        self.manager.push_uploaded(&self.tracker, upload);
         self.tracker
             .upload_total
             .fetch_add(upload as u64, std::sync::atomic::Ordering::Release);
@@ -451,7 +465,6 @@ impl TrackedDatagram {
             manager: manager.clone(),
             tracker: Arc::new(TrackerInfo {
                 uuid,
-                session_holder: sess,
 
                 start_time: chrono::Utc::now(),
                 rule: rule
@@ -462,6 +475,9 @@ impl TrackedDatagram {
                     .map(|x| x.payload().to_owned())
                     .unwrap_or_default(),
                 proxy_chain_holder: chain.clone(),
+                interface_traffic: Some(manager.interface_traffic(&sess).await),
+                proxy_stats: manager.proxy_connection(&chain).await,
+                session_holder: sess,
                 ..Default::default()
             }),
             close_notify: rx,
@@ -505,7 +521,7 @@ impl Stream for TrackedDatagram {
 
         let r = Pin::new(self.inner.as_mut()).poll_next(cx);
         if let Poll::Ready(Some(ref pkt)) = r {
-            self.manager.push_downloaded(pkt.data.len());
+            self.manager.push_downloaded(&self.tracker, pkt.data.len());
             self.tracker.download_total.fetch_add(
                 pkt.data.len() as u64,
                 std::sync::atomic::Ordering::Relaxed,
@@ -549,7 +565,7 @@ impl Sink<UdpPacket> for TrackedDatagram {
         }
 
         let upload = item.data.len();
-        self.manager.push_uploaded(upload);
+        self.manager.push_uploaded(&self.tracker, upload);
         self.tracker
             .upload_total
             .fetch_add(upload as u64, std::sync::atomic::Ordering::Relaxed);
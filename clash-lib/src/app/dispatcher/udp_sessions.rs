@@ -0,0 +1,189 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use serde::Serialize;
+use tokio::task::AbortHandle;
+use tracing::info;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpSessionStats {
+    pub current: usize,
+    pub peak: usize,
+    pub max: Option<usize>,
+}
+
+/// Keeps track of the UDP sessions of all inbounds, and evicts the least
+/// recently used one once `max` sessions are alive.
+pub struct UdpSessionTable {
+    max: Option<usize>,
+    peak: AtomicUsize,
+    inner: Mutex<Lru>,
+}
+
+#[derive(Default)]
+struct Lru {
+    clock: u64,
+    next_id: u64,
+    sessions: HashMap<u64, Entry>,
+    /// last use -> session id
+    order: BTreeMap<u64, u64>,
+}
+
+struct Entry {
+    last_used: u64,
+    desc: String,
+    tasks: [AbortHandle; 2],
+}
+
+impl Lru {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl UdpSessionTable {
+    /// `None` or `0` means unlimited.
+    pub fn new(max: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            max: max.filter(|x| *x > 0),
+            peak: AtomicUsize::new(0),
+            inner: Mutex::new(Lru::default()),
+        })
+    }
+
+    /// Registers a session relayed by `tasks`. The session is removed when
+    /// the returned permit is dropped, or its tasks are aborted when it gets
+    /// evicted to make room for newer ones.
+    pub fn register(
+        self: &Arc<Self>,
+        desc: String,
+        tasks: [AbortHandle; 2],
+    ) -> UdpSessionPermit {
+        let mut lru = self.inner.lock().unwrap();
+
+        if let Some(max) = self.max {
+            while lru.sessions.len() >= max {
+                let Some((_, id)) = lru.order.pop_first() else {
+                    break;
+                };
+                if let Some(evicted) = lru.sessions.remove(&id) {
+                    info!(
+                        "udp session limit {} reached, evicting least recently \
+                         used session {}",
+                        max, evicted.desc
+                    );
+                    for t in evicted.tasks {
+                        t.abort();
+                    }
+                }
+            }
+        }
+
+        let id = lru.next_id;
+        lru.next_id += 1;
+        let last_used = lru.tick();
+        lru.order.insert(last_used, id);
+        lru.sessions.insert(
+            id,
+            Entry {
+                last_used,
+                desc,
+                tasks,
+            },
+        );
+        self.peak.fetch_max(lru.sessions.len(), Ordering::Relaxed);
+
+        UdpSessionPermit {
+            id,
+            table: self.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> UdpSessionStats {
+        UdpSessionStats {
+            current: self.inner.lock().unwrap().sessions.len(),
+            peak: self.peak.load(Ordering::Relaxed),
+            max: self.max,
+        }
+    }
+
+    fn touch(&self, id: u64) {
+        let mut lru = self.inner.lock().unwrap();
+        let now = lru.tick();
+        if let Some(entry) = lru.sessions.get_mut(&id) {
+            let prev = std::mem::replace(&mut entry.last_used, now);
+            lru.order.remove(&prev);
+            lru.order.insert(now, id);
+        }
+    }
+
+    fn remove(&self, id: u64) {
+        let mut lru = self.inner.lock().unwrap();
+        if let Some(entry) = lru.sessions.remove(&id) {
+            lru.order.remove(&entry.last_used);
+        }
+    }
+}
+
+pub struct UdpSessionPermit {
+    id: u64,
+    table: Arc<UdpSessionTable>,
+}
+
+impl UdpSessionPermit {
+    /// Marks the session as recently used.
+    pub fn touch(&self) {
+        self.table.touch(self.id);
+    }
+}
+
+impl Drop for UdpSessionPermit {
+    fn drop(&mut self) {
+        self.table.remove(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UdpSessionTable;
+
+    fn task() -> tokio::task::JoinHandle<()> {
+        tokio::spawn(futures::future::pending())
+    }
+
+    #[tokio::test]
+    async fn test_udp_session_lru_eviction() {
+        let table = UdpSessionTable::new(Some(2));
+
+        let (a1, a2) = (task(), task());
+        let a = table.register("a".into(), [a1.abort_handle(), a2.abort_handle()]);
+        let (b1, b2) = (task(), task());
+        let b = table.register("b".into(), [b1.abort_handle(), b2.abort_handle()]);
+
+        // a is now more recently used than b
+        a.touch();
+
+        let (c1, c2) = (task(), task());
+        let c = table.register("c".into(), [c1.abort_handle(), c2.abort_handle()]);
+
+        assert!(b1.await.unwrap_err().is_cancelled());
+        assert!(b2.await.unwrap_err().is_cancelled());
+        assert!(!a1.is_finished());
+        assert!(!c1.is_finished());
+
+        let stats = table.stats();
+        assert_eq!((stats.current, stats.peak, stats.max), (2, 2, Some(2)));
+
+        drop(b);
+        drop(a);
+        drop(c);
+        let stats = table.stats();
+        assert_eq!((stats.current, stats.peak), (0, 2));
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering::Relaxed},
+    },
+};
+
+use hickory_proto::{op, rr};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::{
+    app::{
+        dns::{
+            config::{BlockConfig, BlockResponse},
+            helper::build_dns_response_message,
+        },
+        remote_content_manager::providers::rule_provider::{
+            RuleSetBehavior, ThreadSafeRuleProvider,
+        },
+    },
+    common::trie,
+    session::{Session, SocksAddr},
+};
+
+/// TTL of answers synthesized for blocked domains
+const BLOCK_TTL: u32 = 60;
+
+/// The list name block counts of `domains` entries are recorded under
+const INLINE_LIST: &str = "domains";
+
+/// Answers queries for domains on the configured blocklists without asking
+/// upstream.
+pub struct DomainBlocker {
+    response: BlockResponse,
+    domains: Option<trie::StringTrie<()>>,
+    rule_set_names: Vec<String>,
+    /// attached once the router has loaded the rule-providers
+    rule_sets: RwLock<Vec<(String, ThreadSafeRuleProvider)>>,
+
+    total: AtomicU64,
+    by_list: HashMap<String, AtomicU64>,
+}
+
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct BlockStats {
+    pub total: u64,
+    pub lists: HashMap<String, u64>,
+}
+
+impl DomainBlocker {
+    pub fn new(cfg: BlockConfig) -> Option<Self> {
+        if cfg.is_empty() {
+            return None;
+        }
+
+        let domains = if !cfg.domains.is_empty() {
+            let mut t = trie::StringTrie::new();
+            for d in &cfg.domains {
+                if !t.insert(d, Arc::new(())) {
+                    warn!("invalid dns block domain: {}", d);
+                }
+            }
+            Some(t)
+        } else {
+            None
+        };
+
+        let by_list = domains
+            .as_ref()
+            .map(|_| INLINE_LIST.to_owned())
+            .into_iter()
+            .chain(cfg.rule_sets.iter().cloned())
+            .map(|x| (x, AtomicU64::new(0)))
+            .collect();
+
+        Some(Self {
+            response: cfg.response,
+            domains,
+            rule_set_names: cfg.rule_sets,
+            rule_sets: RwLock::new(vec![]),
+            total: AtomicU64::new(0),
+            by_list,
+        })
+    }
+
+    /// Picks the configured rule-sets out of the router's rule-providers.
+    pub fn attach_rule_sets(
+        &self,
+        providers: &HashMap<String, ThreadSafeRuleProvider>,
+    ) {
+        let mut rule_sets = vec![];
+        for name in &self.rule_set_names {
+            match providers.get(name) {
+                Some(p) if p.behavior() == RuleSetBehavior::Domain => {
+                    rule_sets.push((name.clone(), p.clone()));
+                }
+                Some(p) => warn!(
+                    "dns block rule-set {} has {} behavior, only domain is \
+                     supported",
+                    name,
+                    p.behavior()
+                ),
+                None => warn!("dns block rule-set {} not found", name),
+            }
+        }
+        *self.rule_sets.write().unwrap() = rule_sets;
+    }
+
+    /// Returns whether `host` is blocked, counting the hit if it is.
+    pub fn check(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        let list = if self
+            .domains
+            .as_ref()
+            .is_some_and(|t| t.search(&host).is_some())
+        {
+            Some(INLINE_LIST.to_owned())
+        } else {
+            let sess = Session {
+                destination: SocksAddr::Domain(host.clone(), 0),
+                ..Default::default()
+            };
+            self.rule_sets
+                .read()
+                .unwrap()
+                .iter()
+                .find(|(_, p)| p.search(&sess))
+                .map(|(name, _)| name.clone())
+        };
+
+        match list {
+            Some(list) => {
+                debug!("dns query for {} blocked by {}", host, list);
+                self.total.fetch_add(1, Relaxed);
+                if let Some(c) = self.by_list.get(&list) {
+                    c.fetch_add(1, Relaxed);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The address a blocked domain resolves to, or `None` for NXDOMAIN.
+    pub fn sinkhole_v4(&self) -> Option<net::Ipv4Addr> {
+        match self.response {
+            BlockResponse::NxDomain => None,
+            BlockResponse::Sinkhole { ipv4, .. } => Some(ipv4),
+        }
+    }
+
+    pub fn sinkhole_v6(&self) -> Option<net::Ipv6Addr> {
+        match self.response {
+            BlockResponse::NxDomain => None,
+            BlockResponse::Sinkhole { ipv6, .. } => Some(ipv6),
+        }
+    }
+
+    /// Builds the reply to a blocked query. A sinkhole answers A/AAAA with
+    /// its addresses and any other type with no records.
+    pub fn reply(&self, message: &op::Message) -> op::Message {
+        let mut reply = build_dns_response_message(message, true, false);
+        let Some(q) = message.query() else {
+            return reply;
+        };
+
+        let rdata = match self.response {
+            BlockResponse::NxDomain => {
+                reply.set_response_code(op::ResponseCode::NXDomain);
+                return reply;
+            }
+            BlockResponse::Sinkhole { ipv4, ipv6 } => match q.query_type() {
+                rr::RecordType::A => rr::RData::A(ipv4.into()),
+                rr::RecordType::AAAA => rr::RData::AAAA(ipv6.into()),
+                _ => return reply,
+            },
+        };
+        reply.add_answer(rr::Record::from_rdata(q.name().clone(), BLOCK_TTL, rdata));
+        reply
+    }
+
+    pub fn stats(&self) -> BlockStats {
+        BlockStats {
+            total: self.total.load(Relaxed),
+            lists: self
+                .by_list
+                .iter()
+                .map(|(k, v)| (k.clone(), v.load(Relaxed)))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use hickory_proto::{op, rr};
+
+    use super::DomainBlocker;
+    use crate::app::dns::config::{BlockConfig, BlockResponse};
+
+    fn query(name: &str, typ: rr::RecordType) -> op::Message {
+        let mut m = op::Message::new();
+        m.add_query(op::Query::query(rr::Name::from_ascii(name).unwrap(), typ));
+        m
+    }
+
+    #[test]
+    fn test_block_domains() {
+        assert!(DomainBlocker::new(BlockConfig::default()).is_none());
+
+        let blocker = DomainBlocker::new(BlockConfig {
+            response: BlockResponse::NxDomain,
+            domains: vec!["ads.example.com".into(), "+.tracker.example".into()],
+            rule_sets: vec![],
+        })
+        .unwrap();
+
+        assert!(blocker.check("ads.example.com."));
+        assert!(blocker.check("Tracker.Example"));
+        assert!(blocker.check("a.b.tracker.example"));
+        assert!(!blocker.check("example.com"));
+        assert!(!blocker.check("x.ads.example.com"));
+
+        let stats = blocker.stats();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.lists, HashMap::from([("domains".to_owned(), 3)]));
+
+        let reply = blocker.reply(&query("ads.example.com.", rr::RecordType::A));
+        assert_eq!(reply.response_code(), op::ResponseCode::NXDomain);
+        assert!(reply.answers().is_empty());
+    }
+
+    #[test]
+    fn test_block_sinkhole_reply() {
+        let blocker = DomainBlocker::new(BlockConfig {
+            response: BlockResponse::Sinkhole {
+                ipv4: "0.0.0.0".parse().unwrap(),
+                ipv6: "::".parse().unwrap(),
+            },
+            domains: vec!["ads.example.com".into()],
+            rule_sets: vec![],
+        })
+        .unwrap();
+
+        let reply = blocker.reply(&query("ads.example.com.", rr::RecordType::A));
+        assert_eq!(reply.response_code(), op::ResponseCode::NoError);
+        assert_eq!(
+            reply.answers()[0].data(),
+            &rr::RData::A("0.0.0.0".parse::<std::net::Ipv4Addr>().unwrap().into())
+        );
+
+        let reply = blocker.reply(&query("ads.example.com.", rr::RecordType::AAAA));
+        assert_eq!(
+            reply.answers()[0].data(),
+            &rr::RData::AAAA("::".parse::<std::net::Ipv6Addr>().unwrap().into())
+        );
+
+        let reply = blocker.reply(&query("ads.example.com.", rr::RecordType::MX));
+        assert_eq!(reply.response_code(), op::ResponseCode::NoError);
+        assert!(reply.answers().is_empty());
+    }
+}
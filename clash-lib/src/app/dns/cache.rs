@@ -0,0 +1,175 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hickory_proto::{op, rr};
+use serde::Serialize;
+
+/// Bounds of how long an answer is cached, whatever its TTL
+const MIN_TTL: Duration = Duration::from_secs(1);
+const MAX_TTL: Duration = Duration::from_secs(60);
+
+/// The question an answer is cached for, the name lowercased
+type Key = (String, u16, u16);
+
+struct Cached {
+    records: Vec<rr::Record>,
+    valid_until: Instant,
+}
+
+/// An answer in the cache, as listed by the API
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: String,
+    /// seconds until the answer expires
+    pub ttl: u64,
+    pub data: Vec<String>,
+}
+
+/// The answers of upstream nameservers, least recently used evicted first.
+pub struct DnsCache {
+    entries: Mutex<lru_time_cache::LruCache<Key, Cached>>,
+}
+
+fn key(q: &op::Query) -> Key {
+    (
+        q.name().to_lowercase().to_ascii(),
+        u16::from(q.query_type()),
+        u16::from(q.query_class()),
+    )
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(lru_time_cache::LruCache::with_capacity(capacity)),
+        }
+    }
+
+    /// The records answering `q`, their TTL lowered to the time left
+    pub fn get(&self, q: &op::Query, now: Instant) -> Option<Vec<rr::Record>> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = key(q);
+        let cached = entries.get(&key)?;
+        let Some(left) = cached.valid_until.checked_duration_since(now) else {
+            entries.remove(&key);
+            return None;
+        };
+        let ttl = left.as_secs().max(1) as u32;
+        Some(
+            cached
+                .records
+                .iter()
+                .cloned()
+                .map(|mut x| {
+                    x.set_ttl(ttl);
+                    x
+                })
+                .collect(),
+        )
+    }
+
+    /// Caches `records` for the shortest TTL among them. Empty answers
+    /// aren't cached.
+    pub fn insert(&self, q: &op::Query, records: &[rr::Record], now: Instant) {
+        let Some(ttl) = records.iter().map(|x| x.ttl()).min() else {
+            return;
+        };
+        let ttl = Duration::from_secs(ttl as u64).clamp(MIN_TTL, MAX_TTL);
+        self.entries.lock().unwrap().insert(
+            key(q),
+            Cached {
+                records: records.to_vec(),
+                valid_until: now + ttl,
+            },
+        );
+    }
+
+    /// The answers that haven't expired yet
+    pub fn entries(&self, now: Instant) -> Vec<CacheEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .peek_iter()
+            .filter_map(|((name, typ, _), cached)| {
+                let left = cached.valid_until.checked_duration_since(now)?;
+                Some(CacheEntry {
+                    name: name.clone(),
+                    typ: rr::RecordType::from(*typ).to_string(),
+                    ttl: left.as_secs(),
+                    data: cached
+                        .records
+                        .iter()
+                        .map(|x| x.data().to_string())
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Drops every answer. Lookups in flight still cache theirs.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use hickory_proto::rr::{Name, RData, Record, RecordType, rdata::A};
+
+    use super::*;
+
+    fn a(name: &str, ttl: u32, ip: Ipv4Addr) -> Record {
+        Record::from_rdata(Name::from_ascii(name).unwrap(), ttl, RData::A(A(ip)))
+    }
+
+    #[test]
+    fn test_dns_cache() {
+        let cache = DnsCache::new(2);
+        let now = Instant::now();
+        let q = op::Query::query(
+            Name::from_ascii("Example.com.").unwrap(),
+            RecordType::A,
+        );
+
+        cache.insert(&q, &[], now);
+        assert!(cache.get(&q, now).is_none());
+
+        cache.insert(
+            &q,
+            &[
+                a("example.com.", 300, Ipv4Addr::new(1, 1, 1, 1)),
+                a("example.com.", 30, Ipv4Addr::new(2, 2, 2, 2)),
+            ],
+            now,
+        );
+        let lower = op::Query::query(
+            Name::from_ascii("example.com.").unwrap(),
+            RecordType::A,
+        );
+        let records = cache.get(&lower, now + Duration::from_secs(10)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|x| x.ttl() == 20));
+
+        assert_eq!(
+            cache.entries(now + Duration::from_secs(10)),
+            vec![CacheEntry {
+                name: "example.com.".to_owned(),
+                typ: "A".to_owned(),
+                ttl: 20,
+                data: vec!["1.1.1.1".to_owned(), "2.2.2.2".to_owned()],
+            }]
+        );
+        assert!(cache.entries(now + Duration::from_secs(31)).is_empty());
+        assert!(cache.get(&q, now + Duration::from_secs(31)).is_none());
+
+        cache.insert(&q, &[a("example.com.", 30, Ipv4Addr::new(1, 1, 1, 1))], now);
+        cache.clear();
+        assert!(cache.get(&q, now).is_none());
+        assert!(cache.entries(now).is_empty());
+    }
+}
@@ -1,19 +1,28 @@
-use super::dns_client::DNSNetMode;
+use super::{
+    dns_client::DNSNetMode,
+    hosts::{Hosts, REGEX_PREFIX},
+};
 use crate::{
     Error,
-    app::net::{OutboundInterface, get_interface_by_name, get_outbound_interface},
+    app::net::{
+        InterfacePreference, OutboundInterface, get_interface_by_name,
+        get_outbound_interface_with,
+    },
     common::trie,
-    config::def::{DNSListen, DNSMode, EdnsClientSubnet as DefEdnsClientSubnet},
+    config::def::{
+        DNSBlock, DNSBlockResponse, DNSListen, DNSMode, DNSQueryPolicy,
+        EdnsClientSubnet as DefEdnsClientSubnet,
+    },
 };
 use ipnet::{AddrParseError, Ipv4Net, Ipv6Net};
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use serde::Deserialize;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Display,
-    net::{IpAddr, SocketAddr},
-    sync::Arc,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
+use tracing::warn;
 use url::Url;
 use watfaq_dns::{DNSListenAddr, DoH3Config, DoHConfig, DoTConfig};
 
@@ -44,6 +53,30 @@ pub struct EdnsClientSubnet {
     pub ipv6: Option<Ipv6Net>,
 }
 
+/// How queries for blocklisted domains are answered
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BlockResponse {
+    #[default]
+    NxDomain,
+    Sinkhole {
+        ipv4: Ipv4Addr,
+        ipv6: Ipv6Addr,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BlockConfig {
+    pub response: BlockResponse,
+    pub domains: Vec<String>,
+    pub rule_sets: Vec<String>,
+}
+
+impl BlockConfig {
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.rule_sets.is_empty()
+    }
+}
+
 #[derive(Default)]
 pub struct Config {
     pub enable: bool,
@@ -58,14 +91,21 @@ pub struct Config {
     pub fake_ip_filter: Vec<String>,
     pub store_fake_ip: bool,
     pub store_smart_stats: bool,
-    pub hosts: Option<trie::StringTrie<IpAddr>>,
+    pub hosts: Option<Hosts>,
     pub nameserver_policy: HashMap<String, NameServer>,
     pub edns_client_subnet: Option<EdnsClientSubnet>,
     pub fw_mark: Option<u32>,
+    pub query_policy: DNSQueryPolicy,
+    pub block: BlockConfig,
+    pub cache_size: usize,
 }
 
 impl Config {
-    pub fn parse_nameserver(servers: &[String]) -> Result<Vec<NameServer>, Error> {
+    /// `auto_iface` picks the interface of `#auto` nameservers
+    pub fn parse_nameserver(
+        servers: &[String],
+        auto_iface: InterfacePreference,
+    ) -> Result<Vec<NameServer>, Error> {
         let mut nameservers = vec![];
 
         for (i, server) in servers.iter().enumerate() {
@@ -105,6 +145,10 @@ impl Config {
                     addr = Config::host_with_default_port(host, "443")?;
                     net = "DoH";
                 }
+                "h3" => {
+                    addr = Config::host_with_default_port(host, "443")?;
+                    net = "DoH3";
+                }
                 "dhcp" => {
                     addr = host.to_string();
                     net = "DHCP";
@@ -124,12 +168,12 @@ impl Config {
                 net,
                 interface: iface
                     .map(|x| match x.as_str() {
-                        "auto" => {
-                            get_outbound_interface().ok_or(Error::InvalidConfig(
+                        "auto" => get_outbound_interface_with(auto_iface).ok_or(
+                            Error::InvalidConfig(
                                 "DNS nameserver [auto] no outbound interface found"
                                     .into(),
-                            ))
-                        }
+                            ),
+                        ),
                         name => get_interface_by_name(name).ok_or(
                             Error::InvalidConfig(format!(
                                 "DNS nameserver [{i}] invalid interface: {name}"
@@ -146,11 +190,13 @@ impl Config {
 
     pub fn parse_nameserver_policy(
         policy_map: &HashMap<String, String>,
+        auto_iface: InterfacePreference,
     ) -> Result<HashMap<String, NameServer>, Error> {
         let mut policy = HashMap::new();
 
         for (domain, server) in policy_map {
-            let nameservers = Config::parse_nameserver(&[server.to_owned()])?;
+            let nameservers =
+                Config::parse_nameserver(&[server.to_owned()], auto_iface)?;
 
             let (_, valid) = trie::valid_and_split_domain(domain);
             if !valid {
@@ -179,21 +225,78 @@ impl Config {
         Ok(output)
     }
 
+    /// Builds the static hosts lookup. Entries of `hosts_mapping` override
+    /// the system hosts file, which overrides the builtin `localhost`.
+    /// Besides plain and wildcard domains, `hosts_mapping` accepts regular
+    /// expressions prefixed with `regex:`.
     pub fn parse_hosts(
         hosts_mapping: &HashMap<String, String>,
-    ) -> anyhow::Result<trie::StringTrie<IpAddr>> {
-        let mut tree = trie::StringTrie::new();
-        tree.insert(
-            "localhost",
-            Arc::new("127.0.0.1".parse::<IpAddr>().unwrap()),
-        );
+        system_hosts: Option<&str>,
+    ) -> Result<Hosts, Error> {
+        let mut hosts = Hosts::default();
+        hosts.insert("localhost", "127.0.0.1".parse::<IpAddr>().unwrap());
+
+        if let Some(content) = system_hosts {
+            for (host, ip) in Config::parse_system_hosts(content) {
+                hosts.insert(&host, ip);
+            }
+        }
 
         for (host, ip_str) in hosts_mapping.iter() {
-            let ip = ip_str.parse::<IpAddr>()?;
-            tree.insert(host.as_str(), Arc::new(ip));
+            let ip = ip_str.parse::<IpAddr>().map_err(|_| {
+                Error::InvalidConfig(format!("invalid hosts entry {host}: {ip_str}"))
+            })?;
+            if let Some(pattern) = host.strip_prefix(REGEX_PREFIX) {
+                let regex = RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| {
+                        Error::InvalidConfig(format!(
+                            "invalid hosts regex {pattern}: {e}"
+                        ))
+                    })?;
+                hosts.insert_regex(regex, ip);
+            } else if !hosts.insert(&host.to_ascii_lowercase(), ip) {
+                warn!("ignoring hosts entry {}: not a domain", host);
+            }
         }
 
-        Ok(tree)
+        Ok(hosts)
+    }
+
+    /// Parses a hosts file in the `/etc/hosts` format. Only the first address
+    /// listed for a hostname is kept.
+    fn parse_system_hosts(content: &str) -> Vec<(String, IpAddr)> {
+        let mut seen = HashSet::new();
+        let mut rv = vec![];
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(ip) = fields.next().and_then(|x| x.parse::<IpAddr>().ok())
+            else {
+                continue;
+            };
+            for host in fields {
+                let host = host.to_ascii_lowercase();
+                if seen.insert(host.clone()) {
+                    rv.push((host, ip));
+                }
+            }
+        }
+        rv
+    }
+
+    fn system_hosts_path() -> std::path::PathBuf {
+        #[cfg(windows)]
+        {
+            let root =
+                std::env::var("SystemRoot").unwrap_or("C:\\Windows".to_owned());
+            std::path::Path::new(&root).join("System32\\drivers\\etc\\hosts")
+        }
+        #[cfg(not(windows))]
+        {
+            "/etc/hosts".into()
+        }
     }
 
     pub fn host_with_default_port(host: &str, port: &str) -> Result<String, Error> {
@@ -254,10 +357,14 @@ impl TryFrom<&crate::config::def::Config> for Config {
             )));
         }
 
-        let nameservers = Config::parse_nameserver(&dc.nameserver)?;
-        let fallback = Config::parse_nameserver(&dc.fallback)?;
+        let auto_iface = InterfacePreference {
+            priority: c.interface_priority.as_deref(),
+            strategy: c.ip_strategy,
+        };
+        let nameservers = Config::parse_nameserver(&dc.nameserver, auto_iface)?;
+        let fallback = Config::parse_nameserver(&dc.fallback, auto_iface)?;
         let nameserver_policy =
-            Config::parse_nameserver_policy(&dc.nameserver_policy)?;
+            Config::parse_nameserver_policy(&dc.nameserver_policy, auto_iface)?;
 
         if dc.default_nameserver.is_empty() {
             return Err(Error::InvalidConfig(String::from(
@@ -265,12 +372,18 @@ impl TryFrom<&crate::config::def::Config> for Config {
             )));
         }
 
-        let default_nameserver = Config::parse_nameserver(&dc.default_nameserver)?;
+        let default_nameserver =
+            Config::parse_nameserver(&dc.default_nameserver, auto_iface)?;
 
-        for ns in &default_nameserver {
-            let _ = ns.address.parse::<SocketAddr>().map_err(|_| {
-                Error::InvalidConfig(String::from("default dns must be ip address"))
-            })?;
+        // the default nameservers bootstrap the resolution of every other
+        // hostname, so they can't be hostnames themselves
+        for (ns, raw) in default_nameserver.iter().zip(&dc.default_nameserver) {
+            if ns.address.parse::<SocketAddr>().is_err() {
+                return Err(Error::InvalidConfig(format!(
+                    "default-nameserver `{raw}` is invalid: only IP addresses are \
+                     allowed, e.g. `1.1.1.1` or `tls://1.1.1.1:853`"
+                )));
+            }
         }
 
         let edns_client_subnet = dc
@@ -281,8 +394,9 @@ impl TryFrom<&crate::config::def::Config> for Config {
 
         Ok(Self {
             enable: dc.enable,
-            ipv6: c.ipv6 && dc.ipv6,
+            ipv6: c.ipv6 && !c.ipv6_disabled && dc.ipv6,
             fw_mark: c.routing_mark,
+            query_policy: dc.query_policy,
             nameserver: nameservers,
             fallback,
             fallback_filter: dc.fallback_filter.clone().into(),
@@ -399,22 +513,62 @@ impl TryFrom<&crate::config::def::Config> for Config {
             fake_ip_filter: dc.fake_ip_filter.clone(),
             store_fake_ip: c.profile.store_fake_ip,
             store_smart_stats: c.profile.store_smart_stats,
-            hosts: if dc.user_hosts && !c.hosts.is_empty() {
-                Config::parse_hosts(&c.hosts).ok()
-            } else {
-                let mut tree = trie::StringTrie::new();
-                tree.insert(
-                    "localhost",
-                    Arc::new("127.0.0.1".parse::<IpAddr>().unwrap()),
-                );
-                Some(tree)
-            },
+            hosts: Some(Config::parse_hosts(
+                if dc.user_hosts {
+                    &c.hosts
+                } else {
+                    &HashMap::new()
+                },
+                dc.use_system_hosts
+                    .then(|| {
+                        let path = Config::system_hosts_path();
+                        std::fs::read_to_string(&path)
+                            .inspect_err(|e| {
+                                warn!(
+                                    "failed to read system hosts {}: {}",
+                                    path.display(),
+                                    e
+                                )
+                            })
+                            .ok()
+                    })
+                    .flatten()
+                    .as_deref(),
+            )?),
             nameserver_policy,
             edns_client_subnet,
+            block: parse_block(&dc.block)?,
+            cache_size: dc.cache_size,
         })
     }
 }
 
+fn parse_block(block: &DNSBlock) -> Result<BlockConfig, Error> {
+    let response = match block.response {
+        DNSBlockResponse::Nxdomain => BlockResponse::NxDomain,
+        DNSBlockResponse::Sinkhole => BlockResponse::Sinkhole {
+            ipv4: block.sinkhole_ipv4.parse().map_err(|_| {
+                Error::InvalidConfig(format!(
+                    "invalid dns block sinkhole-ipv4: {}",
+                    block.sinkhole_ipv4
+                ))
+            })?,
+            ipv6: block.sinkhole_ipv6.parse().map_err(|_| {
+                Error::InvalidConfig(format!(
+                    "invalid dns block sinkhole-ipv6: {}",
+                    block.sinkhole_ipv6
+                ))
+            })?,
+        },
+    };
+
+    Ok(BlockConfig {
+        response,
+        domains: block.domains.clone(),
+        rule_sets: block.rule_sets.clone(),
+    })
+}
+
 fn parse_edns_client_subnet(
     ecs: &DefEdnsClientSubnet,
 ) -> Result<EdnsClientSubnet, Error> {
@@ -462,3 +616,67 @@ impl From<crate::config::def::FallbackFilter> for FallbackFilter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::Config;
+
+    #[test]
+    fn test_parse_hosts() {
+        let system = "\
+# comment
+127.0.0.1 localhost
+10.0.0.1   node.example  Other.Example # trailing
+10.0.0.2 node.example
+::1 ip6-localhost
+";
+        let user = HashMap::from([
+            ("node.example".to_owned(), "1.2.3.4".to_owned()),
+            ("+.blocked.example".to_owned(), "0.0.0.0".to_owned()),
+        ]);
+        let hosts = Config::parse_hosts(&user, Some(system)).unwrap();
+        let lookup = |host: &str| hosts.search(host).map(|x| x.to_string());
+
+        assert_eq!(lookup("node.example").as_deref(), Some("1.2.3.4"));
+        assert_eq!(lookup("other.example").as_deref(), Some("10.0.0.1"));
+        assert_eq!(lookup("ip6-localhost").as_deref(), Some("::1"));
+        assert_eq!(lookup("blocked.example").as_deref(), Some("0.0.0.0"));
+        assert_eq!(lookup("a.blocked.example").as_deref(), Some("0.0.0.0"));
+        assert_eq!(lookup("localhost").as_deref(), Some("127.0.0.1"));
+        assert_eq!(lookup("unknown.example"), None);
+
+        let invalid =
+            HashMap::from([("node.example".to_owned(), "not-an-ip".to_owned())]);
+        assert!(Config::parse_hosts(&invalid, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_hosts_patterns() {
+        let user = HashMap::from([
+            ("api.cdn.example".to_owned(), "10.0.0.1".to_owned()),
+            ("*.cdn.example".to_owned(), "10.0.0.2".to_owned()),
+            ("+.example".to_owned(), "10.0.0.3".to_owned()),
+            (
+                r"regex:^img\d+\.static\.test$".to_owned(),
+                "10.0.0.4".to_owned(),
+            ),
+            (r"regex:\.test$".to_owned(), "10.0.0.5".to_owned()),
+        ]);
+        let hosts = Config::parse_hosts(&user, None).unwrap();
+        let lookup = |host: &str| hosts.search(host).map(|x| x.to_string());
+
+        assert_eq!(lookup("api.cdn.example").as_deref(), Some("10.0.0.1"));
+        assert_eq!(lookup("www.cdn.example").as_deref(), Some("10.0.0.2"));
+        assert_eq!(lookup("a.b.cdn.example").as_deref(), Some("10.0.0.3"));
+        assert_eq!(lookup("example").as_deref(), Some("10.0.0.3"));
+        assert_eq!(lookup("img12.static.test").as_deref(), Some("10.0.0.4"));
+        assert_eq!(lookup("IMG1.static.test").as_deref(), Some("10.0.0.4"));
+        assert_eq!(lookup("other.test").as_deref(), Some("10.0.0.5"));
+        assert_eq!(lookup("unknown.invalid"), None);
+
+        let invalid = HashMap::from([("regex:(".to_owned(), "10.0.0.1".to_owned())]);
+        assert!(Config::parse_hosts(&invalid, None).is_err());
+    }
+}
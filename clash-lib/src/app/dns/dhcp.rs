@@ -3,7 +3,7 @@ use crate::{
         Client, EnhancedResolver, ThreadSafeDNSClient, dns_client::DNSNetMode,
         helper::make_clients,
     },
-    proxy::utils::new_udp_socket,
+    proxy::utils::{ConnectOptions, new_udp_socket},
 };
 use async_trait::async_trait;
 use dhcproto::{Decodable, Encodable};
@@ -170,10 +170,8 @@ async fn listen_dhcp_client(iface: &OutboundInterface) -> io::Result<UdpSocket>
 
     new_udp_socket(
         Some(listen_addr.parse().expect("must parse")),
-        Some(iface),
-        #[cfg(target_os = "linux")]
-        None,
         None,
+        &ConnectOptions::default().iface(Some(iface)),
     )
     .await
 }
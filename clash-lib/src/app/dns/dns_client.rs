@@ -32,12 +32,17 @@ use crate::{
     },
     common::tls::{self, GLOBAL_ROOT_STORE},
     dns::{ThreadSafeDNSClient, dhcp::DhcpClient},
-    proxy::OutboundHandler,
+    proxy::{
+        OutboundHandler, OutboundType,
+        utils::{ConnectOptions, new_udp_socket},
+    },
 };
 use anyhow::anyhow;
+use futures::future;
 use hickory_proto::{
     DnsHandle,
     h2::HttpsClientStreamBuilder,
+    h3::H3ClientStreamBuilder,
     op::Message,
     xfer::{DnsRequest, DnsRequestOptions, FirstAnswer},
 };
@@ -48,6 +53,7 @@ pub enum DNSNetMode {
     Tcp,
     DoT,
     DoH,
+    DoH3,
     Dhcp,
 }
 
@@ -58,6 +64,7 @@ impl Display for DNSNetMode {
             Self::Tcp => write!(f, "TCP"),
             Self::DoT => write!(f, "DoT"),
             Self::DoH => write!(f, "DoH"),
+            Self::DoH3 => write!(f, "DoH3"),
             Self::Dhcp => write!(f, "DHCP"),
         }
     }
@@ -83,6 +90,7 @@ mod tests {
             })),
             cfg: DnsConfig::Udp(addr, None, proxy.clone(), None),
             proxy,
+            resolver: None,
             host: "example.org".to_string(),
             port: 53,
             net: DNSNetMode::Udp,
@@ -198,6 +206,7 @@ impl FromStr for DNSNetMode {
             "UDP" => Ok(Self::Udp),
             "TCP" => Ok(Self::Tcp),
             "DoH" => Ok(Self::DoH),
+            "DoH3" => Ok(Self::DoH3),
             "DoT" => Ok(Self::DoT),
             "DHCP" => Ok(Self::Dhcp),
             _ => Err(Error::DNSError("unsupported protocol".into())),
@@ -239,12 +248,14 @@ enum DnsConfig {
         Arc<dyn OutboundHandler>,
         FwMark,
     ),
+    /// the last field tells whether to try HTTP/3 before HTTP/2
     Https(
         net::SocketAddr,
         String,
         Option<OutboundInterface>,
         Arc<dyn OutboundHandler>,
         FwMark,
+        bool,
     ),
 }
 
@@ -275,8 +286,9 @@ impl Display for DnsConfig {
                 write!(f, "host: {host}")?;
                 write!(f, "via proxy: {}", proxy.name())
             }
-            DnsConfig::Https(addr, host, iface, proxy, _) => {
-                write!(f, "HTTPS: {}:{} ", addr.ip(), addr.port())?;
+            DnsConfig::Https(addr, host, iface, proxy, _, h3) => {
+                let scheme = if *h3 { "HTTP/3" } else { "HTTPS" };
+                write!(f, "{}: {}:{} ", scheme, addr.ip(), addr.port())?;
                 if let Some(iface) = iface {
                     write!(f, "bind: {iface} ")?;
                 }
@@ -298,6 +310,9 @@ pub struct DnsClient {
 
     cfg: DnsConfig,
     proxy: Arc<dyn OutboundHandler>,
+    /// the bootstrap resolver, used to resolve the server of `proxy`.
+    /// `None` if this is a bootstrap client itself.
+    resolver: Option<Arc<dyn ClashResolver>>,
 
     // debug purpose
     host: String,
@@ -316,7 +331,7 @@ impl DnsClient {
             }
 
             other => {
-                let ip = match opts.r {
+                let ip = match &opts.r {
                     Some(r) => {
                         match r.resolve(&opts.host, false).await.map_err(|x| {
                             anyhow!("resolve hostname failure: {}", x)
@@ -356,6 +371,7 @@ impl DnsClient {
 
                             cfg,
                             proxy: opts.proxy,
+                            resolver: opts.r,
 
                             host: opts.host,
                             port: opts.port,
@@ -380,6 +396,7 @@ impl DnsClient {
 
                             cfg,
                             proxy: opts.proxy,
+                            resolver: opts.r,
                             host: opts.host,
                             port: opts.port,
                             net: opts.net,
@@ -404,6 +421,7 @@ impl DnsClient {
 
                             cfg,
                             proxy: opts.proxy,
+                            resolver: opts.r,
                             host: opts.host,
                             port: opts.port,
                             net: opts.net,
@@ -411,13 +429,14 @@ impl DnsClient {
                             ecs: opts.ecs.clone(),
                         }))
                     }
-                    DNSNetMode::DoH => {
+                    DNSNetMode::DoH | DNSNetMode::DoH3 => {
                         let cfg = DnsConfig::Https(
                             net::SocketAddr::new(ip, opts.port),
                             opts.host.clone(),
                             opts.iface.clone(),
                             opts.proxy.clone(),
                             opts.fw_mark,
+                            opts.net == DNSNetMode::DoH3,
                         );
 
                         Ok(Arc::new(Self {
@@ -428,6 +447,7 @@ impl DnsClient {
 
                             cfg,
                             proxy: opts.proxy,
+                            resolver: opts.r,
                             host: opts.host,
                             port: opts.port,
                             net: opts.net,
@@ -530,7 +550,9 @@ impl Client for DnsClient {
                             "dns client background task is finished, likely \
                              connection closed, restarting a new one"
                         );
-                        let (client, bg) = dns_stream_builder(&self.cfg).await?;
+                        let (client, bg) =
+                            dns_stream_builder(&self.cfg, self.resolver.clone())
+                                .await?;
                         inner.c.replace(client);
                         inner.bg_handle.replace(bg);
                     } else {
@@ -543,7 +565,8 @@ impl Client for DnsClient {
                 _ => {
                     // initializing client
                     info!("initializing dns client: {}", &self.cfg);
-                    let (client, bg) = dns_stream_builder(&self.cfg).await?;
+                    let (client, bg) =
+                        dns_stream_builder(&self.cfg, self.resolver.clone()).await?;
                     inner.c.replace(client);
                     inner.bg_handle.replace(bg);
                 }
@@ -573,8 +596,16 @@ impl Client for DnsClient {
 
 async fn dns_stream_builder(
     cfg: &DnsConfig,
+    resolver: Option<Arc<dyn ClashResolver>>,
 ) -> Result<(client::Client, JoinHandle<Result<(), ProtoError>>), Error> {
-    let dns_resolver = Arc::new(dns::SystemResolver::new(false)?);
+    // proxy servers are resolved with the bootstrap resolver so that the
+    // system resolver is never involved. Bootstrap clients have nothing to
+    // fall back on but the system resolver, which is only reached if they are
+    // configured to go through a proxy whose server is a hostname.
+    let dns_resolver = match resolver {
+        Some(r) => r,
+        None => Arc::new(dns::SystemResolver::new(false)?),
+    };
     match cfg {
         DnsConfig::Udp(addr, iface, proxy, fw_mark) => {
             let stream = UdpClientStream::builder(
@@ -643,20 +674,29 @@ async fn dns_stream_builder(
             .map(|(x, y)| (x, tokio::spawn(y)))
             .map_err(|x| Error::DNSError(x.to_string()))
         }
-        DnsConfig::Https(addr, host, iface, proxy, fw_mark) => {
-            let mut tls_config = ClientConfig::builder()
-                .with_root_certificates(GLOBAL_ROOT_STORE.clone())
-                .with_no_client_auth();
-            tls_config.alpn_protocols = vec!["h2".into()];
-
-            if host == &addr.ip().to_string() {
-                tls_config.dangerous().set_certificate_verifier(Arc::new(
-                    tls::NoHostnameTlsVerifier::new(),
-                ));
+        DnsConfig::Https(addr, host, iface, proxy, fw_mark, h3) => {
+            if *h3 {
+                // QUIC needs a UDP socket of our own, which can't be tunneled
+                // through a proxy
+                if !matches!(proxy.proto(), OutboundType::Direct) {
+                    warn!(
+                        "DNS over HTTP/3 can't go through proxy {}, using HTTP/2",
+                        proxy.name()
+                    );
+                } else {
+                    match doh3_client(*addr, host, iface.as_ref(), *fw_mark).await {
+                        Ok(rv) => return Ok(rv),
+                        Err(e) => warn!(
+                            "DNS over HTTP/3 to {} failed: {}, falling back to \
+                             HTTP/2",
+                            addr, e
+                        ),
+                    }
+                }
             }
 
             let stream = HttpsClientStreamBuilder::with_client_config(
-                Arc::new(tls_config),
+                Arc::new(doh_tls_config(addr, host, "h2")),
                 DnsRuntimeProvider::new(
                     proxy.clone(),
                     dns_resolver,
@@ -673,3 +713,43 @@ async fn dns_stream_builder(
         }
     }
 }
+
+fn doh_tls_config(addr: &net::SocketAddr, host: &str, alpn: &str) -> ClientConfig {
+    let mut tls_config = ClientConfig::builder()
+        .with_root_certificates(GLOBAL_ROOT_STORE.clone())
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![alpn.into()];
+
+    if host == addr.ip().to_string() {
+        tls_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(tls::NoHostnameTlsVerifier::new()));
+    }
+    tls_config
+}
+
+async fn doh3_client(
+    addr: net::SocketAddr,
+    host: &str,
+    iface: Option<&OutboundInterface>,
+    fw_mark: FwMark,
+) -> Result<(client::Client, JoinHandle<Result<(), ProtoError>>), Error> {
+    let socket =
+        new_udp_socket(None, Some(addr), &ConnectOptions::new(iface, fw_mark))
+            .await?;
+
+    let stream = H3ClientStreamBuilder::default()
+        .crypto_config(doh_tls_config(&addr, host, "h3"))
+        .build_with_future(
+            future::ready(Ok(socket)),
+            addr,
+            host.to_owned(),
+            "/dns-query".to_string(),
+        );
+
+    tokio::time::timeout(Duration::from_secs(5), client::Client::connect(stream))
+        .await
+        .map_err(|_| Error::DNSError("HTTP/3 handshake timed out".into()))?
+        .map(|(x, y)| (x, tokio::spawn(y)))
+        .map_err(|x| Error::DNSError(x.to_string()))
+}
@@ -0,0 +1,42 @@
+use std::{net::IpAddr, sync::Arc};
+
+use regex::Regex;
+
+use crate::common::trie;
+
+/// Prefix of the hosts entries that are regular expressions, e.g.
+/// `regex:^cdn\d+\.example\.com$`
+pub const REGEX_PREFIX: &str = "regex:";
+
+/// The static hosts. Plain and wildcard (`*.example.com`, `+.example.com`)
+/// entries are kept in a trie where the most specific one wins, regex
+/// entries are only tried when none of those match, longest pattern first.
+#[derive(Default)]
+pub struct Hosts {
+    domains: trie::StringTrie<IpAddr>,
+    regexes: Vec<(Regex, IpAddr)>,
+}
+
+impl Hosts {
+    /// Adds a plain or wildcard entry, replacing an existing one for the same
+    /// domain. Returns false if `domain` is not a valid domain pattern.
+    pub fn insert(&mut self, domain: &str, ip: IpAddr) -> bool {
+        self.domains.insert(domain, Arc::new(ip))
+    }
+
+    pub fn insert_regex(&mut self, regex: Regex, ip: IpAddr) {
+        self.regexes.push((regex, ip));
+        self.regexes
+            .sort_by(|(a, _), (b, _)| b.as_str().len().cmp(&a.as_str().len()));
+    }
+
+    pub fn search(&self, host: &str) -> Option<IpAddr> {
+        if let Some(ip) = self.domains.search(host).and_then(|x| x.get_data()) {
+            return Some(*ip);
+        }
+        self.regexes
+            .iter()
+            .find(|(re, _)| re.is_match(host))
+            .map(|(_, ip)| *ip)
+    }
+}
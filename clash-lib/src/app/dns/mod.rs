@@ -3,24 +3,34 @@ use async_trait::async_trait;
 use std::fmt::Debug;
 
 use hickory_proto::op;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+
+use crate::app::remote_content_manager::providers::rule_provider::ThreadSafeRuleProvider;
 
 #[cfg(test)]
 use mockall::automock;
 
+mod blocker;
+mod cache;
 mod config;
 mod dhcp;
 mod dns_client;
 mod fakeip;
 mod filters;
 mod helper;
+mod hosts;
 pub mod resolver;
 mod runtime;
 mod server;
 
+pub use blocker::BlockStats;
+pub use cache::CacheEntry;
 pub use config::{Config, EdnsClientSubnet};
 
-pub use resolver::{EnhancedResolver, SystemResolver, new as new_resolver};
+pub use resolver::{
+    EnhancedResolver, SystemResolver, default_ip_version, new as new_resolver,
+    set_default_ip_version, with_ip_version, with_server_resolver,
+};
 
 #[cfg(feature = "tun")]
 pub use server::exchange_with_resolver;
@@ -79,4 +89,31 @@ pub trait ClashResolver: Sync + Send {
     fn set_ipv6(&self, enable: bool);
 
     fn kind(&self) -> ResolverKind;
+
+    /// Hands the loaded rule-providers to the DNS blocklist, which picks
+    /// the rule-sets it is configured with
+    fn attach_rule_providers(
+        &self,
+        _providers: &HashMap<String, ThreadSafeRuleProvider>,
+    ) {
+    }
+
+    /// Counts of queries answered by the DNS blocklist, if one is configured
+    fn block_stats(&self) -> Option<BlockStats> {
+        None
+    }
+
+    /// The cached answers, if the resolver has a cache
+    fn cache_entries(&self) -> Option<Vec<CacheEntry>> {
+        None
+    }
+
+    /// Drops the cached answers
+    fn clear_cache(&self) {}
+
+    /// The resolver of the `default-nameserver`s, which the nameservers
+    /// themselves are looked up with, if there is one
+    fn bootstrap(&self) -> Option<ThreadSafeDNSResolver> {
+        None
+    }
 }
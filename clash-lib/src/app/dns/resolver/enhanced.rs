@@ -1,21 +1,29 @@
 use crate::{
     Error,
-    app::{dns::helper::build_dns_response_message, profile::ThreadSafeCacheFile},
+    app::{
+        dns::{helper::build_dns_response_message, hosts::Hosts},
+        profile::ThreadSafeCacheFile,
+        remote_content_manager::providers::rule_provider::ThreadSafeRuleProvider,
+    },
     common::{mmdb::MmdbLookup, trie},
-    config::def::DNSMode,
+    config::def::{DNSMode, DNSQueryPolicy},
     dns::{
-        ClashResolver, Config, ResolverKind, ThreadSafeDNSClient,
+        BlockStats, CacheEntry, ClashResolver, Config, ResolverKind,
+        ThreadSafeDNSClient, ThreadSafeDNSResolver,
+        blocker::DomainBlocker,
+        cache::DnsCache,
         fakeip::{self, FileStore, InMemStore, ThreadSafeFakeDns},
         filters::{
             DomainFilter, FallbackDomainFilter, FallbackIPFilter, GeoIPFilter,
             IPNetFilter,
         },
         helper::make_clients,
+        resolver::failover::Failover,
     },
 };
 use anyhow::anyhow;
 use async_trait::async_trait;
-use futures::{FutureExt, TryFutureExt};
+use futures::{FutureExt, StreamExt, TryFutureExt, stream::FuturesUnordered};
 use hickory_proto::{op, rr};
 use rand::seq::IndexedRandom;
 use std::{
@@ -30,17 +38,27 @@ use std::{
 use tokio::sync::RwLock;
 use tracing::{debug, error, instrument, trace, warn};
 
+/// TTL of answers synthesized from the static hosts
+const HOSTS_TTL: u32 = 60;
+
 pub struct EnhancedResolver {
     ipv6: AtomicBool,
-    hosts: Option<trie::StringTrie<net::IpAddr>>,
+    hosts: Option<Hosts>,
+    blocker: Option<DomainBlocker>,
     main: Vec<ThreadSafeDNSClient>,
 
     fallback: Option<Vec<ThreadSafeDNSClient>>,
     fallback_domain_filters: Option<Vec<Box<dyn FallbackDomainFilter>>>,
     fallback_ip_filters: Option<Vec<Box<dyn FallbackIPFilter>>>,
 
-    lru_cache: Option<Arc<RwLock<hickory_resolver::dns_lru::DnsLru>>>,
+    lru_cache: Option<DnsCache>,
     policy: Option<trie::StringTrie<Vec<ThreadSafeDNSClient>>>,
+    query_policy: DNSQueryPolicy,
+    /// set for the bootstrap resolver, which asks its nameservers one at a
+    /// time instead of following `query_policy`
+    failover: Option<Failover>,
+    /// the resolver of the `default-nameserver`s, unset for itself
+    bootstrap: Option<Arc<EnhancedResolver>>,
 
     fake_dns: Option<ThreadSafeFakeDns>,
 
@@ -59,6 +77,7 @@ impl EnhancedResolver {
         EnhancedResolver {
             ipv6: AtomicBool::new(false),
             hosts: None,
+            blocker: None,
             main: make_clients(
                 vec![NameServer {
                     net: DNSNetMode::Udp,
@@ -77,6 +96,9 @@ impl EnhancedResolver {
             fallback_ip_filters: None,
             lru_cache: None,
             policy: None,
+            query_policy: DNSQueryPolicy::Fastest,
+            failover: None,
+            bootstrap: None,
 
             fake_dns: None,
 
@@ -94,6 +116,7 @@ impl EnhancedResolver {
         let default_resolver = Arc::new(EnhancedResolver {
             ipv6: AtomicBool::new(false),
             hosts: None,
+            blocker: None,
             main: make_clients(
                 cfg.default_nameserver.clone(),
                 None,
@@ -107,6 +130,9 @@ impl EnhancedResolver {
             fallback_ip_filters: None,
             lru_cache: None,
             policy: None,
+            query_policy: DNSQueryPolicy::Fastest,
+            failover: Some(Failover::new()),
+            bootstrap: None,
 
             fake_dns: None,
 
@@ -124,6 +150,7 @@ impl EnhancedResolver {
             )
             .await,
             hosts: cfg.hosts,
+            blocker: DomainBlocker::new(cfg.block),
             fallback: if !cfg.fallback.is_empty() {
                 Some(
                     make_clients(
@@ -170,17 +197,7 @@ impl EnhancedResolver {
             } else {
                 None
             },
-            lru_cache: Some(Arc::new(RwLock::new(
-                hickory_resolver::dns_lru::DnsLru::new(
-                    4096,
-                    hickory_resolver::dns_lru::TtlConfig::new(
-                        Some(Duration::from_secs(1)),
-                        Some(Duration::from_secs(1)),
-                        Some(Duration::from_secs(60)),
-                        Some(Duration::from_secs(10)),
-                    ),
-                ),
-            ))),
+            lru_cache: (cfg.cache_size > 0).then(|| DnsCache::new(cfg.cache_size)),
             policy: if !cfg.nameserver_policy.is_empty() {
                 let mut p = trie::StringTrie::new();
                 for (domain, ns) in &cfg.nameserver_policy {
@@ -202,6 +219,9 @@ impl EnhancedResolver {
             } else {
                 None
             },
+            query_policy: cfg.query_policy,
+            failover: None,
+            bootstrap: Some(default_resolver.clone()),
             fake_dns: match cfg.enhance_mode {
                 DNSMode::FakeIp => Some(Arc::new(RwLock::new(
                     fakeip::FakeDns::new(fakeip::Opts {
@@ -245,6 +265,72 @@ impl EnhancedResolver {
         }
     }
 
+    /// Queries `clients` at once, picking the answer as per `query-policy`.
+    async fn query(
+        &self,
+        clients: &Vec<ThreadSafeDNSClient>,
+        message: &op::Message,
+    ) -> anyhow::Result<op::Message> {
+        if let Some(failover) = &self.failover {
+            return failover.exchange(clients, message).await;
+        }
+        match self.query_policy {
+            DNSQueryPolicy::Fastest => {
+                EnhancedResolver::batch_exchange(clients, message).await
+            }
+            DNSQueryPolicy::FirstSuccess | DNSQueryPolicy::TrustedOnly => {
+                EnhancedResolver::first_success_exchange(clients, message).await
+            }
+        }
+    }
+
+    /// Returns the first answer with records. Without one, the first
+    /// response is returned once all clients are done, so that negative
+    /// answers still get through.
+    #[instrument(skip(message), level = "trace")]
+    pub async fn first_success_exchange(
+        clients: &Vec<ThreadSafeDNSClient>,
+        message: &op::Message,
+    ) -> anyhow::Result<op::Message> {
+        let mut queries = clients
+            .iter()
+            .map(|c| {
+                c.exchange(message).inspect_err(|x| {
+                    error!(
+                        client = c.id(),
+                        err = ?x,
+                        "resolve error");
+                })
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let pick = async {
+            let mut first_response = None;
+            let mut last_error = None;
+            while let Some(result) = queries.next().await {
+                match result {
+                    Ok(m)
+                        if m.response_code() == op::ResponseCode::NoError
+                            && !m.answers().is_empty() =>
+                    {
+                        return Ok(m);
+                    }
+                    Ok(m) => {
+                        first_response.get_or_insert(m);
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            first_response.ok_or_else(|| {
+                last_error.unwrap_or_else(|| anyhow!("no nameserver configured"))
+            })
+        };
+
+        tokio::time::timeout(Duration::from_secs(10), pick)
+            .await
+            .map_err(|_| Error::DNSError("DNS query timeout".into()))?
+    }
+
     #[instrument(skip(message), level = "trace")]
     pub async fn batch_exchange(
         clients: &Vec<ThreadSafeDNSClient>,
@@ -311,23 +397,26 @@ impl EnhancedResolver {
     async fn exchange(&self, message: &op::Message) -> anyhow::Result<op::Message> {
         if let Some(q) = message.query() {
             trace!(q = q.to_string(), "start");
+            if let Some(reply) = self.hosts_exchange(message) {
+                trace!(q = q.to_string(), "answered from hosts");
+                return Ok(reply);
+            }
+            if let Some(blocker) = &self.blocker
+                && blocker.check(&q.name().to_ascii())
+            {
+                return Ok(blocker.reply(message));
+            }
             if let Some(lru) = &self.lru_cache
-                && let Some(cached) = lru.read().await.get(q, Instant::now())
+                && let Some(cached) = lru.get(q, Instant::now())
             {
                 if !message.recursion_desired() {
-                    trace!(q = q.to_string(), "cache hit, RA not desired");
-                    if let Ok(cached) = cached.inspect_err(|x| {
-                        warn!("failed to get cached message: {}", x);
-                    }) {
-                        trace!(
-                            q = q.to_string(),
-                            "cache hit for DNS query, returning cached response",
-                        );
-                        let mut reply =
-                            build_dns_response_message(message, true, false);
-                        reply.add_answers(cached.records().iter().cloned());
-                        return Ok(reply);
-                    }
+                    trace!(
+                        q = q.to_string(),
+                        "cache hit for DNS query, returning cached response",
+                    );
+                    let mut reply = build_dns_response_message(message, true, false);
+                    reply.add_answers(cached);
+                    return Ok(reply);
                 } else {
                     trace!(
                         q = q.to_string(),
@@ -357,15 +446,21 @@ impl EnhancedResolver {
         let q = message.query().unwrap();
 
         let query = async move {
-            if EnhancedResolver::is_ip_request(q) {
-                return self.ip_exchange(message).await;
+            if let Some(matched) = self.match_policy(message) {
+                return self.query(matched, message).await;
             }
 
-            if let Some(matched) = self.match_policy(message) {
-                return EnhancedResolver::batch_exchange(matched, message).await;
+            if self.query_policy == DNSQueryPolicy::TrustedOnly
+                && let Some(fallback) = &self.fallback
+            {
+                return self.query(fallback, message).await;
+            }
+
+            if EnhancedResolver::is_ip_request(q) {
+                return self.ip_exchange(message).await;
             }
 
-            EnhancedResolver::batch_exchange(&self.main, message).await
+            self.query(&self.main, message).await
         };
 
         let rv = query.await;
@@ -375,11 +470,7 @@ impl EnhancedResolver {
             && !(q.query_type() == rr::RecordType::TXT
                 && q.name().to_ascii().starts_with("_acme-challenge."))
         {
-            lru.write().await.insert_records(
-                q.clone(),
-                msg.answers().iter().cloned(),
-                Instant::now(),
-            );
+            lru.insert(q, msg.answers(), Instant::now());
         }
 
         rv
@@ -400,28 +491,17 @@ impl EnhancedResolver {
         &self,
         message: &op::Message,
     ) -> anyhow::Result<op::Message> {
-        if let Some(matched) = self.match_policy(message) {
-            return EnhancedResolver::batch_exchange(matched, message).await;
-        }
-
         if self.should_only_query_fallback(message) {
-            return EnhancedResolver::batch_exchange(
-                self.fallback.as_ref().unwrap(),
-                message,
-            )
-            .await;
+            return self.query(self.fallback.as_ref().unwrap(), message).await;
         }
 
-        let main_query = EnhancedResolver::batch_exchange(&self.main, message);
+        let main_query = self.query(&self.main, message);
 
         if self.fallback.is_none() {
             return main_query.await;
         }
 
-        let fallback_query = EnhancedResolver::batch_exchange(
-            self.fallback.as_ref().unwrap(),
-            message,
-        );
+        let fallback_query = self.query(self.fallback.as_ref().unwrap(), message);
 
         if let Ok(main_result) = main_query.await {
             let ip_list = EnhancedResolver::ip_list_of_message(&main_result);
@@ -465,6 +545,33 @@ impl EnhancedResolver {
                 || q.query_type() == rr::RecordType::AAAA)
     }
 
+    /// Answers A/AAAA questions for hostnames in the static hosts. A
+    /// hostname mapped to an address of the other family gets an empty
+    /// answer rather than being sent upstream.
+    fn hosts_exchange(&self, message: &op::Message) -> Option<op::Message> {
+        let q = message.query()?;
+        if !matches!(q.query_type(), rr::RecordType::A | rr::RecordType::AAAA) {
+            return None;
+        }
+        let host = q
+            .name()
+            .to_ascii()
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        let ip = self.hosts.as_ref()?.search(&host)?;
+
+        let mut reply = build_dns_response_message(message, true, false);
+        let rdata = match (q.query_type(), ip) {
+            (rr::RecordType::A, net::IpAddr::V4(v4)) => rr::RData::A(v4.into()),
+            (rr::RecordType::AAAA, net::IpAddr::V6(v6)) => {
+                rr::RData::AAAA(v6.into())
+            }
+            _ => return Some(reply),
+        };
+        reply.add_answer(rr::Record::from_rdata(q.name().clone(), HOSTS_TTL, rdata));
+        Some(reply)
+    }
+
     fn domain_name_of_message(m: &op::Message) -> Option<String> {
         m.query()
             .map(|x| x.name().to_ascii().trim_end_matches('.').to_owned())
@@ -533,12 +640,21 @@ impl ClashResolver for EnhancedResolver {
     ) -> anyhow::Result<Option<net::Ipv4Addr>> {
         if enhanced
             && let Some(hosts) = &self.hosts
-            && let Some(v) = hosts.search(host)
+            && let Some(ip) = hosts.search(host)
         {
-            return Ok(v.get_data().map(|v| match v {
-                net::IpAddr::V4(v4) => *v4,
-                _ => unreachable!("invalid IP family"),
-            }));
+            return Ok(match ip {
+                net::IpAddr::V4(v4) => Some(v4),
+                _ => None,
+            });
+        }
+
+        if enhanced
+            && let Some(blocker) = &self.blocker
+            && blocker.check(host)
+        {
+            return blocker.sinkhole_v4().map(Some).ok_or_else(|| {
+                Error::DNSError(format!("{host} is blocked")).into()
+            });
         }
 
         if let Ok(ip) = host.parse::<net::Ipv4Addr>() {
@@ -578,12 +694,21 @@ impl ClashResolver for EnhancedResolver {
 
         if enhanced
             && let Some(hosts) = &self.hosts
-            && let Some(v) = hosts.search(host)
+            && let Some(ip) = hosts.search(host)
         {
-            return Ok(v.get_data().map(|v| match v {
-                net::IpAddr::V6(v6) => *v6,
-                _ => unreachable!("invalid IP family"),
-            }));
+            return Ok(match ip {
+                net::IpAddr::V6(v6) => Some(v6),
+                _ => None,
+            });
+        }
+
+        if enhanced
+            && let Some(blocker) = &self.blocker
+            && blocker.check(host)
+        {
+            return blocker.sinkhole_v6().map(Some).ok_or_else(|| {
+                Error::DNSError(format!("{host} is blocked")).into()
+            });
         }
 
         if let Ok(ip) = host.parse::<net::Ipv6Addr>() {
@@ -643,6 +768,33 @@ impl ClashResolver for EnhancedResolver {
         ResolverKind::Clash
     }
 
+    fn attach_rule_providers(
+        &self,
+        providers: &HashMap<String, ThreadSafeRuleProvider>,
+    ) {
+        if let Some(blocker) = &self.blocker {
+            blocker.attach_rule_sets(providers);
+        }
+    }
+
+    fn block_stats(&self) -> Option<BlockStats> {
+        self.blocker.as_ref().map(|b| b.stats())
+    }
+
+    fn cache_entries(&self) -> Option<Vec<CacheEntry>> {
+        self.lru_cache.as_ref().map(|x| x.entries(Instant::now()))
+    }
+
+    fn clear_cache(&self) {
+        if let Some(lru) = &self.lru_cache {
+            lru.clear();
+        }
+    }
+
+    fn bootstrap(&self) -> Option<ThreadSafeDNSResolver> {
+        self.bootstrap.clone().map(|x| x as ThreadSafeDNSResolver)
+    }
+
     fn fake_ip_enabled(&self) -> bool {
         self.fake_dns.is_some()
     }
@@ -797,6 +949,27 @@ mod tests {
         test_client(c).await;
     }
 
+    #[tokio::test]
+    #[ignore = "network unstable on CI"]
+    async fn test_doh3_resolve() {
+        let default_resolver = Arc::new(EnhancedResolver::new_default().await);
+
+        let c = DnsClient::new_client(Opts {
+            r: Some(default_resolver.clone()),
+            host: "cloudflare-dns.com".to_string(),
+            port: 443,
+            net: DNSNetMode::DoH3,
+            iface: None,
+            proxy: get_default_outbound(),
+            ecs: None,
+            fw_mark: None,
+        })
+        .await
+        .expect("build client");
+
+        test_client(c).await;
+    }
+
     #[tokio::test]
     #[ignore = "network unstable on CI"]
     async fn test_dhcp_client() {
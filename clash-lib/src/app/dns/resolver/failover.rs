@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering::Relaxed},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use hickory_proto::op;
+use tracing::{debug, warn};
+
+use crate::{Error, dns::ThreadSafeDNSClient};
+
+/// How long a single nameserver gets to answer before the next one is asked
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a nameserver is skipped after its first failure, doubled on
+/// every further failure up to [`MAX_BACKOFF`]
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct ServerState {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+/// Asks a list of nameservers one at a time, round-robin among the healthy
+/// ones, moving on to the next one when a server fails or doesn't answer in
+/// time. Servers that keep failing are backed off and only tried after the
+/// healthy ones.
+///
+/// Used for the bootstrap nameservers, so that a blocked server doesn't
+/// stall resolving the other nameservers and the proxy servers.
+#[derive(Default)]
+pub struct Failover {
+    next: AtomicUsize,
+    /// by client id
+    servers: Mutex<HashMap<String, ServerState>>,
+}
+
+impl Failover {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The order to try `ids` in: healthy servers round-robin, then the
+    /// backed off ones by the time they are due for a retry.
+    fn order(&self, ids: &[String], now: Instant) -> Vec<usize> {
+        if ids.is_empty() {
+            return vec![];
+        }
+        let start = self.next.fetch_add(1, Relaxed) % ids.len();
+        let servers = self.servers.lock().unwrap();
+
+        let (mut healthy, mut backed_off): (Vec<_>, Vec<_>) = (0..ids.len())
+            .map(|i| (start + i) % ids.len())
+            .map(|i| {
+                let retry_at = servers
+                    .get(&ids[i])
+                    .and_then(|s| s.retry_at)
+                    .filter(|t| *t > now);
+                (i, retry_at)
+            })
+            .partition(|(_, retry_at)| retry_at.is_none());
+        backed_off.sort_by_key(|(_, retry_at)| *retry_at);
+        healthy.append(&mut backed_off);
+        healthy.into_iter().map(|(i, _)| i).collect()
+    }
+
+    fn report(&self, id: &str, ok: bool, now: Instant) {
+        let mut servers = self.servers.lock().unwrap();
+        let state = servers.entry(id.to_owned()).or_default();
+        if ok {
+            if state.failures > 0 {
+                debug!("bootstrap nameserver {} recovered", id);
+            }
+            *state = ServerState::default();
+        } else {
+            state.failures += 1;
+            let backoff = BASE_BACKOFF
+                .saturating_mul(1 << (state.failures - 1).min(16))
+                .min(MAX_BACKOFF);
+            state.retry_at = Some(now + backoff);
+            warn!(
+                "bootstrap nameserver {} failed {} time(s), backing off for {:?}",
+                id, state.failures, backoff
+            );
+        }
+    }
+
+    pub async fn exchange(
+        &self,
+        clients: &[ThreadSafeDNSClient],
+        message: &op::Message,
+    ) -> anyhow::Result<op::Message> {
+        let ids = clients.iter().map(|c| c.id()).collect::<Vec<_>>();
+        let mut last_response = None;
+        let mut last_error = None;
+
+        for i in self.order(&ids, Instant::now()) {
+            let rv =
+                tokio::time::timeout(ATTEMPT_TIMEOUT, clients[i].exchange(message))
+                    .await
+                    .map_err(|_| Error::DNSError("DNS query timeout".into()).into())
+                    .and_then(|x| x);
+            match rv {
+                Ok(m)
+                    if matches!(
+                        m.response_code(),
+                        op::ResponseCode::NoError | op::ResponseCode::NXDomain
+                    ) =>
+                {
+                    self.report(&ids[i], true, Instant::now());
+                    return Ok(m);
+                }
+                Ok(m) => {
+                    debug!(
+                        "bootstrap nameserver {} answered {}, trying the next one",
+                        ids[i],
+                        m.response_code()
+                    );
+                    self.report(&ids[i], false, Instant::now());
+                    last_response = Some(m);
+                }
+                Err(e) => {
+                    debug!(
+                        "bootstrap nameserver {} failed: {}, trying the next one",
+                        ids[i], e
+                    );
+                    self.report(&ids[i], false, Instant::now());
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        last_response.ok_or_else(|| {
+            last_error.unwrap_or_else(|| anyhow!("no nameserver configured"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Failover;
+
+    #[test]
+    fn test_failover_order() {
+        let ids = ["a", "b", "c"].map(String::from);
+        let f = Failover::new();
+        let now = Instant::now();
+
+        // round-robin among healthy servers
+        assert_eq!(f.order(&ids, now), vec![0, 1, 2]);
+        assert_eq!(f.order(&ids, now), vec![1, 2, 0]);
+
+        // failed servers go last, the earliest retry first
+        f.report("a", false, now);
+        f.report("a", false, now);
+        f.report("b", false, now);
+        assert_eq!(f.order(&ids, now), vec![2, 1, 0]);
+
+        // retried once the backoff is over
+        assert_eq!(f.order(&ids, now + Duration::from_secs(6)), vec![1, 2, 0]);
+
+        f.report("a", true, now);
+        assert_eq!(f.order(&ids, now), vec![2, 0, 1]);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use hickory_proto::op;
+
+use crate::{
+    app::{
+        dns::{
+            BlockStats, CacheEntry, ClashResolver, ResolverKind,
+            ThreadSafeDNSResolver,
+        },
+        remote_content_manager::providers::rule_provider::ThreadSafeRuleProvider,
+    },
+    config::def::IpVersion,
+};
+
+/// `ip-version`, for connections whose rule doesn't set one
+static DEFAULT_IP_VERSION: RwLock<IpVersion> = RwLock::new(IpVersion::Dual);
+
+pub fn set_default_ip_version(version: IpVersion) {
+    *DEFAULT_IP_VERSION.write().unwrap() = version;
+}
+
+pub fn default_ip_version() -> IpVersion {
+    *DEFAULT_IP_VERSION.read().unwrap()
+}
+
+/// Restricts [`ClashResolver::resolve`] of `resolver` to the address families
+/// allowed by `version`. Returns `resolver` itself for `None` and
+/// [`IpVersion::Dual`].
+pub fn with_ip_version(
+    resolver: ThreadSafeDNSResolver,
+    version: Option<IpVersion>,
+) -> ThreadSafeDNSResolver {
+    match version {
+        None | Some(IpVersion::Dual) => resolver,
+        Some(version) => Arc::new(IpVersionResolver {
+            inner: resolver,
+            version,
+        }),
+    }
+}
+
+/// Only `resolve`, which is what the dialers use, is constrained; the family
+/// specific lookups are passed through as they already pick a family.
+struct IpVersionResolver {
+    inner: ThreadSafeDNSResolver,
+    version: IpVersion,
+}
+
+impl IpVersionResolver {
+    async fn resolve_v4_ip(
+        &self,
+        host: &str,
+        enhanced: bool,
+    ) -> anyhow::Result<Option<net::IpAddr>> {
+        Ok(self
+            .inner
+            .resolve_v4(host, enhanced)
+            .await?
+            .map(net::IpAddr::from))
+    }
+
+    async fn resolve_v6_ip(
+        &self,
+        host: &str,
+        enhanced: bool,
+    ) -> anyhow::Result<Option<net::IpAddr>> {
+        Ok(self
+            .inner
+            .resolve_v6(host, enhanced)
+            .await?
+            .map(net::IpAddr::from))
+    }
+}
+
+#[async_trait]
+impl ClashResolver for IpVersionResolver {
+    async fn resolve(
+        &self,
+        host: &str,
+        enhanced: bool,
+    ) -> anyhow::Result<Option<net::IpAddr>> {
+        match self.version {
+            IpVersion::Dual => self.inner.resolve(host, enhanced).await,
+            IpVersion::Ipv4 => self.resolve_v4_ip(host, enhanced).await,
+            IpVersion::Ipv6 => self.resolve_v6_ip(host, enhanced).await,
+            IpVersion::PreferIpv4 => {
+                match self.resolve_v4_ip(host, enhanced).await {
+                    Ok(Some(ip)) => Ok(Some(ip)),
+                    _ => self.resolve_v6_ip(host, enhanced).await,
+                }
+            }
+            IpVersion::PreferIpv6 => {
+                match self.resolve_v6_ip(host, enhanced).await {
+                    Ok(Some(ip)) => Ok(Some(ip)),
+                    _ => self.resolve_v4_ip(host, enhanced).await,
+                }
+            }
+        }
+    }
+
+    async fn resolve_v4(
+        &self,
+        host: &str,
+        enhanced: bool,
+    ) -> anyhow::Result<Option<net::Ipv4Addr>> {
+        self.inner.resolve_v4(host, enhanced).await
+    }
+
+    async fn resolve_v6(
+        &self,
+        host: &str,
+        enhanced: bool,
+    ) -> anyhow::Result<Option<net::Ipv6Addr>> {
+        self.inner.resolve_v6(host, enhanced).await
+    }
+
+    async fn cached_for(&self, ip: net::IpAddr) -> Option<String> {
+        self.inner.cached_for(ip).await
+    }
+
+    async fn exchange(&self, message: &op::Message) -> anyhow::Result<op::Message> {
+        self.inner.exchange(message).await
+    }
+
+    async fn reverse_lookup(&self, ip: net::IpAddr) -> Option<String> {
+        self.inner.reverse_lookup(ip).await
+    }
+
+    async fn is_fake_ip(&self, ip: net::IpAddr) -> bool {
+        self.inner.is_fake_ip(ip).await
+    }
+
+    fn fake_ip_enabled(&self) -> bool {
+        self.inner.fake_ip_enabled()
+    }
+
+    fn ipv6(&self) -> bool {
+        self.inner.ipv6()
+    }
+
+    fn set_ipv6(&self, enable: bool) {
+        self.inner.set_ipv6(enable)
+    }
+
+    fn kind(&self) -> ResolverKind {
+        self.inner.kind()
+    }
+
+    fn attach_rule_providers(
+        &self,
+        providers: &HashMap<String, ThreadSafeRuleProvider>,
+    ) {
+        self.inner.attach_rule_providers(providers)
+    }
+
+    fn block_stats(&self) -> Option<BlockStats> {
+        self.inner.block_stats()
+    }
+
+    fn cache_entries(&self) -> Option<Vec<CacheEntry>> {
+        self.inner.cache_entries()
+    }
+
+    fn clear_cache(&self) {
+        self.inner.clear_cache()
+    }
+
+    fn bootstrap(&self) -> Option<ThreadSafeDNSResolver> {
+        self.inner
+            .bootstrap()
+            .map(|x| with_ip_version(x, Some(self.version)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net, sync::Arc};
+
+    use super::with_ip_version;
+    use crate::{app::dns::MockClashResolver, config::def::IpVersion};
+
+    fn resolver(v4: Option<&str>, v6: Option<&str>) -> MockClashResolver {
+        let v4 = v4.map(|x| x.parse::<net::Ipv4Addr>().unwrap());
+        let v6 = v6.map(|x| x.parse::<net::Ipv6Addr>().unwrap());
+        let mut r = MockClashResolver::new();
+        r.expect_resolve_v4().returning(move |_, _| Ok(v4));
+        r.expect_resolve_v6().returning(move |_, _| Ok(v6));
+        r
+    }
+
+    #[tokio::test]
+    async fn test_ip_version_resolve() {
+        let v4: net::IpAddr = "1.1.1.1".parse().unwrap();
+        let v6: net::IpAddr = "::1".parse().unwrap();
+
+        let r = with_ip_version(
+            Arc::new(resolver(Some("1.1.1.1"), Some("::1"))),
+            Some(IpVersion::Ipv6),
+        );
+        assert_eq!(r.resolve("a", false).await.unwrap(), Some(v6));
+
+        let r = with_ip_version(
+            Arc::new(resolver(Some("1.1.1.1"), Some("::1"))),
+            Some(IpVersion::PreferIpv4),
+        );
+        assert_eq!(r.resolve("a", false).await.unwrap(), Some(v4));
+
+        let r = with_ip_version(
+            Arc::new(resolver(Some("1.1.1.1"), None)),
+            Some(IpVersion::PreferIpv6),
+        );
+        assert_eq!(r.resolve("a", false).await.unwrap(), Some(v4));
+
+        let r = with_ip_version(
+            Arc::new(resolver(None, Some("::1"))),
+            Some(IpVersion::Ipv4),
+        );
+        assert_eq!(r.resolve("a", false).await.unwrap(), None);
+    }
+}
@@ -1,4 +1,7 @@
 mod enhanced;
+mod failover;
+mod ip_version;
+mod server_resolver;
 
 #[cfg(all(target_feature = "crt-static", target_env = "gnu"))]
 #[path = "system_static_crt.rs"]
@@ -11,6 +14,8 @@ mod system;
 use std::{collections::HashMap, sync::Arc};
 
 pub use enhanced::EnhancedResolver;
+pub use ip_version::{default_ip_version, set_default_ip_version, with_ip_version};
+pub use server_resolver::with_server_resolver;
 pub use system::SystemResolver;
 
 use super::{Config, ThreadSafeDNSResolver};
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use crate::{
+    app::dns::{SystemResolver, ThreadSafeDNSResolver},
+    config::def::ServerResolver,
+};
+
+/// The resolver a proxy looks its server address up with, picked by `kind`
+/// out of `resolver` and those it's built on. `resolver` itself for `None`,
+/// [`ServerResolver::Dns`], and [`ServerResolver::Bootstrap`] when there is
+/// no bootstrap resolver, i.e. the DNS is disabled.
+pub fn with_server_resolver(
+    resolver: ThreadSafeDNSResolver,
+    kind: Option<ServerResolver>,
+) -> ThreadSafeDNSResolver {
+    match kind {
+        None | Some(ServerResolver::Dns) => resolver,
+        Some(ServerResolver::Bootstrap) => resolver.bootstrap().unwrap_or(resolver),
+        Some(ServerResolver::System) => match SystemResolver::new(resolver.ipv6()) {
+            Ok(system) => Arc::new(system),
+            Err(_) => resolver,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net, sync::Arc};
+
+    use super::with_server_resolver;
+    use crate::{
+        app::dns::{MockClashResolver, ThreadSafeDNSResolver},
+        config::def::ServerResolver,
+    };
+
+    fn resolver(ip: &str) -> MockClashResolver {
+        let ip = ip.parse::<net::IpAddr>().unwrap();
+        let mut r = MockClashResolver::new();
+        r.expect_resolve().returning(move |_, _| Ok(Some(ip)));
+        r
+    }
+
+    #[tokio::test]
+    async fn test_server_resolver() {
+        let mut main = resolver("1.1.1.1");
+        main.expect_bootstrap().returning(|| {
+            Some(Arc::new(resolver("2.2.2.2")) as ThreadSafeDNSResolver)
+        });
+        let main: ThreadSafeDNSResolver = Arc::new(main);
+
+        for (kind, ip) in [
+            (None, "1.1.1.1"),
+            (Some(ServerResolver::Dns), "1.1.1.1"),
+            (Some(ServerResolver::Bootstrap), "2.2.2.2"),
+        ] {
+            let r = with_server_resolver(main.clone(), kind);
+            assert_eq!(
+                r.resolve("a", false).await.unwrap(),
+                Some(ip.parse().unwrap())
+            );
+        }
+
+        // without a bootstrap resolver the main one is used
+        let mut main = resolver("1.1.1.1");
+        main.expect_bootstrap().returning(|| None);
+        let r =
+            with_server_resolver(Arc::new(main), Some(ServerResolver::Bootstrap));
+        assert_eq!(
+            r.resolve("a", false).await.unwrap(),
+            Some("1.1.1.1".parse().unwrap())
+        );
+    }
+}
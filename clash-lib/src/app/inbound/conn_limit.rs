@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Semaphore,
+};
+use tracing::debug;
+
+use crate::proxy::socks::SOCKS5_VERSION;
+
+const REJECT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Rejections sent at once, sockets refused beyond that are closed outright
+const MAX_PENDING_REJECTIONS: usize = 64;
+
+pub type ThreadSafeConnectionLimiter = Arc<ConnectionLimiter>;
+
+/// Tracks the number of concurrent inbound connections per source IP and
+/// refuses new ones once the configured limit is reached. Only the HTTP,
+/// SOCKS5 and mixed inbounds go through it, tproxy, redir and tunnel ones
+/// are not limited.
+pub struct ConnectionLimiter {
+    max_per_ip: Option<usize>,
+    counts: Mutex<HashMap<IpAddr, usize>>,
+    rejecting: Arc<Semaphore>,
+}
+
+impl ConnectionLimiter {
+    /// `None` or `Some(0)` disables the limit.
+    pub fn new(max_per_ip: Option<usize>) -> ThreadSafeConnectionLimiter {
+        Arc::new(Self {
+            max_per_ip: max_per_ip.filter(|x| *x > 0),
+            counts: Mutex::new(HashMap::new()),
+            rejecting: Arc::new(Semaphore::new(MAX_PENDING_REJECTIONS)),
+        })
+    }
+
+    /// Reserve a connection slot for `ip`.
+    /// Returns `None` if the source already holds the maximum number of
+    /// connections. The slot is released when the returned guard is dropped.
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionGuard> {
+        let Some(max) = self.max_per_ip else {
+            return Some(ConnectionGuard {
+                limiter: self.clone(),
+                ip: None,
+            });
+        };
+
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(ip).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+
+        Some(ConnectionGuard {
+            limiter: self.clone(),
+            ip: Some(ip),
+        })
+    }
+
+    /// Tell an HTTP client that it has too many open connections.
+    pub fn reject_http(&self, socket: TcpStream) {
+        self.spawn_rejection(reject_http(socket));
+    }
+
+    /// Answer the SOCKS5 method negotiation with NO ACCEPTABLE METHODS so the
+    /// client gets a protocol level refusal instead of a bare reset.
+    pub fn reject_socks5(&self, socket: TcpStream) {
+        self.spawn_rejection(reject_socks5(socket));
+    }
+
+    /// Runs `rejection` in the background, unless [`MAX_PENDING_REJECTIONS`]
+    /// are under way already, then the socket it owns is just closed, so a
+    /// flood of connections can't pile up tasks.
+    fn spawn_rejection(&self, rejection: impl Future<Output = ()> + Send + 'static) {
+        let Ok(permit) = self.rejecting.clone().try_acquire_owned() else {
+            debug!("too many pending rejections, closing");
+            return;
+        };
+        tokio::spawn(async move {
+            rejection.await;
+            drop(permit);
+        });
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&ip) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(&ip);
+            }
+        }
+    }
+}
+
+/// Releases the connection slot held for a source IP on drop.
+pub struct ConnectionGuard {
+    limiter: ThreadSafeConnectionLimiter,
+    ip: Option<IpAddr>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(ip) = self.ip {
+            self.limiter.release(ip);
+        }
+    }
+}
+
+async fn reject_http(mut socket: TcpStream) {
+    let reply = async {
+        socket
+            .write_all(
+                b"HTTP/1.1 429 Too Many Requests\r\nConnection: close\r\n\
+                  Content-Length: 0\r\n\r\n",
+            )
+            .await?;
+        socket.shutdown().await
+    };
+    if let Err(e) = tokio::time::timeout(REJECT_HANDSHAKE_TIMEOUT, reply)
+        .await
+        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+    {
+        debug!("failed to send http rejection: {e}");
+    }
+}
+
+async fn reject_socks5(mut socket: TcpStream) {
+    let handshake = async {
+        let mut buf = [0u8; 2];
+        socket.read_exact(&mut buf).await?;
+        if buf[0] != SOCKS5_VERSION {
+            return Ok(());
+        }
+        let mut methods = vec![0u8; buf[1] as usize];
+        socket.read_exact(&mut methods).await?;
+        socket.write_all(&[SOCKS5_VERSION, 0xff]).await?;
+        socket.shutdown().await
+    };
+    if let Err(e) = tokio::time::timeout(REJECT_HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+    {
+        debug!("failed to send socks5 rejection: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{ConnectionLimiter, MAX_PENDING_REJECTIONS};
+
+    impl ConnectionLimiter {
+        fn count(&self, ip: IpAddr) -> usize {
+            self.counts.lock().unwrap().get(&ip).copied().unwrap_or(0)
+        }
+    }
+
+    #[test]
+    fn test_limit_per_ip() {
+        let limiter = ConnectionLimiter::new(Some(2));
+        let a = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 3));
+
+        let g1 = limiter.try_acquire(a).expect("first slot");
+        let g2 = limiter.try_acquire(a).expect("second slot");
+        assert!(limiter.try_acquire(a).is_none());
+        assert!(limiter.try_acquire(b).is_some());
+        assert_eq!(limiter.count(a), 2);
+
+        drop(g1);
+        assert_eq!(limiter.count(a), 1);
+        let _g3 = limiter.try_acquire(a).expect("slot freed");
+        drop(g2);
+        assert_eq!(limiter.count(a), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejections_bounded() {
+        let limiter = ConnectionLimiter::new(Some(1));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // what a client gets back when its connection is rejected
+        let reject = async |limiter: &ConnectionLimiter| {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let (socket, _) = listener.accept().await.unwrap();
+            limiter.reject_http(socket);
+            let mut reply = Vec::new();
+            client.read_to_end(&mut reply).await.unwrap();
+            reply
+        };
+
+        let busy = limiter
+            .rejecting
+            .clone()
+            .acquire_many_owned(MAX_PENDING_REJECTIONS as u32)
+            .await
+            .unwrap();
+        assert!(reject(&limiter).await.is_empty());
+
+        drop(busy);
+        assert!(reject(&limiter).await.starts_with(b"HTTP/1.1 429"));
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = ConnectionLimiter::new(None);
+        let a = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let guards = (0..100)
+            .map(|_| limiter.try_acquire(a).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(limiter.count(a), 0);
+        drop(guards);
+    }
+}
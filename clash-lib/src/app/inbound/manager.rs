@@ -3,7 +3,11 @@ use tokio::{sync::RwLock, task::JoinHandle};
 
 use crate::{
     app::{
-        dispatcher::Dispatcher, inbound::network_listener::build_network_listeners,
+        dispatcher::Dispatcher,
+        inbound::{
+            conn_limit::ThreadSafeConnectionLimiter,
+            network_listener::build_network_listeners,
+        },
     },
     common::auth::ThreadSafeAuthenticator,
     config::internal::{config::BindAddress, listener::InboundOpts},
@@ -32,6 +36,7 @@ pub struct Ports {
 pub struct InboundManager {
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
 
     /// Inbound options for each inbound type -> listening Task
     inbound_handlers: RwLock<HashMap<InboundOpts, Option<JoinHandle<()>>>>,
@@ -41,6 +46,7 @@ impl InboundManager {
     pub async fn new(
         dispatcher: Arc<Dispatcher>,
         authenticator: ThreadSafeAuthenticator,
+        conn_limiter: ThreadSafeConnectionLimiter,
         inbounds_opt: HashSet<InboundOpts>,
     ) -> Self {
         Self {
@@ -49,6 +55,7 @@ impl InboundManager {
             ),
             dispatcher,
             authenticator,
+            conn_limiter,
         }
     }
 
@@ -78,6 +85,7 @@ impl InboundManager {
                 opts,
                 self.dispatcher.clone(),
                 self.authenticator.clone(),
+                self.conn_limiter.clone(),
             )
             .map(|r| {
                 tokio::spawn(async move {
@@ -1,2 +1,3 @@
+pub mod conn_limit;
 pub mod manager;
 pub mod network_listener;
@@ -1,5 +1,6 @@
 use crate::{
     Runner,
+    app::inbound::conn_limit::ThreadSafeConnectionLimiter,
     common::auth::ThreadSafeAuthenticator,
     config::listener::InboundOpts,
     proxy::{
@@ -24,12 +25,15 @@ pub(crate) fn build_network_listeners(
     inbound_opts: &InboundOpts,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
 ) -> Option<Vec<Runner>> {
     let name = &inbound_opts.common_opts().name;
     let addr = inbound_opts.common_opts().listen.0;
     let port = inbound_opts.common_opts().port;
 
-    if let Some(handler) = build_handler(inbound_opts, dispatcher, authenticator) {
+    if let Some(handler) =
+        build_handler(inbound_opts, dispatcher, authenticator, conn_limiter)
+    {
         let mut runners: Vec<Runner> = Vec::new();
 
         if handler.handle_tcp() {
@@ -77,15 +81,21 @@ fn build_handler(
     listener: &InboundOpts,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
 ) -> Option<Arc<dyn InboundHandlerTrait>> {
     let fw_mark = listener.common_opts().fw_mark;
+    let tfo = listener.common_opts().tfo_queue();
+    let reuse_port = listener.common_opts().reuse_port;
     match listener {
         InboundOpts::Http { common_opts, .. } => Some(Arc::new(HttpInbound::new(
             (common_opts.listen.0, common_opts.port).into(),
             common_opts.allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         ))),
 
         InboundOpts::Socks { common_opts, .. } => Some(Arc::new(SocksInbound::new(
@@ -93,19 +103,27 @@ fn build_handler(
             common_opts.allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         ))),
         InboundOpts::Mixed { common_opts, .. } => Some(Arc::new(MixedInbound::new(
             (common_opts.listen.0, common_opts.port).into(),
             common_opts.allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         ))),
         #[cfg(feature = "tproxy")]
         InboundOpts::TProxy {
             #[cfg(target_os = "linux")]
             common_opts,
+            #[cfg(target_os = "linux")]
+            udp_source_nat,
             ..
         } => {
             #[cfg(target_os = "linux")]
@@ -115,6 +133,9 @@ fn build_handler(
                     common_opts.allow_lan,
                     dispatcher,
                     fw_mark,
+                    tfo,
+                    reuse_port,
+                    *udp_source_nat,
                 )))
             }
 
@@ -137,6 +158,8 @@ fn build_handler(
                     common_opts.allow_lan,
                     dispatcher,
                     fw_mark,
+                    tfo,
+                    reuse_port,
                 )))
             }
             #[cfg(not(target_os = "linux"))]
@@ -155,6 +178,8 @@ fn build_handler(
             network.clone(),
             target.clone(),
             fw_mark,
+            tfo,
+            reuse_port,
         )
         .inspect_err(|x| {
             warn!("tunnel inbound handler failed to create: {x}");
@@ -176,6 +201,8 @@ fn build_handler(
             dispatcher,
             authenticator,
             fw_mark: common_opts.fw_mark,
+            tfo,
+            reuse_port,
         }))),
     }
 }
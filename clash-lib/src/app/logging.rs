@@ -1,10 +1,10 @@
 use crate::def::LogLevel;
 use anyhow::anyhow;
-#[cfg(feature = "tracing")]
+#[cfg(feature = "otel")]
 use opentelemetry::trace::TracerProvider;
-#[cfg(feature = "tracing")]
+#[cfg(feature = "otel")]
 use opentelemetry_otlp::{Protocol, WithExportConfig};
-#[cfg(feature = "tracing")]
+#[cfg(feature = "otel")]
 use opentelemetry_semantic_conventions::{
     SCHEMA_URL,
     attribute::{DEPLOYMENT_ENVIRONMENT_NAME, SERVICE_VERSION},
@@ -14,7 +14,7 @@ use std::{io::IsTerminal, sync::Once};
 use tokio::sync::broadcast::Sender;
 use tracing::level_filters::LevelFilter;
 use tracing_log::LogTracer;
-#[cfg(feature = "tracing")]
+#[cfg(feature = "otel")]
 use tracing_opentelemetry::OpenTelemetryLayer;
 #[cfg(target_os = "ios")]
 use tracing_oslog::OsLogger;
@@ -83,7 +83,7 @@ struct LoggingGuard {
     _file_appender: Option<tracing_appender::non_blocking::WorkerGuard>,
     #[cfg(feature = "tracing")]
     _tracing_chrome: Option<tracing_chrome::FlushGuard>,
-    #[cfg(feature = "tracing")]
+    #[cfg(feature = "otel")]
     _tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
 }
 
@@ -160,14 +160,14 @@ fn setup_logging_inner(
         (None, None)
     };
 
-    #[cfg(feature = "tracing")]
+    #[cfg(feature = "otel")]
     let exporter = opentelemetry_otlp::SpanExporter::builder()
         .with_http()
         .with_protocol(Protocol::HttpBinary)
         .build()
         .unwrap();
 
-    #[cfg(feature = "tracing")]
+    #[cfg(feature = "otel")]
     let tracer_provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
         // Customize sampling strategy
         .with_sampler(opentelemetry_sdk::trace::Sampler::ParentBased(Box::new(opentelemetry_sdk::trace::Sampler::TraceIdRatioBased(
@@ -194,7 +194,7 @@ fn setup_logging_inner(
         .build())
         .with_batch_exporter(exporter)
         .build();
-    #[cfg(feature = "tracing")]
+    #[cfg(feature = "otel")]
     let tracer = tracer_provider.tracer("tracing-otel-subscriber");
 
     let subscriber = tracing_subscriber::registry();
@@ -239,25 +239,15 @@ fn setup_logging_inner(
         .with_writer(std::io::stdout)
         .with_filter(exclude.clone());
 
-    let subscriber = {
-        #[cfg(feature = "tracing")]
-        {
-            subscriber
-        .with(filter) // Global filter
-        .with(tracing_chrome)
-        .with(OpenTelemetryLayer::new(tracer))
-        .with(collector.with_filter(exclude.clone()))
-        .with(log_to_file_layer)
-        .with(log_stdout_layer)
-        }
-        #[cfg(not(feature = "tracing"))]
-        {
-            subscriber.with(filter) // Global filter
+    let subscriber = subscriber.with(filter); // Global filter
+    #[cfg(feature = "tracing")]
+    let subscriber = subscriber.with(tracing_chrome);
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(OpenTelemetryLayer::new(tracer));
+    let subscriber = subscriber
         .with(collector.with_filter(exclude.clone()))
         .with(log_to_file_layer)
-        .with(log_stdout_layer)
-        }
-    };
+        .with(log_stdout_layer);
 
     #[cfg(target_os = "ios")]
     let subscriber =
@@ -270,7 +260,7 @@ fn setup_logging_inner(
         _file_appender: guard,
         #[cfg(feature = "tracing")]
         _tracing_chrome: tracing_chrome_g,
-        #[cfg(feature = "tracing")]
+        #[cfg(feature = "otel")]
         _tracer_provider: Some(tracer_provider),
     }))
 }
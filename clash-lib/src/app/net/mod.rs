@@ -2,26 +2,356 @@ use network_interface::{
     NetworkInterface, NetworkInterfaceConfig, V4IfAddr, V6IfAddr,
 };
 use std::{
+    collections::{HashMap, HashSet},
     fmt::Display,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::{Arc, LazyLock},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    str::FromStr,
+    sync::{
+        Arc, LazyLock, RwLock,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+use tracing::{error, info, trace, warn};
+
+use crate::config::def::{BogonPolicy, IpStrategy, LocalAddressPolicy};
+
+mod route_events;
 
 pub static DEFAULT_OUTBOUND_INTERFACE: LazyLock<
     Arc<tokio::sync::RwLock<Option<OutboundInterface>>>,
 > = LazyLock::new(Default::default);
 pub static TUN_SOMARK: LazyLock<tokio::sync::RwLock<Option<u32>>> =
     LazyLock::new(Default::default);
+/// Whether `DEFAULT_OUTBOUND_INTERFACE` was configured by the user, in which
+/// case all outbound connections go through it
+static OUTBOUND_INTERFACE_PINNED: AtomicBool = AtomicBool::new(false);
+/// Whether `DEFAULT_OUTBOUND_INTERFACE` was detected rather than configured,
+/// in which case it follows the interface changes seen by the watcher
+static OUTBOUND_INTERFACE_AUTO: AtomicBool = AtomicBool::new(false);
+/// The configured outbound interface that could not be found, when
+/// `interface-fallback` is off, in which case outbound sockets are refused
+/// rather than left unbound
+static MISSING_OUTBOUND_INTERFACE: RwLock<Option<String>> = RwLock::new(None);
+/// The configured `interface` and `interface-fallback`
+static INTERFACE_SELECTION: RwLock<(Option<Interface>, bool)> =
+    RwLock::new((None, true));
+/// Whether outbound sockets should set `IP_FREEBIND` before binding
+static OUTBOUND_FREEBIND: AtomicBool = AtomicBool::new(false);
+
+pub fn set_outbound_freebind(enabled: bool) {
+    OUTBOUND_FREEBIND.store(enabled, Ordering::Relaxed);
+}
+
+pub fn outbound_freebind() -> bool {
+    OUTBOUND_FREEBIND.load(Ordering::Relaxed)
+}
+
+/// Name fragments of the interfaces to prefer as the outbound interface,
+/// most preferred first, used when `interface-priority` isn't set
+const DEFAULT_INTERFACE_PRIORITY: &[&str] = &[
+    "eth",
+    "en",
+    "pdp_ip",
+    "WLAN",
+    "wlp",
+    "Ethernet",
+    "vEthernet",
+    "Wi-Fi",
+    "Tailscale",
+];
+
+/// The configured `interface-priority`
+static INTERFACE_PRIORITY: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
+pub fn set_interface_priority(priority: Option<Vec<String>>) {
+    *INTERFACE_PRIORITY.write().unwrap() = priority;
+    invalidate_outbound_interface_cache();
+}
+
+static IP_STRATEGY: AtomicU8 = AtomicU8::new(IpStrategy::PreferIpv6 as u8);
+
+pub fn set_ip_strategy(strategy: IpStrategy) {
+    IP_STRATEGY.store(strategy as u8, Ordering::Relaxed);
+    invalidate_outbound_interface_cache();
+}
+
+pub fn ip_strategy() -> IpStrategy {
+    match IP_STRATEGY.load(Ordering::Relaxed) {
+        x if x == IpStrategy::PreferIpv4 as u8 => IpStrategy::PreferIpv4,
+        x if x == IpStrategy::Ipv4Only as u8 => IpStrategy::Ipv4Only,
+        x if x == IpStrategy::Ipv6Only as u8 => IpStrategy::Ipv6Only,
+        _ => IpStrategy::PreferIpv6,
+    }
+}
+
+/// How the default outbound interface is picked, `interface-priority` and
+/// `ip-strategy`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InterfacePreference<'a> {
+    pub priority: Option<&'a [String]>,
+    pub strategy: IpStrategy,
+}
+
+/// The last interface picked by [`get_outbound_interface`], and when
+static OUTBOUND_INTERFACE_CACHE: RwLock<Option<(Instant, OutboundInterface)>> =
+    RwLock::new(None);
+/// How long [`OUTBOUND_INTERFACE_CACHE`] is used, `interface-cache-ttl`
+static OUTBOUND_INTERFACE_CACHE_TTL: RwLock<Duration> =
+    RwLock::new(Duration::from_secs(5));
+
+pub fn set_outbound_interface_cache_ttl(ttl: Duration) {
+    *OUTBOUND_INTERFACE_CACHE_TTL.write().unwrap() = ttl;
+    invalidate_outbound_interface_cache();
+}
+
+/// Makes the next [`get_outbound_interface`] look at the interfaces again,
+/// e.g. once the network changed
+pub fn invalidate_outbound_interface_cache() {
+    *OUTBOUND_INTERFACE_CACHE.write().unwrap() = None;
+}
+
+/// The default outbound interface as last seen by the watcher
+static INTERFACE_CHANGES: LazyLock<watch::Sender<Option<OutboundInterface>>> =
+    LazyLock::new(|| watch::Sender::new(None));
+/// The task of [`watch_outbound_interface`], replaced on reload
+static INTERFACE_WATCHER: std::sync::Mutex<Option<JoinHandle<()>>> =
+    std::sync::Mutex::new(None);
+
+/// Receives the default outbound interface, as picked by `ip-strategy` and
+/// `interface-priority`, every time it changes, e.g. when roaming from
+/// Wi-Fi to Ethernet, for dialers to make new connections on the new one.
+/// Changes are seen once the OS tells of them, on Linux, macOS and Windows,
+/// and every `interface-watch-interval` anyway.
+pub fn subscribe_interface_changes() -> watch::Receiver<Option<OutboundInterface>> {
+    INTERFACE_CHANGES.subscribe()
+}
+
+/// Looks for a change of the default outbound interface on the network
+/// change notifications of the OS, if it has some, and every `interval`, or
+/// not at all for `None`. The watcher of a previous configuration is
+/// stopped either way.
+pub fn watch_outbound_interface(interval: Option<Duration>) {
+    let mut watcher = INTERFACE_WATCHER.lock().unwrap();
+    if let Some(handle) = watcher.take() {
+        handle.abort();
+    }
+    *watcher = interval.map(|x| tokio::spawn(poll_outbound_interface(x)));
+}
+
+/// How long to wait for more after a network change notification, as a
+/// change usually comes with a burst of them, e.g. an address and its routes
+const NETWORK_CHANGE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+async fn poll_outbound_interface(interval: Duration) {
+    let mut changes = route_events::route_changes();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let notified = match changes.as_mut() {
+            Some(rx) => tokio::select! {
+                _ = ticker.tick() => Some(false),
+                x = rx.recv() => x.map(|_| true),
+            },
+            None => {
+                ticker.tick().await;
+                Some(false)
+            }
+        };
+        match notified {
+            Some(true) => {
+                tokio::time::sleep(NETWORK_CHANGE_DEBOUNCE).await;
+                if let Some(rx) = changes.as_mut() {
+                    while rx.try_recv().is_ok() {}
+                }
+                trace!("network changed, looking at the interfaces again");
+                // the addresses of the cached pick may be gone too
+                invalidate_outbound_interface_cache();
+            }
+            Some(false) => {}
+            None => {
+                warn!(
+                    "network change notifications stopped, looking every {:?}",
+                    interval
+                );
+                changes = None;
+                continue;
+            }
+        }
+
+        // not the cached pick, which may be as old as `interface-cache-ttl`
+        let current = get_outbound_interface_with(InterfacePreference {
+            priority: INTERFACE_PRIORITY.read().unwrap().as_deref(),
+            strategy: ip_strategy(),
+        });
+        let changed = INTERFACE_CHANGES.send_if_modified(|last| {
+            if same_interface(last.as_ref(), current.as_ref()) {
+                return false;
+            }
+            *last = current.clone();
+            true
+        });
+        if !changed {
+            continue;
+        }
+
+        info!(
+            "default outbound interface changed to {:?}",
+            current.as_ref().map(|x| &x.name)
+        );
+        invalidate_outbound_interface_cache();
+        // a configured interface stays, and so does an unset one, which leaves
+        // sockets unbound. a detected one follows, also back from none
+        if OUTBOUND_INTERFACE_AUTO.load(Ordering::Relaxed) {
+            *DEFAULT_OUTBOUND_INTERFACE.write().await = current;
+        }
+    }
+}
+
+fn same_interface(
+    a: Option<&OutboundInterface>,
+    b: Option<&OutboundInterface>,
+) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            a.name == b.name
+                && a.index == b.index
+                && a.addr_v4 == b.addr_v4
+                && a.addr_v6 == b.addr_v6
+        }
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// The DSCP value of outbound sockets, `dscp`
+static OUTBOUND_DSCP: RwLock<Option<u8>> = RwLock::new(None);
+
+pub fn set_outbound_dscp(dscp: Option<u8>) {
+    *OUTBOUND_DSCP.write().unwrap() = dscp;
+}
+
+pub fn outbound_dscp() -> Option<u8> {
+    *OUTBOUND_DSCP.read().unwrap()
+}
+
+/// Whether DIRECT connections leave from the address they arrived on
+static DIRECT_PRESERVE_SOURCE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_direct_preserve_source(enabled: bool) {
+    DIRECT_PRESERVE_SOURCE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn direct_preserve_source() -> bool {
+    DIRECT_PRESERVE_SOURCE.load(Ordering::Relaxed)
+}
+
+/// Whether IPv6 was turned off with `ipv6-disabled`
+static IPV6_DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_ipv6_disabled(disabled: bool) {
+    IPV6_DISABLED.store(disabled, Ordering::Relaxed);
+}
+
+pub fn ipv6_disabled() -> bool {
+    IPV6_DISABLED.load(Ordering::Relaxed)
+}
+
+static BOGON_POLICY: AtomicU8 = AtomicU8::new(BogonPolicy::Block as u8);
+
+pub fn set_bogon_policy(policy: BogonPolicy) {
+    BOGON_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn bogon_policy() -> BogonPolicy {
+    match BOGON_POLICY.load(Ordering::Relaxed) {
+        x if x == BogonPolicy::Allow as u8 => BogonPolicy::Allow,
+        x if x == BogonPolicy::Direct as u8 => BogonPolicy::Direct,
+        _ => BogonPolicy::Block,
+    }
+}
+
+static LOCAL_ADDRESS_POLICY: AtomicU8 =
+    AtomicU8::new(LocalAddressPolicy::Direct as u8);
+
+pub fn set_local_address_policy(policy: LocalAddressPolicy) {
+    LOCAL_ADDRESS_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn local_address_policy() -> LocalAddressPolicy {
+    match LOCAL_ADDRESS_POLICY.load(Ordering::Relaxed) {
+        x if x == LocalAddressPolicy::Allow as u8 => LocalAddressPolicy::Allow,
+        x if x == LocalAddressPolicy::Block as u8 => LocalAddressPolicy::Block,
+        _ => LocalAddressPolicy::Direct,
+    }
+}
+
+/// How long the enumerated local addresses are used before looking again
+const LOCAL_ADDRESSES_TTL: Duration = Duration::from_secs(30);
+
+/// The addresses of all local interfaces, and when they were enumerated
+static LOCAL_ADDRESSES: LazyLock<RwLock<(Option<Instant>, HashSet<IpAddr>)>> =
+    LazyLock::new(Default::default);
+
+/// Whether `ip` is one of the addresses of the local machine.
+pub fn is_local_address(ip: &IpAddr) -> bool {
+    let ip = ip.to_canonical();
+    {
+        let cache = LOCAL_ADDRESSES.read().unwrap();
+        if cache.0.is_some_and(|x| x.elapsed() < LOCAL_ADDRESSES_TTL) {
+            return cache.1.contains(&ip);
+        }
+    }
+
+    let addrs = list_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|iface| iface.addr)
+        .map(|addr| match addr {
+            network_interface::Addr::V4(v4) => IpAddr::V4(v4.ip),
+            network_interface::Addr::V6(v6) => IpAddr::V6(v6.ip),
+        })
+        .collect::<HashSet<_>>();
+    let found = addrs.contains(&ip);
+    *LOCAL_ADDRESSES.write().unwrap() = (Some(Instant::now()), addrs);
+    found
+}
+
+/// Whether `ip` is in a range that must never be seen as a destination on
+/// the internet, as opposed to private or loopback ranges, which are
+/// legitimate destinations on the local network.
+pub fn is_bogon(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            // 0.0.0.0/8, "this network"
+            ip.octets()[0] == 0
+                || ip.is_reserved()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_bogon(&v4.into()),
+            None => ip.is_unspecified() || ip.is_documentation(),
+        },
+    }
+}
 
 /// Initialize network configuration
 /// globally manage default outbound interface
 /// This function should be called as early as possible
 /// so that other config initialization can use the default outbound interface
-pub async fn init_net_config(tun_somark: Option<u32>) {
-    *DEFAULT_OUTBOUND_INTERFACE.write().await = get_outbound_interface();
+///
+/// When the configured `interface` can't be found, outbound sockets are left
+/// unbound and follow the default routes if `fallback` is set, or refused
+/// otherwise.
+pub async fn init_net_config(
+    tun_somark: Option<u32>,
+    interface: Option<&Interface>,
+    fallback: bool,
+) {
+    select_outbound_interface(interface, fallback).await;
     *TUN_SOMARK.write().await = tun_somark;
 
     trace!(
@@ -31,6 +361,56 @@ pub async fn init_net_config(tun_somark: Option<u32>) {
     );
 }
 
+/// Picks the default outbound interface again, from the interfaces as they
+/// are now. Connections already established are not affected.
+pub async fn select_outbound_interface(
+    interface: Option<&Interface>,
+    fallback: bool,
+) {
+    *INTERFACE_SELECTION.write().unwrap() = (interface.cloned(), fallback);
+    invalidate_outbound_interface_cache();
+    let configured = interface.and_then(|x| {
+        let resolved = x.resolve();
+        if resolved.is_none() {
+            if fallback {
+                warn!(
+                    "outbound interface {} not found, outbound connections will \
+                     not be bound to any interface and follow the default routes",
+                    x
+                );
+            } else {
+                error!(
+                    "outbound interface {} not found, outbound connections will be \
+                     refused as interface-fallback is disabled",
+                    x
+                );
+            }
+        }
+        resolved
+    });
+    *MISSING_OUTBOUND_INTERFACE.write().unwrap() = match (interface, &configured) {
+        (Some(x), None) if !fallback => Some(x.to_string()),
+        _ => None,
+    };
+    OUTBOUND_INTERFACE_PINNED.store(configured.is_some(), Ordering::Relaxed);
+    OUTBOUND_INTERFACE_AUTO.store(configured.is_none(), Ordering::Relaxed);
+    *DEFAULT_OUTBOUND_INTERFACE.write().await =
+        configured.or_else(get_outbound_interface);
+}
+
+/// The `interface` and `interface-fallback` currently in effect.
+pub fn interface_selection() -> (Option<Interface>, bool) {
+    INTERFACE_SELECTION.read().unwrap().clone()
+}
+
+/// Forgets the outbound interface of a previous configuration.
+pub fn unpin_outbound_interface(fallback: bool) {
+    *INTERFACE_SELECTION.write().unwrap() = (None, fallback);
+    OUTBOUND_INTERFACE_PINNED.store(false, Ordering::Relaxed);
+    OUTBOUND_INTERFACE_AUTO.store(false, Ordering::Relaxed);
+    *MISSING_OUTBOUND_INTERFACE.write().unwrap() = None;
+}
+
 /// Represents a parsed outbound interface for use in runtime.
 #[derive(Serialize, Debug, Clone)]
 pub struct OutboundInterface {
@@ -43,6 +423,19 @@ pub struct OutboundInterface {
     pub broadcast_v6: Option<Ipv6Addr>,
     pub index: u32,
     pub mac_addr: Option<String>,
+    /// Local address to bind in addition to the device, set when the
+    /// interface was picked by one of its addresses.
+    #[serde(skip)]
+    pub bind_addr: Option<IpAddr>,
+    /// Set when the interface was asked for by its name, which sockets are
+    /// then bound to even on Android.
+    #[serde(skip)]
+    pub by_name: bool,
+    /// Set when this isn't an interface but a source address no interface
+    /// has (yet), see [`freebind_source`]. Sockets are bound to the address
+    /// with `IP_FREEBIND`, not to a device.
+    #[serde(skip)]
+    pub freebind: bool,
 }
 
 impl From<NetworkInterface> for OutboundInterface {
@@ -52,26 +445,32 @@ impl From<NetworkInterface> for OutboundInterface {
         ) -> (Option<V4IfAddr>, Option<V6IfAddr>) {
             let mut v4 = None;
             let mut v6 = None;
+            let mut v6_rank = None;
+
+            let v6_flags = ipv6_addr_flags(&iface.name);
 
             for addr in iface.addr.iter() {
                 trace!("inspect interface address: {:?} on {}", addr, iface.name);
 
-                if v4.is_some() && v6.is_some() {
-                    break;
-                }
-
                 match addr {
                     network_interface::Addr::V4(addr) => {
-                        if !addr.ip.is_loopback()
+                        if v4.is_none()
+                            && !addr.ip.is_loopback()
                             && !addr.ip.is_link_local()
                             && !addr.ip.is_unspecified()
                         {
                             v4 = Some(*addr);
                         }
                     }
+                    network_interface::Addr::V6(_) if ipv6_disabled() => {}
                     network_interface::Addr::V6(addr) => {
-                        if addr.ip.is_unique_local() || addr.ip.is_global() {
+                        let rank = rank_ipv6_addr(
+                            &addr.ip,
+                            v6_flags.get(&addr.ip).copied().unwrap_or_default(),
+                        );
+                        if rank > v6_rank {
                             v6 = Some(*addr);
+                            v6_rank = rank;
                         }
                     }
                 }
@@ -91,6 +490,9 @@ impl From<NetworkInterface> for OutboundInterface {
             broadcast_v6: addr.1.and_then(|x| x.broadcast),
             index: iface.index,
             mac_addr: iface.mac_addr,
+            bind_addr: None,
+            by_name: false,
+            freebind: false,
         }
     }
 }
@@ -112,14 +514,40 @@ impl std::fmt::Display for OutboundInterface {
     }
 }
 
+/// Lists the local interfaces. Enumeration may be denied or come back empty
+/// in sandboxes and containers, which is logged as no interface can be
+/// picked then.
+fn list_interfaces() -> Option<Vec<NetworkInterface>> {
+    match NetworkInterface::show() {
+        Ok(all) if all.is_empty() => {
+            warn!(
+                "no network interface found, interface enumeration may be \
+                 restricted in this environment"
+            );
+            None
+        }
+        Ok(all) => Some(all),
+        Err(e) => {
+            warn!("failed to enumerate network interfaces: {}", e);
+            None
+        }
+    }
+}
+
+/// The configured outbound interface, if it could not be found and outbound
+/// connections must not fall back to the default routes.
+pub fn missing_outbound_interface() -> Option<String> {
+    MISSING_OUTBOUND_INTERFACE.read().unwrap().clone()
+}
+
 pub fn get_interface_by_name(name: &str) -> Option<OutboundInterface> {
     let now = std::time::Instant::now();
 
-    let outbound = network_interface::NetworkInterface::show()
-        .ok()?
+    let mut outbound: OutboundInterface = list_interfaces()?
         .into_iter()
         .find(|iface| iface.name == name)?
         .into();
+    outbound.by_name = true;
 
     trace!(
         "found interface by name: {:?}, took: {}ms",
@@ -130,54 +558,179 @@ pub fn get_interface_by_name(name: &str) -> Option<OutboundInterface> {
     Some(outbound)
 }
 
+/// Finds the interface that owns `ip`, set up to send from that address.
+pub fn get_interface_by_ip(ip: IpAddr) -> Option<OutboundInterface> {
+    get_interface_by_scoped_ip(ip, None)
+}
+
+/// Like [`get_interface_by_ip`], only looking at the interface named or
+/// numbered `zone` if given, as the same link-local address may be on
+/// several interfaces.
+fn get_interface_by_scoped_ip(
+    ip: IpAddr,
+    zone: Option<&str>,
+) -> Option<OutboundInterface> {
+    if ip.is_ipv6() && ipv6_disabled() {
+        return None;
+    }
+    let owns = |addr: &network_interface::Addr| match addr {
+        network_interface::Addr::V4(v4) => IpAddr::V4(v4.ip) == ip,
+        network_interface::Addr::V6(v6) => IpAddr::V6(v6.ip) == ip,
+    };
+    let in_zone = |iface: &NetworkInterface| {
+        zone.is_none_or(|z| iface.name == z || z.parse() == Ok(iface.index))
+    };
+    let (iface, addr) = list_interfaces()?.into_iter().find_map(|iface| {
+        if !in_zone(&iface) {
+            return None;
+        }
+        let addr = iface.addr.iter().find(|x| owns(x)).cloned()?;
+        Some((iface, addr))
+    })?;
+
+    let mut outbound: OutboundInterface = iface.into();
+    match addr {
+        network_interface::Addr::V4(v4) => {
+            outbound.addr_v4 = Some(v4.ip);
+            outbound.netmask_v4 = v4.netmask;
+            outbound.broadcast_v4 = v4.broadcast;
+        }
+        network_interface::Addr::V6(v6) => {
+            outbound.addr_v6 = Some(v6.ip);
+            outbound.netmask_v6 = v6.netmask;
+            outbound.broadcast_v6 = v6.broadcast;
+        }
+    }
+    outbound.bind_addr = Some(ip);
+
+    trace!("found interface by ip {}: {:?}", ip, outbound);
+    Some(outbound)
+}
+
+/// A source address that isn't on any interface, to bind to with
+/// `IP_FREEBIND`, e.g. a floating IP that isn't up yet.
+pub fn freebind_source(ip: IpAddr) -> OutboundInterface {
+    OutboundInterface {
+        name: ip.to_string(),
+        addr_v4: match ip {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        },
+        netmask_v4: None,
+        broadcast_v4: None,
+        addr_v6: match ip {
+            IpAddr::V4(_) => None,
+            IpAddr::V6(ip) => Some(ip),
+        },
+        netmask_v6: None,
+        broadcast_v6: None,
+        index: 0,
+        mac_addr: None,
+        bind_addr: Some(ip),
+        by_name: false,
+        freebind: true,
+    }
+}
+
+/// Finds an interface given either its name or one of its addresses, as
+/// accepted by the `interface-name` options.
+pub fn find_interface(name_or_ip: &str) -> Option<OutboundInterface> {
+    name_or_ip.parse::<Interface>().ok()?.resolve()
+}
+
+/// The user configured outbound interface, if any.
+pub async fn pinned_outbound_interface() -> Option<OutboundInterface> {
+    if !OUTBOUND_INTERFACE_PINNED.load(Ordering::Relaxed) {
+        return None;
+    }
+    DEFAULT_OUTBOUND_INTERFACE.read().await.clone()
+}
+
+/// Whether `iface` has an address of the families `strategy` allows
+fn allowed_by_strategy(iface: &OutboundInterface, strategy: IpStrategy) -> bool {
+    match strategy {
+        IpStrategy::Ipv4Only => iface.addr_v4.is_some(),
+        IpStrategy::Ipv6Only => iface.addr_v6.is_some(),
+        IpStrategy::PreferIpv4 | IpStrategy::PreferIpv6 => {
+            iface.addr_v4.is_some() || iface.addr_v6.is_some()
+        }
+    }
+}
+
+/// Orders interfaces with the preferred family of `strategy` first, for
+/// IPv6 those with a global address before the others
+fn compare_families(
+    left: &OutboundInterface,
+    right: &OutboundInterface,
+    strategy: IpStrategy,
+) -> std::cmp::Ordering {
+    match strategy {
+        IpStrategy::PreferIpv4 | IpStrategy::Ipv4Only => {
+            right.addr_v4.is_some().cmp(&left.addr_v4.is_some())
+        }
+        IpStrategy::PreferIpv6 | IpStrategy::Ipv6Only => {
+            // None < Some(false) < Some(true), the greatest first
+            let global_v6 =
+                |x: &OutboundInterface| x.addr_v6.map(|ip| ip.is_unicast_global());
+            global_v6(right).cmp(&global_v6(left))
+        }
+    }
+}
+
+/// Position of the first entry of `priority`, or of
+/// [`DEFAULT_INTERFACE_PRIORITY`] for `None`, that is part of `name`
+fn interface_rank(name: &str, priority: Option<&[String]>) -> Option<usize> {
+    match priority {
+        Some(priority) => priority.iter().position(|x| name.contains(x.as_str())),
+        None => DEFAULT_INTERFACE_PRIORITY
+            .iter()
+            .position(|x| name.contains(x)),
+    }
+}
+
+/// The interface outbound connections go through by default, picked by
+/// `ip-strategy` and `interface-priority`. Enumerating the interfaces takes a
+/// few ms, so the pick is reused for `interface-cache-ttl`, unless invalidated
+/// by [`invalidate_outbound_interface_cache`].
 pub fn get_outbound_interface() -> Option<OutboundInterface> {
+    let ttl = *OUTBOUND_INTERFACE_CACHE_TTL.read().unwrap();
+    if let Some((at, iface)) = &*OUTBOUND_INTERFACE_CACHE.read().unwrap()
+        && at.elapsed() < ttl
+    {
+        return Some(iface.clone());
+    }
+
+    let iface = get_outbound_interface_with(InterfacePreference {
+        priority: INTERFACE_PRIORITY.read().unwrap().as_deref(),
+        strategy: ip_strategy(),
+    })?;
+    *OUTBOUND_INTERFACE_CACHE.write().unwrap() =
+        Some((Instant::now(), iface.clone()));
+    Some(iface)
+}
+
+/// Picks the outbound interface with `preference` in place of the
+/// configured one, for use before it's set
+pub fn get_outbound_interface_with(
+    preference: InterfacePreference,
+) -> Option<OutboundInterface> {
     let now = std::time::Instant::now();
+    let InterfacePreference { priority, strategy } = preference;
 
-    let mut all_outbounds = network_interface::NetworkInterface::show()
-        .ok()?
+    let mut all_outbounds = list_interfaces()?
         .into_iter()
         .map(Into::into)
         .filter(|iface: &OutboundInterface| {
-            !iface.name.contains("tun")
-                && (iface.addr_v4.is_some() || iface.addr_v6.is_some())
+            !iface.name.contains("tun") && allowed_by_strategy(iface, strategy)
         })
         .collect::<Vec<_>>();
 
-    let priority = [
-        "eth",
-        "en",
-        "pdp_ip",
-        "WLAN",
-        "wlp",
-        "Ethernet",
-        "vEthernet",
-        "Wi-Fi",
-        "Tailscale",
-    ];
-
     all_outbounds.sort_by(|left, right| {
-        match (left.addr_v6, right.addr_v6) {
-            (Some(_), None) => return std::cmp::Ordering::Less,
-            (None, Some(_)) => return std::cmp::Ordering::Greater,
-            (Some(left), Some(right)) => {
-                if left.is_unicast_global() && !right.is_unicast_global() {
-                    return std::cmp::Ordering::Less;
-                } else if !left.is_unicast_global() && right.is_unicast_global() {
-                    return std::cmp::Ordering::Greater;
-                }
-            }
-            _ => {}
-        }
-        let left = priority
-            .iter()
-            .position(|x| left.name.contains(x))
-            .unwrap_or(usize::MAX);
-        let right = priority
-            .iter()
-            .position(|x| right.name.contains(x))
-            .unwrap_or(usize::MAX);
-
-        left.cmp(&right)
+        compare_families(left, right, strategy).then_with(|| {
+            let left = interface_rank(&left.name, priority).unwrap_or(usize::MAX);
+            let right = interface_rank(&right.name, priority).unwrap_or(usize::MAX);
+            left.cmp(&right)
+        })
     });
 
     trace!(
@@ -186,16 +739,107 @@ pub fn get_outbound_interface() -> Option<OutboundInterface> {
         now.elapsed().as_millis()
     );
 
+    if let (Some(priority), Some(first)) = (priority, all_outbounds.first()) {
+        match interface_rank(&first.name, Some(priority)) {
+            Some(i) => trace!(
+                "outbound interface {} matched interface-priority {:?}",
+                first.name, priority[i]
+            ),
+            None => trace!(
+                "outbound interface {} matched no interface-priority entry",
+                first.name
+            ),
+        }
+    }
+
     all_outbounds.into_iter().next()
 }
 
+// IPv6 address flags, see `IFA_F_*` in linux/if_addr.h
+const IFA_F_TEMPORARY: u32 = 0x01;
+const IFA_F_DADFAILED: u32 = 0x08;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_TENTATIVE: u32 = 0x40;
+
+/// How suitable an IPv6 address is as the source of new connections, higher
+/// is better, `None` if it must not be used at all.
+///
+/// Preferred addresses come before deprecated ones, which still work for
+/// existing sockets but may fail to bind once their lifetime runs out. Among
+/// those, global addresses come before unique local ones, and temporary
+/// (privacy extension) addresses before stable ones.
+fn rank_ipv6_addr(ip: &Ipv6Addr, flags: u32) -> Option<(bool, bool, bool)> {
+    if !(ip.is_unique_local() || ip.is_global())
+        || flags & (IFA_F_TENTATIVE | IFA_F_DADFAILED) != 0
+    {
+        return None;
+    }
+    Some((
+        flags & IFA_F_DEPRECATED == 0,
+        ip.is_global(),
+        flags & IFA_F_TEMPORARY != 0,
+    ))
+}
+
+/// Flags of the IPv6 addresses on `iface`, as listed in `/proc/net/if_inet6`.
+#[cfg(target_os = "linux")]
+fn ipv6_addr_flags(iface: &str) -> HashMap<Ipv6Addr, u32> {
+    std::fs::read_to_string("/proc/net/if_inet6")
+        .map(|content| parse_if_inet6(&content, iface))
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn ipv6_addr_flags(_iface: &str) -> HashMap<Ipv6Addr, u32> {
+    HashMap::new()
+}
+
+/// Each line is `address ifindex prefixlen scope flags name`, all but the
+/// name in hex.
+#[cfg(target_os = "linux")]
+fn parse_if_inet6(content: &str, iface: &str) -> HashMap<Ipv6Addr, u32> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() != 6 || fields[5] != iface {
+                return None;
+            }
+            let addr = u128::from_str_radix(fields[0], 16).ok()?;
+            let flags = u32::from_str_radix(fields[4], 16).ok()?;
+            Some((Ipv6Addr::from(addr), flags))
+        })
+        .collect()
+}
+
 /// Represents a network interface in configuration.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Interface {
     IpAddr(IpAddr),
+    /// An IPv6 address with its zone, e.g. `fe80::1%eth0` or `fe80::1%2`,
+    /// the name or index of the interface a link-local address is on
+    ScopedIpAddr(Ipv6Addr, String),
     Name(String),
 }
 
+impl FromStr for Interface {
+    type Err = std::convert::Infallible;
+
+    /// An address if it parses as one, a name otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self::IpAddr(ip));
+        }
+        Ok(match s.split_once('%') {
+            Some((ip, zone)) if !zone.is_empty() => match ip.parse::<Ipv6Addr>() {
+                Ok(ip) => Self::ScopedIpAddr(ip, zone.to_owned()),
+                Err(_) => Self::Name(s.to_owned()),
+            },
+            _ => Self::Name(s.to_owned()),
+        })
+    }
+}
+
 impl From<&str> for Interface {
     fn from(s: &str) -> Self {
         Self::Name(s.to_owned())
@@ -212,6 +856,7 @@ impl Display for Interface {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Interface::IpAddr(ip) => write!(f, "{ip}"),
+            Interface::ScopedIpAddr(ip, zone) => write!(f, "{ip}%{zone}"),
             Interface::Name(name) => write!(f, "{name}"),
         }
     }
@@ -221,6 +866,7 @@ impl Interface {
     pub fn into_ip_addr(self) -> Option<IpAddr> {
         match self {
             Interface::IpAddr(ip) => Some(ip),
+            Interface::ScopedIpAddr(ip, _) => Some(ip.into()),
             _ => None,
         }
     }
@@ -228,14 +874,229 @@ impl Interface {
     pub fn into_socket_addr(self) -> Option<SocketAddr> {
         match self {
             Interface::IpAddr(ip) => Some(SocketAddr::new(ip, 0)),
+            Interface::ScopedIpAddr(ip, zone) => {
+                let scope_id = zone.parse().ok().or_else(|| {
+                    get_interface_by_name(&zone).map(|iface| iface.index)
+                })?;
+                Some(SocketAddrV6::new(ip, 0, 0, scope_id).into())
+            }
             _ => None,
         }
     }
 
     pub fn into_iface_name(self) -> Option<String> {
         match self {
-            Interface::IpAddr(_) => None,
+            Interface::IpAddr(_) | Interface::ScopedIpAddr(..) => None,
             Interface::Name(name) => Some(name),
         }
     }
+
+    /// Looks up the interface by name, or the one that owns the address.
+    /// With `freebind`, an address no interface has resolves to a
+    /// [`freebind_source`].
+    pub fn resolve(&self) -> Option<OutboundInterface> {
+        match self {
+            Interface::IpAddr(ip) => get_interface_by_ip(*ip).or_else(|| {
+                (cfg!(target_os = "linux") && outbound_freebind())
+                    .then(|| freebind_source(*ip))
+            }),
+            Interface::ScopedIpAddr(ip, zone) => {
+                get_interface_by_scoped_ip((*ip).into(), Some(zone))
+            }
+            Interface::Name(name) => get_interface_by_name(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv6Addr, time::Duration};
+
+    use super::{
+        IFA_F_DEPRECATED, IFA_F_TEMPORARY, IFA_F_TENTATIVE, INTERFACE_WATCHER,
+        Interface, OutboundInterface, allowed_by_strategy, compare_families,
+        get_outbound_interface, interface_rank, is_bogon, rank_ipv6_addr,
+        same_interface, subscribe_interface_changes, watch_outbound_interface,
+    };
+    use crate::config::def::IpStrategy;
+
+    fn iface(v4: Option<&str>, v6: Option<&str>) -> OutboundInterface {
+        OutboundInterface {
+            name: "eth0".to_owned(),
+            addr_v4: v4.map(|x| x.parse().unwrap()),
+            netmask_v4: None,
+            broadcast_v4: None,
+            addr_v6: v6.map(|x| x.parse().unwrap()),
+            netmask_v6: None,
+            broadcast_v6: None,
+            index: 1,
+            mac_addr: None,
+            bind_addr: None,
+            by_name: false,
+            freebind: false,
+        }
+    }
+
+    #[test]
+    fn test_ip_strategy() {
+        use std::cmp::Ordering::*;
+
+        let v4 = iface(Some("192.168.1.2"), None);
+        let global_v6 = iface(None, Some("2400:cb00::1"));
+        let ula_v6 = iface(Some("10.0.0.2"), Some("fd00::1"));
+
+        assert_eq!(
+            compare_families(&global_v6, &ula_v6, IpStrategy::PreferIpv6),
+            Less
+        );
+        assert_eq!(compare_families(&ula_v6, &v4, IpStrategy::PreferIpv6), Less);
+        assert_eq!(
+            compare_families(&v4, &global_v6, IpStrategy::PreferIpv4),
+            Less
+        );
+        assert_eq!(
+            compare_families(&v4, &ula_v6, IpStrategy::PreferIpv4),
+            Equal
+        );
+
+        assert!(!allowed_by_strategy(&global_v6, IpStrategy::Ipv4Only));
+        assert!(allowed_by_strategy(&ula_v6, IpStrategy::Ipv4Only));
+        assert!(!allowed_by_strategy(&v4, IpStrategy::Ipv6Only));
+        assert!(!allowed_by_strategy(
+            &iface(None, None),
+            IpStrategy::PreferIpv6
+        ));
+    }
+
+    #[test]
+    fn test_parse_scoped_interface() {
+        let iface = "fe80::1%eth0".parse::<Interface>().unwrap();
+        assert!(matches!(
+            &iface,
+            Interface::ScopedIpAddr(ip, zone)
+                if *ip == "fe80::1".parse::<Ipv6Addr>().unwrap() && zone == "eth0"
+        ));
+        assert_eq!(iface.to_string(), "fe80::1%eth0");
+        assert_eq!(
+            "fe80::1%2".parse::<Interface>().unwrap().into_socket_addr(),
+            Some("[fe80::1%2]:0".parse().unwrap())
+        );
+
+        for name in ["eth0", "192.168.1.1%eth0", "fe80::1%"] {
+            assert!(matches!(
+                name.parse::<Interface>().unwrap(),
+                Interface::Name(x) if x == name
+            ));
+        }
+    }
+
+    #[test]
+    fn test_same_interface() {
+        let v4 = iface(Some("192.168.1.2"), None);
+        assert!(same_interface(Some(&v4), Some(&v4.clone())));
+        assert!(same_interface(None, None));
+        assert!(!same_interface(Some(&v4), None));
+        // the address changed, e.g. a new DHCP lease
+        let renewed = iface(Some("192.168.1.3"), None);
+        assert!(!same_interface(Some(&v4), Some(&renewed)));
+    }
+
+    #[tokio::test]
+    async fn test_watch_outbound_interface() {
+        let mut rx = subscribe_interface_changes();
+        watch_outbound_interface(Some(Duration::from_millis(10)));
+        // the first look is a change unless no interface is found at all
+        if get_outbound_interface().is_some() {
+            tokio::time::timeout(Duration::from_secs(1), rx.changed())
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        watch_outbound_interface(None);
+        assert!(INTERFACE_WATCHER.lock().unwrap().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_interface_by_name() {
+        let lo = Interface::Name("lo".to_owned()).resolve().unwrap();
+        assert!(lo.by_name);
+        let lo = Interface::IpAddr("127.0.0.1".parse().unwrap())
+            .resolve()
+            .unwrap();
+        assert!(!lo.by_name);
+    }
+
+    #[test]
+    fn test_interface_rank() {
+        assert_eq!(interface_rank("eth0", None), Some(0));
+        assert_eq!(interface_rank("pdp_ip0", None), Some(2));
+        assert_eq!(interface_rank("docker0", None), None);
+
+        let priority = vec!["usb".to_owned(), "en".to_owned(), "pdp_ip".to_owned()];
+        assert_eq!(interface_rank("usb0", Some(priority.as_slice())), Some(0));
+        assert_eq!(
+            interface_rank("pdp_ip0", Some(priority.as_slice())),
+            Some(2)
+        );
+        assert_eq!(interface_rank("eth0", Some(priority.as_slice())), None);
+        assert_eq!(interface_rank("eth0", Some(&[][..])), None);
+    }
+
+    #[test]
+    fn test_is_bogon() {
+        for ip in [
+            "0.1.2.3",
+            "240.0.0.1",
+            "255.255.255.255",
+            "192.0.2.1",
+            "::",
+            "2001:db8::1",
+            "::ffff:0.0.0.1",
+        ] {
+            assert!(is_bogon(&ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["1.1.1.1", "10.0.0.1", "127.0.0.1", "::1", "2400:cb00::1"] {
+            assert!(!is_bogon(&ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_rank_ipv6_addr() {
+        let global: Ipv6Addr = "2400:cb00::1".parse().unwrap();
+        let ula: Ipv6Addr = "fd00::1".parse().unwrap();
+        let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+
+        assert!(rank_ipv6_addr(&link_local, 0).is_none());
+        assert!(rank_ipv6_addr(&global, IFA_F_TENTATIVE).is_none());
+
+        assert!(rank_ipv6_addr(&global, 0) > rank_ipv6_addr(&ula, 0));
+        assert!(
+            rank_ipv6_addr(&global, IFA_F_TEMPORARY) > rank_ipv6_addr(&global, 0)
+        );
+        assert!(
+            rank_ipv6_addr(&ula, 0)
+                > rank_ipv6_addr(&global, IFA_F_TEMPORARY | IFA_F_DEPRECATED)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_if_inet6() {
+        let content = "\
+20010db8000100000000000000000001 02 40 00 80     eth0
+20010db80001000051a2b3c4d5e6f708 02 40 00 a1     eth0
+fe800000000000000000000000000001 02 40 20 80     eth0
+00000000000000000000000000000001 01 80 10 80       lo
+";
+        let flags = super::parse_if_inet6(content, "eth0");
+        assert_eq!(flags.len(), 3);
+        assert_eq!(
+            flags[&"2001:db8:1:0:51a2:b3c4:d5e6:f708"
+                .parse::<Ipv6Addr>()
+                .unwrap()],
+            IFA_F_TEMPORARY | IFA_F_DEPRECATED | 0x80
+        );
+        assert_eq!(flags[&"2001:db8:1::1".parse::<Ipv6Addr>().unwrap()], 0x80);
+    }
 }
@@ -0,0 +1,253 @@
+//! Notifications of changes to the interfaces, addresses and routes of the
+//! host: netlink on Linux, a routing socket on macOS, and the IP Helper
+//! change notifications on Windows.
+
+use tokio::sync::mpsc;
+
+/// Receives one message per change notification, or `None` where the
+/// platform has no such notifications, in which case the interfaces can only
+/// be polled. Dropping the receiver stops listening.
+pub(super) fn route_changes() -> Option<mpsc::UnboundedReceiver<()>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    match listen(tx) {
+        Ok(true) => Some(rx),
+        Ok(false) => None,
+        Err(e) => {
+            tracing::warn!("failed to listen for network changes: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn listen(tx: mpsc::UnboundedSender<()>) -> std::io::Result<bool> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    use tokio::io::unix::AsyncFd;
+
+    let fd = unsafe { OwnedFd::from_raw_fd(open_route_socket()?) };
+    let fd = AsyncFd::new(fd)?;
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let rv = tokio::select! {
+                guard = fd.readable() => {
+                    let Ok(mut guard) = guard else { return };
+                    guard.try_io(|fd| read_messages(fd.get_ref(), &mut buf))
+                }
+                _ = tx.closed() => return,
+            };
+            match rv {
+                Ok(Ok(_)) => {
+                    if tx.send(()).is_err() {
+                        return;
+                    }
+                }
+                Ok(Err(e)) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                // overrun by a burst, the next look sees the result anyway
+                Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                    if tx.send(()).is_err() {
+                        return;
+                    }
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!("stopped listening for network changes: {e}");
+                    return;
+                }
+                // not readable after all
+                Err(_) => {}
+            }
+        }
+    });
+    Ok(true)
+}
+
+/// A non-blocking netlink socket in the groups of link, address and route
+/// changes
+#[cfg(target_os = "linux")]
+fn open_route_socket() -> std::io::Result<std::os::fd::RawFd> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = (libc::RTMGRP_LINK
+        | libc::RTMGRP_IPV4_IFADDR
+        | libc::RTMGRP_IPV6_IFADDR
+        | libc::RTMGRP_IPV4_ROUTE
+        | libc::RTMGRP_IPV6_ROUTE) as u32;
+    let rv = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if rv != 0 {
+        let e = std::io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+/// A non-blocking routing socket, which gets every change to the routing
+/// table and the interface addresses
+#[cfg(target_os = "macos")]
+fn open_route_socket() -> std::io::Result<std::os::fd::RawFd> {
+    let fd =
+        unsafe { libc::socket(libc::PF_ROUTE, libc::SOCK_RAW, libc::AF_UNSPEC) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let rv = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC)
+    };
+    if rv != 0 {
+        let e = std::io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+    Ok(fd)
+}
+
+/// Reads all pending messages, their content doesn't matter, as the
+/// interfaces are looked at again anyway
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn read_messages(
+    fd: &std::os::fd::OwnedFd,
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    use std::os::fd::AsRawFd;
+
+    let mut read = 0;
+    loop {
+        let n = unsafe {
+            libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0)
+        };
+        match n {
+            n if n > 0 => read += n as usize,
+            0 => return Ok(read),
+            _ => {
+                let e = std::io::Error::last_os_error();
+                return match e.kind() {
+                    std::io::ErrorKind::WouldBlock if read > 0 => Ok(read),
+                    _ => Err(e),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn listen(tx: mpsc::UnboundedSender<()>) -> std::io::Result<bool> {
+    use std::ffi::c_void;
+
+    use windows::Win32::{
+        Foundation::HANDLE,
+        NetworkManagement::IpHelper::{
+            CancelMibChangeNotify2, MIB_IPFORWARD_ROW2, MIB_NOTIFICATION_TYPE,
+            MIB_UNICASTIPADDRESS_ROW, NotifyRouteChange2,
+            NotifyUnicastIpAddressChange,
+        },
+        Networking::WinSock::{ADDRESS_FAMILY, AF_UNSPEC},
+    };
+
+    unsafe extern "system" fn on_route_change(
+        context: *const c_void,
+        _row: *const MIB_IPFORWARD_ROW2,
+        _kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        let tx = unsafe { &*(context as *const mpsc::UnboundedSender<()>) };
+        let _ = tx.send(());
+    }
+
+    unsafe extern "system" fn on_address_change(
+        context: *const c_void,
+        _row: *const MIB_UNICASTIPADDRESS_ROW,
+        _kind: MIB_NOTIFICATION_TYPE,
+    ) {
+        let tx = unsafe { &*(context as *const mpsc::UnboundedSender<()>) };
+        let _ = tx.send(());
+    }
+
+    // owned by the callbacks until both are cancelled
+    let context = Box::into_raw(Box::new(tx.clone()));
+    let family = ADDRESS_FAMILY(AF_UNSPEC.0);
+    let mut route_handle = HANDLE::default();
+    let mut address_handle = HANDLE::default();
+    let registered = unsafe {
+        NotifyRouteChange2(
+            family,
+            Some(on_route_change),
+            Some(context as *const c_void),
+            false,
+            &mut route_handle,
+        )
+        .ok()
+        .and_then(|_| {
+            NotifyUnicastIpAddressChange(
+                family,
+                Some(on_address_change),
+                Some(context as *const c_void),
+                false,
+                &mut address_handle,
+            )
+            .ok()
+        })
+    };
+    if let Err(e) = registered {
+        unsafe {
+            if !route_handle.is_invalid() {
+                let _ = CancelMibChangeNotify2(route_handle);
+            }
+            drop(Box::from_raw(context));
+        }
+        return Err(std::io::Error::other(e));
+    }
+
+    // the handles and the context aren't Send, they're kept as integers
+    let (route_handle, address_handle, context) = (
+        route_handle.0 as usize,
+        address_handle.0 as usize,
+        context as usize,
+    );
+    tokio::spawn(async move {
+        tx.closed().await;
+        unsafe {
+            let _ = CancelMibChangeNotify2(HANDLE(route_handle as *mut c_void));
+            let _ = CancelMibChangeNotify2(HANDLE(address_handle as *mut c_void));
+            // no callback runs once cancelled
+            drop(Box::from_raw(context as *mut mpsc::UnboundedSender<()>));
+        }
+    });
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn listen(_tx: mpsc::UnboundedSender<()>) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::route_changes;
+
+    #[tokio::test]
+    async fn test_route_changes() {
+        let rx = route_changes();
+        assert!(rx.is_some());
+        // stops listening
+        drop(rx);
+    }
+}
@@ -1,4 +1,8 @@
 use super::utils::proxy_groups_dag_sort;
+#[cfg(feature = "hysteria2")]
+use crate::proxy::hysteria2;
+#[cfg(all(unix, feature = "icmp"))]
+use crate::proxy::icmp;
 #[cfg(feature = "shadowquic")]
 use crate::proxy::shadowquic;
 #[cfg(feature = "shadowsocks")]
@@ -17,19 +21,22 @@ use crate::{
         dns::ThreadSafeDNSResolver,
         profile::ThreadSafeCacheFile,
         remote_content_manager::{
-            ProxyManager,
+            ProxyManager, UdpQuality,
             healthcheck::HealthCheck,
             providers::{
-                ProviderVehicleType, file_vehicle, http_vehicle,
+                ProviderVehicleType, ThreadSafeProviderVehicle, dir_vehicle,
+                file_vehicle, http_vehicle,
                 proxy_provider::{
                     PlainProvider, ProxySetProvider, ThreadSafeProxyProvider,
                 },
             },
         },
     },
+    common::{mmdb::MmdbLookup, utils::proxy_name_eq},
     config::internal::proxy::{
-        OutboundGroupProtocol, OutboundProxyProtocol, OutboundProxyProviderDef,
-        PROXY_DIRECT, PROXY_GLOBAL, PROXY_REJECT,
+        HealthCheckMethod, LoadBalanceHashKey, OutboundGroupProtocol,
+        OutboundProxyProtocol, OutboundProxyProviderDef, PROXY_DIRECT, PROXY_GLOBAL,
+        PROXY_REJECT,
     },
     print_and_exit,
     proxy::{
@@ -37,17 +44,19 @@ use crate::{
         direct::{self},
         fallback,
         group::smart,
-        hysteria2, loadbalance, reject, relay,
+        loadbalance, reject, relay,
         selector::{self, ThreadSafeSelectorControl},
         socks, trojan, urltest,
-        utils::{DirectConnector, ProxyConnector},
+        utils::{DirectConnector, ProxyConnector, RetryPolicy},
         vless, vmess,
     },
 };
 use anyhow::Result;
 use erased_serde::Serialize;
 use hyper::Uri;
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration,
+};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
@@ -63,6 +72,7 @@ pub struct OutboundManager {
 }
 
 static DEFAULT_LATENCY_TEST_URL: &str = "http://www.gstatic.com/generate_204";
+static DEFAULT_IP_ECHO_URL: &str = "https://api.ipify.org";
 
 pub type ThreadSafeOutboundManager = Arc<OutboundManager>;
 
@@ -89,10 +99,12 @@ impl OutboundManager {
         outbound_groups: Vec<OutboundGroupProtocol>,
         proxy_providers: HashMap<String, OutboundProxyProviderDef>,
         proxy_names: Vec<String>,
+        global_default: Option<String>,
         dns_resolver: ThreadSafeDNSResolver,
         cache_store: ThreadSafeCacheFile,
         cwd: String,
         fw_mark: Option<u32>,
+        country_mmdb: Option<MmdbLookup>,
     ) -> Result<Self, Error> {
         let handlers = HashMap::new();
         let provider_registry = HashMap::new();
@@ -111,8 +123,15 @@ impl OutboundManager {
             .await?;
 
         debug!("initializing handlers");
-        m.load_handlers(outbounds, outbound_groups, proxy_names, cache_store)
-            .await?;
+        m.load_handlers(
+            outbounds,
+            outbound_groups,
+            proxy_names,
+            global_default,
+            cache_store,
+            country_mmdb,
+        )
+        .await?;
 
         debug!("initializing connectors");
         m.init_handler_connectors().await?;
@@ -124,6 +143,17 @@ impl OutboundManager {
         self.handlers.get(name).cloned()
     }
 
+    /// Like `get_outbound`, for a name from the API which may not be spelled
+    /// exactly like the configured one, see `proxy_name_eq`
+    pub fn find_outbound(&self, name: &str) -> Option<AnyOutboundHandler> {
+        self.get_outbound(name).or_else(|| {
+            self.handlers
+                .iter()
+                .find(|(k, _)| proxy_name_eq(k, name))
+                .map(|(_, v)| v.clone())
+        })
+    }
+
     /// this doesn't populate history/liveness information
     pub fn get_proxy_provider(&self, name: &str) -> Option<ThreadSafeProxyProvider> {
         self.proxy_providers.get(name).cloned()
@@ -154,12 +184,16 @@ impl OutboundManager {
 
             let alive = proxy_manager.alive(k).await;
             let history = proxy_manager.delay_history(k).await;
-            let support_udp = v.support_udp().await;
+            let support_udp = v.support_udp().await
+                || proxy_manager.observed_udp(k).await == Some(true);
 
             m.insert("history".to_string(), Box::new(history));
             m.insert("alive".to_string(), Box::new(alive));
             m.insert("name".to_string(), Box::new(k.to_owned()));
             m.insert("udp".to_string(), Box::new(support_udp));
+            if let Some(q) = proxy_manager.udp_quality(k).await {
+                m.insert("udpQuality".to_string(), Box::new(q));
+            }
 
             r.insert(k.clone(), Box::new(m) as _);
         }
@@ -183,12 +217,16 @@ impl OutboundManager {
 
         let alive = proxy_manager.alive(proxy.name()).await;
         let history = proxy_manager.delay_history(proxy.name()).await;
-        let support_udp = proxy.support_udp().await;
+        let support_udp = proxy.support_udp().await
+            || proxy_manager.observed_udp(proxy.name()).await == Some(true);
 
         r.insert("history".to_string(), Box::new(history));
         r.insert("alive".to_string(), Box::new(alive));
         r.insert("name".to_string(), Box::new(proxy.name().to_owned()));
         r.insert("udp".to_string(), Box::new(support_udp));
+        if let Some(q) = proxy_manager.udp_quality(proxy.name()).await {
+            r.insert("udpQuality".to_string(), Box::new(q));
+        }
 
         r
     }
@@ -201,7 +239,32 @@ impl OutboundManager {
         timeout: Duration,
     ) -> Vec<std::io::Result<(Duration, Duration)>> {
         let proxy_manager = self.proxy_manager.clone();
-        proxy_manager.check(outbounds, url, Some(timeout)).await
+        proxy_manager
+            .check(outbounds, url, HealthCheckMethod::default(), Some(timeout))
+            .await
+    }
+
+    /// a wrapper of proxy_manager.udp_test so that proxy_manager is not exposed
+    pub async fn udp_test(
+        &self,
+        outbound: AnyOutboundHandler,
+        server: SocketAddr,
+        count: u16,
+        timeout: Duration,
+    ) -> std::io::Result<UdpQuality> {
+        self.proxy_manager
+            .udp_test(outbound, server, count, Some(timeout))
+            .await
+    }
+
+    /// UDP support of `name` as observed by optimistic attempts, see
+    /// `udp-fallback`
+    pub async fn observed_udp(&self, name: &str) -> Option<bool> {
+        self.proxy_manager.observed_udp(name).await
+    }
+
+    pub async fn report_udp(&self, name: &str, supported: bool) {
+        self.proxy_manager.report_udp(name, supported).await
     }
 
     pub fn get_proxy_providers(&self) -> HashMap<String, ThreadSafeProxyProvider> {
@@ -299,6 +362,7 @@ impl OutboundManager {
                         })
                         .ok()
                 }
+                #[cfg(feature = "hysteria2")]
                 OutboundProxyProtocol::Hysteria2(h) => {
                     let name = h.name.clone();
                     h.try_into()
@@ -324,6 +388,16 @@ impl OutboundManager {
                         })
                         .ok()
                 }
+                #[cfg(all(unix, feature = "icmp"))]
+                OutboundProxyProtocol::Icmp(icmp) => {
+                    let name = icmp.name.clone();
+                    icmp.try_into()
+                        .map(|x: icmp::Handler| Arc::new(x) as _)
+                        .inspect_err(|e| {
+                            error!("failed to load icmp outbound {}: {}", name, e);
+                        })
+                        .ok()
+                }
                 #[cfg(feature = "ssh")]
                 OutboundProxyProtocol::Ssh(ssh) => {
                     let name = ssh.common_opts.name.clone();
@@ -370,6 +444,17 @@ impl OutboundManager {
                         })
                         .ok()
                 }
+                #[cfg(feature = "plugin")]
+                OutboundProxyProtocol::Plugin(p) => {
+                    crate::proxy::plugin::build_outbound(&p.proto, p.config)
+                        .inspect_err(|e| {
+                            error!(
+                                "failed to load {} outbound {}: {}",
+                                p.proto, p.name, e
+                            );
+                        })
+                        .ok()
+                }
             })
             .collect()
     }
@@ -384,15 +469,21 @@ impl OutboundManager {
         outbounds: Vec<AnyOutboundHandler>,
         outbound_groups: Vec<OutboundGroupProtocol>,
         proxy_names: Vec<String>,
+        global_default: Option<String>,
         cache_store: ThreadSafeCacheFile,
+        country_mmdb: Option<MmdbLookup>,
     ) -> Result<(), Error> {
         self.handlers.extend(outbounds.into_iter().map(|h| {
             let name = h.name().to_owned();
             (name, h)
         }));
 
-        self.load_group_outbounds(outbound_groups, cache_store.clone())
-            .await?;
+        self.load_group_outbounds(
+            outbound_groups,
+            cache_store.clone(),
+            country_mmdb,
+        )
+        .await?;
 
         // insert GLOBAL
         let mut g = vec![];
@@ -420,7 +511,10 @@ impl OutboundManager {
             hc,
         )?));
 
-        let stored_selection = cache_store.get_selected(PROXY_GLOBAL).await;
+        let stored_selection = cache_store
+            .get_selected(PROXY_GLOBAL)
+            .await
+            .or(global_default);
         let mut providers: Vec<ThreadSafeProxyProvider> = vec![pd.clone()];
         for p in self.proxy_providers.values() {
             let vehicle_type = p.read().await.vehicle_type();
@@ -460,6 +554,7 @@ impl OutboundManager {
         &mut self,
         outbound_groups: Vec<OutboundGroupProtocol>,
         cache_store: ThreadSafeCacheFile,
+        country_mmdb: Option<MmdbLookup>,
     ) -> Result<(), Error> {
         // Sort outbound groups to ensure dependencies are resolved
         let mut outbound_groups = outbound_groups;
@@ -476,6 +571,8 @@ impl OutboundManager {
             proxies: &[String],
             interval: u64,
             lazy: bool,
+            method: HealthCheckMethod,
+            udp_server: Option<SocketAddr>,
             handlers: &HashMap<String, AnyOutboundHandler>,
             proxy_manager: ProxyManager,
             provider_registry: &mut HashMap<String, ThreadSafeProxyProvider>,
@@ -503,7 +600,9 @@ impl OutboundManager {
                 interval,
                 lazy,
                 proxy_manager.clone(),
-            );
+            )
+            .with_method(method)
+            .with_udp_server(udp_server);
 
             let pd = Arc::new(RwLock::new(
                 PlainProvider::new(name.to_owned(), proxies, hc).map_err(|x| {
@@ -563,6 +662,8 @@ impl OutboundManager {
                             proxies,
                             0,
                             true,
+                            HealthCheckMethod::default(),
+                            None,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -582,6 +683,10 @@ impl OutboundManager {
                                 icon: proto.icon.clone(),
                                 url: proto.url.clone(),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
                         },
                         providers,
@@ -604,6 +709,8 @@ impl OutboundManager {
                             proxies,
                             proto.interval,
                             proto.lazy.unwrap_or_default(),
+                            proto.health_check_method.unwrap_or_default(),
+                            proto.health_check_udp_server,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -616,13 +723,17 @@ impl OutboundManager {
                         &mut providers,
                     );
 
-                    let url_test = urltest::Handler::new(
+                    let mut url_test = urltest::Handler::new(
                         urltest::HandlerOptions {
                             name: proto.name.clone(),
                             common_opts: crate::proxy::HandlerCommonOptions {
                                 icon: proto.icon.clone(),
                                 url: Some(proto.url.clone()),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
                             ..Default::default()
                         },
@@ -631,6 +742,26 @@ impl OutboundManager {
                         proxy_manager.clone(),
                     );
 
+                    if let Some(country) = &proto.expected_country {
+                        let mmdb = country_mmdb.clone().ok_or_else(|| {
+                            Error::InvalidConfig(format!(
+                                "proxy group {}: expected-country requires the \
+                                 country mmdb",
+                                proto.name
+                            ))
+                        })?;
+                        url_test =
+                            url_test.expected_country(urltest::ExpectedCountry {
+                                country: country.clone(),
+                                ip_echo_url: proto
+                                    .ip_echo_url
+                                    .clone()
+                                    .unwrap_or(DEFAULT_IP_ECHO_URL.to_owned()),
+                                interval: Duration::from_secs(proto.interval),
+                                mmdb,
+                            });
+                    }
+
                     handlers.insert(proto.name.clone(), Arc::new(url_test));
                 }
                 OutboundGroupProtocol::Fallback(proto) => {
@@ -648,6 +779,8 @@ impl OutboundManager {
                             proxies,
                             proto.interval,
                             proto.lazy.unwrap_or_default(),
+                            proto.health_check_method.unwrap_or_default(),
+                            proto.health_check_udp_server,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -660,6 +793,15 @@ impl OutboundManager {
                         &mut providers,
                     );
 
+                    let retry_policy =
+                        RetryPolicy::new(proto.retry_on.clone().unwrap_or_default())
+                            .map_err(|e| {
+                                Error::InvalidConfig(format!(
+                                    "proxy group {}: invalid retry-on: {e}",
+                                    proto.name
+                                ))
+                            })?;
+
                     let fallback = fallback::Handler::new(
                         fallback::HandlerOptions {
                             name: proto.name.clone(),
@@ -667,7 +809,12 @@ impl OutboundManager {
                                 icon: proto.icon.clone(),
                                 url: Some(proto.url.clone()),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
+                            retry_policy,
                             ..Default::default()
                         },
                         providers,
@@ -692,6 +839,8 @@ impl OutboundManager {
                             proxies,
                             proto.interval,
                             proto.lazy.unwrap_or_default(),
+                            proto.health_check_method.unwrap_or_default(),
+                            proto.health_check_udp_server,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -711,7 +860,23 @@ impl OutboundManager {
                                 icon: proto.icon.clone(),
                                 url: Some(proto.url.clone()),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
+                            strategy: proto.strategy.unwrap_or_default(),
+                            hash_key: proto.hash_key.unwrap_or_default(),
+                            affinity: proto.affinity_ttl.filter(|x| *x > 0).map(
+                                |ttl| {
+                                    (
+                                        proto
+                                            .affinity_key
+                                            .unwrap_or(LoadBalanceHashKey::Host),
+                                        Duration::from_secs(ttl),
+                                    )
+                                },
+                            ),
                             ..Default::default()
                         },
                         providers,
@@ -736,6 +901,8 @@ impl OutboundManager {
                             proxies,
                             0,
                             true,
+                            HealthCheckMethod::default(),
+                            None,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -759,6 +926,10 @@ impl OutboundManager {
                                 icon: proto.icon.clone(),
                                 url: proto.url.clone(),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
                         },
                         providers,
@@ -785,6 +956,8 @@ impl OutboundManager {
                             proxies,
                             0,
                             proto.lazy.unwrap_or_default(),
+                            HealthCheckMethod::default(),
+                            None,
                             handlers,
                             proxy_manager.clone(),
                             provider_registry,
@@ -804,6 +977,10 @@ impl OutboundManager {
                                 icon: proto.icon.clone(),
                                 url: proto.url.clone(),
                                 connector: None,
+                                interface_name: proto.interface_name.clone(),
+                                routing_mark: proto.routing_mark,
+                                dscp: proto.dscp,
+                                ..Default::default()
                             },
                             udp: proto.udp.unwrap_or(true),
                             max_retries: proto.max_retries,
@@ -847,40 +1024,61 @@ impl OutboundManager {
                         http.health_check.interval,
                         http.health_check.lazy.unwrap_or_default(),
                         proxy_manager.clone(),
-                    );
+                    )
+                    .with_method(http.health_check.method)
+                    .with_udp_server(http.health_check.udp_server);
 
                     let provider = ProxySetProvider::new(
                         name.clone(),
                         Duration::from_secs(http.interval),
                         Arc::new(vehicle),
                         hc,
+                        http.proxy_override,
                     )
                     .map_err(|x| {
                         Error::InvalidConfig(format!("invalid provider config: {x}"))
-                    })?;
+                    })?
+                    .with_retry(http.retry.into());
 
                     provider_registry.insert(name, Arc::new(RwLock::new(provider)));
                 }
                 OutboundProxyProviderDef::File(file) => {
-                    let vehicle = file_vehicle::Vehicle::new(
-                        PathBuf::from(cwd.clone())
-                            .join(&file.path)
-                            .to_str()
-                            .unwrap(),
-                    );
+                    let path = PathBuf::from(cwd.clone()).join(&file.path);
+                    let (vehicle, interval): (ThreadSafeProviderVehicle, _) = if path
+                        .is_dir()
+                    {
+                        (
+                            Arc::new(dir_vehicle::Vehicle::new(
+                                path.to_str().unwrap(),
+                            )),
+                            file.interval
+                                .map(Duration::from_secs)
+                                .unwrap_or(dir_vehicle::WATCH_INTERVAL),
+                        )
+                    } else {
+                        (
+                            Arc::new(file_vehicle::Vehicle::new(
+                                path.to_str().unwrap(),
+                            )),
+                            Duration::from_secs(file.interval.unwrap_or_default()),
+                        )
+                    };
                     let hc = HealthCheck::new(
                         vec![],
                         file.health_check.url,
                         file.health_check.interval,
                         file.health_check.lazy.unwrap_or_default(),
                         proxy_manager.clone(),
-                    );
+                    )
+                    .with_method(file.health_check.method)
+                    .with_udp_server(file.health_check.udp_server);
 
                     let provider = ProxySetProvider::new(
                         name.clone(),
-                        Duration::from_secs(file.interval.unwrap_or_default()),
-                        Arc::new(vehicle),
+                        interval,
+                        vehicle,
                         hc,
+                        file.proxy_override,
                     )
                     .map_err(|x| {
                         Error::InvalidConfig(format!("invalid provider config: {x}"))
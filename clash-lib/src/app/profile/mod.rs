@@ -3,7 +3,9 @@ use std::{collections::HashMap, sync::Arc};
 use serde::{Deserialize, Serialize};
 use tracing::{error, trace, warn};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::app::dispatcher::InterfaceTrafficSnapshot;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct Db {
     #[serde(default)]
     selected: HashMap<String, String>,
@@ -15,13 +17,20 @@ struct Db {
     smart_stats: HashMap<String, crate::proxy::group::smart::state::SmartStateData>,
     #[serde(default)]
     smart_policy_priority: HashMap<String, String>,
+    #[serde(default)]
+    interface_traffic: HashMap<String, InterfaceTrafficSnapshot>,
 }
 
 #[derive(Clone)]
 pub struct ThreadSafeCacheFile(Arc<tokio::sync::RwLock<CacheFile>>);
 
 impl ThreadSafeCacheFile {
-    pub fn new(path: &str, store_selected: bool) -> Self {
+    pub fn new(
+        path: &str,
+        store_selected: bool,
+        store_fake_ip: bool,
+        store_interface_traffic: bool,
+    ) -> Self {
         let store = Arc::new(tokio::sync::RwLock::new(CacheFile::new(
             path,
             store_selected,
@@ -30,7 +39,7 @@ impl ThreadSafeCacheFile {
         let path = path.to_string();
         let store_clone = store.clone();
 
-        if store_selected {
+        if store_selected || store_fake_ip || store_interface_traffic {
             tokio::spawn(async move {
                 let store = store_clone;
                 loop {
@@ -122,6 +131,21 @@ impl ThreadSafeCacheFile {
         let g = self.0.read().await;
         g.get_smart_stats(group_name)
     }
+
+    /// Store the traffic relayed through each outbound interface
+    pub async fn set_interface_traffic(
+        &self,
+        traffic: HashMap<String, InterfaceTrafficSnapshot>,
+    ) {
+        self.0.write().await.db.interface_traffic = traffic;
+    }
+
+    /// Get the traffic relayed through each outbound interface
+    pub async fn get_interface_traffic(
+        &self,
+    ) -> HashMap<String, InterfaceTrafficSnapshot> {
+        self.0.read().await.db.interface_traffic.clone()
+    }
 }
 
 struct CacheFile {
@@ -136,28 +160,26 @@ impl CacheFile {
             Ok(s) => match serde_yaml::from_str(&s) {
                 Ok(db) => db,
                 Err(e) => {
-                    error!(
-                        "failed to parse cache file: {}, initializing a new one",
-                        e
-                    );
-                    Db {
-                        selected: HashMap::new(),
-                        ip_to_host: HashMap::new(),
-                        host_to_ip: HashMap::new(),
-                        smart_stats: HashMap::new(),
-                        smart_policy_priority: HashMap::new(),
+                    // kept aside rather than overwritten by the next flush
+                    let backup = format!("{path}.bak");
+                    match std::fs::rename(path, &backup) {
+                        Ok(_) => warn!(
+                            "failed to parse cache file: {}, moved it to {} and \
+                             initializing a new one",
+                            e, backup
+                        ),
+                        Err(re) => warn!(
+                            "failed to parse cache file: {}, initializing a new \
+                             one, failed to back it up: {}",
+                            e, re
+                        ),
                     }
+                    Db::default()
                 }
             },
             Err(e) => {
                 warn!("failed to read cache file: {}, initializing a new one", e);
-                Db {
-                    selected: HashMap::new(),
-                    ip_to_host: HashMap::new(),
-                    host_to_ip: HashMap::new(),
-                    smart_stats: HashMap::new(),
-                    smart_policy_priority: HashMap::new(),
-                }
+                Db::default()
             }
         };
 
@@ -214,3 +236,33 @@ impl CacheFile {
         self.db.smart_stats.get(group_name).cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrupt_cache_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.db");
+        let path = path.to_str().unwrap();
+
+        std::fs::write(path, "selected: {GLOBAL: a}\nip_to_host: [").unwrap();
+        let cache = CacheFile::new(path, true);
+        assert!(cache.get_selected_map().is_empty());
+        assert!(!std::path::Path::new(path).exists());
+        assert!(std::path::Path::new(&format!("{path}.bak")).exists());
+
+        std::fs::write(
+            path,
+            "selected: {GLOBAL: a}\nip_to_host: {198.18.0.2: example.com}\n",
+        )
+        .unwrap();
+        let cache = CacheFile::new(path, true);
+        assert_eq!(cache.get_selected_map().get("GLOBAL").unwrap(), "a");
+        assert_eq!(
+            cache.get_fake_ip("198.18.0.2").as_deref(),
+            Some("example.com")
+        );
+    }
+}
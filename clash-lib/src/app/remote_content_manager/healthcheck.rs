@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::time::Instant;
 use tracing::debug;
 
-use crate::proxy::AnyOutboundHandler;
+use crate::{config::internal::proxy::HealthCheckMethod, proxy::AnyOutboundHandler};
 
 use super::ProxyManager;
 
@@ -15,6 +15,9 @@ struct HealCheckInner {
 
 pub struct HealthCheck {
     url: String,
+    method: HealthCheckMethod,
+    /// also measure UDP jitter and loss against this DNS server
+    udp_server: Option<SocketAddr>,
     interval: u64,
     lazy: bool,
     proxy_manager: ProxyManager,
@@ -31,6 +34,8 @@ impl HealthCheck {
     ) -> Self {
         Self {
             url,
+            method: HealthCheckMethod::default(),
+            udp_server: None,
             interval,
             lazy,
             proxy_manager,
@@ -42,17 +47,32 @@ impl HealthCheck {
         }
     }
 
+    pub fn with_method(mut self, method: HealthCheckMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn with_udp_server(mut self, udp_server: Option<SocketAddr>) -> Self {
+        self.udp_server = udp_server;
+        self
+    }
+
     pub async fn kick_off(&self) {
         let proxy_manager = self.proxy_manager.clone();
         let interval = self.interval;
         let lazy = self.lazy;
+        let method = self.method;
+        let udp_server = self.udp_server;
         let proxies = self.inner.read().await.proxies.clone();
 
         {
             let url = self.url.clone();
             let proxies = proxies.clone();
             tokio::spawn(async move {
-                proxy_manager.check(&proxies, &url, None).await;
+                proxy_manager.check(&proxies, &url, method, None).await;
+                if let Some(server) = udp_server {
+                    proxy_manager.check_udp(&proxies, server, None).await;
+                }
             });
         }
 
@@ -69,7 +89,10 @@ impl HealthCheck {
                         let now = tokio::time::Instant::now();
                         let last_check = inner.read().await.last_check;
                         if !lazy || now.duration_since(last_check).as_secs() >= interval {
-                            proxy_manager.check(&proxies, &url, None).await;
+                            proxy_manager.check(&proxies, &url, method, None).await;
+                            if let Some(server) = udp_server {
+                                proxy_manager.check_udp(&proxies, server, None).await;
+                            }
                             let mut w = inner.write().await;
                             w.last_check = now;
                         }
@@ -87,7 +110,12 @@ impl HealthCheck {
 
     pub async fn check(&self) {
         let proxies = self.inner.read().await.proxies.clone();
-        self.proxy_manager.check(&proxies, &self.url, None).await;
+        self.proxy_manager
+            .check(&proxies, &self.url, self.method, None)
+            .await;
+        if let Some(server) = self.udp_server {
+            self.proxy_manager.check_udp(&proxies, server, None).await;
+        }
     }
 
     pub async fn update(&self, proxies: Vec<AnyOutboundHandler>) {
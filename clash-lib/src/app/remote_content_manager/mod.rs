@@ -7,24 +7,26 @@ use crate::{
         tls::GLOBAL_ROOT_STORE,
         utils::serialize_duration,
     },
-    proxy::AnyOutboundHandler,
+    config::internal::proxy::HealthCheckMethod,
+    proxy::{AnyOutboundHandler, datagram::UdpPacket, utils::with_connect_timeout},
     session::Session,
 };
 use anyhow::Context;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use futures::{FutureExt, StreamExt, stream::FuturesUnordered};
-use http_body_util::Empty;
+use futures::{FutureExt, SinkExt, StreamExt, stream::FuturesUnordered};
+use http_body_util::{BodyExt, Empty};
 use hyper::Request;
 use hyper_util::rt::TokioIo;
 use serde::Serialize;
 use std::{
     collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
     sync::{
-        Arc,
+        Arc, Mutex,
         atomic::{AtomicBool, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::sync::RwLock;
 use tracing::{debug, instrument, trace, warn};
@@ -32,6 +34,19 @@ use tracing::{debug, instrument, trace, warn};
 pub mod healthcheck;
 pub mod providers;
 
+/// Probes sent per UDP check
+pub const DEFAULT_UDP_PROBES: u16 = 10;
+/// Gap between the probes of a UDP check
+const UDP_PROBE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long probes wait for the TCP connection to the node, at most, so
+/// that a dead one fails fast
+const HEALTH_CHECK_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a proxy seen failing UDP is left out of optimistic attempts,
+/// after which it's tried again in case the failure was transient
+const UDP_UNSUPPORTED_TTL: Duration = Duration::from_secs(300);
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct TrafficStats {
     /// Total bytes uploaded in this session
@@ -89,10 +104,72 @@ pub struct DelayHistory {
     delay: Duration,
 }
 
+/// UDP round trip quality of a proxy, measured over a burst of DNS queries
+#[derive(Clone, Debug, Serialize)]
+pub struct UdpQuality {
+    time: DateTime<Utc>,
+    /// mean round trip time of the answered queries
+    #[serde(serialize_with = "serialize_duration")]
+    latency: Duration,
+    /// mean difference between consecutive round trip times
+    #[serde(serialize_with = "serialize_duration")]
+    jitter: Duration,
+    /// share of the queries that went unanswered, from 0 to 1
+    loss: f64,
+}
+
+impl UdpQuality {
+    /// `rtts` in the order the probes were sent, `None` for the lost ones
+    fn from_rtts(rtts: &[Option<Duration>]) -> Option<Self> {
+        let answered: Vec<_> = rtts.iter().flatten().copied().collect();
+        if answered.is_empty() {
+            return None;
+        }
+        let latency = answered.iter().sum::<Duration>() / answered.len() as u32;
+        let jitter = if answered.len() > 1 {
+            answered
+                .windows(2)
+                .map(|w| w[0].abs_diff(w[1]))
+                .sum::<Duration>()
+                / (answered.len() - 1) as u32
+        } else {
+            Duration::ZERO
+        };
+        Some(Self {
+            time: Utc::now(),
+            latency,
+            jitter,
+            loss: 1.0 - answered.len() as f64 / rtts.len() as f64,
+        })
+    }
+
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    pub fn loss(&self) -> f64 {
+        self.loss
+    }
+}
+
 #[derive(Default)]
 struct ProxyState {
     alive: AtomicBool,
     delay_history: VecDeque<DelayHistory>,
+    /// The public IP address the proxy connects from, as seen by the IP
+    /// echo service
+    exit_ip: Option<IpAddr>,
+    /// Whether UDP through the proxy has been seen working, for proxies
+    /// that don't declare UDP support
+    udp: Option<bool>,
+    /// When a UDP failure is forgotten, see `UDP_UNSUPPORTED_TTL`
+    udp_retry_at: Option<Instant>,
+    /// The result of the last UDP check
+    udp_quality: Option<UdpQuality>,
 }
 
 /// ProxyManager is the latency registry.
@@ -121,12 +198,13 @@ impl ProxyManager {
         }
     }
 
-    /// Handy wrapper of `url_test` that checks multiple proxies
+    /// Handy wrapper of `health_test` that checks multiple proxies
     #[instrument(skip(self))]
     pub async fn check(
         &self,
         outbounds: &Vec<AnyOutboundHandler>,
         url: &str,
+        method: HealthCheckMethod,
         timeout: Option<Duration>,
     ) -> Vec<std::io::Result<(Duration, Duration)>> {
         let mut futs = vec![];
@@ -137,7 +215,7 @@ impl ProxyManager {
             futs.push(tokio::spawn(async move {
                 let proxy_name = outbound.name().to_owned();
                 manager
-                    .url_test(outbound, url.as_str(), timeout)
+                    .health_test(outbound, url.as_str(), method, timeout)
                     .await
                     .inspect_err(|e| {
                         warn!("healthcheck {} -> {} failed: {}", proxy_name, url, e)
@@ -193,6 +271,206 @@ impl ProxyManager {
             .map(|x| x.delay.to_owned())
     }
 
+    pub async fn exit_ip(&self, name: &str) -> Option<IpAddr> {
+        self.proxy_state
+            .read()
+            .await
+            .get(name)
+            .and_then(|x| x.exit_ip)
+    }
+
+    pub async fn report_exit_ip(&self, name: &str, ip: IpAddr) {
+        let mut state = self.proxy_state.write().await;
+        state.entry(name.to_owned()).or_default().exit_ip = Some(ip);
+    }
+
+    /// Whether UDP through `name` was seen working, `None` if it wasn't tried
+    /// yet or a failure has expired
+    pub async fn observed_udp(&self, name: &str) -> Option<bool> {
+        self.proxy_state
+            .read()
+            .await
+            .get(name)
+            .and_then(|x| match x.udp {
+                Some(false)
+                    if x.udp_retry_at.is_some_and(|at| at <= Instant::now()) =>
+                {
+                    None
+                }
+                udp => udp,
+            })
+    }
+
+    pub async fn report_udp(&self, name: &str, supported: bool) {
+        let mut state = self.proxy_state.write().await;
+        let state = state.entry(name.to_owned()).or_default();
+        state.udp = Some(supported);
+        state.udp_retry_at =
+            (!supported).then(|| Instant::now() + UDP_UNSUPPORTED_TTL);
+    }
+
+    pub async fn udp_quality(&self, name: &str) -> Option<UdpQuality> {
+        self.proxy_state
+            .read()
+            .await
+            .get(name)
+            .and_then(|x| x.udp_quality.clone())
+    }
+
+    /// Handy wrapper of `udp_test` that checks the proxies supporting UDP
+    #[instrument(skip(self))]
+    pub async fn check_udp(
+        &self,
+        outbounds: &Vec<AnyOutboundHandler>,
+        server: SocketAddr,
+        timeout: Option<Duration>,
+    ) -> Vec<std::io::Result<UdpQuality>> {
+        let mut futs = vec![];
+        for outbound in outbounds {
+            if !outbound.support_udp().await
+                && self.observed_udp(outbound.name()).await != Some(true)
+            {
+                continue;
+            }
+            let outbound = outbound.clone();
+            let manager = self.clone();
+            futs.push(tokio::spawn(async move {
+                let proxy_name = outbound.name().to_owned();
+                manager
+                    .udp_test(outbound, server, DEFAULT_UDP_PROBES, timeout)
+                    .await
+                    .inspect_err(|e| {
+                        warn!("udp check {} -> {} failed: {}", proxy_name, server, e)
+                    })
+            }));
+        }
+
+        let futs: FuturesUnordered<_> = futs.into_iter().collect();
+        futs.map(|r| r.unwrap_or_else(|e| Err(new_io_error(e.to_string()))))
+            .collect()
+            .await
+    }
+
+    /// Sends `count` DNS queries to `server` through `outbound`'s UDP path,
+    /// `UDP_PROBE_INTERVAL` apart, and measures the latency, jitter and
+    /// loss of the answers that arrive within `timeout`.
+    #[instrument(skip(self, outbound), fields(name = %outbound.name()))]
+    pub async fn udp_test(
+        &self,
+        outbound: AnyOutboundHandler,
+        server: SocketAddr,
+        count: u16,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<UdpQuality> {
+        let name = outbound.name().to_owned();
+        let timeout = timeout.unwrap_or(Duration::from_secs(5));
+        let count = count.max(1);
+
+        let sess = Session {
+            destination: server.into(),
+            iface: DEFAULT_OUTBOUND_INTERFACE.read().await.clone(),
+            so_mark: self.fw_mark,
+            ..Default::default()
+        };
+        let datagram = tokio::time::timeout(
+            timeout,
+            with_connect_timeout(
+                timeout.min(HEALTH_CHECK_CONNECT_TIMEOUT),
+                outbound.connect_datagram(&sess, self.dns_resolver.clone()),
+            ),
+        )
+        .await
+        .context("UDP test timeout")
+        .into_io()??;
+        let (mut w, mut r) = datagram.split();
+
+        let base_id: u16 = rand::random();
+        let sent_at = Mutex::new(vec![None::<Instant>; count as usize]);
+        let rtts = Mutex::new(vec![None::<Duration>; count as usize]);
+
+        let sender = async {
+            for i in 0..count {
+                let mut m = hickory_proto::op::Message::new();
+                m.set_id(base_id.wrapping_add(i))
+                    .set_recursion_desired(true)
+                    .add_query(hickory_proto::op::Query::query(
+                        hickory_proto::rr::Name::root(),
+                        hickory_proto::rr::RecordType::NS,
+                    ));
+                let packet = UdpPacket {
+                    data: m.to_vec().map_err(new_io_error)?,
+                    dst_addr: server.into(),
+                    ..Default::default()
+                };
+                sent_at.lock().unwrap()[i as usize] = Some(Instant::now());
+                w.send(packet).await?;
+                tokio::time::sleep(UDP_PROBE_INTERVAL).await;
+            }
+            std::io::Result::Ok(())
+        };
+        let receiver = async {
+            let mut answered = 0;
+            while answered < count {
+                let Some(packet) = r.next().await else {
+                    break;
+                };
+                let Ok(m) = hickory_proto::op::Message::from_vec(&packet.data)
+                else {
+                    continue;
+                };
+                let i = m.id().wrapping_sub(base_id) as usize;
+                if i >= count as usize {
+                    continue;
+                }
+                let Some(sent) = sent_at.lock().unwrap()[i] else {
+                    continue;
+                };
+                let mut slots = rtts.lock().unwrap();
+                if slots[i].is_none() {
+                    slots[i] = Some(sent.elapsed());
+                    answered += 1;
+                }
+            }
+        };
+
+        let burst = async {
+            let (sent, _) = futures::join!(sender, async {
+                // stop waiting for answers `timeout` after the last probe
+                let _ = tokio::time::timeout(
+                    UDP_PROBE_INTERVAL * count as u32 + timeout,
+                    receiver,
+                )
+                .await;
+            });
+            sent
+        };
+        burst.await?;
+
+        let rtts = rtts.into_inner().unwrap();
+        let result = UdpQuality::from_rtts(&rtts).ok_or_else(|| {
+            new_io_error(format!("no answer from {server} in {count} probes"))
+        });
+
+        let mut state = self.proxy_state.write().await;
+        let state = state.entry(name).or_default();
+        match &result {
+            Ok(q) => {
+                debug!(
+                    latency = ?q.latency,
+                    jitter = ?q.jitter,
+                    loss = q.loss,
+                    "udp test done"
+                );
+                state.udp = Some(true);
+                state.udp_retry_at = None;
+                state.udp_quality = Some(q.clone());
+            }
+            Err(_) => state.udp_quality = None,
+        }
+
+        result
+    }
+
     pub async fn get_packet_loss(&self, name: &str) -> Option<f64> {
         let history = self.delay_history(name).await;
         if history.is_empty() {
@@ -678,7 +956,6 @@ impl ProxyManager {
         }
     }
 
-    #[instrument(skip(self))]
     /// returns (actual_http_round_trip_time,
     /// overall_round_trip_time_including_tls_handshake)
     pub async fn url_test(
@@ -686,6 +963,20 @@ impl ProxyManager {
         outbound: AnyOutboundHandler,
         url: &str,
         timeout: Option<Duration>,
+    ) -> std::io::Result<(Duration, Duration)> {
+        self.health_test(outbound, url, HealthCheckMethod::Get, timeout)
+            .await
+    }
+
+    #[instrument(skip(self))]
+    /// Like `url_test`, with the given method. A `tcp` check returns the
+    /// time taken to connect through the proxy for both.
+    pub async fn health_test(
+        &self,
+        outbound: AnyOutboundHandler,
+        url: &str,
+        method: HealthCheckMethod,
+        timeout: Option<Duration>,
     ) -> std::io::Result<(Duration, Duration)> {
         trace!("started");
         let name = outbound.name().to_owned();
@@ -697,26 +988,7 @@ impl ProxyManager {
         let tester = async move {
             let name = name_clone;
 
-            let uri = url
-                .parse::<http::Uri>()
-                .map_err(|e| new_io_error(format!("invalid url: {url}: {e}")))?;
-
-            let host = uri
-                .host()
-                .ok_or(new_io_error(format!("invalid url: {url}: no host found")))?
-                .to_owned();
-            let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
-                None => 80,
-                Some(s) => match s {
-                    "http" => 80,
-                    "https" => 443,
-                    _ => {
-                        return Err(new_io_error(format!(
-                            "invalid url: {url}: unsupported scheme {s}"
-                        )));
-                    }
-                },
-            });
+            let (uri, host, port) = parse_test_url(url)?;
 
             let sess = Session {
                 destination: (host.to_owned(), port)
@@ -729,14 +1001,31 @@ impl ProxyManager {
 
             let (stream, connect_delay) = tokio::time::timeout(
                 timeout,
-                TimedFuture::new(outbound.connect_stream(&sess, dns_resolver)),
+                TimedFuture::new(
+                    with_connect_timeout(
+                        timeout.min(HEALTH_CHECK_CONNECT_TIMEOUT),
+                        outbound.connect_stream(&sess, dns_resolver),
+                    )
+                    .boxed(),
+                ),
             )
             .await
             .context("URL test timeout")
             .into_io()?;
             let stream = stream?;
 
-            let req = Request::get(url)
+            let http_method = match method {
+                HealthCheckMethod::Get => http::Method::GET,
+                HealthCheckMethod::Head => http::Method::HEAD,
+                HealthCheckMethod::Tcp => {
+                    trace!(delay = ?connect_delay, "connected");
+                    return Ok((connect_delay, connect_delay));
+                }
+            };
+
+            let req = Request::builder()
+                .method(http_method)
+                .uri(url)
                 .header(hyper::header::HOST, host.as_str())
                 .header("Connection", "Close")
                 .version(hyper::Version::HTTP_11)
@@ -863,6 +1152,88 @@ impl ProxyManager {
         result
     }
 
+    /// Find out the public IP address `outbound` connects from by fetching
+    /// `url` through it. The response body must be the caller's IP address
+    /// in plain text, e.g. https://api.ipify.org
+    #[instrument(skip(self, outbound), fields(name = %outbound.name()))]
+    pub async fn probe_exit_ip(
+        &self,
+        outbound: AnyOutboundHandler,
+        url: &str,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<IpAddr> {
+        let name = outbound.name().to_owned();
+        let timeout = timeout.unwrap_or(Duration::from_secs(5));
+        let dns_resolver = self.dns_resolver.clone();
+
+        let prober = async move {
+            let (uri, host, port) = parse_test_url(url)?;
+
+            let sess = Session {
+                destination: (host.to_owned(), port)
+                    .try_into()
+                    .expect("must be valid destination"),
+                iface: DEFAULT_OUTBOUND_INTERFACE.read().await.clone(),
+                so_mark: self.fw_mark,
+                ..Default::default()
+            };
+
+            let stream = outbound.connect_stream(&sess, dns_resolver).await?;
+
+            let req = Request::get(url)
+                .header(hyper::header::HOST, host.as_str())
+                .header("Connection", "Close")
+                .version(hyper::Version::HTTP_11)
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+
+            let body = match uri.scheme() {
+                Some(scheme) if scheme == &http::uri::Scheme::HTTP => {
+                    fetch_body(stream, req).await?
+                }
+                Some(scheme) if scheme == &http::uri::Scheme::HTTPS => {
+                    let tls_config = rustls::ClientConfig::builder()
+                        .with_root_certificates(GLOBAL_ROOT_STORE.clone())
+                        .with_no_client_auth();
+                    let connector =
+                        tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+                    let server_name = host.try_into().map_err(|e| {
+                        new_io_error(format!("invalid url: {url}: {e}"))
+                    })?;
+                    let stream = connector.connect(server_name, stream).await?;
+                    fetch_body(stream, req).await?
+                }
+                _ => {
+                    return Err(new_io_error(format!(
+                        "invalid url: {url}: unsupported scheme"
+                    )));
+                }
+            };
+
+            std::str::from_utf8(&body)
+                .ok()
+                .and_then(|x| x.trim().parse::<IpAddr>().ok())
+                .ok_or_else(|| {
+                    new_io_error(format!("{url} did not respond with an ip address"))
+                })
+        };
+
+        let result = match tokio::time::timeout(timeout, prober).await {
+            Ok(r) => r,
+            Err(_) => Err(new_io_error(format!("timeout for {url}"))),
+        };
+
+        match &result {
+            Ok(ip) => {
+                debug!("exit ip of {name}: {ip}");
+                self.report_exit_ip(&name, *ip).await;
+            }
+            Err(e) => warn!("failed to probe exit ip of {name}: {e}"),
+        }
+
+        result
+    }
+
     /// Based on session characteristics and traffic statistics.
     pub async fn get_site_tuning(&self, sess: &Session) -> SiteTuning {
         // Extract traffic statistics from the session if available
@@ -989,6 +1360,64 @@ impl ProxyManager {
     }
 }
 
+fn parse_test_url(url: &str) -> std::io::Result<(http::Uri, String, u16)> {
+    let uri = url
+        .parse::<http::Uri>()
+        .map_err(|e| new_io_error(format!("invalid url: {url}: {e}")))?;
+
+    let host = uri
+        .host()
+        .ok_or(new_io_error(format!("invalid url: {url}: no host found")))?
+        .to_owned();
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        None => 80,
+        Some(s) => match s {
+            "http" => 80,
+            "https" => 443,
+            _ => {
+                return Err(new_io_error(format!(
+                    "invalid url: {url}: unsupported scheme {s}"
+                )));
+            }
+        },
+    });
+
+    Ok((uri, host, port))
+}
+
+async fn fetch_body<S>(
+    stream: S,
+    req: Request<Empty<Bytes>>,
+) -> std::io::Result<Bytes>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| new_io_error(format!("failed to handshake: {e}")))?;
+
+    tokio::task::spawn(async move {
+        if let Err(err) = conn.await {
+            warn!("HTTP connection error: {}", err);
+        }
+    });
+
+    let resp = sender.send_request(req).await.map_err(new_io_error)?;
+    if !resp.status().is_success() {
+        return Err(new_io_error(format!(
+            "unexpected status code: {}",
+            resp.status()
+        )));
+    }
+
+    resp.into_body()
+        .collect()
+        .await
+        .map(|x| x.to_bytes())
+        .map_err(new_io_error)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -1002,6 +1431,10 @@ mod tests {
     };
     use futures::TryFutureExt;
     use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
 
     #[tokio::test]
     async fn test_proxy_manager_alive() {
@@ -1099,4 +1532,128 @@ mod tests {
         assert_eq!(manager.last_delay(PROXY_DIRECT).await, None);
         assert_eq!(manager.delay_history(PROXY_DIRECT).await.len(), 1);
     }
+
+    #[test]
+    fn test_udp_quality() {
+        let ms = Duration::from_millis;
+        let q = remote_content_manager::UdpQuality::from_rtts(&[
+            Some(ms(10)),
+            None,
+            Some(ms(30)),
+            Some(ms(20)),
+        ])
+        .unwrap();
+        assert_eq!(q.latency(), ms(20));
+        assert_eq!(q.jitter(), ms(15));
+        assert_eq!(q.loss(), 0.25);
+
+        let q =
+            remote_content_manager::UdpQuality::from_rtts(&[Some(ms(10))]).unwrap();
+        assert_eq!(q.jitter(), Duration::ZERO);
+        assert_eq!(q.loss(), 0.0);
+
+        assert!(remote_content_manager::UdpQuality::from_rtts(&[None]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_udp_failure_expires() {
+        let manager = remote_content_manager::ProxyManager::new(
+            Arc::new(MockClashResolver::new()),
+            None,
+        );
+        assert_eq!(manager.observed_udp("p").await, None);
+
+        manager.report_udp("p", false).await;
+        assert_eq!(manager.observed_udp("p").await, Some(false));
+
+        // as if `UDP_UNSUPPORTED_TTL` went by
+        manager
+            .proxy_state
+            .write()
+            .await
+            .get_mut("p")
+            .unwrap()
+            .udp_retry_at = Some(std::time::Instant::now());
+        assert_eq!(manager.observed_udp("p").await, None);
+
+        // seen working is kept
+        manager.report_udp("p", true).await;
+        assert_eq!(manager.observed_udp("p").await, Some(true));
+    }
+
+    #[test]
+    fn test_parse_test_url() {
+        let parse = |url| {
+            remote_content_manager::parse_test_url(url)
+                .map(|(_, host, port)| (host, port))
+        };
+        assert_eq!(
+            parse("http://www.gstatic.com/generate_204").unwrap(),
+            ("www.gstatic.com".to_owned(), 80)
+        );
+        assert_eq!(
+            parse("https://api.ipify.org").unwrap(),
+            ("api.ipify.org".to_owned(), 443)
+        );
+        assert_eq!(
+            parse("https://1.1.1.1:8443/ip").unwrap(),
+            ("1.1.1.1".to_owned(), 8443)
+        );
+        assert!(parse("ftp://example.com/ip").is_err());
+        assert!(parse("/ip").is_err());
+    }
+
+    /// Answers a single HTTP request on loopback with `body`
+    async fn serve_once(body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: \
+                 close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(resp.as_bytes()).await.unwrap();
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_probe_exit_ip() {
+        let mut mock_resolver = MockClashResolver::new();
+        mock_resolver
+            .expect_resolve()
+            .returning(|_, _| Ok(Some(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST))));
+        let manager =
+            remote_content_manager::ProxyManager::new(Arc::new(mock_resolver), None);
+        let direct = Arc::new(direct::Handler::new(PROXY_DIRECT));
+
+        let port = serve_once("203.0.113.7\n").await;
+        let ip = manager
+            .probe_exit_ip(
+                direct.clone(),
+                &format!("http://127.0.0.1:{port}/"),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(ip, Ipv4Addr::new(203, 0, 113, 7));
+        assert_eq!(manager.exit_ip(PROXY_DIRECT).await, Some(ip));
+
+        // not an address, the one known stays
+        let port = serve_once("<html></html>").await;
+        let err = manager
+            .probe_exit_ip(direct, &format!("http://127.0.0.1:{port}/"), None)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("did not respond with an ip address")
+        );
+        assert_eq!(manager.exit_ip(PROXY_DIRECT).await, Some(ip));
+    }
 }
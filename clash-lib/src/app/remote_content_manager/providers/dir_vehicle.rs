@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use serde_yaml::{Mapping, Value};
+use std::{collections::HashSet, fs, path::Path, time::Duration};
+use tracing::warn;
+
+use super::{ProviderVehicle, ProviderVehicleType};
+
+/// How often a directory is checked for changes when the provider has no
+/// `interval` of its own
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+const EXTENSIONS: &[&str] = &["yaml", "yml", "json"];
+
+/// Reads the `proxies` of every YAML or JSON file in a directory, merged into
+/// a single document in file name order. A file that fails to parse is
+/// skipped, as is a proxy whose name an earlier file already used.
+pub struct Vehicle {
+    path: String,
+}
+
+impl Vehicle {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+fn read_proxies(path: &Path) -> anyhow::Result<Vec<Value>> {
+    let content = fs::read(path)?;
+    let doc: Value = serde_yaml::from_slice(&content)?;
+    match doc.get("proxies") {
+        Some(Value::Sequence(proxies)) => Ok(proxies.clone()),
+        Some(Value::Null) | None => Ok(vec![]),
+        Some(_) => Err(anyhow::anyhow!("proxies is not a list")),
+    }
+}
+
+#[async_trait]
+impl ProviderVehicle for Vehicle {
+    async fn read(&self) -> std::io::Result<Vec<u8>> {
+        let mut files = fs::read_dir(&self.path)?
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .filter(|x| {
+                x.is_file()
+                    && x.extension()
+                        .and_then(|x| x.to_str())
+                        .is_some_and(|x| EXTENSIONS.contains(&x))
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let mut names = HashSet::new();
+        let mut proxies = vec![];
+        for file in files {
+            match read_proxies(&file) {
+                Ok(list) => {
+                    for proxy in list {
+                        let name = proxy.get("name").and_then(|x| x.as_str());
+                        if let Some(name) = name
+                            && !names.insert(name.to_owned())
+                        {
+                            warn!(
+                                "skipping proxy {} of {}: duplicated name",
+                                name,
+                                file.display()
+                            );
+                            continue;
+                        }
+                        proxies.push(proxy);
+                    }
+                }
+                Err(e) => warn!("skipping proxy file {}: {}", file.display(), e),
+            }
+        }
+
+        let mut doc = Mapping::new();
+        doc.insert("proxies".into(), Value::Sequence(proxies));
+        serde_yaml::to_string(&doc)
+            .map(String::into_bytes)
+            .map_err(std::io::Error::other)
+    }
+
+    fn path(&self) -> &str {
+        self.path.as_str()
+    }
+
+    fn typ(&self) -> ProviderVehicleType {
+        ProviderVehicleType::File
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dir_vehicle() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.yaml"),
+            "proxies:\n  - {name: a, type: socks5, server: 10.0.0.1, port: 1080}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.json"),
+            r#"{"proxies": [{"name": "b", "type": "socks5", "server": "10.0.0.2",
+                "port": 1080}, {"name": "a", "type": "direct"}]}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("c.yml"), "proxies: [").unwrap();
+        fs::write(dir.path().join("notes.txt"), "proxies: []").unwrap();
+
+        let vehicle = Vehicle::new(dir.path().to_str().unwrap());
+        let doc: Value = serde_yaml::from_slice(&vehicle.read().await.unwrap())
+            .expect("merged document");
+        let names = doc["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(doc["proxies"][0]["server"].as_str(), Some("10.0.0.1"));
+    }
+}
@@ -8,15 +8,57 @@ use std::{
 use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use tokio::sync::RwLock;
-use tracing::{info, trace, warn};
+use tracing::{debug, info, trace, warn};
 
-use crate::common::utils;
+use crate::{common::utils, config::internal::proxy::ProviderRetry};
 
 use super::{ProviderVehicleType, ThreadSafeProviderVehicle};
 
+/// Bounded retries with jittered exponential backoff for remote fetches.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Backoff before the retry following the `attempt`-th failure, picked
+    /// uniformly from the upper half of the capped exponential delay.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .min(self.max_backoff);
+        let millis = delay.as_millis() as u64;
+        if millis < 2 {
+            return delay;
+        }
+        Duration::from_millis(utils::rand_range(millis / 2..=millis))
+    }
+
+    /// How soon to try again once every attempt failed, `None` to wait for
+    /// the regular interval.
+    fn reschedule(&self, interval: Duration) -> Option<Duration> {
+        (self.attempts > 0 && self.max_backoff < interval)
+            .then_some(self.max_backoff)
+    }
+}
+
+impl From<ProviderRetry> for RetryPolicy {
+    fn from(value: ProviderRetry) -> Self {
+        Self {
+            attempts: value.attempts,
+            backoff: Duration::from_millis(value.backoff),
+            max_backoff: Duration::from_millis(value.max_backoff.max(value.backoff)),
+        }
+    }
+}
+
 struct Inner {
     updated_at: SystemTime,
     hash: [u8; 16],
+    next_retry: Option<SystemTime>,
 
     thread_handle: Option<tokio::task::JoinHandle<()>>,
 }
@@ -26,6 +68,7 @@ pub struct Fetcher<U, P> {
     interval: Duration,
     vehicle: ThreadSafeProviderVehicle,
     ticker_interval: Duration,
+    retry: RetryPolicy,
     inner: Arc<RwLock<Inner>>,
     parser: Arc<P>,
     pub on_update: Option<Arc<U>>,
@@ -49,9 +92,11 @@ where
             interval,
             vehicle,
             ticker_interval: interval,
+            retry: RetryPolicy::default(),
             inner: Arc::new(tokio::sync::RwLock::new(Inner {
                 updated_at: SystemTime::UNIX_EPOCH,
                 hash: [0; 16],
+                next_retry: None,
                 thread_handle: None,
             })),
             parser: Arc::new(parser),
@@ -59,6 +104,11 @@ where
         }
     }
 
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
     pub fn name(&self) -> &str {
         self.name.as_str()
     }
@@ -71,6 +121,11 @@ where
         self.inner.read().await.updated_at.into()
     }
 
+    /// When the next fetch is due after the last scheduled one failed.
+    pub async fn next_retry_at(&self) -> Option<DateTime<Utc>> {
+        self.inner.read().await.next_retry.map(Into::into)
+    }
+
     pub async fn initial(&self) -> anyhow::Result<T> {
         let mut is_local = false;
         let mut immediately_update = false;
@@ -80,7 +135,7 @@ where
         let mut inner = self.inner.write().await;
 
         let content = match metadata(&vehicle_path) {
-            Ok(meta) => {
+            Ok(meta) if !meta.is_dir() => {
                 let content = fs::read(&vehicle_path)?;
                 is_local = true;
                 inner.updated_at = meta.modified()?;
@@ -90,7 +145,7 @@ where
                     > self.interval;
                 content
             }
-            Err(_) => self.vehicle.read().await?,
+            _ => self.vehicle.read().await?,
         };
 
         let parser_guard = &self.parser;
@@ -152,6 +207,7 @@ where
         let proxies = parser(&content)?;
 
         let now = SystemTime::now();
+        this.next_retry = None;
         let hash = utils::md5(&content)[..16]
             .try_into()
             .expect("md5 must be 16 bytes");
@@ -179,6 +235,37 @@ where
         Ok((proxies, false))
     }
 
+    async fn update_with_retry(
+        inner: Arc<RwLock<Inner>>,
+        vehicle: ThreadSafeProviderVehicle,
+        parser: Arc<P>,
+        retry: RetryPolicy,
+        name: &str,
+    ) -> anyhow::Result<(T, bool)> {
+        let mut attempt = 0;
+        loop {
+            match Fetcher::<U, P>::update_inner(
+                inner.clone(),
+                vehicle.clone(),
+                parser.clone(),
+            )
+            .await
+            {
+                Ok(rv) => return Ok(rv),
+                Err(e) if attempt < retry.attempts => {
+                    let delay = retry.delay(attempt);
+                    attempt += 1;
+                    debug!(
+                        "{} update failed: {}, retry {}/{} in {:?}",
+                        name, e, attempt, retry.attempts, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     #[cfg(test)]
     pub async fn destroy(&mut self) {
         if let Some(handle) = self.inner.write().await.thread_handle.take() {
@@ -196,6 +283,8 @@ where
         let parser = self.parser.clone();
         let on_update = self.on_update.clone();
         let name = self.name.clone();
+        let retry = self.retry;
+        let interval = self.ticker_interval;
         let fire_immediately = immediately_update;
 
         let thread_handle = Some(tokio::spawn(async move {
@@ -207,35 +296,55 @@ where
                 let on_update = on_update.clone();
                 trace!("fetcher {} tick", &name);
 
+                // returns how soon to try again if the update failed
                 let update = || async move {
-                    let (elm, same) =
-                        match Fetcher::<U, P>::update_inner(inner, vehicle, parser)
-                            .await
-                        {
-                            Ok((elm, same)) => (elm, same),
-                            Err(e) => {
-                                warn!("{} update failed: {}", &name, e);
-                                return;
-                            }
-                        };
+                    let (elm, same) = match Fetcher::<U, P>::update_with_retry(
+                        inner.clone(),
+                        vehicle,
+                        parser,
+                        retry,
+                        &name,
+                    )
+                    .await
+                    {
+                        Ok((elm, same)) => (elm, same),
+                        Err(e) => {
+                            // keep serving the last good set until then
+                            let delay = retry.reschedule(interval);
+                            warn!(
+                                "{} update failed: {}, next attempt in {:?}",
+                                &name,
+                                e,
+                                delay.unwrap_or(interval)
+                            );
+                            inner.write().await.next_retry =
+                                delay.map(|d| SystemTime::now() + d);
+                            return delay;
+                        }
+                    };
 
                     if same {
                         trace!("fetcher {} no update", &name);
-                        return;
+                        return None;
                     }
 
                     if let Some(on_update) = on_update {
                         info!("fetcher {} updated", &name);
                         on_update(elm).await;
                     }
+                    None
                 };
 
                 if fire_immediately {
-                    update().await;
+                    if let Some(delay) = update().await {
+                        ticker.reset_after(delay);
+                    }
                     ticker.tick().await;
                 } else {
                     ticker.tick().await;
-                    update().await;
+                    if let Some(delay) = update().await {
+                        ticker.reset_after(delay);
+                    }
                 }
             }
         }));
@@ -255,7 +364,8 @@ mod tests {
         MockProviderVehicle, ProviderVehicleType,
     };
 
-    use super::Fetcher;
+    use super::{Fetcher, RetryPolicy};
+    use crate::config::internal::proxy::ProviderRetry;
 
     #[tokio::test]
     async fn test_fetcher() {
@@ -319,4 +429,31 @@ mod tests {
         assert_eq!(parsed[0], vec![1, 2, 3]);
         assert_eq!(parsed[1], vec![4, 5, 6]);
     }
+
+    #[test]
+    fn test_retry_backoff() {
+        let retry: RetryPolicy = ProviderRetry {
+            attempts: 5,
+            backoff: 100,
+            max_backoff: 1000,
+        }
+        .into();
+
+        for (attempt, upper) in [(0, 100), (1, 200), (2, 400), (3, 800), (4, 1000)] {
+            let delay = retry.delay(attempt);
+            assert!(delay >= Duration::from_millis(upper / 2));
+            assert!(delay <= Duration::from_millis(upper));
+        }
+        assert!(retry.delay(40) <= Duration::from_millis(1000));
+
+        assert_eq!(
+            retry.reschedule(Duration::from_secs(3600)),
+            Some(Duration::from_millis(1000))
+        );
+        assert_eq!(retry.reschedule(Duration::from_millis(500)), None);
+        assert_eq!(
+            RetryPolicy::default().reschedule(Duration::from_secs(3600)),
+            None
+        );
+    }
 }
@@ -1,23 +1,62 @@
 use super::{ProviderVehicle, ProviderVehicleType};
 use crate::{
     app::dns::ThreadSafeDNSResolver,
-    common::{
-        errors::map_io_error,
-        http::{HttpClient, new_http_client},
-    },
+    common::http::{HttpClient, new_http_client},
 };
 
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder};
 use async_trait::async_trait;
 
+use futures::TryStreamExt;
 use http_body_util::BodyExt;
-use hyper::Uri;
+use hyper::{Uri, body::Incoming};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::io::StreamReader;
 
-use std::io;
+use std::{io, pin::Pin};
 
 use crate::common::http::DEFAULT_USER_AGENT;
-use http::Request;
+use http::{Request, Response, header};
 use std::path::{Path, PathBuf};
 
+/// Bodies that decompress to more than this are refused, so that a small
+/// compressed response can't exhaust the memory
+const MAX_BODY_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Reads the body, decoding it as per its `Content-Encoding`
+async fn read_body(res: Response<Incoming>, limit: u64) -> io::Result<Vec<u8>> {
+    let encoding = res
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.trim().to_ascii_lowercase());
+    let body = StreamReader::new(
+        res.into_body().into_data_stream().map_err(io::Error::other),
+    );
+    let reader: Pin<Box<dyn AsyncRead + Send>> = match encoding.as_deref() {
+        None | Some("") | Some("identity") => Box::pin(body),
+        Some("gzip") | Some("x-gzip") => Box::pin(GzipDecoder::new(body)),
+        Some("deflate") => Box::pin(ZlibDecoder::new(body)),
+        Some("br") => Box::pin(BrotliDecoder::new(body)),
+        Some(other) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported content-encoding: {other}"),
+            ));
+        }
+    };
+
+    let mut buf = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut buf).await?;
+    if buf.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("body is larger than {limit} bytes"),
+        ));
+    }
+    Ok(buf)
+}
+
 pub struct Vehicle {
     pub url: Uri,
     pub path: PathBuf,
@@ -53,17 +92,18 @@ impl ProviderVehicle for Vehicle {
             http::header::USER_AGENT,
             DEFAULT_USER_AGENT.parse().expect("must parse user agent"),
         );
+        req.headers_mut().insert(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("gzip, deflate, br"),
+        );
         *req.body_mut() = http_body_util::Empty::<bytes::Bytes>::new();
         *req.uri_mut() = self.url.clone();
-        self.http_client
+        let res = self
+            .http_client
             .request(req)
             .await
-            .map_err(|x| io::Error::other(x.to_string()))?
-            .into_body()
-            .collect()
-            .await
-            .map(|x| x.to_bytes().to_vec())
-            .map_err(map_io_error)
+            .map_err(|x| io::Error::other(x.to_string()))?;
+        read_body(res, MAX_BODY_SIZE).await
     }
 
     fn path(&self) -> &str {
@@ -80,11 +120,14 @@ mod tests {
     use super::ProviderVehicle;
     use crate::{
         app::dns::{EnhancedResolver, ThreadSafeDNSResolver},
+        common::http::new_http_client,
         tests::initialize,
     };
+    use async_compression::tokio::bufread::GzipEncoder;
     use httpmock::{Method::GET, MockServer};
     use hyper::Uri;
     use std::{str, sync::Arc};
+    use tokio::io::AsyncReadExt;
 
     #[tokio::test]
     async fn test_http_vehicle() {
@@ -105,4 +148,53 @@ mod tests {
         mock.assert();
         assert_eq!(str::from_utf8(&data).unwrap(), "HTTPBIN is awesome");
     }
+
+    async fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        GzipEncoder::new(data).read_to_end(&mut out).await.unwrap();
+        out
+    }
+
+    #[tokio::test]
+    async fn test_http_vehicle_gzip() {
+        initialize();
+        let server = MockServer::start();
+        let body = gzip(b"proxies: []").await;
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/test_http_vehicle_gzip")
+                .header_exists("accept-encoding");
+            then.status(200)
+                .header("content-encoding", "gzip")
+                .body(body);
+        });
+        let bomb = gzip(&[0; 4096]).await;
+        server.mock(|when, then| {
+            when.method(GET).path("/test_http_vehicle_bomb");
+            then.status(200)
+                .header("content-encoding", "gzip")
+                .body(bomb);
+        });
+        let r =
+            Arc::new(EnhancedResolver::new_default().await) as ThreadSafeDNSResolver;
+        let client = new_http_client(r, None).unwrap();
+        let get = |path: &str| {
+            let mut req =
+                http::Request::new(http_body_util::Empty::<bytes::Bytes>::new());
+            *req.uri_mut() = server.url(path).parse::<Uri>().unwrap();
+            req.headers_mut().insert(
+                http::header::ACCEPT_ENCODING,
+                http::HeaderValue::from_static("gzip"),
+            );
+            client.request(req)
+        };
+
+        let res = get("/test_http_vehicle_gzip").await.unwrap();
+        let data = super::read_body(res, 1024).await.unwrap();
+        mock.assert();
+        assert_eq!(data, b"proxies: []");
+
+        let res = get("/test_http_vehicle_bomb").await.unwrap();
+        assert!(super::read_body(res, 1024).await.is_err());
+    }
 }
@@ -8,6 +8,7 @@ use std::{
     sync::Arc,
 };
 
+pub mod dir_vehicle;
 pub mod fetcher;
 pub mod file_vehicle;
 pub mod http_vehicle;
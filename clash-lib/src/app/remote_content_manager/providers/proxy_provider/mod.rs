@@ -5,9 +5,10 @@ pub mod proxy_set_provider;
 pub use plain_provider::PlainProvider;
 pub use proxy_set_provider::ProxySetProvider;
 
-use std::sync::Arc;
+use std::{collections::HashMap, io, sync::Arc};
 
 use async_trait::async_trait;
+use serde::Serialize;
 use tokio::sync::RwLock;
 
 use crate::{
@@ -22,4 +23,71 @@ pub trait ProxyProvider: Provider {
     async fn touch(&self);
     /// this is a blocking call, you may want to spawn a new task to run this
     async fn healthcheck(&self);
+    /// Like [`Provider::update`], but reports which proxies the update
+    /// changed
+    async fn update_and_diff(&self) -> io::Result<ProxySetDiff> {
+        self.update().await.map(|_| ProxySetDiff::default())
+    }
+}
+
+/// The proxies added, removed and changed by a provider update, by name
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct ProxySetDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ProxySetDiff {
+    /// Compares the proxy configs, by proxy name, before and after an update
+    pub fn new<T: PartialEq>(
+        old: &HashMap<String, T>,
+        new: &HashMap<String, T>,
+    ) -> Self {
+        let mut diff = Self::default();
+        for (name, config) in new {
+            match old.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(old) if old != config => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .cloned()
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort();
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::ProxySetDiff;
+
+    #[test]
+    fn test_proxy_set_diff() {
+        let old = HashMap::from(
+            [("a", 1), ("b", 2), ("c", 3)].map(|(k, v)| (k.to_owned(), v)),
+        );
+        let new = HashMap::from(
+            [("b", 2), ("c", 4), ("d", 5)].map(|(k, v)| (k.to_owned(), v)),
+        );
+
+        assert_eq!(
+            ProxySetDiff::new(&old, &new),
+            ProxySetDiff {
+                added: vec!["d".to_owned()],
+                removed: vec!["a".to_owned()],
+                changed: vec!["c".to_owned()],
+            }
+        );
+        assert_eq!(ProxySetDiff::new(&new, &new), ProxySetDiff::default());
+    }
 }
@@ -1,4 +1,8 @@
-use super::ProxyProvider;
+use super::{ProxyProvider, ProxySetDiff};
+#[cfg(feature = "hysteria2")]
+use crate::proxy::hysteria2;
+#[cfg(all(unix, feature = "icmp"))]
+use crate::proxy::icmp;
 #[cfg(feature = "shadowsocks")]
 use crate::proxy::shadowsocks;
 #[cfg(feature = "ssh")]
@@ -15,15 +19,15 @@ use crate::{
         healthcheck::HealthCheck,
         providers::{
             Provider, ProviderType, ProviderVehicleType, ThreadSafeProviderVehicle,
-            fetcher::Fetcher,
+            fetcher::{Fetcher, RetryPolicy},
         },
     },
     common::errors::map_io_error,
-    config::internal::proxy::OutboundProxyProtocol,
+    config::internal::proxy::{OutboundProxyProtocol, ProxyOverride},
     proxy::{
         AnyOutboundHandler,
         direct::{self},
-        hysteria2, reject, socks, trojan, vless, vmess,
+        reject, socks, trojan, vless, vmess,
     },
 };
 use async_trait::async_trait;
@@ -40,20 +44,27 @@ struct ProviderScheme {
     proxies: Option<Vec<HashMap<String, Value>>>,
 }
 
+type ProxyConfig = HashMap<String, Value>;
+
+/// A proxy and the config it was built from, kept to tell which proxies an
+/// update changed
+struct ParsedProxy {
+    handler: AnyOutboundHandler,
+    config: ProxyConfig,
+}
+
 struct Inner {
     proxies: Vec<AnyOutboundHandler>,
+    /// by proxy name
+    configs: HashMap<String, ProxyConfig>,
+    last_diff: ProxySetDiff,
     hc: Arc<HealthCheck>,
 }
 
-type ProxyUpdater = Box<
-    dyn Fn(Vec<AnyOutboundHandler>) -> BoxFuture<'static, ()>
-        + Send
-        + Sync
-        + 'static,
->;
-type ProxyParser = Box<
-    dyn Fn(&[u8]) -> anyhow::Result<Vec<AnyOutboundHandler>> + Send + Sync + 'static,
->;
+type ProxyUpdater =
+    Box<dyn Fn(Vec<ParsedProxy>) -> BoxFuture<'static, ()> + Send + Sync + 'static>;
+type ProxyParser =
+    Box<dyn Fn(&[u8]) -> anyhow::Result<Vec<ParsedProxy>> + Send + Sync + 'static>;
 
 pub struct ProxySetProvider {
     fetcher: Fetcher<ProxyUpdater, ProxyParser>,
@@ -66,6 +77,7 @@ impl ProxySetProvider {
         interval: Duration,
         vehicle: ThreadSafeProviderVehicle,
         hc: HealthCheck,
+        proxy_override: ProxyOverride,
     ) -> anyhow::Result<Self> {
         let hc = Arc::new(hc);
 
@@ -79,33 +91,42 @@ impl ProxySetProvider {
 
         let inner = Arc::new(tokio::sync::RwLock::new(Inner {
             proxies: vec![],
+            configs: HashMap::new(),
+            last_diff: ProxySetDiff::default(),
             hc: hc.clone(),
         }));
 
         let inner_clone = inner.clone();
 
         let n = name.clone();
-        let updater: ProxyUpdater = Box::new(
-            move |input: Vec<AnyOutboundHandler>| -> BoxFuture<'static, ()> {
+        let updater: ProxyUpdater =
+            Box::new(move |input: Vec<ParsedProxy>| -> BoxFuture<'static, ()> {
                 let hc = hc.clone();
                 let n = n.clone();
                 let inner: Arc<tokio::sync::RwLock<Inner>> = inner_clone.clone();
                 Box::pin(async move {
                     let mut inner = inner.write().await;
                     debug!("updating {} proxies for: {}", n, input.len());
-                    inner.proxies.clone_from(&input);
-                    hc.update(input).await;
+                    let configs = input
+                        .iter()
+                        .map(|p| (p.handler.name().to_owned(), p.config.clone()))
+                        .collect();
+                    inner.last_diff = ProxySetDiff::new(&inner.configs, &configs);
+                    inner.configs = configs;
+                    let proxies =
+                        input.into_iter().map(|p| p.handler).collect::<Vec<_>>();
+                    inner.proxies.clone_from(&proxies);
+                    hc.update(proxies).await;
                     // check once after update
                     tokio::spawn(async move {
                         hc.check().await;
                     });
                 })
-            },
-        );
+            });
 
         let n = name.clone();
         let parser: ProxyParser = Box::new(
-            move |input: &[u8]| -> anyhow::Result<Vec<AnyOutboundHandler>> {
+            move |input: &[u8]| -> anyhow::Result<Vec<ParsedProxy>> {
                 let scheme: ProviderScheme =
                     serde_yaml::from_slice(input).map_err(|x| {
                         Error::InvalidConfig(format!(
@@ -117,8 +138,15 @@ impl ProxySetProvider {
                     Some(proxies) => {
                         let proxies = proxies
                             .into_iter()
-                            .filter_map(|x| OutboundProxyProtocol::try_from(x).ok())
-                            .map(|x| match x {
+                            .filter_map(|mut config| {
+                                proxy_override.apply(&mut config);
+                                OutboundProxyProtocol::try_from(config.clone())
+                                    .ok()
+                                    .map(|x| (x, config))
+                            })
+                            .map(|(x, config)| {
+                                let handler: Result<AnyOutboundHandler, Error> =
+                                    match x {
                                 OutboundProxyProtocol::Direct(d) => {
                                     Ok(Arc::new(direct::Handler::new(&d.name)) as _)
                                 }
@@ -148,10 +176,16 @@ impl ProxySetProvider {
                                     let h: vless::Handler = vl.try_into()?;
                                     Ok(Arc::new(h) as _)
                                 }
+                                #[cfg(feature = "hysteria2")]
                                 OutboundProxyProtocol::Hysteria2(h) => {
                                     let h: hysteria2::Handler = h.try_into()?;
                                     Ok(Arc::new(h) as _)
                                 }
+                                #[cfg(all(unix, feature = "icmp"))]
+                                OutboundProxyProtocol::Icmp(i) => {
+                                    let h: icmp::Handler = i.try_into()?;
+                                    Ok(Arc::new(h) as _)
+                                }
                                 #[cfg(feature = "ssh")]
                                 OutboundProxyProtocol::Ssh(s) => {
                                     let h: ssh::Handler = s.try_into()?;
@@ -178,6 +212,14 @@ impl ProxySetProvider {
                                         sq.try_into()?;
                                     Ok(Arc::new(h) as _)
                                 }
+                                #[cfg(feature = "plugin")]
+                                OutboundProxyProtocol::Plugin(p) => {
+                                    crate::proxy::plugin::build_outbound(
+                                        &p.proto, p.config,
+                                    )
+                                }
+                                };
+                                handler.map(|handler| ParsedProxy { handler, config })
                             })
                             .collect::<Result<Vec<_>, crate::Error>>();
                         Ok(proxies?)
@@ -191,6 +233,11 @@ impl ProxySetProvider {
         let fetcher = Fetcher::new(name, interval, vehicle, parser, Some(updater));
         Ok(Self { fetcher, inner })
     }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.fetcher = self.fetcher.with_retry(retry);
+        self
+    }
 }
 
 #[async_trait]
@@ -217,17 +264,7 @@ impl Provider for ProxySetProvider {
     }
 
     async fn update(&self) -> std::io::Result<()> {
-        let (ele, same) = self.fetcher.update().await.map_err(map_io_error)?;
-        debug!(
-            "{} updated with {} proxies, same? {}",
-            self.name(),
-            ele.len(),
-            same
-        );
-        if !same && let Some(updater) = self.fetcher.on_update.as_ref() {
-            updater(ele).await;
-        }
-        Ok(())
+        self.update_and_diff().await.map(|_| ())
     }
 
     async fn as_map(&self) -> HashMap<String, Box<dyn ESerialize + Send>> {
@@ -244,6 +281,10 @@ impl Provider for ProxySetProvider {
             "updatedAt".to_owned(),
             Box::new(self.fetcher.updated_at().await),
         );
+        m.insert(
+            "nextRetryAt".to_owned(),
+            Box::new(self.fetcher.next_retry_at().await),
+        );
 
         m
     }
@@ -262,6 +303,23 @@ impl ProxyProvider for ProxySetProvider {
     async fn healthcheck(&self) {
         self.inner.read().await.hc.check().await;
     }
+
+    async fn update_and_diff(&self) -> std::io::Result<ProxySetDiff> {
+        let (ele, same) = self.fetcher.update().await.map_err(map_io_error)?;
+        debug!(
+            "{} updated with {} proxies, same? {}",
+            self.name(),
+            ele.len(),
+            same
+        );
+        if same {
+            return Ok(ProxySetDiff::default());
+        }
+        if let Some(updater) = self.fetcher.on_update.as_ref() {
+            updater(ele).await;
+        }
+        Ok(self.inner.read().await.last_diff.clone())
+    }
 }
 
 #[cfg(test)]
@@ -270,18 +328,21 @@ mod tests {
 
     use tokio::time::sleep;
 
-    use crate::app::{
-        dns::MockClashResolver,
-        remote_content_manager::{
-            ProxyManager,
-            healthcheck::HealthCheck,
-            providers::{
-                MockProviderVehicle, Provider, ProviderVehicleType,
-                proxy_provider::{
-                    ProxyProvider, proxy_set_provider::ProxySetProvider,
+    use crate::{
+        app::{
+            dns::MockClashResolver,
+            remote_content_manager::{
+                ProxyManager,
+                healthcheck::HealthCheck,
+                providers::{
+                    MockProviderVehicle, Provider, ProviderVehicleType,
+                    proxy_provider::{
+                        ProxyProvider, proxy_set_provider::ProxySetProvider,
+                    },
                 },
             },
         },
+        config::internal::proxy::ProxyOverride,
     };
 
     #[tokio::test]
@@ -327,6 +388,7 @@ proxies:
             Duration::from_secs(1),
             vehicle,
             hc,
+            ProxyOverride::default(),
         )
         .unwrap();
 
@@ -338,4 +400,64 @@ proxies:
 
         assert_eq!(provider.proxies().await.len(), 1);
     }
+
+    #[tokio::test]
+    async fn test_proxy_set_provider_override() {
+        let mut mock_vehicle = MockProviderVehicle::new();
+
+        mock_vehicle.expect_read().returning(|| {
+            Ok(r#"
+proxies:
+  - name: "ss"
+    type: ss
+    server: localhost
+    port: 8388
+    cipher: aes-256-gcm
+    password: "password"
+    udp: true
+  - name: "socks"
+    type: socks5
+    server: localhost
+    port: 1080
+"#
+            .as_bytes()
+            .to_vec())
+        });
+        mock_vehicle
+            .expect_path()
+            .return_const("/tmp/test_proxy_set_provider_override".to_owned());
+        mock_vehicle
+            .expect_typ()
+            .return_const(ProviderVehicleType::File);
+
+        let latency_manager =
+            ProxyManager::new(Arc::new(MockClashResolver::new()), None);
+        let hc = HealthCheck::new(
+            vec![],
+            "http://www.google.com".to_owned(),
+            0,
+            true,
+            latency_manager,
+        );
+
+        let provider = ProxySetProvider::new(
+            "test".to_owned(),
+            Duration::from_secs(1),
+            Arc::new(mock_vehicle),
+            hc,
+            ProxyOverride {
+                udp: Some(false),
+                skip_cert_verify: Some(true),
+            },
+        )
+        .unwrap();
+
+        provider.initialize().await.unwrap();
+
+        let proxies = provider.proxies().await;
+        assert_eq!(proxies.len(), 2);
+        for p in proxies {
+            assert!(!p.support_udp().await, "{}", p.name());
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use super::{
     dns::ThreadSafeDNSResolver,
+    net::find_interface,
     remote_content_manager::providers::{
         file_vehicle, http_vehicle,
         rule_provider::{RuleProviderImpl, ThreadSafeRuleProvider},
@@ -9,18 +10,29 @@ use crate::{
     Error,
     app::router::rules::{
         domain::Domain, domain_keyword::DomainKeyword, domain_suffix::DomainSuffix,
-        final_::Final, ipcidr::IpCidr, ruleset::RuleSet,
+        final_::Final, ipcidr::IpCidr, port::PortKind, ruleset::RuleSet,
+    },
+    config::internal::{
+        config::RuleProviderDef,
+        rule::{Rule, RuleType, SocketOverrides},
     },
-    config::internal::{config::RuleProviderDef, rule::RuleType},
     print_and_exit,
     session::Session,
 };
 
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
 use hyper::Uri;
 use rules::domain_regex::DomainRegex;
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
 mod rules;
 
@@ -29,7 +41,14 @@ pub use rules::RuleMatcher;
 
 pub struct Router {
     rules: Vec<Box<dyn RuleMatcher>>,
+    /// the socket options of each rule, by index
+    socket_overrides: Vec<SocketOverrides>,
+    /// how many sessions each rule matched, by index
+    hits: Vec<AtomicU64>,
+    /// whether any rule matches on ALPN, so TLS has to be sniffed
+    sniff_alpn: bool,
     dns_resolver: ThreadSafeDNSResolver,
+    rule_provider_registry: HashMap<String, ThreadSafeRuleProvider>,
 
     asn_mmdb: Option<MmdbLookup>,
 }
@@ -40,7 +59,7 @@ const MATCH: &str = "MATCH";
 
 impl Router {
     pub async fn new(
-        rules: Vec<RuleType>,
+        rules: Vec<Rule>,
         rule_providers: HashMap<String, RuleProviderDef>,
         dns_resolver: ThreadSafeDNSResolver,
         country_mmdb: Option<MmdbLookup>,
@@ -61,24 +80,46 @@ impl Router {
         .await
         .ok();
 
-        Self {
-            rules: rules
-                .into_iter()
-                .map(|r| {
+        let (rules, socket_overrides): (_, Vec<_>) = rules
+            .into_iter()
+            .map(|r| {
+                (
                     map_rule_type(
-                        r,
+                        r.rule_type,
                         country_mmdb.clone(),
                         geodata.clone(),
                         Some(&rule_provider_registry),
-                    )
-                })
-                .collect(),
+                    ),
+                    r.socket,
+                )
+            })
+            .unzip();
+        let hits = socket_overrides.iter().map(|_| AtomicU64::new(0)).collect();
+        let sniff_alpn = rules.iter().any(|r| r.type_name() == "ALPN");
+
+        Self {
+            rules,
+            socket_overrides,
+            hits,
+            sniff_alpn,
             dns_resolver,
+            rule_provider_registry,
 
             asn_mmdb,
         }
     }
 
+    /// The loaded rule-providers, by name
+    pub fn rule_providers(&self) -> &HashMap<String, ThreadSafeRuleProvider> {
+        &self.rule_provider_registry
+    }
+
+    /// Whether TCP connections should be sniffed for their ALPN before
+    /// routing. `ALPN` rules inside rule-sets don't turn this on.
+    pub fn sniff_alpn(&self) -> bool {
+        self.sniff_alpn
+    }
+
     /// this mutates the session, attaching resolved IP and ASN
     pub async fn match_route(
         &self,
@@ -86,7 +127,12 @@ impl Router {
     ) -> (&str, Option<&Box<dyn RuleMatcher>>) {
         let mut sess_resolved = false;
 
-        for r in self.rules.iter() {
+        for ((r, socket), hits) in self
+            .rules
+            .iter()
+            .zip(&self.socket_overrides)
+            .zip(&self.hits)
+        {
             if sess.destination.is_domain()
                 && r.should_resolve_ip()
                 && !sess_resolved
@@ -126,6 +172,8 @@ impl Router {
                     r.target(),
                     r.type_name()
                 );
+                hits.fetch_add(1, Ordering::Relaxed);
+                apply_socket_overrides(socket, sess);
                 return (r.target(), Some(r));
             }
         }
@@ -235,6 +283,41 @@ impl Router {
     pub fn get_all_rules(&self) -> &Vec<Box<dyn RuleMatcher>> {
         &self.rules
     }
+
+    /// How many sessions each rule matched since the config was loaded, in
+    /// the order of [`Self::get_all_rules`]
+    pub fn rule_hits(&self) -> Vec<u64> {
+        self.hits
+            .iter()
+            .map(|x| x.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+fn apply_socket_overrides(socket: &SocketOverrides, sess: &mut Session) {
+    if let Some(name) = &socket.interface {
+        match find_interface(name) {
+            Some(iface) => {
+                sess.iface = Some(iface);
+                sess.iface_from_rule = true;
+            }
+            None => warn!("interface {} of the matched rule not found", name),
+        }
+    }
+    if let Some(mark) = socket.routing_mark {
+        sess.so_mark = Some(mark);
+        sess.so_mark_from_rule = true;
+    }
+    if let Some(dscp) = socket.dscp {
+        sess.dscp = Some(dscp);
+        sess.dscp_from_rule = true;
+    }
+    if let Some(ip_version) = socket.ip_version {
+        sess.ip_version = Some(ip_version);
+    }
+    if let Some(tag) = &socket.tag {
+        sess.tag = Some(tag.clone());
+    }
 }
 
 pub fn map_rule_type(
@@ -310,12 +393,17 @@ pub fn map_rule_type(
         RuleType::SRCPort { target, port } => Box::new(rules::port::Port {
             port,
             target,
-            is_src: true,
+            kind: PortKind::Src,
         }),
         RuleType::DSTPort { target, port } => Box::new(rules::port::Port {
             port,
             target,
-            is_src: false,
+            kind: PortKind::Dst,
+        }),
+        RuleType::InPort { target, port } => Box::new(rules::port::Port {
+            port,
+            target,
+            kind: PortKind::In,
         }),
         RuleType::ProcessName {
             process_name,
@@ -354,6 +442,9 @@ pub fn map_rule_type(
         RuleType::Network { network, target } => {
             Box::new(rules::network::NetworkRule { network, target })
         }
+        RuleType::Alpn { alpn, target } => {
+            Box::new(rules::alpn::Alpn { alpn, target })
+        }
     }
 }
 
@@ -441,7 +532,10 @@ mod tests {
                     domain_suffix: "git.io".to_string(),
                     target: "DS2".to_string(),
                 },
-            ],
+            ]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
             Default::default(),
             mock_resolver,
             Some(Arc::new(mmdb)),
@@ -486,6 +580,7 @@ mod tests {
                 desc
             );
         }
+        assert_eq!(router.rule_hits(), vec![1, 1, 1, 1, 1]);
     }
 
     #[tokio::test]
@@ -506,7 +601,10 @@ mod tests {
                     network: crate::session::Network::Udp,
                     target: "UDP-PROXY".to_string(),
                 },
-            ],
+            ]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
             Default::default(),
             mock_resolver,
             None,
@@ -0,0 +1,57 @@
+use crate::{app::router::rules::RuleMatcher, session::Session};
+
+/// Matches TLS connections whose ClientHello offers `alpn`, e.g. `h2`.
+/// Sessions that weren't sniffed, which includes all UDP ones, never match.
+#[derive(Clone)]
+pub struct Alpn {
+    pub alpn: String,
+    pub target: String,
+}
+
+impl std::fmt::Display for Alpn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} alpn {}", self.target, self.alpn)
+    }
+}
+
+impl RuleMatcher for Alpn {
+    fn apply(&self, sess: &Session) -> bool {
+        sess.alpn
+            .as_ref()
+            .is_some_and(|offered| offered.contains(&self.alpn))
+    }
+
+    fn target(&self) -> &str {
+        self.target.as_str()
+    }
+
+    fn payload(&self) -> String {
+        self.alpn.clone()
+    }
+
+    fn type_name(&self) -> &str {
+        "ALPN"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpn_membership() {
+        let rule = Alpn {
+            alpn: "h2".to_owned(),
+            target: "PROXY".to_owned(),
+        };
+        let offered = |alpn: &[&str]| Session {
+            alpn: Some(alpn.iter().map(|x| x.to_string()).collect()),
+            ..Default::default()
+        };
+        assert!(rule.apply(&offered(&["h2", "http/1.1"])));
+        assert!(rule.apply(&offered(&["http/1.1", "h2"])));
+        assert!(!rule.apply(&offered(&["http/1.1"])));
+        assert!(!rule.apply(&offered(&[])));
+        assert!(!rule.apply(&Session::default()));
+    }
+}
@@ -1,10 +1,18 @@
 use crate::{app::router::rules::RuleMatcher, session::Session};
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PortKind {
+    Src,
+    Dst,
+    /// the port of the inbound listener the connection arrived on
+    In,
+}
+
 #[derive(Clone)]
 pub struct Port {
     pub port: u16,
     pub target: String,
-    pub is_src: bool,
+    pub kind: PortKind,
 }
 
 impl std::fmt::Display for Port {
@@ -13,7 +21,11 @@ impl std::fmt::Display for Port {
             f,
             "{} {} port {}",
             self.target,
-            if self.is_src { "src" } else { "dst" },
+            match self.kind {
+                PortKind::Src => "src",
+                PortKind::Dst => "dst",
+                PortKind::In => "in",
+            },
             self.port
         )
     }
@@ -21,10 +33,11 @@ impl std::fmt::Display for Port {
 
 impl RuleMatcher for Port {
     fn apply(&self, sess: &Session) -> bool {
-        if self.is_src {
-            sess.source.port() == self.port
-        } else {
-            sess.destination.port() == self.port
+        match self.kind {
+            PortKind::Src => sess.source.port() == self.port,
+            PortKind::Dst => sess.destination.port() == self.port,
+            // unknown for inbounds without a listener of their own, e.g. tun
+            PortKind::In => sess.local_addr.is_some_and(|x| x.port() == self.port),
         }
     }
 
@@ -40,3 +53,27 @@ impl RuleMatcher for Port {
         "Port"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_port() {
+        let rule = Port {
+            port: 7891,
+            target: "DIRECT".to_owned(),
+            kind: PortKind::In,
+        };
+        let sess = Session {
+            local_addr: Some(([127, 0, 0, 1], 7891).into()),
+            ..Default::default()
+        };
+        assert!(rule.apply(&sess));
+        assert!(!rule.apply(&Session {
+            local_addr: Some(([127, 0, 0, 1], 7890).into()),
+            ..Default::default()
+        }));
+        assert!(!rule.apply(&Session::default()));
+    }
+}
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+
+use erased_serde::Serialize;
+
 use crate::{
     app::{
         remote_content_manager::providers::rule_provider::ThreadSafeRuleProvider,
@@ -49,4 +53,17 @@ impl RuleMatcher for RuleSet {
     fn type_name(&self) -> &str {
         "RuleSet"
     }
+
+    fn as_map(&self) -> HashMap<String, Box<dyn Serialize + Send>> {
+        let mut m: HashMap<String, Box<dyn Serialize + Send>> = HashMap::new();
+        m.insert("type".to_string(), Box::new(self.type_name().to_owned()));
+        m.insert("proxy".to_string(), Box::new(self.target().to_owned()));
+        m.insert("payload".to_string(), Box::new(self.payload()));
+        m.insert("provider".to_string(), Box::new(self.rule_set.clone()));
+        m.insert(
+            "behavior".to_string(),
+            Box::new(self.rule_provider.behavior().to_string()),
+        );
+        m
+    }
 }
@@ -1,7 +1,34 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+/// The tracing target of the authentication audit log, e.g.
+/// `RUST_LOG=clash::audit::auth=info` to keep only that
+pub const AUDIT_TARGET: &str = "clash::audit::auth";
+
+/// At most one failure is logged per source within this interval, the
+/// others are counted and reported with the next one
+const FAILURE_LOG_INTERVAL: Duration = Duration::from_secs(60);
+/// Failures counted for the audit log when no ban is configured
+const DEFAULT_FAILURE_WINDOW: Duration = Duration::from_secs(600);
+/// How often stale sources are dropped
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+/// Sources tracked at most, so that failures from many addresses, e.g. a
+/// whole IPv6 prefix, can't grow the map without bound
+const MAX_TRACKED_SOURCES: usize = 4096;
 
 pub trait Authenticator {
-    fn authenticate(&self, username: &str, password: &str) -> bool;
+    /// Checks a login attempt from `src`, recording it in the audit log.
+    /// Always fails while `src` is banned.
+    fn authenticate(&self, src: IpAddr, username: &str, password: &str) -> bool;
+    /// Whether connections from `src` are refused after too many failed
+    /// attempts
+    fn banned(&self, src: IpAddr) -> bool;
     #[allow(unused)]
     fn users(&self) -> Vec<String>;
     fn enabled(&self) -> bool;
@@ -17,29 +44,234 @@ impl User {
     }
 }
 
+/// Temporarily refuse a source after too many failed attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BanPolicy {
+    /// failures within `window` that get a source banned
+    pub max_failures: u32,
+    pub window: Duration,
+    /// the first ban, doubled for every ban that follows within
+    /// `max_duration` of the previous one
+    pub duration: Duration,
+    pub max_duration: Duration,
+}
+
+impl BanPolicy {
+    fn ban_duration(&self, previous_bans: u32) -> Duration {
+        self.duration
+            .saturating_mul(1 << previous_bans.min(16))
+            .min(self.max_duration)
+    }
+}
+
+#[derive(Default)]
+struct SourceState {
+    /// times of the failures within the window, oldest first
+    failures: VecDeque<Instant>,
+    banned_until: Option<Instant>,
+    /// bans so far, for the exponential duration
+    bans: u32,
+    last_ban: Option<Instant>,
+    last_logged: Option<Instant>,
+    /// failures and refused attempts not logged since `last_logged`
+    suppressed: u32,
+}
+
+struct Sources {
+    by_ip: HashMap<IpAddr, SourceState>,
+    last_cleanup: Instant,
+}
+
+/// Logs authentication attempts to [`AUDIT_TARGET`] and bans sources that
+/// keep failing.
+pub struct AuthAudit {
+    ban: Option<BanPolicy>,
+    sources: Mutex<Sources>,
+}
+
+impl AuthAudit {
+    pub fn new(ban: Option<BanPolicy>) -> Self {
+        Self {
+            ban: ban.filter(|b| b.max_failures > 0),
+            sources: Mutex::new(Sources {
+                by_ip: HashMap::new(),
+                last_cleanup: Instant::now(),
+            }),
+        }
+    }
+
+    fn banned(&self, src: IpAddr, now: Instant) -> bool {
+        let mut sources = self.sources.lock().unwrap();
+        let Some(state) = sources.by_ip.get_mut(&src) else {
+            return false;
+        };
+        match state.banned_until {
+            Some(until) if until > now => {
+                state.suppressed += 1;
+                true
+            }
+            Some(_) => {
+                info!(
+                    target: AUDIT_TARGET,
+                    "ban of {} expired, {} attempts refused", src, state.suppressed
+                );
+                state.banned_until = None;
+                state.suppressed = 0;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn record(&self, src: IpAddr, username: &str, ok: bool, now: Instant) {
+        let mut sources = self.sources.lock().unwrap();
+        if now - sources.last_cleanup >= CLEANUP_INTERVAL {
+            self.cleanup(&mut sources.by_ip, now);
+            sources.last_cleanup = now;
+        }
+
+        if ok {
+            info!(
+                target: AUDIT_TARGET,
+                "authentication succeeded for user {} from {}", username, src
+            );
+            if let Some(state) = sources.by_ip.get_mut(&src) {
+                state.failures.clear();
+            }
+            return;
+        }
+
+        if !sources.by_ip.contains_key(&src)
+            && sources.by_ip.len() >= MAX_TRACKED_SOURCES
+        {
+            self.cleanup(&mut sources.by_ip, now);
+            sources.last_cleanup = now;
+            if sources.by_ip.len() >= MAX_TRACKED_SOURCES {
+                evict_one(&mut sources.by_ip, now);
+            }
+        }
+
+        let window = self.window();
+        let state = sources.by_ip.entry(src).or_default();
+        while state.failures.front().is_some_and(|t| now - *t >= window) {
+            state.failures.pop_front();
+        }
+        state.failures.push_back(now);
+
+        if state
+            .last_logged
+            .is_none_or(|t| now - t >= FAILURE_LOG_INTERVAL)
+        {
+            warn!(
+                target: AUDIT_TARGET,
+                "authentication failed for user {} from {} ({} recent failures, \
+                 {} not logged)",
+                username,
+                src,
+                state.failures.len(),
+                state.suppressed
+            );
+            state.last_logged = Some(now);
+            state.suppressed = 0;
+        } else {
+            state.suppressed += 1;
+        }
+
+        let Some(ban) = self.ban else {
+            return;
+        };
+        if state.failures.len() >= ban.max_failures as usize {
+            if state.last_ban.is_some_and(|t| now - t >= ban.max_duration) {
+                state.bans = 0;
+            }
+            let duration = ban.ban_duration(state.bans);
+            warn!(
+                target: AUDIT_TARGET,
+                "banning {} for {:?} after {} failed authentication attempts",
+                src,
+                duration,
+                state.failures.len()
+            );
+            state.banned_until = Some(now + duration);
+            state.bans += 1;
+            state.last_ban = Some(now);
+            state.failures.clear();
+        }
+    }
+
+    /// Drops the sources that are neither banned, nor have failures within
+    /// the window, nor a ban recent enough to make the next one longer.
+    fn cleanup(&self, by_ip: &mut HashMap<IpAddr, SourceState>, now: Instant) {
+        let window = self.window();
+        let max_duration = self.ban.map(|b| b.max_duration).unwrap_or_default();
+        by_ip.retain(|_, s| {
+            s.banned_until.is_some_and(|t| t > now)
+                || s.failures.back().is_some_and(|t| now - *t < window)
+                || s.last_ban.is_some_and(|t| now - t < max_duration)
+        });
+    }
+
+    fn window(&self) -> Duration {
+        self.ban.map_or(DEFAULT_FAILURE_WINDOW, |b| b.window)
+    }
+}
+
+/// Makes room for a source when none is stale: drops the one not banned
+/// that failed longest ago, or else the one whose ban ends first.
+fn evict_one(by_ip: &mut HashMap<IpAddr, SourceState>, now: Instant) {
+    let victim = by_ip
+        .iter()
+        .min_by_key(|(_, s)| {
+            let banned_until = s.banned_until.filter(|t| *t > now);
+            (
+                banned_until.is_some(),
+                banned_until.or(s.failures.back().copied()),
+            )
+        })
+        .map(|(ip, _)| *ip);
+    if let Some(ip) = victim {
+        by_ip.remove(&ip);
+    }
+}
+
 pub struct PlainAuthenticator {
     store: HashMap<String, String>,
     usernames: Vec<String>,
+    audit: AuthAudit,
 }
 
 impl PlainAuthenticator {
-    pub fn new(users: Vec<User>) -> Self {
+    pub fn new(users: Vec<User>, ban: Option<BanPolicy>) -> Self {
         let mut store = HashMap::new();
         let mut usernames = Vec::new();
         for user in users {
             store.insert(user.0.clone(), user.1.clone());
             usernames.push(user.0.clone());
         }
-        Self { store, usernames }
+        Self {
+            store,
+            usernames,
+            audit: AuthAudit::new(ban),
+        }
     }
 }
 
 impl Authenticator for PlainAuthenticator {
-    fn authenticate(&self, username: &str, password: &str) -> bool {
-        match self.store.get(username) {
+    fn authenticate(&self, src: IpAddr, username: &str, password: &str) -> bool {
+        let now = Instant::now();
+        if self.audit.banned(src, now) {
+            return false;
+        }
+        let ok = match self.store.get(username) {
             Some(p) => p == password,
             None => false,
-        }
+        };
+        self.audit.record(src, username, ok, now);
+        ok
+    }
+
+    fn banned(&self, src: IpAddr) -> bool {
+        self.audit.banned(src, Instant::now())
     }
 
     fn users(&self) -> Vec<String> {
@@ -50,3 +282,95 @@ impl Authenticator for PlainAuthenticator {
         !self.usernames.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    use super::{AuthAudit, BanPolicy, MAX_TRACKED_SOURCES};
+
+    fn policy() -> BanPolicy {
+        BanPolicy {
+            max_failures: 3,
+            window: Duration::from_secs(600),
+            duration: Duration::from_secs(60),
+            max_duration: Duration::from_secs(3600),
+        }
+    }
+
+    #[test]
+    fn test_ban_after_failures() {
+        let audit = AuthAudit::new(Some(policy()));
+        let a = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 3));
+        let now = Instant::now();
+
+        // a success resets the count
+        audit.record(a, "user", false, now);
+        audit.record(a, "user", false, now);
+        audit.record(a, "user", true, now);
+        audit.record(a, "user", false, now);
+        audit.record(a, "user", false, now);
+        assert!(!audit.banned(a, now));
+
+        audit.record(a, "user", false, now);
+        assert!(audit.banned(a, now));
+        assert!(!audit.banned(b, now));
+
+        assert!(!audit.banned(a, now + Duration::from_secs(61)));
+
+        // failures spread out over more than the window don't add up
+        for i in 0..5 {
+            audit.record(b, "user", false, now + Duration::from_secs(301 * i));
+        }
+        assert!(!audit.banned(b, now + Duration::from_secs(301 * 4)));
+    }
+
+    #[test]
+    fn test_ban_duration_grows() {
+        let audit = AuthAudit::new(Some(policy()));
+        let a = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2));
+        let mut now = Instant::now();
+
+        for expected in [60, 120, 240, 480] {
+            for _ in 0..3 {
+                audit.record(a, "user", false, now);
+            }
+            assert!(audit.banned(a, now + Duration::from_secs(expected - 1)));
+            now += Duration::from_secs(expected);
+            assert!(!audit.banned(a, now));
+        }
+
+        // forgotten after a quiet max-duration
+        now += Duration::from_secs(3600);
+        for _ in 0..3 {
+            audit.record(a, "user", false, now);
+        }
+        assert!(!audit.banned(a, now + Duration::from_secs(60)));
+
+        assert_eq!(policy().ban_duration(10), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn test_tracked_sources_capped() {
+        let audit = AuthAudit::new(Some(policy()));
+        let banned = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let now = Instant::now();
+        for _ in 0..3 {
+            audit.record(banned, "user", false, now);
+        }
+
+        // one failure each, within the window so none is stale
+        for i in 0..MAX_TRACKED_SOURCES as u32 + 16 {
+            let src = IpAddr::V4(Ipv4Addr::from(0xc0a8_0000 + i));
+            audit.record(src, "user", false, now);
+        }
+        let sources = audit.sources.lock().unwrap();
+        assert_eq!(sources.by_ip.len(), MAX_TRACKED_SOURCES);
+        drop(sources);
+        assert!(audit.banned(banned, now));
+    }
+}
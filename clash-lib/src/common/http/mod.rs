@@ -1,5 +1,79 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use http::HeaderMap;
+
 pub mod client;
 
 pub use client::*;
 
 pub const DEFAULT_USER_AGENT: &str = concat!("clash-rs/", env!("CARGO_PKG_VERSION"));
+
+/// Headers added to plain HTTP requests relayed by the HTTP inbound and to
+/// the requests of HTTP based transports, see `global-ua` and
+/// `global-headers`.
+static GLOBAL_HEADERS: LazyLock<RwLock<HeaderMap>> = LazyLock::new(Default::default);
+
+pub fn set_global_headers(headers: HeaderMap) {
+    *GLOBAL_HEADERS.write().unwrap() = headers;
+}
+
+/// Adds the global headers that `headers` doesn't have yet, so that the
+/// values set by the request or the outbound take precedence.
+pub fn merge_global_headers(headers: &mut HeaderMap) {
+    for (k, v) in GLOBAL_HEADERS.read().unwrap().iter() {
+        if !headers.contains_key(k) {
+            headers.insert(k, v.clone());
+        }
+    }
+}
+
+/// Same as [`merge_global_headers`], for the headers of an outbound as they
+/// are configured.
+pub fn with_global_headers(
+    headers: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut merged = headers.clone();
+    for (k, v) in GLOBAL_HEADERS.read().unwrap().iter() {
+        if let Ok(v) = v.to_str()
+            && !headers.keys().any(|x| x.eq_ignore_ascii_case(k.as_str()))
+        {
+            merged.insert(k.to_string(), v.to_owned());
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use http::{HeaderMap, HeaderValue, header};
+
+    use super::{merge_global_headers, set_global_headers, with_global_headers};
+
+    #[test]
+    fn test_global_headers_do_not_override() {
+        let mut global = HeaderMap::new();
+        global.insert(header::USER_AGENT, HeaderValue::from_static("global"));
+        global.insert("x-token", HeaderValue::from_static("abc"));
+        set_global_headers(global);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl"));
+        merge_global_headers(&mut headers);
+        assert_eq!(headers[header::USER_AGENT], "curl");
+        assert_eq!(headers["x-token"], "abc");
+
+        let configured =
+            HashMap::from([("User-Agent".to_owned(), "outbound".to_owned())]);
+        let merged = with_global_headers(&configured);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged["User-Agent"], "outbound");
+        assert_eq!(merged["x-token"], "abc");
+
+        set_global_headers(HeaderMap::new());
+    }
+}
@@ -16,6 +16,13 @@ use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 mod splice;
 #[cfg(all(target_os = "linux", feature = "zero_copy"))]
 pub use splice::zero_copy_bidirectional;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+mod uring;
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+pub use uring::uring_bidirectional;
+mod watchdog;
+
+pub use watchdog::{IdleReadTimeout, IdleReadWatchdog};
 
 use crate::{app::dispatcher::TrackedStream, proxy::ClientStream};
 
@@ -332,6 +339,17 @@ where
     }
 }
 
+/// Relay TCP connections on io_uring where possible, see the `io_uring`
+/// feature.
+pub fn set_io_uring_relay(enabled: bool) {
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    uring::set_enabled(enabled);
+    #[cfg(not(all(target_os = "linux", feature = "io_uring")))]
+    if enabled {
+        tracing::warn!("io_uring relay is not supported by this build, ignoring");
+    }
+}
+
 pub async fn copy_bidirectional(
     mut a: Box<dyn ClientStream>,
     mut b: TrackedStream,
@@ -356,6 +374,21 @@ pub async fn copy_bidirectional(
         match (a_raw, b_raw) {
             // zero copy is only available when both streams are raw TcpStream
             (Some(a), Some(wrapper)) => {
+                #[cfg(all(target_os = "linux", feature = "io_uring"))]
+                if uring::enabled()
+                    && let Some(rv) = uring::uring_bidirectional(
+                        a,
+                        wrapper.inner_mut(),
+                        r_tracker.clone(),
+                        w_tracker.clone(),
+                        a_to_b_timeout_duration,
+                        b_to_a_timeout_duration,
+                    )
+                    .await
+                {
+                    return rv;
+                }
+
                 tracing::trace!("using zero copy for bidirectional copy");
                 zero_copy_bidirectional(
                     a,
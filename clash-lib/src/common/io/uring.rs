@@ -0,0 +1,603 @@
+//! Bidirectional copy between two TCP sockets driven by io_uring, an
+//! alternative to the epoll based [`super::zero_copy_bidirectional`].
+//!
+//! A single driver thread owns the ring and relays every connection handed
+//! over to it, so that relaying doesn't cost a readiness event and a task
+//! wakeup per read and per write. The driver is started on first use, if the
+//! kernel doesn't support io_uring, or it's blocked e.g. by seccomp, callers
+//! fall back to the epoll path.
+//!
+//! The kernel reads and writes the buffers of a connection, and uses its
+//! fds, until the operations referencing them complete, not until they're
+//! submitted. So a [`Conn`] is only dropped once it has nothing in flight,
+//! its buffers are boxed so they don't move with it, and if the ring fails
+//! with operations in flight, the connections are leaked rather than freed.
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+
+use io_uring::{IoUring, opcode, squeue, types};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+};
+use tracing::{error, trace, warn};
+
+use crate::app::dispatcher::TrackCopy;
+
+use super::CopyBidirectionalError;
+
+type Tracker = Arc<dyn TrackCopy + Send + Sync>;
+
+const RING_ENTRIES: u32 = 1024;
+/// per direction of each connection
+const BUF_SIZE: usize = 16 * 1024;
+/// `user_data` of the eventfd poll that wakes the driver up for new jobs
+const WAKE: u64 = u64::MAX;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DRIVER: OnceLock<Option<Driver>> = OnceLock::new();
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Copies data in both directions between `a` and `b` on the io_uring
+/// driver, returns `None` if io_uring is not available.
+pub async fn uring_bidirectional(
+    a: &mut TcpStream,
+    b: &mut TcpStream,
+    read_tracker: Tracker,
+    write_tracker: Tracker,
+    a_to_b_timeout_duration: Duration,
+    b_to_a_timeout_duration: Duration,
+) -> Option<Result<(u64, u64), CopyBidirectionalError>> {
+    let driver = Driver::get()?;
+
+    // the driver works on duplicates, so that the sockets stay open until it's
+    // done with them even if this future is dropped
+    let fds = match (
+        a.as_fd().try_clone_to_owned(),
+        b.as_fd().try_clone_to_owned(),
+    ) {
+        (Ok(a), Ok(b)) => Arc::new([a, b]),
+        (Err(e), _) | (_, Err(e)) => {
+            return Some(Err(CopyBidirectionalError::Other(e)));
+        }
+    };
+    let closing = Arc::new(AtomicBool::new(false));
+    let (events, mut rx) = unbounded_channel();
+    if !driver.submit(Job {
+        fds: fds.clone(),
+        trackers: [write_tracker, read_tracker],
+        closing: closing.clone(),
+        events,
+    }) {
+        return None;
+    }
+
+    let mut guard = RelayGuard {
+        fds,
+        closing,
+        done: false,
+    };
+    let mut delay: Option<Pin<Box<tokio::time::Sleep>>> = None;
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Some(Event::HalfClosed(direction)) => {
+                    if delay.is_none() {
+                        // give the other direction some time to finish
+                        let timeout = if direction == 0 {
+                            b_to_a_timeout_duration
+                        } else {
+                            a_to_b_timeout_duration
+                        };
+                        delay = Some(Box::pin(tokio::time::sleep(timeout)));
+                    }
+                }
+                Some(Event::Done(rv)) => {
+                    guard.done = true;
+                    return Some(rv.map_err(CopyBidirectionalError::Other));
+                }
+                None => {
+                    return Some(Err(CopyBidirectionalError::Other(
+                        io::Error::other("io_uring relay stopped"),
+                    )));
+                }
+            },
+            _ = async { delay.as_mut().unwrap().await },
+                if delay.is_some() && !guard.closing.load(Ordering::Relaxed) =>
+            {
+                // the driver finishes once the pending operations are aborted
+                guard.close();
+            }
+        }
+    }
+}
+
+/// Aborts the relay, unless it's done
+struct RelayGuard {
+    fds: Arc<[OwnedFd; 2]>,
+    closing: Arc<AtomicBool>,
+    done: bool,
+}
+
+impl RelayGuard {
+    fn close(&self) {
+        self.closing.store(true, Ordering::Relaxed);
+        for fd in self.fds.iter() {
+            // Safety: the fd is kept open by `self.fds`
+            unsafe { libc::shutdown(fd.as_raw_fd(), libc::SHUT_RDWR) };
+        }
+    }
+}
+
+impl Drop for RelayGuard {
+    fn drop(&mut self) {
+        if !self.done {
+            self.close();
+        }
+    }
+}
+
+enum Event {
+    /// the direction got EOF and passed it on
+    HalfClosed(usize),
+    Done(io::Result<(u64, u64)>),
+}
+
+struct Job {
+    fds: Arc<[OwnedFd; 2]>,
+    /// of a to b and b to a
+    trackers: [Tracker; 2],
+    /// set when the relay is aborted, errors are expected from then on
+    closing: Arc<AtomicBool>,
+    events: UnboundedSender<Event>,
+}
+
+struct Driver {
+    jobs: mpsc::Sender<Job>,
+    wake: OwnedFd,
+}
+
+impl Driver {
+    fn get() -> Option<&'static Driver> {
+        DRIVER
+            .get_or_init(|| match Self::start() {
+                Ok(driver) => Some(driver),
+                Err(e) => {
+                    warn!("io_uring is not available, falling back to epoll: {}", e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    fn start() -> io::Result<Self> {
+        let ring = IoUring::new(RING_ENTRIES)?;
+        // Safety: takes no pointers
+        let wake = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if wake < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safety: the fd was just created and nothing else owns it
+        let wake = unsafe { OwnedFd::from_raw_fd(wake) };
+
+        let (jobs, rx) = mpsc::channel();
+        let relay = Relay {
+            ring,
+            jobs: rx,
+            wake: wake.as_raw_fd(),
+            conns: HashMap::new(),
+            next_id: 0,
+        };
+        std::thread::Builder::new()
+            .name("io-uring-relay".to_owned())
+            .spawn(move || {
+                let mut relay = relay;
+                if let Err(e) = relay.run() {
+                    error!("io_uring relay stopped: {}", e);
+                    relay.abandon();
+                }
+            })?;
+
+        Ok(Self { jobs, wake })
+    }
+
+    fn submit(&self, job: Job) -> bool {
+        if self.jobs.send(job).is_err() {
+            return false;
+        }
+        let one = 1u64;
+        // Safety: `one` is valid for the 8 bytes written, and the eventfd is
+        // kept open by `self.wake`
+        unsafe {
+            libc::write(
+                self.wake.as_raw_fd(),
+                &one as *const u64 as *const libc::c_void,
+                8,
+            )
+        };
+        true
+    }
+}
+
+struct Direction {
+    src: RawFd,
+    dst: RawFd,
+    /// read into and sent from by the kernel, never reallocated
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    /// whether the pending operation is a send of `buf[pos..len]`, or a recv
+    sending: bool,
+    /// whether the pending operation is a poll for the socket of the next
+    /// send or recv to be ready
+    polling: bool,
+    amount: u64,
+    tracker: Tracker,
+}
+
+impl Direction {
+    fn new(src: RawFd, dst: RawFd, tracker: Tracker) -> Self {
+        Self {
+            src,
+            dst,
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            sending: false,
+            polling: false,
+            amount: 0,
+            tracker,
+        }
+    }
+
+    fn next_op(&mut self, user_data: u64) -> squeue::Entry {
+        if self.polling {
+            let (fd, events) = if self.sending {
+                (self.dst, libc::POLLOUT)
+            } else {
+                (self.src, libc::POLLIN)
+            };
+            opcode::PollAdd::new(types::Fd(fd), events as u32).build()
+        } else if self.sending {
+            opcode::Send::new(
+                types::Fd(self.dst),
+                self.buf[self.pos..].as_ptr(),
+                (self.len - self.pos) as u32,
+            )
+            .flags(libc::MSG_NOSIGNAL)
+            .build()
+        } else {
+            opcode::Recv::new(
+                types::Fd(self.src),
+                self.buf.as_mut_ptr(),
+                self.buf.len() as u32,
+            )
+            .build()
+        }
+        .user_data(user_data)
+    }
+}
+
+struct Conn {
+    // keeps the fds of `dirs` open
+    _fds: Arc<[OwnedFd; 2]>,
+    dirs: [Direction; 2],
+    /// operations submitted and not completed yet, at most one per direction
+    inflight: usize,
+    error: Option<io::Error>,
+    closing: Arc<AtomicBool>,
+    events: UnboundedSender<Event>,
+}
+
+impl Conn {
+    /// Handles the completion of the pending operation of direction `d`,
+    /// returns the next one to submit.
+    fn advance(&mut self, id: u64, d: usize, res: i32) -> Option<squeue::Entry> {
+        let user_data = (id << 1) | d as u64;
+        let dir = &mut self.dirs[d];
+
+        if dir.polling && res >= 0 {
+            // ready, or hung up, in which case the retried operation reports it
+            dir.polling = false;
+            return Some(dir.next_op(user_data));
+        }
+        if res == -libc::EINTR {
+            return Some(dir.next_op(user_data));
+        }
+        if res == -libc::EAGAIN {
+            // the sockets are shared with tokio and stay non-blocking, wait
+            // for readiness before trying again
+            dir.polling = true;
+            return Some(dir.next_op(user_data));
+        }
+        if res < 0 {
+            if !self.closing.load(Ordering::Relaxed) && self.error.is_none() {
+                self.error = Some(io::Error::from_raw_os_error(-res));
+                // abort the other direction
+                self.closing.store(true, Ordering::Relaxed);
+                for dir in self.dirs.iter() {
+                    // Safety: the fds of `dirs` are kept open by `_fds`
+                    unsafe { libc::shutdown(dir.src, libc::SHUT_RDWR) };
+                }
+            }
+            return None;
+        }
+        if self.error.is_some() {
+            return None;
+        }
+
+        let n = res as usize;
+        if dir.sending {
+            dir.pos += n;
+            dir.amount += n as u64;
+            dir.tracker.track(n);
+            if dir.pos == dir.len {
+                dir.sending = false;
+            }
+            Some(dir.next_op(user_data))
+        } else if n == 0 {
+            // Safety: the fds of `dirs` are kept open by `_fds`
+            unsafe { libc::shutdown(dir.dst, libc::SHUT_WR) };
+            let _ = self.events.send(Event::HalfClosed(d));
+            None
+        } else {
+            dir.pos = 0;
+            dir.len = n;
+            dir.sending = true;
+            Some(dir.next_op(user_data))
+        }
+    }
+
+    fn finish(self) {
+        let rv = match self.error {
+            Some(e) => Err(e),
+            None => Ok((self.dirs[0].amount, self.dirs[1].amount)),
+        };
+        let _ = self.events.send(Event::Done(rv));
+    }
+
+    /// Ends the connection while its operations may still be in flight,
+    /// leaking what they reference.
+    fn abandon(self) {
+        let Conn {
+            _fds: fds,
+            dirs,
+            events,
+            ..
+        } = self;
+        for dir in dirs.iter() {
+            // Safety: the fds of `dirs` are kept open by `_fds`
+            unsafe { libc::shutdown(dir.src, libc::SHUT_RDWR) };
+        }
+        let _ = events
+            .send(Event::Done(Err(io::Error::other("io_uring relay stopped"))));
+        std::mem::forget(dirs);
+        std::mem::forget(fds);
+    }
+}
+
+struct Relay {
+    ring: IoUring,
+    jobs: mpsc::Receiver<Job>,
+    wake: RawFd,
+    conns: HashMap<u64, Conn>,
+    next_id: u64,
+}
+
+impl Relay {
+    fn run(&mut self) -> io::Result<()> {
+        self.arm_wake()?;
+
+        let mut completions = Vec::new();
+        loop {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            completions.extend(
+                self.ring
+                    .completion()
+                    .map(|cqe| (cqe.user_data(), cqe.result())),
+            );
+            for (user_data, res) in completions.drain(..) {
+                if user_data == WAKE {
+                    self.accept_jobs()?;
+                    self.arm_wake()?;
+                } else {
+                    self.complete(user_data >> 1, (user_data & 1) as usize, res)?;
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, entry: squeue::Entry) -> io::Result<()> {
+        // Safety: the entries reference the buffers and fds of a connection
+        // in `self.conns`, which is only removed once none of its operations
+        // are in flight, and the boxed buffers don't move with it. `next_op`
+        // keeps the ranges within the buffers. The wake poll references the
+        // eventfd of the `Driver`, which is never dropped.
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.ring.submit()?;
+        }
+        Ok(())
+    }
+
+    /// Ends every connection after the ring failed
+    fn abandon(&mut self) {
+        for (_, conn) in self.conns.drain() {
+            conn.abandon();
+        }
+    }
+
+    fn arm_wake(&mut self) -> io::Result<()> {
+        self.push(
+            opcode::PollAdd::new(types::Fd(self.wake), libc::POLLIN as u32)
+                .build()
+                .user_data(WAKE),
+        )
+    }
+
+    fn accept_jobs(&mut self) -> io::Result<()> {
+        let mut counter = 0u64;
+        // Safety: `counter` is valid for the 8 bytes read
+        unsafe {
+            libc::read(self.wake, &mut counter as *mut u64 as *mut libc::c_void, 8)
+        };
+
+        while let Ok(job) = self.jobs.try_recv() {
+            // the fds share their file status flags with the tokio sockets, so
+            // they are left non-blocking and EAGAIN is handled with a poll
+            let [a, b] = [job.fds[0].as_raw_fd(), job.fds[1].as_raw_fd()];
+
+            let id = self.next_id;
+            self.next_id += 1;
+            let [upload, download] = job.trackers;
+            let mut conn = Conn {
+                _fds: job.fds,
+                dirs: [Direction::new(a, b, upload), Direction::new(b, a, download)],
+                inflight: 2,
+                error: None,
+                closing: job.closing,
+                events: job.events,
+            };
+            let ops = [
+                conn.dirs[0].next_op(id << 1),
+                conn.dirs[1].next_op((id << 1) | 1),
+            ];
+            self.conns.insert(id, conn);
+            for op in ops {
+                self.push(op)?;
+            }
+            trace!("relaying connection {} on io_uring", id);
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self, id: u64, d: usize, res: i32) -> io::Result<()> {
+        let Some(conn) = self.conns.get_mut(&id) else {
+            return Ok(());
+        };
+        conn.inflight -= 1;
+
+        if let Some(op) = conn.advance(id, d, res) {
+            conn.inflight += 1;
+            return self.push(op);
+        }
+        if conn.inflight == 0
+            && let Some(conn) = self.conns.remove(&id)
+        {
+            conn.finish();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{Driver, uring_bidirectional};
+    use crate::app::dispatcher::TrackCopy;
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl TrackCopy for Counter {
+        fn track(&self, total: usize) {
+            self.0.fetch_add(total, Ordering::Relaxed);
+        }
+    }
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (connected, accepted) = tokio::join!(
+            TcpStream::connect(listener.local_addr().unwrap()),
+            listener.accept()
+        );
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_uring_bidirectional() {
+        if Driver::get().is_none() {
+            eprintln!("io_uring is not available, skipping");
+            return;
+        }
+        let (mut client, mut a) = pair().await;
+        let (mut b, mut server) = pair().await;
+        let upload = Arc::new(Counter::default());
+        let download = Arc::new(Counter::default());
+
+        let relay = tokio::spawn({
+            let (upload, download) = (upload.clone(), download.clone());
+            async move {
+                uring_bidirectional(
+                    &mut a,
+                    &mut b,
+                    download,
+                    upload,
+                    Duration::from_secs(1),
+                    Duration::from_secs(1),
+                )
+                .await
+            }
+        });
+
+        // larger than the relay buffer, so it takes several rounds
+        let request: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        let response = vec![0x5au8; 1234];
+        let client_side = async {
+            client.write_all(&request).await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.unwrap();
+            buf
+        };
+        let server_side = async {
+            let mut buf = Vec::new();
+            server.read_to_end(&mut buf).await.unwrap();
+            server.write_all(&response).await.unwrap();
+            server.shutdown().await.unwrap();
+            buf
+        };
+        let (received_by_client, received_by_server) =
+            tokio::join!(client_side, server_side);
+
+        let rv = relay.await.unwrap().expect("io_uring relay");
+        assert_eq!(received_by_server, request);
+        assert_eq!(received_by_client, response);
+        assert_eq!(rv.unwrap(), (request.len() as u64, response.len() as u64));
+        assert_eq!(upload.0.load(Ordering::Relaxed), request.len());
+        assert_eq!(download.0.load(Ordering::Relaxed), response.len());
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+/// The error a read fails with once [`IdleReadWatchdog`] fires, wrapped in an
+/// [`io::ErrorKind::TimedOut`] error.
+#[derive(thiserror::Error, Debug)]
+#[error("idle read timeout: nothing received for {0:?} after sending data")]
+pub struct IdleReadTimeout(pub Duration);
+
+/// Fails reads that see no data within `timeout` of a write, to catch
+/// black-holed connections that stay established while data stops flowing.
+///
+/// The watchdog is armed by the first write after a read and disarmed by the
+/// next read, so a connection that is idle in both directions is left alone.
+pub struct IdleReadWatchdog<S> {
+    inner: S,
+    timeout: Duration,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> IdleReadWatchdog<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            deadline: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleReadWatchdog<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                self.deadline = None;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => {
+                if let Some(deadline) = self.deadline.as_mut()
+                    && deadline.as_mut().poll(cx).is_ready()
+                {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        IdleReadTimeout(self.timeout),
+                    )));
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleReadWatchdog<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let rv = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = rv
+            && n > 0
+            && self.deadline.is_none()
+        {
+            self.deadline = Some(Box::pin(tokio::time::sleep(self.timeout)));
+            // the reader may already be parked on the inner stream only
+            cx.waker().wake_by_ref();
+        }
+        rv
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{IdleReadTimeout, IdleReadWatchdog};
+
+    #[tokio::test]
+    async fn test_idle_read_watchdog() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = IdleReadWatchdog::new(client, Duration::from_millis(50));
+        let mut buf = [0u8; 8];
+
+        // idle in both directions, not armed
+        let rv =
+            tokio::time::timeout(Duration::from_millis(200), client.read(&mut buf))
+                .await;
+        assert!(rv.is_err());
+
+        // answered in time
+        client.write_all(b"ping").await.unwrap();
+        server.write_all(b"pong").await.unwrap();
+        assert_eq!(client.read(&mut buf).await.unwrap(), 4);
+
+        // no answer
+        client.write_all(b"ping").await.unwrap();
+        let err = client.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(
+            err.get_ref()
+                .is_some_and(|e| e.downcast_ref::<IdleReadTimeout>().is_some())
+        );
+    }
+}
@@ -1,11 +1,31 @@
 use rustls::{
     RootCertStore,
-    client::{WebPkiServerVerifier, danger::ServerCertVerifier},
+    client::{Resumption, WebPkiServerVerifier, danger::ServerCertVerifier},
     pki_types::{CertificateDer, ServerName, UnixTime},
 };
 use tracing::warn;
 
-use std::sync::{Arc, LazyLock};
+use std::sync::{
+    Arc, LazyLock,
+    atomic::{AtomicBool, Ordering},
+};
+
+static TLS_SESSION_RESUMPTION: AtomicBool = AtomicBool::new(true);
+
+pub fn set_tls_session_resumption(enabled: bool) {
+    TLS_SESSION_RESUMPTION.store(enabled, Ordering::Relaxed);
+}
+
+/// Resumption policy for outbound TLS clients.
+/// Sessions are cached in memory per client config, so a config must be
+/// reused across connections for resumption to take effect.
+pub fn client_resumption() -> Resumption {
+    if TLS_SESSION_RESUMPTION.load(Ordering::Relaxed) {
+        Resumption::default()
+    } else {
+        Resumption::disabled()
+    }
+}
 
 pub static GLOBAL_ROOT_STORE: LazyLock<Arc<RootCertStore>> =
     LazyLock::new(global_root_store);
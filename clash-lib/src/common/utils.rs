@@ -87,6 +87,17 @@ pub fn default_bool_true() -> bool {
     true
 }
 
+/// Whether two proxy names are the same, ignoring emoji variation
+/// selectors which subscriptions and GUIs don't agree on, e.g. a name
+/// selected as `🇯🇵 Tokyo` for a proxy named `🇯🇵\u{FE0F} Tokyo`
+pub fn proxy_name_eq(a: &str, b: &str) -> bool {
+    let significant = |c: &char| !matches!(c, '\u{FE0E}' | '\u{FE0F}');
+    a == b
+        || a.chars()
+            .filter(significant)
+            .eq(b.chars().filter(significant))
+}
+
 pub fn serialize_duration<S>(
     duration: &std::time::Duration,
     serializer: S,
@@ -54,6 +54,7 @@ pub struct TunConfig {
     /// tun interface address for IPv6
     /// # Note
     /// - set this to enable IPv6 support in the tun interface
+    /// - ignored with `ipv6-disabled`, like the IPv6 `routes`
     /// - Example: `2001:fac::1/64`
     #[serde(alias = "gateway-v6")]
     pub gateway_v6: Option<String>,
@@ -70,6 +71,32 @@ pub struct TunConfig {
     /// setting to a list has the same effect as setting to true
     #[serde(default)]
     pub dns_hijack: DnsHijack,
+    /// The network stack TCP connections from the tun are terminated by
+    #[serde(default)]
+    pub stack: TunStack,
+}
+
+/// # Tradeoffs
+/// - `system` lets the OS terminate TCP, which is fast and behaves exactly like
+///   the OS does, e.g. window scaling and congestion control. It needs a free
+///   address in the tun subnet next to `gateway`, and the OS firewall must
+///   allow connections to the tun address.
+/// - `gvisor` terminates TCP in userspace, which works wherever a tun can be
+///   opened, including on a tun passed in by `fd://`.
+///
+/// UDP is handled in userspace by both.
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TunStack {
+    /// `system` on Linux and macOS when the tun is created by clash and
+    /// its subnet has room, `gvisor` otherwise
+    Auto,
+    /// Falls back to `gvisor` if it can't be set up
+    #[serde(alias = "mixed")]
+    System,
+    #[default]
+    #[serde(alias = "userspace")]
+    Gvisor,
 }
 
 #[derive(Serialize, Deserialize, Default, Copy, Clone)]
@@ -84,6 +111,98 @@ pub enum RunMode {
     Direct,
 }
 
+/// What to do with connections to reserved/bogon destinations, e.g.
+/// `0.0.0.0/8` or `240.0.0.0/4`
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum BogonPolicy {
+    /// drop them
+    #[default]
+    Block,
+    /// route them like any other destination
+    Allow,
+    /// send them DIRECT regardless of the rules
+    Direct,
+}
+
+/// What to do with connections to the addresses of the local machine, e.g.
+/// its LAN IP
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum LocalAddressPolicy {
+    /// send them DIRECT regardless of the rules
+    #[default]
+    Direct,
+    /// route them like any other destination
+    Allow,
+    /// drop them
+    Block,
+}
+
+/// Which address families a connection may resolve and dial
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpVersion {
+    /// IPv4 only
+    Ipv4,
+    /// IPv6 only
+    Ipv6,
+    /// whichever answers first
+    #[default]
+    Dual,
+    /// IPv4, or IPv6 if there is no IPv4 address
+    PreferIpv4,
+    /// IPv6, or IPv4 if there is no IPv6 address
+    PreferIpv6,
+}
+
+/// Which address family the default outbound interface is picked for
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum IpStrategy {
+    /// interfaces with an IPv4 address first
+    PreferIpv4,
+    /// interfaces with a global IPv6 address first, then any IPv6 address
+    #[default]
+    PreferIpv6,
+    /// only interfaces with an IPv4 address
+    Ipv4Only,
+    /// only interfaces with an IPv6 address
+    Ipv6Only,
+}
+
+/// Which resolver a proxy looks its server address up with
+#[derive(Serialize, Deserialize, Default, Copy, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServerResolver {
+    /// the resolver configured in `dns`, or the system one if it's disabled
+    #[default]
+    Dns,
+    /// the `default-nameserver`s only, e.g. when the `dns` nameservers are
+    /// reached through this very proxy
+    Bootstrap,
+    /// the system resolver
+    System,
+}
+
+impl FromStr for IpVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ipv4" => Ok(IpVersion::Ipv4),
+            "ipv6" => Ok(IpVersion::Ipv6),
+            "dual" => Ok(IpVersion::Dual),
+            "prefer-ipv4" => Ok(IpVersion::PreferIpv4),
+            "prefer-ipv6" => Ok(IpVersion::PreferIpv6),
+            _ => Err(Error::InvalidConfig(format!("invalid ip-version: {s}"))),
+        }
+    }
+}
+
 impl Display for RunMode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -150,6 +269,7 @@ impl Display for LogLevel {
 ///   enhanced-mode: fake-ip
 ///   fake-ip-range: 198.18.0.2/16 # Fake IP addresses pool CIDR
 ///   # use-hosts: true # lookup hosts and return IP record
+///   # use-system-hosts: false # also lookup the system hosts file
 ///
 ///   # Hostnames in this list will not be resolved with fake IPs
 ///   # i.e. questions to these domain names will always be answered with their
@@ -166,6 +286,7 @@ impl Display for LogLevel {
 ///     - 1.1.1.1 # default value
 ///     - tls://1.1.1.1:853 # DNS over TLS
 ///     - https://1.1.1.1/dns-query # DNS over HTTPS
+///     - h3://1.1.1.1/dns-query # DNS over HTTP/3, falls back to HTTP/2
 /// #    - dhcp://en0 # dns from dhcp
 ///
 /// allow-lan: true
@@ -371,8 +492,80 @@ pub struct Config {
 
     /// HTTP and SOCKS5 proxy authentication
     pub authentication: Vec<String>,
+    /// Temporarily refuse connections from source IPs that keep failing
+    /// `authentication`. Attempts are logged under the `clash::audit::auth`
+    /// target either way.
+    /// # Example
+    /// ```yaml
+    /// authentication-ban:
+    ///   max-failures: 5
+    ///   window: 600 # seconds the failures are counted over
+    ///   duration: 600 # seconds of the first ban, doubled on every repeat
+    ///   max-duration: 86400
+    /// ```
+    pub authentication_ban: Option<AuthenticationBan>,
     /// Allow connections from IP addresses other than local listening address
     pub allow_lan: Option<bool>,
+    /// Enable TCP Fast Open on the listeners of `port`, `socks-port`,
+    /// `mixed-port`, `redir-port` and `tproxy-port`, saving a round trip for
+    /// clients that support it. Linux and macOS only.
+    /// # Note
+    /// - entries in `listeners` set it with `tfo: true`, and the queue of
+    ///   pending TFO requests with `tfo-backlog` (default 256)
+    pub inbound_tfo: bool,
+    /// Use TCP Fast Open on outbound TCP connections, so that the first
+    /// write, e.g. a proxy handshake, goes with the SYN to servers that have
+    /// been connected to before. Linux only, a no-op elsewhere, including
+    /// Windows. Default is `false`.
+    /// # Note
+    /// - also needs client support in `net.ipv4.tcp_fastopen` on the host, and
+    ///   on the server side of the server
+    pub outbound_tfo: bool,
+    /// DSCP value, 0 to 63, to mark the packets of outbound connections
+    /// with, for QoS on the way. Set with `IP_TOS` for IPv4 and
+    /// `IPV6_TCLASS` for IPv6. Unix only.
+    /// # Note
+    /// - proxy groups set it for their members with `dscp`, and rules with the
+    ///   `dscp=` parameter, which take precedence
+    #[serde(deserialize_with = "super::utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
+    /// SO_SNDBUF, in bytes, of outbound UDP sockets, e.g. for Hysteria and
+    /// TUIC to take bursts without dropping packets. The OS default unless
+    /// set.
+    /// # Note
+    /// - the kernel may cap it, e.g. at `net.core.wmem_max` on Linux, which
+    ///   also doubles the value for its bookkeeping
+    /// # Example
+    /// ```yaml
+    /// udp-send-buffer: 4194304
+    /// ```
+    pub udp_send_buffer: Option<usize>,
+    /// SO_RCVBUF, in bytes, of outbound UDP sockets, capped at
+    /// `net.core.rmem_max` on Linux. The OS default unless set.
+    pub udp_recv_buffer: Option<usize>,
+    /// Maximum number of concurrent connections accepted from a single source
+    /// IP on the HTTP/SOCKS5/mixed inbounds. New connections beyond the limit
+    /// are refused with a protocol level error response. TProxy, redir and
+    /// tunnel inbounds are not limited.
+    /// # Note
+    /// - unset or `0` means unlimited
+    /// # Example
+    /// ```yaml
+    /// max-connections-per-ip: 256
+    /// ```
+    pub max_connections_per_ip: Option<usize>,
+    /// Maximum number of concurrent UDP sessions across all inbounds. Once
+    /// reached, the least recently used session is closed to make room for a
+    /// new one.
+    /// # Note
+    /// - unset or `0` means unlimited
+    /// - the current and peak number of sessions are available at `GET
+    ///   /connections/udp`
+    /// # Example
+    /// ```yaml
+    /// max-udp-sessions: 1024
+    /// ```
+    pub max_udp_sessions: Option<usize>,
     /// The address that the inbound listens on
     /// # Note
     /// - setting this to `*` will listen on all interfaces, which is
@@ -421,6 +614,14 @@ pub struct Config {
     /// this will affect the DNS server response to AAAA questions
     /// default is `false`
     pub ipv6: bool,
+    /// turns IPv6 off across the engine, for networks where it's broken
+    /// # Note
+    /// - the resolver drops AAAA results, and the API can't enable them
+    /// - outbound sockets are never created for IPv6, connecting to an IPv6
+    ///   address fails, and UDP sockets bind to `0.0.0.0`
+    /// - interface selection ignores the IPv6 addresses of interfaces
+    /// - the TUN device gets no `gateway-v6`, and IPv6 `routes` are skipped
+    pub ipv6_disabled: bool,
     /// external controller address
     pub external_controller: Option<String>,
 
@@ -438,16 +639,207 @@ pub struct Config {
     ///   - "https://example.com"
     #[serde(rename = "cors-allow-origins")]
     pub cors_allow_origins: Option<Vec<String>>,
-    /// outbound interface name
+    /// Outbound interface, by name or by one of its addresses
     /// # Note
-    /// - not implemented yet
+    /// - when an address is given, the interface that owns it is used, and
+    ///   outbound sockets are bound to both the interface and the address
+    /// - an IPv6 link-local address may carry the interface as its zone, in
+    ///   case it's on several
+    /// # Example
+    /// ```yaml
+    /// interface-name: eth0
+    /// interface-name: 192.168.1.2
+    /// interface-name: fe80::1%eth0
+    /// ```
+    #[serde(alias = "interface-name")]
     pub interface: Option<String>,
-    /// fwmark on Linux only
+    /// What to do when `interface` can't be found, e.g. when interface
+    /// enumeration is not permitted in a sandbox or container.
+    /// Default is `true`.
+    /// # Note
+    /// - `true`: outbound connections are not bound to any interface and follow
+    ///   the default routes
+    /// - `false`: outbound connections are refused, so that traffic never
+    ///   leaves through an unexpected interface
+    #[educe(Default = true)]
+    pub interface_fallback: bool,
+    /// Name fragments of the interfaces to prefer when picking the default
+    /// outbound interface, most preferred first. An interface matches the
+    /// first entry its name contains; those matching none come last.
+    /// Interfaces with a global IPv6 address are still preferred before
+    /// any of these.
+    /// Default is `[eth, en, pdp_ip, WLAN, wlp, Ethernet, vEthernet, Wi-Fi,
+    /// Tailscale]`.
+    /// # Example
+    /// ```yaml
+    /// interface-priority: [eth, usb, en, pdp_ip]
+    /// ```
+    pub interface_priority: Option<Vec<String>>,
+    /// Which address family to pick the default outbound interface for,
+    /// before `interface-priority` is looked at. One of `prefer-ipv6`
+    /// (default), `prefer-ipv4`, `ipv4-only` or `ipv6-only`.
+    /// # Note
+    /// - use `prefer-ipv4` or `ipv4-only` where IPv6 is configured but doesn't
+    ///   work, so that an interface with IPv4 wins
+    /// - `ipv4-only` skips interfaces that only have IPv6, and `ipv6-only`
+    ///   those that only have IPv4, leaving none to pick if there's no other
+    pub ip_strategy: IpStrategy,
+    /// Which address families outbound connections resolve their
+    /// destination to: `dual` (default), `ipv4`, `ipv6`, `prefer-ipv4` or
+    /// `prefer-ipv6`. The `ip-version` of a rule, and then of a proxy,
+    /// take precedence.
+    /// # Note
+    /// - use `ipv4` where IPv6 is configured but broken, so that nothing is
+    ///   dialed over it, along with `ip-strategy: ipv4-only`
+    /// - with fake-ip, clients get fake addresses of the `fake-ip-range` family
+    ///   only whatever this is; this applies to the lookups of the real
+    ///   addresses, made when connecting out
+    pub ip_version: IpVersion,
+    /// Seconds the default outbound interface is reused before the
+    /// interfaces are enumerated again. `0` looks every time.
+    /// Default is `5`.
+    #[educe(Default = 5)]
+    pub interface_cache_ttl: u64,
+    /// Seconds between looks at the interfaces for a change of the default
+    /// outbound interface, e.g. when roaming from Wi-Fi to Ethernet, which
+    /// new connections then go through. `0` turns it off.
+    /// Default is `5`.
+    /// # Note
+    /// - on Linux, macOS and Windows changes are also noticed as the OS reports
+    ///   them, within half a second
+    #[educe(Default = 5)]
+    pub interface_watch_interval: u64,
+    /// fwmark on Linux, the routing table (FIB) on FreeBSD
     /// # Note
     /// - traffics originated from clash will be marked with this value
     /// - so you can use this value to match the traffic in iptables to avoid
     ///   traffic loops
+    /// - ignored with a warning elsewhere, e.g. on macOS, where
+    ///   `interface-name` picks the route instead
     pub routing_mark: Option<u32>,
+    /// Allow outbound sockets to bind to a source address that is not (yet)
+    /// configured on any local interface, via `IP_FREEBIND`.
+    /// Linux only, default is `false`.
+    /// # Note
+    /// - useful for binding to floating/virtual IPs before they come up
+    /// - requires `CAP_NET_ADMIN` on some kernels, or the
+    ///   `net.ipv4.ip_nonlocal_bind` sysctl
+    pub freebind: bool,
+    /// Bind DIRECT connections to the local address the inbound connection
+    /// arrived on, so that on a multi-homed host the traffic leaves through
+    /// the uplink it came in from. Default is `false`.
+    /// # Note
+    /// - only applies to TCP connections accepted by the `http`, `socks`,
+    ///   `mixed` and `tunnel` listeners
+    /// - an `interface` set by the matched rule takes precedence, and
+    ///   connections that arrived on a loopback or wildcard address fall back
+    ///   to the global `interface`
+    pub direct_preserve_source: bool,
+    /// Outbound to send UDP through when the selected proxy doesn't support
+    /// it.
+    /// # Note
+    /// - when set, UDP is still attempted through proxies that don't declare
+    ///   `udp: true`, and only falls back if that fails within a few seconds
+    /// - the observed UDP support of each proxy is remembered until reload
+    /// # Example
+    /// ```yaml
+    /// udp-fallback: DIRECT
+    /// ```
+    pub udp_fallback: Option<String>,
+    /// Outbound the `GLOBAL` group selects in `global` mode, until another
+    /// one is selected through the API.
+    /// # Note
+    /// - with `profile.store-selected`, a selection made through the API is
+    ///   kept across restarts and takes precedence over this
+    /// # Example
+    /// ```yaml
+    /// global-default: Proxy
+    /// ```
+    pub global_default: Option<String>,
+    /// Drop QUIC connection attempts to HTTPS servers (UDP port 443), so that
+    /// browsers fall back to HTTP/2 over TCP. Other UDP traffic is not
+    /// affected.
+    /// # Note
+    /// - QUIC is recognized by the Initial packet that opens a connection,
+    ///   connections already established keep working
+    pub block_quic: bool,
+    /// Resume TLS sessions (session tickets / PSK) of TLS and QUIC based
+    /// outbounds to save a full handshake on reconnect, default is `true`.
+    /// Disable if resumption based linking of connections is a concern.
+    #[educe(Default = true)]
+    pub tls_session_resumption: bool,
+    /// Seconds to wait for outbound TCP connections, to proxy servers and
+    /// DIRECT destinations alike, to establish. Lower it on mobile links so
+    /// that fallback and url-test groups move on from a dead node sooner.
+    #[educe(Default = 10)]
+    pub connect_timeout: u64,
+    /// Milliseconds a connection attempt gets before the next address of a
+    /// dual-stack server is dialed too, Happy Eyeballs style (RFC 8305).
+    /// Proxies can override it with their own `happy-eyeballs-delay`, e.g.
+    /// a larger one for servers with fast IPv6, or `0` to race both
+    /// families at once.
+    #[educe(Default = 250)]
+    pub happy_eyeballs_delay: u64,
+    /// Seconds outbounds wait for the TLS and protocol handshake with the
+    /// proxy server once the TCP connection is up, `0` for no limit. A
+    /// timed out handshake fails with `handshake-timeout`, which groups
+    /// fail over on.
+    #[educe(Default = 10)]
+    pub handshake_timeout: u64,
+    /// How many more times the trojan, vmess and vless outbounds dial the
+    /// server when the connection is reset or closed during the handshake,
+    /// which some networks do to the first TLS handshake now and then.
+    /// Certificate and other handshake errors aren't retried. `0`, the
+    /// default, fails right away, or over to the next member of a group.
+    pub handshake_retries: u32,
+    /// Keepalive of accepted inbound connections, and TCP_NODELAY of
+    /// outbound ones. Raise the times on mobile or satellite links where
+    /// idle connections get dropped too early, or for long idle SSH and
+    /// database sessions.
+    /// # Example
+    /// ```yaml
+    /// tcp-keepalive:
+    ///   enabled: true # false turns keepalive off
+    ///   time: 10 # seconds idle before the first probe
+    ///   interval: 1 # seconds between probes
+    ///   retries: 3 # unanswered probes before giving up, ignored on Windows
+    ///   nodelay: true
+    /// ```
+    pub tcp_keepalive: TcpKeepalive,
+    /// User-Agent to send where the client or the outbound didn't set one,
+    /// shorthand for a `User-Agent` entry in `global-headers`.
+    /// # Example
+    /// ```yaml
+    /// global-ua: clash.meta
+    /// ```
+    pub global_ua: Option<String>,
+    /// Headers added to the plain HTTP requests relayed by the HTTP inbound,
+    /// and to the requests of HTTP based transports (`ws`, `h2`, vmess
+    /// `http` obfuscation).
+    /// # Note
+    /// - headers already set by the request, or by the `headers` of the
+    ///   outbound, take precedence
+    /// - requests tunneled with `CONNECT` are not touched
+    /// # Example
+    /// ```yaml
+    /// global-headers:
+    ///   X-Forwarded-Proto: https
+    /// ```
+    pub global_headers: Option<HashMap<String, String>>,
+    /// What to do with connections to reserved/bogon IP ranges, such as
+    /// `0.0.0.0/8`, `240.0.0.0/4` or documentation prefixes.
+    /// One of `block` (default), `allow` or `direct`.
+    /// # Note
+    /// - destinations given as domains are only checked when dialed directly,
+    ///   after being resolved
+    pub bogon_policy: BogonPolicy,
+    /// What to do with connections to one of the addresses of the local
+    /// interfaces, which would otherwise loop back through the proxy or fail.
+    /// One of `direct` (default), `allow` or `block`.
+    /// # Note
+    /// - the local addresses are enumerated again at most every 30 seconds
+    /// - like `bogon-policy`, destinations given as domains are not checked
+    pub local_address_policy: LocalAddressPolicy,
     #[serde(rename = "proxy-providers")]
     /// proxy provider settings
     pub proxy_provider: Option<HashMap<String, HashMap<String, Value>>>,
@@ -466,6 +858,24 @@ pub struct Config {
     /// ```
     pub tun: Option<TunConfig>,
 
+    /// extra inbound listeners
+    /// # Example
+    /// ```yaml
+    /// listeners:
+    ///   - name: socks-in
+    ///     type: socks
+    ///     port: 7891
+    ///     tfo: true
+    ///     tfo-backlog: 512
+    ///     # SO_REUSEPORT, not on Windows
+    ///     reuse-port: true
+    ///   - name: tproxy-in
+    ///     type: tproxy
+    ///     port: 7894
+    ///     # preserve: reuse a transparent reply socket per destination
+    ///     # per-packet: bind one for each reply, for `-m socket` setups
+    ///     udp-source-nat: preserve
+    /// ```
     pub listeners: Option<Vec<HashMap<String, Value>>>,
 }
 
@@ -543,7 +953,11 @@ pub struct DNS {
     pub ipv6: bool,
     /// Whether to `Config::hosts` as when resolving hostnames
     #[educe(Default = true)]
+    #[serde(alias = "use-hosts")]
     pub user_hosts: bool,
+    /// Whether to also consult the system hosts file, e.g. `/etc/hosts`.
+    /// Entries in `Config::hosts` take precedence.
+    pub use_system_hosts: bool,
     /// DNS servers
     pub nameserver: Vec<String>,
     /// Fallback DNS servers
@@ -560,7 +974,11 @@ pub struct DNS {
     pub fake_ip_range: String,
     /// Fake IP addresses filter
     pub fake_ip_filter: Vec<String>,
-    /// Default nameservers, used to resolve DoH hostnames
+    /// Default nameservers, used to bootstrap the resolution of the hostnames
+    /// of other nameservers and of proxy servers used to reach them.
+    /// Must be IP addresses, the system resolver is never used in their
+    /// place. They are asked one at a time, spreading queries over the ones
+    /// that answer and skipping ones that failed recently.
     #[educe(Default = vec![
       String::from("114.114.114.114"),
       String::from("8.8.8.8")]
@@ -570,6 +988,117 @@ pub struct DNS {
     pub nameserver_policy: HashMap<String, String>,
     /// Configure EDNS Client Subnet information to send with upstream queries
     pub edns_client_subnet: Option<EdnsClientSubnet>,
+    /// How to pick the answer when several nameservers are queried at once
+    /// # Example
+    /// ```yaml
+    /// query-policy: first-success
+    /// ```
+    pub query_policy: DNSQueryPolicy,
+    /// Answer queries for blocklisted domains locally instead of resolving
+    /// them. `domains` takes the same patterns as `fake-ip-filter`, and
+    /// `rule-sets` names `domain` behavior rule-providers; a query is blocked
+    /// when any of the lists matches.
+    /// # Example
+    /// ```yaml
+    /// block:
+    ///   response: sinkhole
+    ///   sinkhole-ipv4: 0.0.0.0
+    ///   sinkhole-ipv6: "::"
+    ///   domains:
+    ///     - ads.example.com
+    ///     - +.tracker.example.com
+    ///   rule-sets:
+    ///     - ad-domains
+    /// ```
+    pub block: DNSBlock,
+    /// Answers kept in the DNS cache, the least recently used are dropped
+    /// first. `0` disables the cache.
+    #[educe(Default = 4096)]
+    pub cache_size: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Educe)]
+#[serde(rename_all = "kebab-case", default)]
+#[educe(Default)]
+pub struct TcpKeepalive {
+    /// Send keepalive probes at all
+    #[educe(Default = true)]
+    pub enabled: bool,
+    /// Seconds a connection is idle before the first keepalive probe
+    #[educe(Default = 10)]
+    pub time: u64,
+    /// Seconds between unanswered probes
+    #[educe(Default = 1)]
+    pub interval: u64,
+    /// Unanswered probes before the connection is dropped. Windows doesn't
+    /// support setting it and ignores it.
+    #[educe(Default = 3)]
+    pub retries: u32,
+    /// Set TCP_NODELAY on outbound connections
+    #[educe(Default = true)]
+    pub nodelay: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Educe)]
+#[serde(rename_all = "kebab-case", default)]
+#[educe(Default)]
+pub struct AuthenticationBan {
+    /// Failed attempts from one IP within `window` before it's banned
+    #[educe(Default = 5)]
+    pub max_failures: u32,
+    /// Seconds failed attempts are counted over
+    #[educe(Default = 600)]
+    pub window: u64,
+    /// Seconds the IP is banned for the first time. Every ban within
+    /// `max-duration` of the previous one lasts twice as long.
+    #[educe(Default = 600)]
+    pub duration: u64,
+    /// Seconds a ban lasts at most
+    #[educe(Default = 86400)]
+    pub max_duration: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Educe)]
+#[serde(rename_all = "kebab-case", default)]
+#[educe(Default)]
+pub struct DNSBlock {
+    /// How blocked queries are answered
+    pub response: DNSBlockResponse,
+    /// The address returned for blocked A queries with `response: sinkhole`
+    #[educe(Default = String::from("0.0.0.0"))]
+    pub sinkhole_ipv4: String,
+    /// The address returned for blocked AAAA queries with `response:
+    /// sinkhole`
+    #[educe(Default = String::from("::"))]
+    pub sinkhole_ipv6: String,
+    /// Domains to block
+    pub domains: Vec<String>,
+    /// Names of `domain` behavior rule-providers whose domains are blocked
+    pub rule_sets: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DNSBlockResponse {
+    /// Answer with NXDOMAIN
+    #[default]
+    Nxdomain,
+    /// Answer with the sinkhole addresses
+    Sinkhole,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DNSQueryPolicy {
+    /// Take the first response, even if it has no records
+    #[default]
+    Fastest,
+    /// Take the first response that has records, a response without records
+    /// is only used once every nameserver has answered
+    FirstSuccess,
+    /// Like `first-success`, but only ask the `fallback` nameservers when
+    /// there are any
+    TrustedOnly,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -612,6 +1141,12 @@ pub struct EdnsClientSubnet {
 pub struct Experimental {
     /// buffer size for tcp stream bidirectional copy
     pub tcp_buffer_size: Option<usize>,
+    /// Relay TCP connections that are plain sockets on both ends, e.g. a
+    /// SOCKS5 inbound to DIRECT, with io_uring instead of epoll.
+    /// Linux only and requires the `io_uring` feature, falls back to epoll if
+    /// io_uring is not available.
+    #[serde(default)]
+    pub io_uring: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -626,6 +1161,8 @@ pub struct Profile {
     /// Store smart proxy group statistics and preferences
     #[serde(rename = "store-smart-stats")]
     pub store_smart_stats: bool,
+    /// Keep the traffic accounted per outbound interface across restarts
+    pub store_interface_traffic: bool,
 }
 
 impl Default for Profile {
@@ -634,6 +1171,7 @@ impl Default for Profile {
             store_selected: true,
             store_fake_ip: false,
             store_smart_stats: true,
+            store_interface_traffic: false,
         }
     }
 }
@@ -684,7 +1222,7 @@ mod tests {
 
     use crate::config::def::Port;
 
-    use super::Config;
+    use super::{Config, IpStrategy, IpVersion, TunStack};
 
     #[test]
     fn parse_simple() {
@@ -704,6 +1242,148 @@ mod tests {
         assert_eq!(c.port, Some(Port(9090)));
     }
 
+    #[test]
+    fn parse_tcp_keepalive() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.tcp_keepalive.time, 10);
+        assert!(c.tcp_keepalive.nodelay);
+
+        let cfg = r#"
+        tcp-keepalive:
+          time: 120
+          retries: 5
+        "#;
+        let c = cfg.parse::<Config>().expect("should parse");
+        assert_eq!(c.tcp_keepalive.time, 120);
+        assert_eq!(c.tcp_keepalive.interval, 1);
+        assert_eq!(c.tcp_keepalive.retries, 5);
+        assert!(c.tcp_keepalive.enabled);
+
+        let cfg = r#"
+        tcp-keepalive:
+          enabled: false
+        "#;
+        let c = cfg.parse::<Config>().expect("should parse");
+        assert!(!c.tcp_keepalive.enabled);
+        assert_eq!(c.tcp_keepalive.time, 10);
+    }
+
+    #[test]
+    fn parse_interface_priority() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert!(c.interface_priority.is_none());
+
+        let c = "interface-priority: [usb, pdp_ip]"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(
+            c.interface_priority,
+            Some(vec!["usb".to_owned(), "pdp_ip".to_owned()])
+        );
+    }
+
+    #[test]
+    fn parse_ip_version() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.ip_version, IpVersion::Dual);
+
+        let c = "ip-version: prefer-ipv4".parse::<Config>().unwrap();
+        assert_eq!(c.ip_version, IpVersion::PreferIpv4);
+
+        assert!("ip-version: ipv4-only".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn parse_ip_strategy() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.ip_strategy, IpStrategy::PreferIpv6);
+
+        let c = "ip-strategy: ipv4-only".parse::<Config>().unwrap();
+        assert_eq!(c.ip_strategy, IpStrategy::Ipv4Only);
+
+        assert!("ip-strategy: dual".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn parse_interface_cache_ttl() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.interface_cache_ttl, 5);
+
+        let c = "interface-cache-ttl: 0".parse::<Config>().unwrap();
+        assert_eq!(c.interface_cache_ttl, 0);
+    }
+
+    #[test]
+    fn parse_interface_watch_interval() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.interface_watch_interval, 5);
+
+        let c = "interface-watch-interval: 0".parse::<Config>().unwrap();
+        assert_eq!(c.interface_watch_interval, 0);
+    }
+
+    #[test]
+    fn parse_outbound_tfo() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert!(!c.outbound_tfo);
+
+        let c = "outbound-tfo: true".parse::<Config>().unwrap();
+        assert!(c.outbound_tfo);
+    }
+
+    #[test]
+    fn parse_udp_buffers() {
+        let c = "port: 7890".parse::<Config>().unwrap();
+        assert_eq!(c.udp_send_buffer, None);
+        assert_eq!(c.udp_recv_buffer, None);
+
+        let c = "udp-send-buffer: 4194304\nudp-recv-buffer: 8388608"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(c.udp_send_buffer, Some(4194304));
+        assert_eq!(c.udp_recv_buffer, Some(8388608));
+    }
+
+    #[test]
+    fn parse_dscp() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.dscp, None);
+
+        let c = "dscp: 46".parse::<Config>().unwrap();
+        assert_eq!(c.dscp, Some(46));
+
+        assert!("dscp: 64".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn parse_connect_timeout() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.connect_timeout, 10);
+
+        let c = "connect-timeout: 3".parse::<Config>().unwrap();
+        assert_eq!(c.connect_timeout, 3);
+    }
+
+    #[test]
+    fn parse_tun_stack() {
+        let c = "tun:\n  enable: true".parse::<Config>().unwrap();
+        assert_eq!(c.tun.unwrap().stack, TunStack::Gvisor);
+
+        let c = "tun:\n  enable: true\n  stack: mixed"
+            .parse::<Config>()
+            .unwrap();
+        assert_eq!(c.tun.unwrap().stack, TunStack::System);
+    }
+
+    #[test]
+    fn parse_happy_eyeballs_delay() {
+        let c = "port: 9090".parse::<Config>().unwrap();
+        assert_eq!(c.happy_eyeballs_delay, 250);
+
+        let c = "happy-eyeballs-delay: 0".parse::<Config>().unwrap();
+        assert_eq!(c.happy_eyeballs_delay, 0);
+    }
+
     #[test]
     fn parse_example() {
         let example_cfg = r###"
@@ -727,13 +1407,20 @@ socks-port: 7891
 #  - "user1:pass1"
 #  - "user2:pass2"
 
+# temporarily ban IPs that keep failing authentication
+# authentication-ban:
+#   max-failures: 5
+#   window: 600
+#   duration: 600
+#   max-duration: 86400
+
 # Set to true to allow connections to the local-end server from
 # other LAN IP addresses
 allow-lan: false
 
 tun:
   enable: true
-  stack: system
+  stack: gvisor # or system, auto. See `TunStack` for the tradeoffs
   device-id: dev://clash0
 
 # This is only applicable when `allow-lan` is `true`
@@ -755,6 +1442,10 @@ log-level: info
 # When set to false, resolver won't translate hostnames to IPv6 addresses
 ipv6: false
 
+# Turns IPv6 off across the engine, no IPv6 socket, interface address or tun
+# route is used; only do so when IPv6 is broken on your network
+# ipv6-disabled: true
+
 # RESTful web API listening address
 external-controller: 127.0.0.1:9090
 
@@ -770,6 +1461,11 @@ external-ui: folder
 
 # Outbound interface name
 interface-name: en0
+# Whether to go out through the default routes when the interface above
+# can't be found, rather than refusing connections
+# interface-fallback: true
+# Interfaces to prefer as the default outbound interface, by name
+# interface-priority: [eth, en, pdp_ip]
 
 # fwmark on Linux only
 routing-mark: 6666
@@ -780,10 +1476,13 @@ routing-mark: 6666
 # Non-wildcard domain names have a higher priority than wildcard domain names
 # e.g. foo.example.com > *.example.com > .example.com
 # P.S. +.foo.com equals to .foo.com and foo.com
+# Regular expressions, prefixed with `regex:`, are only tried when none of the
+# domains above match, longest expression first.
 hosts:
   # '*.clash.dev': 127.0.0.1
   # '.dev': 127.0.0.1
   # 'alpha.clash.dev': '::1'
+  # 'regex:^cdn\d+\.clash\.dev$': 127.0.0.1
 
 profile:
   # Store the `select` results in $HOME/.config/clash/.cache
@@ -794,6 +1493,10 @@ profile:
   # persistence fakeip
   store-fake-ip: true
 
+  # keep the traffic per outbound interface (GET /traffic/interfaces)
+  # across restarts
+  # store-interface-traffic: false
+
 # DNS server settings
 # This section is optional. When not present, the DNS server will be disabled.
 dns:
@@ -809,6 +1512,9 @@ dns:
   enhanced-mode: fake-ip # or redir-host (not recommended)
   fake-ip-range: 198.18.0.1/16 # Fake IP addresses pool CIDR
   # use-hosts: true # lookup hosts and return IP record
+  # use-system-hosts: false # also lookup the system hosts file
+  # cache-size: 4096 # answers cached, 0 disables the cache. Inspect with
+  # GET /dns/cache, flush with DELETE /dns/cache
 
   # Hostnames in this list will not be resolved with fake IPs
   # i.e. questions to these domain names will always be answered with their
@@ -825,6 +1531,7 @@ dns:
     - 8.8.8.8 # default value
     - tls://dns.rubyfish.cn:853 # DNS over TLS
     - https://1.1.1.1/dns-query # DNS over HTTPS
+    - h3://1.1.1.1/dns-query # DNS over HTTP/3, falls back to HTTP/2
     - dhcp://en0 # dns from dhcp
     # - '8.8.8.8#en0'
 
@@ -971,6 +1678,10 @@ proxies:
     tls: true
     servername: example.com
     # skip-cert-verify: true
+    # offer cipher suites and groups in a browser's order, for trojan, vmess
+    # and vless: chrome, firefox, safari, or randomized to pick one per
+    # connection. Only that order matches the browser, not the whole hello.
+    # client-fingerprint: chrome
     grpc-opts:
       grpc-service-name: "example"
 
@@ -1087,6 +1798,12 @@ proxy-groups:
       - vmess1
     # tolerance: 150
     # lazy: true
+    # health-check-method: head # or get (default), tcp to only time the connect
+    # also measure UDP jitter and packet loss with DNS queries to this server
+    # health-check-udp-server: 1.1.1.1:53
+    # prefer proxies exiting in Japan, requires the country mmdb
+    # expected-country: JP
+    # ip-echo-url: 'https://api.ipify.org'
     url: 'http://www.gstatic.com/generate_204'
     interval: 300
 
@@ -1099,6 +1816,11 @@ proxy-groups:
       - vmess1
     url: 'http://www.gstatic.com/generate_204'
     interval: 300
+    # whether to try the next proxy after an upstream error
+    # retry-on:
+    #   socks5-host-unreachable: true
+    #   socks5-auth-failed: false
+    #   other: true
 
   # load-balance: The request of the same eTLD+1 will be dial to the same proxy.
   - name: "load-balance"
@@ -1110,6 +1832,10 @@ proxy-groups:
     url: 'http://www.gstatic.com/generate_204'
     interval: 300
     # strategy: consistent-hashing # or round-robin
+    # hash-key: domain # or host, host-port; for consistent-hashing
+    # keep sending a destination through the same proxy for 10 minutes
+    # affinity-ttl: 600
+    # affinity-key: host # or host-port, domain
 
   # select is used for selecting proxy or proxy group
   # you can use RESTful API to switch proxy is recommended for use in GUI.
@@ -1148,7 +1874,13 @@ proxy-providers:
       enable: true
       interval: 600
       # lazy: true
+      # method: head # or get, tcp
+      # udp-server: 1.1.1.1:53
       url: http://www.gstatic.com/generate_204
+    # forced on every proxy of the provider
+    # override:
+    #   udp: true
+    #   skip-cert-verify: true
   test:
     type: file
     path: /test.yaml
@@ -1156,6 +1888,15 @@ proxy-providers:
       enable: true
       interval: 36000
       url: http://www.gstatic.com/generate_204
+  # a directory: the proxies of all its .yaml, .yml and .json files are merged,
+  # and it's checked for changes every `interval` seconds (default 5)
+  # nodes:
+  #   type: file
+  #   path: ./nodes
+  #   health-check:
+  #     enable: true
+  #     interval: 300
+  #     url: http://www.gstatic.com/generate_204
 
 rules:
   - DOMAIN-SUFFIX,google.com,auto
@@ -1167,7 +1908,16 @@ rules:
   - IP-CIDR,127.0.0.0/8,DIRECT
   - GEOIP,CN,DIRECT
   - DST-PORT,80,DIRECT
+  # optional socket params "interface", "routing-mark" and "dscp" for any rule,
+  # and "tag", which labels the matched connections in the API
+  - DST-PORT,6881,DIRECT,interface=eth1,routing-mark=0x200,tag=p2p
   - SRC-PORT,7777,DIRECT
+  # the port of the inbound the connection arrived on
+  - IN-PORT,7891,DIRECT
+  # a protocol offered in the TLS ClientHello, sniffed from TCP connections
+  # only: QUIC is not sniffed, so HTTP/3 never matches and needs e.g.
+  # NETWORK,UDP instead
+  - ALPN,h2,auto
   - RULE-SET,apple,REJECT # Premium only
   - MATCH,auto
   "###;
@@ -9,9 +9,13 @@ use crate::{
     },
     common::auth,
     config::{
-        def::{self, LogLevel, RunMode},
-        internal::{proxy::OutboundProxy, rule::RuleType},
+        def::{
+            self, BogonPolicy, IpStrategy, IpVersion, LocalAddressPolicy, LogLevel,
+            RunMode, TunStack,
+        },
+        internal::{proxy::OutboundProxy, rule::Rule},
     },
+    proxy::utils::TcpOptions,
 };
 use anyhow::anyhow;
 use ipnet::{IpNet, Ipv4Net, Ipv6Net};
@@ -20,6 +24,7 @@ use std::{
     collections::{HashMap, HashSet},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     str::FromStr,
+    time::Duration,
 };
 
 use super::{listener::InboundOpts, proxy::OutboundProxyProviderDef};
@@ -30,9 +35,10 @@ pub struct Config {
     pub tun: TunConfig,
     pub experimental: Option<def::Experimental>,
     pub profile: Profile,
-    pub rules: Vec<RuleType>,
+    pub rules: Vec<Rule>,
     pub rule_providers: HashMap<String, RuleProviderDef>,
     pub users: Vec<auth::User>,
+    pub auth_ban: Option<auth::BanPolicy>,
     /// a list maintaining the order from the config file
     pub proxy_names: Vec<String>,
     pub proxies: HashMap<String, OutboundProxy>,
@@ -53,6 +59,22 @@ impl Config {
                 )));
             }
         }
+        if let Some(fallback) = &self.general.udp_fallback
+            && !self.proxies.contains_key(fallback)
+            && !self.proxy_groups.contains_key(fallback)
+        {
+            return Err(Error::InvalidConfig(format!(
+                "udp-fallback proxy `{fallback}` was not found"
+            )));
+        }
+        if let Some(default) = &self.general.global_default
+            && !self.proxies.contains_key(default)
+            && !self.proxy_groups.contains_key(default)
+        {
+            return Err(Error::InvalidConfig(format!(
+                "global-default proxy `{default}` was not found"
+            )));
+        }
         Ok(self)
     }
 }
@@ -60,12 +82,36 @@ impl Config {
 pub struct General {
     pub authentication: Vec<String>,
     pub bind_address: BindAddress,
+    pub max_connections_per_ip: Option<usize>,
+    pub max_udp_sessions: Option<usize>,
     pub(crate) controller: Controller,
     pub mode: RunMode,
     pub log_level: LogLevel,
     pub ipv6: bool,
+    /// `ipv6-disabled`, IPv6 is off across the engine
+    pub ipv6_disabled: bool,
     pub interface: Option<Interface>,
+    pub interface_fallback: bool,
+    pub interface_priority: Option<Vec<String>>,
+    pub ip_strategy: IpStrategy,
+    pub ip_version: IpVersion,
+    pub interface_cache_ttl: Duration,
+    pub interface_watch_interval: Option<Duration>,
     pub routing_mask: Option<u32>,
+    pub freebind: bool,
+    pub dscp: Option<u8>,
+    pub direct_preserve_source: bool,
+    pub udp_fallback: Option<String>,
+    pub global_default: Option<String>,
+    pub block_quic: bool,
+    pub tls_session_resumption: bool,
+    pub handshake_timeout: Option<Duration>,
+    pub handshake_retries: u32,
+    pub tcp_options: TcpOptions,
+    pub udp_options: UdpOptions,
+    pub global_headers: http::HeaderMap,
+    pub bogon_policy: BogonPolicy,
+    pub local_address_policy: LocalAddressPolicy,
     pub mmdb: Option<String>,
     pub mmdb_download_url: Option<String>,
     pub asn_mmdb: Option<String>,
@@ -78,8 +124,9 @@ pub struct General {
 pub struct Profile {
     pub store_selected: bool,
     pub store_smart_stats: bool,
-    // this is read to dns config directly
-    // store_fake_ip: bool,
+    pub store_interface_traffic: bool,
+    /// the fake-ip store itself is picked by the dns config
+    pub store_fake_ip: bool,
 }
 
 #[derive(Default)]
@@ -94,6 +141,7 @@ pub struct TunConfig {
     pub so_mark: Option<u32>,
     pub route_table: u32,
     pub dns_hijack: bool,
+    pub stack: TunStack,
 }
 
 #[derive(Serialize, Clone, Debug, Copy, PartialEq, Hash, Eq)]
@@ -237,4 +285,49 @@ mod tests {
             _ => false,
         }));
     }
+
+    #[cfg(not(feature = "onion"))]
+    #[test]
+    fn protocol_not_compiled_in() {
+        let cfg = r#"
+        proxies:
+          - name: tor
+            type: tor
+        "#;
+        let err = convert(cfg.parse::<def::Config>().unwrap())
+            .err()
+            .expect("should fail");
+        assert!(err.to_string().contains("rebuild with the `onion` feature"));
+    }
+
+    #[test]
+    fn ipv6_disabled() {
+        let cfg = r#"
+        ipv6: true
+        ipv6-disabled: true
+        tun:
+          enable: true
+          gateway-v6: "fd00:fac::1/64"
+          routes:
+            - 1.1.1.1/32
+            - 2001:db8::/32
+        "#;
+        let cc = convert(cfg.parse::<def::Config>().unwrap()).unwrap();
+        assert!(cc.general.ipv6_disabled);
+        assert!(!cc.dns.ipv6);
+        assert_eq!(cc.tun.gateway_v6, None);
+        assert_eq!(cc.tun.routes, vec!["1.1.1.1/32".parse().unwrap()]);
+
+        // ipv6: false only keeps v6 out of dns
+        let cfg = r#"
+        ipv6: false
+        tun:
+          enable: true
+          gateway-v6: "fd00:fac::1/64"
+        "#;
+        let cc = convert(cfg.parse::<def::Config>().unwrap()).unwrap();
+        assert!(!cc.general.ipv6_disabled);
+        assert!(!cc.dns.ipv6);
+        assert!(cc.tun.gateway_v6.is_some());
+    }
 }
@@ -1,4 +1,6 @@
-use std::net::IpAddr;
+use std::time::Duration;
+
+use http::{HeaderMap, HeaderName, HeaderValue, header};
 
 use crate::{
     app::net::Interface,
@@ -6,16 +8,48 @@ use crate::{
         config::{BindAddress, Controller, General},
         def,
     },
+    proxy::utils::{TcpKeepaliveConfig, TcpOptions, UdpOptions},
 };
 
-pub(super) fn convert(c: &def::Config) -> Result<General, crate::Error> {
-    let bind_address = if c.bind_address == BindAddress::default() && c.ipv6 {
-        BindAddress::dual_stack()
-    } else {
-        c.bind_address
+fn convert_global_headers(c: &def::Config) -> Result<HeaderMap, crate::Error> {
+    let invalid = |k: &str, e: &dyn std::fmt::Display| {
+        crate::Error::InvalidConfig(format!("invalid global header {k}: {e}"))
     };
+    let mut headers = HeaderMap::new();
+    for (k, v) in c.global_headers.iter().flatten() {
+        let name = HeaderName::try_from(k.as_str()).map_err(|e| invalid(k, &e))?;
+        if name == header::HOST {
+            return Err(invalid(k, &"Host can't be set globally"));
+        }
+        let value = HeaderValue::try_from(v.as_str()).map_err(|e| invalid(k, &e))?;
+        headers.insert(name, value);
+    }
+    if let Some(ua) = &c.global_ua {
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::try_from(ua.as_str())
+                .map_err(|e| invalid("global-ua", &e))?,
+        );
+    }
+    Ok(headers)
+}
+
+pub(super) fn convert(c: &def::Config) -> Result<General, crate::Error> {
+    if c.connect_timeout == 0 {
+        return Err(crate::Error::InvalidConfig(
+            "connect-timeout must be at least 1 second".to_owned(),
+        ));
+    }
+    let bind_address =
+        if c.bind_address == BindAddress::default() && c.ipv6 && !c.ipv6_disabled {
+            BindAddress::dual_stack()
+        } else {
+            c.bind_address
+        };
     Ok(General {
         authentication: c.authentication.clone(),
+        max_connections_per_ip: c.max_connections_per_ip,
+        max_udp_sessions: c.max_udp_sessions,
         controller: Controller {
             external_controller: c.external_controller.clone(),
             external_ui: c.external_ui.clone(),
@@ -25,15 +59,49 @@ pub(super) fn convert(c: &def::Config) -> Result<General, crate::Error> {
         },
         mode: c.mode,
         log_level: c.log_level,
-        ipv6: c.ipv6,
-        interface: c.interface.as_ref().map(|iface| {
-            if let Ok(addr) = iface.parse::<IpAddr>() {
-                Interface::IpAddr(addr)
-            } else {
-                Interface::Name(iface.to_string())
-            }
-        }),
+        ipv6: c.ipv6 && !c.ipv6_disabled,
+        ipv6_disabled: c.ipv6_disabled,
+        interface: c
+            .interface
+            .as_ref()
+            .map(|iface| iface.parse::<Interface>().unwrap()),
+        interface_fallback: c.interface_fallback,
+        interface_priority: c.interface_priority.clone(),
+        ip_strategy: c.ip_strategy,
+        ip_version: c.ip_version,
+        interface_cache_ttl: Duration::from_secs(c.interface_cache_ttl),
+        interface_watch_interval: (c.interface_watch_interval > 0)
+            .then(|| Duration::from_secs(c.interface_watch_interval)),
         routing_mask: c.routing_mark,
+        freebind: c.freebind,
+        dscp: c.dscp,
+        direct_preserve_source: c.direct_preserve_source,
+        udp_fallback: c.udp_fallback.to_owned(),
+        global_default: c.global_default.to_owned(),
+        block_quic: c.block_quic,
+        tls_session_resumption: c.tls_session_resumption,
+        handshake_timeout: (c.handshake_timeout > 0)
+            .then(|| Duration::from_secs(c.handshake_timeout)),
+        handshake_retries: c.handshake_retries,
+        tcp_options: TcpOptions {
+            keepalive: TcpKeepaliveConfig {
+                time: Duration::from_secs(c.tcp_keepalive.time),
+                interval: Duration::from_secs(c.tcp_keepalive.interval),
+                retries: c.tcp_keepalive.retries,
+                enabled: c.tcp_keepalive.enabled,
+            },
+            nodelay: c.tcp_keepalive.nodelay,
+            fast_open: c.outbound_tfo,
+            connect_timeout: Duration::from_secs(c.connect_timeout),
+            attempt_delay: Duration::from_millis(c.happy_eyeballs_delay),
+        },
+        udp_options: UdpOptions {
+            send_buffer: c.udp_send_buffer,
+            recv_buffer: c.udp_recv_buffer,
+        },
+        global_headers: convert_global_headers(c)?,
+        bogon_policy: c.bogon_policy,
+        local_address_policy: c.local_address_policy,
         mmdb: c.mmdb.to_owned(),
         mmdb_download_url: c.mmdb_download_url.to_owned(),
         asn_mmdb: c.asn_mmdb.to_owned(),
@@ -9,7 +9,7 @@ use crate::{
         config::BindAddress,
         def::{self, Port},
         listener::{CommonInboundOpts, InboundOpts},
-        proxy::map_serde_error,
+        proxy::{map_serde_error, unsupported_protocol},
     },
 };
 
@@ -26,11 +26,12 @@ pub(super) fn convert(
     let tproxy_port = c.tproxy_port;
     #[cfg(feature = "redir")]
     let redir_port = c.redir_port;
-    let bind_address = if c.bind_address == BindAddress::default() && c.ipv6 {
-        BindAddress::dual_stack()
-    } else {
-        c.bind_address
-    };
+    let bind_address =
+        if c.bind_address == BindAddress::default() && c.ipv6 && !c.ipv6_disabled {
+            BindAddress::dual_stack()
+        } else {
+            c.bind_address
+        };
 
     let inbounds = raw
         .unwrap_or_default()
@@ -56,6 +57,9 @@ pub(super) fn convert(
                 port: http_port,
                 allow_lan: c.allow_lan.unwrap_or_default(),
                 fw_mark: c.routing_mark,
+                tfo: c.inbound_tfo,
+                tfo_backlog: None,
+                reuse_port: false,
             },
         })
     {
@@ -69,6 +73,9 @@ pub(super) fn convert(
                 port: socks_port,
                 allow_lan: c.allow_lan.unwrap_or_default(),
                 fw_mark: c.routing_mark,
+                tfo: c.inbound_tfo,
+                tfo_backlog: None,
+                reuse_port: false,
             },
             udp: true,
         })
@@ -83,6 +90,9 @@ pub(super) fn convert(
                 port: mixed_port,
                 allow_lan: c.allow_lan.unwrap_or_default(),
                 fw_mark: c.routing_mark,
+                tfo: c.inbound_tfo,
+                tfo_backlog: None,
+                reuse_port: false,
             },
             udp: true,
         })
@@ -98,6 +108,9 @@ pub(super) fn convert(
                 port: redir_port,
                 allow_lan: c.allow_lan.unwrap_or_default(),
                 fw_mark: c.routing_mark,
+                tfo: c.inbound_tfo,
+                tfo_backlog: None,
+                reuse_port: false,
             },
         })
     {
@@ -112,8 +125,12 @@ pub(super) fn convert(
                 port: tproxy_port,
                 allow_lan: c.allow_lan.unwrap_or_default(),
                 fw_mark: c.routing_mark,
+                tfo: c.inbound_tfo,
+                tfo_backlog: None,
+                reuse_port: false,
             },
             udp: true,
+            udp_source_nat: Default::default(),
         })
     {
         warn!("Duplicate TPROXY inbound listener found: {}", tproxy_port);
@@ -121,6 +138,13 @@ pub(super) fn convert(
     Ok(all_inbounds)
 }
 
+/// Listeners behind a cargo feature, see `unsupported_protocol`
+const OPTIONAL_LISTENERS: &[(&str, &str, bool)] = &[
+    ("tproxy", "tproxy", cfg!(feature = "tproxy")),
+    ("redir", "redir", cfg!(feature = "redir")),
+    ("shadowsocks", "shadowsocks", cfg!(feature = "shadowsocks")),
+];
+
 impl TryFrom<HashMap<String, Value>> for InboundOpts {
     type Error = crate::Error;
 
@@ -133,6 +157,12 @@ impl TryFrom<HashMap<String, Value>> for InboundOpts {
                 "missing field `name` in inbound listener".to_owned(),
             ))?
             .to_owned();
+        if let Some(typ) = mapping.get("type").and_then(|x| x.as_str())
+            && let Some(e) =
+                unsupported_protocol("listener", &name, typ, OPTIONAL_LISTENERS)
+        {
+            return Err(e);
+        }
         InboundOpts::deserialize(MapDeserializer::new(mapping.into_iter()))
             .map_err(map_serde_error(name))
     }
@@ -11,7 +11,7 @@ use crate::{
         def,
         internal::{
             proxy::{OutboundProxy, PROXY_DIRECT, PROXY_REJECT},
-            rule::RuleType,
+            rule::Rule,
         },
         proxy::{OutboundDirect, OutboundReject},
     },
@@ -58,10 +58,12 @@ pub(super) fn convert(mut c: def::Config) -> Result<config::Config, crate::Error
         general: general::convert(&c)?,
         dns: (&c).try_into()?,
         experimental: c.experimental.take(),
-        tun: tun::convert(c.tun.take())?,
+        tun: tun::convert(c.tun.take(), c.ipv6_disabled)?,
         profile: Profile {
             store_selected: c.profile.store_selected,
             store_smart_stats: c.profile.store_smart_stats,
+            store_interface_traffic: c.profile.store_interface_traffic,
+            store_fake_ip: c.profile.store_fake_ip,
         },
         rules: c
             .rule
@@ -69,7 +71,7 @@ pub(super) fn convert(mut c: def::Config) -> Result<config::Config, crate::Error
             .unwrap_or_default()
             .into_iter()
             .map(|x| {
-                x.parse::<RuleType>()
+                x.parse::<Rule>()
                     .map_err(|x| Error::InvalidConfig(x.to_string()))
             })
             .collect::<Result<Vec<_>, _>>()?,
@@ -85,6 +87,12 @@ pub(super) fn convert(mut c: def::Config) -> Result<config::Config, crate::Error
                 auth::User::new(username, password)
             })
             .collect(),
+        auth_ban: c.authentication_ban.as_ref().map(|b| auth::BanPolicy {
+            max_failures: b.max_failures,
+            window: std::time::Duration::from_secs(b.window),
+            duration: std::time::Duration::from_secs(b.duration),
+            max_duration: std::time::Duration::from_secs(b.max_duration),
+        }),
         proxies: c.proxy.take().unwrap_or_default().into_iter().try_fold(
             HashMap::from([
                 (
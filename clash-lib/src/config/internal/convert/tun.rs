@@ -1,3 +1,5 @@
+use tracing::warn;
+
 use crate::{
     Error,
     config::{config, def},
@@ -5,6 +7,26 @@ use crate::{
 
 pub fn convert(
     before: Option<def::TunConfig>,
+    ipv6_disabled: bool,
+) -> Result<config::TunConfig, crate::Error> {
+    let mut tun = convert_tun(before)?;
+    if ipv6_disabled {
+        if tun.gateway_v6.take().is_some() {
+            warn!("ipv6 is disabled, ignoring tun gateway-v6");
+        }
+        tun.routes.retain(|r| {
+            let keep = r.addr().is_ipv4();
+            if !keep {
+                warn!("ipv6 is disabled, ignoring tun route {r}");
+            }
+            keep
+        });
+    }
+    Ok(tun)
+}
+
+fn convert_tun(
+    before: Option<def::TunConfig>,
 ) -> Result<config::TunConfig, crate::Error> {
     match before {
         Some(t) => Ok(config::TunConfig {
@@ -39,6 +61,7 @@ pub fn convert(
                 def::DnsHijack::Switch(b) => b,
                 def::DnsHijack::List(_) => true,
             },
+            stack: t.stack,
         }),
         None => Ok(config::TunConfig::default()),
     }
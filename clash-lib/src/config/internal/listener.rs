@@ -33,6 +33,9 @@ pub enum InboundOpts {
         common_opts: CommonInboundOpts,
         #[serde(default = "default_bool_true")]
         udp: bool,
+        /// How UDP replies are sent back from the original destination
+        #[serde(rename = "udp-source-nat", default)]
+        udp_source_nat: SourceNatMode,
     },
     #[cfg(feature = "redir")]
     #[serde(alias = "redir")]
@@ -101,4 +104,42 @@ pub struct CommonInboundOpts {
     pub port: u16,
     /// Linux routing mark
     pub fw_mark: Option<u32>,
+    /// TCP Fast Open on the listening socket
+    #[serde(default)]
+    pub tfo: bool,
+    /// Maximum number of pending TFO requests, default is 256
+    pub tfo_backlog: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets, so that several processes
+    /// can listen on the same port. Ignored on Windows, which has no
+    /// SO_REUSEPORT.
+    #[serde(default)]
+    pub reuse_port: bool,
+}
+
+/// How the tproxy inbound sends UDP replies to the client. Either way a
+/// reply leaves from a socket bound with `IP_TRANSPARENT` to the original
+/// destination the client sent to, so it gets the source it expects.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, Hash, Eq, PartialEq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceNatMode {
+    /// One reply socket per original destination, closed after 60s idle
+    #[default]
+    Preserve,
+    /// A reply socket bound for every reply and closed right after. Nothing
+    /// stays bound on the destination address, for setups whose
+    /// `-m socket --transparent` rules would otherwise steer the client's
+    /// next packets into the reply socket instead of the tproxy listener.
+    PerPacket,
+}
+
+const DEFAULT_TFO_BACKLOG: u32 = 256;
+
+impl CommonInboundOpts {
+    /// The TFO queue length, if TFO is enabled
+    pub fn tfo_queue(&self) -> Option<u32> {
+        self.tfo
+            .then(|| self.tfo_backlog.unwrap_or(DEFAULT_TFO_BACKLOG))
+    }
 }
@@ -1,4 +1,11 @@
-use crate::{Error, common::utils::default_bool_true, config::utils};
+use crate::{
+    Error,
+    common::utils::default_bool_true,
+    config::{
+        def::{IpVersion, ServerResolver},
+        utils,
+    },
+};
 use serde::{Deserialize, de::value::MapDeserializer};
 use serde_yaml::Value;
 #[cfg(feature = "shadowquic")]
@@ -6,6 +13,7 @@ use shadowquic::config::CongestionControl as SQCongestionControl;
 use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
+    net::SocketAddr,
 };
 use uuid::Uuid;
 
@@ -46,6 +54,38 @@ pub fn map_serde_error(
     }
 }
 
+/// Protocols behind a cargo feature: the `type` they're configured with,
+/// the feature, and whether this build has it
+const OPTIONAL_PROTOCOLS: &[(&str, &str, bool)] = &[
+    ("ss", "shadowsocks", cfg!(feature = "shadowsocks")),
+    ("wireguard", "wireguard", cfg!(feature = "wireguard")),
+    ("tor", "onion", cfg!(feature = "onion")),
+    ("tuic", "tuic", cfg!(feature = "tuic")),
+    ("hysteria2", "hysteria2", cfg!(feature = "hysteria2")),
+    ("ssh", "ssh", cfg!(feature = "ssh")),
+    ("shadowquic", "shadowquic", cfg!(feature = "shadowquic")),
+    ("icmp", "icmp", cfg!(all(unix, feature = "icmp"))),
+];
+
+/// An error naming the feature `proto` needs, if it's a protocol this build
+/// left out
+pub(crate) fn unsupported_protocol(
+    kind: &str,
+    name: &str,
+    proto: &str,
+    protocols: &[(&str, &str, bool)],
+) -> Option<Error> {
+    protocols
+        .iter()
+        .find(|(p, _, compiled)| *p == proto && !compiled)
+        .map(|(_, feature, _)| {
+            Error::InvalidConfig(format!(
+                "{kind} {name} is of type `{proto}`, which this build doesn't \
+                 support, rebuild with the `{feature}` feature"
+            ))
+        })
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum OutboundProxyProtocol {
@@ -73,6 +113,7 @@ pub enum OutboundProxyProtocol {
     #[cfg(feature = "tuic")]
     #[serde(rename = "tuic")]
     Tuic(OutboundTuic),
+    #[cfg(feature = "hysteria2")]
     #[serde(rename = "hysteria2")]
     Hysteria2(OutboundHysteria2),
     #[serde(rename = "ssh")]
@@ -81,6 +122,13 @@ pub enum OutboundProxyProtocol {
     #[serde(rename = "shadowquic")]
     #[cfg(feature = "shadowquic")]
     ShadowQuic(OutboundShadowQuic),
+    #[serde(rename = "icmp")]
+    #[cfg(all(unix, feature = "icmp"))]
+    Icmp(OutboundIcmp),
+    /// A protocol registered with [`crate::proxy::plugin::register_outbound`]
+    #[serde(skip)]
+    #[cfg(feature = "plugin")]
+    Plugin(OutboundPlugin),
 }
 
 impl OutboundProxyProtocol {
@@ -102,11 +150,16 @@ impl OutboundProxyProtocol {
             OutboundProxyProtocol::Tor(tor) => &tor.name,
             #[cfg(feature = "tuic")]
             OutboundProxyProtocol::Tuic(tuic) => &tuic.common_opts.name,
+            #[cfg(feature = "hysteria2")]
             OutboundProxyProtocol::Hysteria2(hysteria2) => &hysteria2.name,
             #[cfg(feature = "ssh")]
             OutboundProxyProtocol::Ssh(ssh) => &ssh.common_opts.name,
             #[cfg(feature = "shadowquic")]
             OutboundProxyProtocol::ShadowQuic(sq) => &sq.common_opts.name,
+            #[cfg(all(unix, feature = "icmp"))]
+            OutboundProxyProtocol::Icmp(icmp) => &icmp.name,
+            #[cfg(feature = "plugin")]
+            OutboundProxyProtocol::Plugin(p) => &p.name,
         }
     }
 }
@@ -122,6 +175,22 @@ impl TryFrom<HashMap<String, Value>> for OutboundProxyProtocol {
                 "missing field `name` in outbound proxy protocol".to_owned(),
             ))?
             .to_owned();
+        #[cfg(feature = "plugin")]
+        if let Some(proto) = mapping.get("type").and_then(|x| x.as_str())
+            && crate::proxy::plugin::is_registered(proto)
+        {
+            return Ok(OutboundProxyProtocol::Plugin(OutboundPlugin {
+                name,
+                proto: proto.to_owned(),
+                config: mapping,
+            }));
+        }
+        if let Some(proto) = mapping.get("type").and_then(|x| x.as_str())
+            && let Some(e) =
+                unsupported_protocol("proxy", &name, proto, OPTIONAL_PROTOCOLS)
+        {
+            return Err(e);
+        }
         OutboundProxyProtocol::deserialize(MapDeserializer::new(mapping.into_iter()))
             .map_err(map_serde_error(name))
     }
@@ -144,15 +213,30 @@ impl Display for OutboundProxyProtocol {
             OutboundProxyProtocol::Tor(_) => write!(f, "Tor"),
             #[cfg(feature = "tuic")]
             OutboundProxyProtocol::Tuic(_) => write!(f, "Tuic"),
+            #[cfg(feature = "hysteria2")]
             OutboundProxyProtocol::Hysteria2(_) => write!(f, "Hysteria2"),
             #[cfg(feature = "ssh")]
             OutboundProxyProtocol::Ssh(_) => write!(f, "Ssh"),
             #[cfg(feature = "shadowquic")]
             OutboundProxyProtocol::ShadowQuic(_) => write!(f, "ShadowQUIC"),
+            #[cfg(all(unix, feature = "icmp"))]
+            OutboundProxyProtocol::Icmp(_) => write!(f, "Icmp"),
+            #[cfg(feature = "plugin")]
+            OutboundProxyProtocol::Plugin(p) => write!(f, "{}", p.proto),
         }
     }
 }
 
+/// A proxy of a protocol registered at runtime, kept as written for the
+/// registered factory to parse.
+#[cfg(feature = "plugin")]
+#[derive(Debug, Clone)]
+pub struct OutboundPlugin {
+    pub name: String,
+    pub proto: String,
+    pub config: HashMap<String, Value>,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct CommonConfigOptions {
@@ -165,6 +249,23 @@ pub struct CommonConfigOptions {
     /// nothing
     #[serde(alias = "dialer-proxy")]
     pub connect_via: Option<String>,
+    /// seconds to wait for the remote to send anything after data was sent
+    /// to it before the connection is closed as black-holed
+    pub idle_read_timeout: Option<u64>,
+    /// which address families the server address is resolved to: `ipv4`,
+    /// `ipv6`, `dual` (default), `prefer-ipv4` or `prefer-ipv6`.
+    /// Only for shadowsocks, socks5, trojan, vmess and vless.
+    pub ip_version: Option<IpVersion>,
+    /// the resolver the server address is looked up with: `dns` (default)
+    /// for the `dns` config, `bootstrap` for its `default-nameserver`s only,
+    /// or `system`. Destinations are left to the server, or to the DNS
+    /// inside the tunnel for wireguard with `remote-dns-resolve`.
+    /// Only for shadowsocks, socks5, trojan, vmess, vless, tuic and
+    /// wireguard.
+    pub server_resolver: Option<ServerResolver>,
+    /// milliseconds, the global `happy-eyeballs-delay` for this server
+    /// only. Only for shadowsocks, socks5, trojan, vmess and vless.
+    pub happy_eyeballs_delay: Option<u64>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
@@ -238,6 +339,10 @@ pub struct OutboundTrojan {
     pub alpn: Option<Vec<String>>,
     pub sni: Option<String>,
     pub skip_cert_verify: Option<bool>,
+    /// `chrome`, `firefox`, `safari` or `randomized`: the cipher suites and
+    /// key exchange groups are offered in that browser's order, the rest of
+    /// the ClientHello is still rustls'
+    pub client_fingerprint: Option<String>,
     pub udp: Option<bool>,
     pub network: Option<String>,
     pub grpc_opts: Option<GrpcOpt>,
@@ -258,6 +363,10 @@ pub struct OutboundVmess {
     pub skip_cert_verify: Option<bool>,
     #[serde(alias = "servername")]
     pub server_name: Option<String>,
+    /// `chrome`, `firefox`, `safari` or `randomized`: the cipher suites and
+    /// key exchange groups are offered in that browser's order, the rest of
+    /// the ClientHello is still rustls'
+    pub client_fingerprint: Option<String>,
     pub network: Option<String>,
     pub ws_opts: Option<WsOpt>,
     pub h2_opts: Option<H2Opt>,
@@ -275,6 +384,10 @@ pub struct OutboundVless {
     pub skip_cert_verify: Option<bool>,
     #[serde(alias = "servername")]
     pub server_name: Option<String>,
+    /// `chrome`, `firefox`, `safari` or `randomized`: the cipher suites and
+    /// key exchange groups are offered in that browser's order, the rest of
+    /// the ClientHello is still rustls'
+    pub client_fingerprint: Option<String>,
     pub network: Option<String>,
     pub ws_opts: Option<WsOpt>,
     pub h2_opts: Option<H2Opt>,
@@ -336,7 +449,15 @@ pub struct OutboundTuic {
     /// millis
     pub gc_lifetime: Option<u64>,
     pub send_window: Option<u64>,
+    /// bytes the server may send on a stream before it's read, default 8MiB
     pub receive_window: Option<u64>,
+    /// bytes the server may send on the whole connection before it's read,
+    /// default 20MiB
+    pub receive_window_conn: Option<u64>,
+    /// bytes, the UDP payload size before path MTU discovery, default 1252
+    pub initial_mtu: Option<u16>,
+    /// UDP segmentation offload on Linux, default true
+    pub gso: Option<bool>,
 }
 
 #[cfg(feature = "shadowquic")]
@@ -372,6 +493,23 @@ pub struct OutboundShadowQuic {
     pub keep_alive_interval: Option<u32>,
 }
 
+/// Tunnels over ICMP echo, see [`crate::proxy::icmp`]. There's no port, and
+/// no `dialer-proxy` as ICMP can't go through another proxy.
+#[cfg(all(unix, feature = "icmp"))]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct OutboundIcmp {
+    pub name: String,
+    pub server: String,
+    /// authenticates the connections opened on the server
+    pub password: String,
+    #[serde(default = "default_bool_true")]
+    pub udp: bool,
+    pub idle_read_timeout: Option<u64>,
+    pub ip_version: Option<IpVersion>,
+    pub server_resolver: Option<ServerResolver>,
+}
+
 #[cfg(feature = "ssh")]
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
 #[serde(rename_all = "kebab-case")]
@@ -430,8 +568,31 @@ pub struct OutboundHysteria2 {
     pub fingerprint: Option<String>,
     pub udp_mtu: Option<u32>,
     pub disable_mtu_discovery: Option<bool>,
+    /// bytes, the UDP payload size before path MTU discovery, default 1252
+    pub initial_mtu: Option<u16>,
+    /// bbr, cubic or new_reno, default bbr
+    pub congestion_controller: Option<String>,
+    /// bytes the server may send on a stream before it's read, default 8MiB.
+    /// The window doesn't grow, so it's the maximum as well
+    #[serde(alias = "max-stream-receive-window")]
+    pub stream_receive_window: Option<u64>,
+    /// bytes the server may send on the whole connection before it's read,
+    /// default 20MiB
+    #[serde(alias = "max-connection-receive-window")]
+    pub connection_receive_window: Option<u64>,
+    /// UDP segmentation offload on Linux, default true
+    pub gso: Option<bool>,
     /// bbr congestion control window
     pub cwnd: Option<u64>,
+    /// interval in milliseconds of the PINGs sent to keep the idle QUIC
+    /// connection, and the NAT mapping to the server, alive. 0 disables
+    /// them, default 10000
+    pub keep_alive_interval: Option<u64>,
+    /// milliseconds without anything, PINGs included, acknowledged by the
+    /// server after which the connection is closed, and a new one is made
+    /// for the next stream. Must be larger than `keep-alive-interval`,
+    /// default 30000
+    pub idle_timeout: Option<u64>,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
@@ -505,6 +666,19 @@ pub struct OutboundGroupRelay {
     #[serde(rename = "use")]
     pub use_provider: Option<Vec<String>>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
     pub url: Option<String>,
 }
 
@@ -522,6 +696,39 @@ pub struct OutboundGroupUrlTest {
     pub lazy: Option<bool>,
     pub tolerance: Option<u16>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
+
+    /// How the proxies are checked: `get` (default), `head` or `tcp`
+    #[serde(rename = "health-check-method")]
+    pub health_check_method: Option<HealthCheckMethod>,
+    /// Also measure UDP jitter and packet loss of the proxies supporting
+    /// UDP, with DNS queries to this server
+    #[serde(rename = "health-check-udp-server")]
+    pub health_check_udp_server: Option<SocketAddr>,
+
+    /// ISO country code (e.g. "JP") of the preferred exit. When set, only
+    /// proxies whose exit IP is located in this country are considered,
+    /// unless none of them is, in which case all proxies are. The first
+    /// connection through the group waits for the exit IPs to be probed.
+    /// Requires the country mmdb.
+    #[serde(rename = "expected-country")]
+    pub expected_country: Option<String>,
+    /// URL returning the caller's IP address as plain text, used to find the
+    /// exit IP of each proxy (default: https://api.ipify.org)
+    #[serde(rename = "ip-echo-url")]
+    pub ip_echo_url: Option<String>,
 }
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
 pub struct OutboundGroupFallback {
@@ -536,6 +743,34 @@ pub struct OutboundGroupFallback {
     pub interval: u64,
     pub lazy: Option<bool>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
+
+    /// How the proxies are checked: `get` (default), `head` or `tcp`
+    #[serde(rename = "health-check-method")]
+    pub health_check_method: Option<HealthCheckMethod>,
+    /// Also measure UDP jitter and packet loss of the proxies supporting
+    /// UDP, with DNS queries to this server
+    #[serde(rename = "health-check-udp-server")]
+    pub health_check_udp_server: Option<SocketAddr>,
+
+    /// Whether to try the next proxy when one fails with a given upstream
+    /// error, e.g. `socks5-host-unreachable: true`, `socks5-auth-failed:
+    /// false` or `other: true` for failures not reported by the upstream
+    /// server. By default everything but authentication failures is retried.
+    #[serde(rename = "retry-on")]
+    pub retry_on: Option<HashMap<String, bool>>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
@@ -551,7 +786,41 @@ pub struct OutboundGroupLoadBalance {
     pub interval: u64,
     pub lazy: Option<bool>,
     pub strategy: Option<LoadBalanceStrategy>,
+    /// what `consistent-hashing` picks a proxy by: `domain` (default),
+    /// `host` or `host-port`
+    #[serde(rename = "hash-key")]
+    pub hash_key: Option<LoadBalanceHashKey>,
+    /// Seconds a destination keeps using the proxy it was first sent
+    /// through, whatever the strategy, as long as the proxy is alive.
+    /// Unset or 0 disables the affinity.
+    #[serde(rename = "affinity-ttl")]
+    pub affinity_ttl: Option<u64>,
+    /// what a destination is recognized by for `affinity-ttl`: `host`
+    /// (default), `host-port` or `domain`
+    #[serde(rename = "affinity-key")]
+    pub affinity_key: Option<LoadBalanceHashKey>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
+
+    /// How the proxies are checked: `get` (default), `head` or `tcp`
+    #[serde(rename = "health-check-method")]
+    pub health_check_method: Option<HealthCheckMethod>,
+    /// Also measure UDP jitter and packet loss of the proxies supporting
+    /// UDP, with DNS queries to this server
+    #[serde(rename = "health-check-udp-server")]
+    pub health_check_udp_server: Option<SocketAddr>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default)]
@@ -565,6 +834,21 @@ pub enum LoadBalanceStrategy {
     StickySession,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default)]
+pub enum LoadBalanceHashKey {
+    /// the registrable domain of the destination, e.g. `example.com` for
+    /// `www.example.com`, so that all subdomains share a proxy
+    #[default]
+    #[serde(rename = "domain")]
+    Domain,
+    /// the destination host
+    #[serde(rename = "host")]
+    Host,
+    /// the destination host and port
+    #[serde(rename = "host-port")]
+    HostPort,
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
 pub struct OutboundGroupSmart {
     pub name: String,
@@ -576,6 +860,19 @@ pub struct OutboundGroupSmart {
 
     pub lazy: Option<bool>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
     pub url: Option<String>,
 
     /// Maximum retries for failed connections (default: 3)
@@ -604,6 +901,19 @@ pub struct OutboundGroupSelect {
 
     pub url: Option<String>,
     pub icon: Option<String>,
+
+    /// Interface, by name or address, the members dial from unless the
+    /// matched rule or a nested group picks another
+    #[serde(rename = "interface-name")]
+    pub interface_name: Option<String>,
+    /// SO_MARK the members dial with unless the matched rule or a nested
+    /// group sets another
+    #[serde(rename = "routing-mark")]
+    pub routing_mark: Option<u32>,
+    /// DSCP value the members mark their packets with unless the matched
+    /// rule or a nested group sets another, 0 to 63
+    #[serde(default, deserialize_with = "utils::deserialize_dscp")]
+    pub dscp: Option<u8>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -623,6 +933,34 @@ pub struct OutboundHttpProvider {
     pub interval: u64,
     pub path: String,
     pub health_check: HealthCheck,
+    #[serde(default)]
+    pub retry: ProviderRetry,
+    #[serde(default, rename = "override")]
+    pub proxy_override: ProxyOverride,
+}
+
+/// Retry policy for failed provider fetches.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct ProviderRetry {
+    /// Extra attempts after a failed fetch, 0 disables retrying
+    pub attempts: u32,
+    /// Initial backoff in milliseconds, doubled after every attempt
+    pub backoff: u64,
+    /// Upper bound of the backoff in milliseconds. After all attempts fail,
+    /// the next fetch is scheduled after this delay instead of a full
+    /// interval.
+    pub max_backoff: u64,
+}
+
+impl Default for ProviderRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: 1000,
+            max_backoff: 60_000,
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -633,6 +971,30 @@ pub struct OutboundFileProvider {
     pub path: String,
     pub interval: Option<u64>,
     pub health_check: HealthCheck,
+    #[serde(default, rename = "override")]
+    pub proxy_override: ProxyOverride,
+}
+
+/// Fields forced on every proxy of a provider, as subscriptions often leave
+/// them out
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProxyOverride {
+    pub udp: Option<bool>,
+    /// only for the protocols that verify a certificate
+    pub skip_cert_verify: Option<bool>,
+}
+
+impl ProxyOverride {
+    /// Applies the overrides to the config of a proxy before it's parsed
+    pub fn apply(&self, config: &mut HashMap<String, Value>) {
+        if let Some(udp) = self.udp {
+            config.insert("udp".to_owned(), Value::Bool(udp));
+        }
+        if let Some(skip) = self.skip_cert_verify {
+            config.insert("skip-cert-verify".to_owned(), Value::Bool(skip));
+        }
+    }
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -641,6 +1003,28 @@ pub struct HealthCheck {
     pub url: String,
     pub interval: u64,
     pub lazy: Option<bool>,
+    #[serde(default)]
+    pub method: HealthCheckMethod,
+    /// Also measure UDP jitter and packet loss of the proxies supporting
+    /// UDP, with DNS queries to this server
+    #[serde(rename = "udp-server")]
+    pub udp_server: Option<SocketAddr>,
+}
+
+/// How a proxy is health checked against the test URL
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheckMethod {
+    /// request the URL with GET
+    #[default]
+    Get,
+    /// request the URL with HEAD, for servers that send a large body
+    Head,
+    /// only connect to the URL's host through the proxy and time that,
+    /// without sending a request
+    Tcp,
 }
 
 impl TryFrom<HashMap<String, Value>> for OutboundProxyProviderDef {
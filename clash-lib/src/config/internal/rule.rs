@@ -1,4 +1,4 @@
-use crate::{Error, print_and_exit};
+use crate::{Error, config::def::IpVersion, print_and_exit};
 use std::{fmt::Display, str::FromStr};
 
 pub enum RuleType {
@@ -45,6 +45,11 @@ pub enum RuleType {
         target: String,
         port: u16,
     },
+    /// the port of the inbound listener
+    InPort {
+        target: String,
+        port: u16,
+    },
     ProcessName {
         process_name: String,
         target: String,
@@ -64,6 +69,11 @@ pub enum RuleType {
         network: crate::session::Network,
         target: String,
     },
+    /// a protocol offered in the sniffed TLS ClientHello
+    Alpn {
+        alpn: String,
+        target: String,
+    },
 }
 
 impl RuleType {
@@ -79,11 +89,13 @@ impl RuleType {
             RuleType::SrcCidr { target, .. } => target,
             RuleType::SRCPort { target, .. } => target,
             RuleType::DSTPort { target, .. } => target,
+            RuleType::InPort { target, .. } => target,
             RuleType::ProcessName { target, .. } => target,
             RuleType::ProcessPath { target, .. } => target,
             RuleType::RuleSet { target, .. } => target,
             RuleType::Match { target } => target,
             RuleType::Network { target, .. } => target,
+            RuleType::Alpn { target, .. } => target,
         }
     }
 }
@@ -105,11 +117,13 @@ impl Display for RuleType {
             RuleType::SrcCidr { .. } => write!(f, "SRC-IP-CIDR"),
             RuleType::SRCPort { .. } => write!(f, "SRC-PORT"),
             RuleType::DSTPort { .. } => write!(f, "DST-PORT"),
+            RuleType::InPort { .. } => write!(f, "IN-PORT"),
             RuleType::ProcessName { .. } => write!(f, "PROCESS-NAME"),
             RuleType::ProcessPath { .. } => write!(f, "PROCESS-PATH"),
             RuleType::RuleSet { .. } => write!(f, "RULE-SET"),
             RuleType::Match { .. } => write!(f, "MATCH"),
             RuleType::Network { .. } => write!(f, "NETWORK"),
+            RuleType::Alpn { .. } => write!(f, "ALPN"),
         }
     }
 }
@@ -182,6 +196,12 @@ impl RuleType {
                     print_and_exit!("invalid port: {}", payload)
                 }),
             }),
+            "IN-PORT" => Ok(RuleType::InPort {
+                target: target.to_string(),
+                port: payload.parse().unwrap_or_else(|_| {
+                    print_and_exit!("invalid port: {}", payload)
+                }),
+            }),
             "PROCESS-NAME" => Ok(RuleType::ProcessName {
                 process_name: payload.to_string(),
                 target: target.to_string(),
@@ -213,6 +233,10 @@ impl RuleType {
                     target: target.to_string(),
                 })
             }
+            "ALPN" => Ok(RuleType::Alpn {
+                alpn: payload.to_string(),
+                target: target.to_string(),
+            }),
             _ => Err(Error::InvalidConfig(format!(
                 "unsupported rule type: {proto}"
             ))),
@@ -245,6 +269,98 @@ impl FromStr for RuleType {
     }
 }
 
+/// Socket options applied to the connections matched by a rule, given as
+/// `key=value` params after the target, e.g.
+/// `DST-PORT,6881,DIRECT,interface=eth1,routing-mark=0x200,dscp=8,
+/// ip-version=ipv4,tag=p2p`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SocketOverrides {
+    pub interface: Option<String>,
+    pub routing_mark: Option<u32>,
+    pub dscp: Option<u8>,
+    pub ip_version: Option<IpVersion>,
+    /// not a socket option, a label shown with the connections in the API
+    pub tag: Option<String>,
+}
+
+impl SocketOverrides {
+    fn parse(params: &[&str]) -> Result<Self, Error> {
+        let mut rv = Self::default();
+        for param in params {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+            let invalid =
+                || Error::InvalidConfig(format!("invalid rule param: {param}"));
+            match key.trim() {
+                "interface" => rv.interface = Some(value.trim().to_owned()),
+                "routing-mark" => {
+                    let value = value.trim();
+                    rv.routing_mark = Some(
+                        match value.strip_prefix("0x") {
+                            Some(hex) => u32::from_str_radix(hex, 16),
+                            None => value.parse(),
+                        }
+                        .map_err(|_| invalid())?,
+                    );
+                }
+                "dscp" => {
+                    rv.dscp = Some(
+                        value
+                            .trim()
+                            .parse()
+                            .ok()
+                            .filter(|x| *x < 64)
+                            .ok_or_else(invalid)?,
+                    );
+                }
+                "ip-version" => {
+                    rv.ip_version =
+                        Some(value.trim().parse().map_err(|_| invalid())?);
+                }
+                "tag" => {
+                    let value = value.trim();
+                    if value.is_empty() {
+                        return Err(invalid());
+                    }
+                    rv.tag = Some(value.to_owned());
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(rv)
+    }
+}
+
+/// A rule line of the config: what to match, where to send it and how.
+pub struct Rule {
+    pub rule_type: RuleType,
+    pub socket: SocketOverrides,
+}
+
+impl From<RuleType> for Rule {
+    fn from(rule_type: RuleType) -> Self {
+        Self {
+            rule_type,
+            socket: Default::default(),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rule_type = s.parse()?;
+        let parts = s.split(',').map(str::trim).collect::<Vec<&str>>();
+        let socket = match parts.as_slice() {
+            [_, _, _, params @ ..] => SocketOverrides::parse(params)?,
+            _ => Default::default(),
+        };
+        Ok(Self { rule_type, socket })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,4 +401,55 @@ mod tests {
         let rule = RuleType::try_from("NETWORK,INVALID,PROXY".to_string());
         assert!(rule.is_err());
     }
+
+    #[test]
+    fn test_alpn_rule_parsing() {
+        let rule = RuleType::try_from("ALPN,h2,PROXY".to_string()).unwrap();
+        match rule {
+            RuleType::Alpn { alpn, target } => {
+                assert_eq!(alpn, "h2");
+                assert_eq!(target, "PROXY");
+            }
+            _ => panic!("Expected Alpn rule"),
+        }
+    }
+
+    #[test]
+    fn test_rule_socket_overrides() {
+        let rule: Rule = "DST-PORT,6881,DIRECT,interface=eth1,routing-mark=0x200,\
+                          dscp=8,ip-version=prefer-ipv6,tag=p2p"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.rule_type.target(), "DIRECT");
+        assert_eq!(
+            rule.socket,
+            SocketOverrides {
+                interface: Some("eth1".to_owned()),
+                routing_mark: Some(0x200),
+                dscp: Some(8),
+                ip_version: Some(IpVersion::PreferIpv6),
+                tag: Some("p2p".to_owned()),
+            }
+        );
+
+        let rule: Rule = "IP-CIDR,10.0.0.0/8,DIRECT,no-resolve,routing-mark=512"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            rule.rule_type,
+            RuleType::IpCidr {
+                no_resolve: true,
+                ..
+            }
+        ));
+        assert_eq!(rule.socket.routing_mark, Some(512));
+
+        let rule: Rule = "MATCH,DIRECT".parse().unwrap();
+        assert_eq!(rule.socket, SocketOverrides::default());
+
+        assert!("MATCH,,DIRECT,dscp=64".parse::<Rule>().is_err());
+        assert!("MATCH,,DIRECT,tos=1".parse::<Rule>().is_err());
+        assert!("MATCH,,DIRECT,ip-version=ipv5".parse::<Rule>().is_err());
+        assert!("MATCH,,DIRECT,tag=".parse::<Rule>().is_err());
+    }
 }
@@ -20,3 +20,16 @@ where
         StringOrNum::Num(n) => Ok(n),
     }
 }
+
+/// An optional DSCP value, which has 6 bits
+pub fn deserialize_dscp<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(x) if x >= 64 => Err(serde::de::Error::custom(format!(
+            "invalid dscp {x}, must be 0 to 63"
+        ))),
+        x => Ok(x),
+    }
+}
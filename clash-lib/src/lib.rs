@@ -8,24 +8,40 @@
 
 use crate::{
     app::{
-        dispatcher::Dispatcher, dns, inbound::manager::InboundManager,
-        outbound::manager::OutboundManager, router::Router,
+        dispatcher::Dispatcher,
+        dns,
+        inbound::{conn_limit::ConnectionLimiter, manager::InboundManager},
+        outbound::manager::OutboundManager,
+        router::Router,
     },
     common::{
         geodata::{DEFAULT_GEOSITE_DOWNLOAD_URL, GeoDataLookup},
         mmdb::{DEFAULT_ASN_MMDB_DOWNLOAD_URL, DEFAULT_COUNTRY_MMDB_DOWNLOAD_URL},
+        tls::set_tls_session_resumption,
     },
     config::{
         def,
         internal::{InternalConfig, proxy::OutboundProxy},
     },
-    proxy::OutboundHandler,
+    proxy::{
+        OutboundHandler,
+        utils::{
+            set_handshake_retries, set_handshake_timeout, set_tcp_options,
+            set_udp_options,
+        },
+    },
 };
 use app::{
     dispatcher::StatisticsManager,
-    dns::{SystemResolver, ThreadSafeDNSResolver},
+    dns::{SystemResolver, ThreadSafeDNSResolver, set_default_ip_version},
     logging::LogEvent,
-    net::init_net_config,
+    net::{
+        init_net_config, set_bogon_policy, set_direct_preserve_source,
+        set_interface_priority, set_ip_strategy, set_ipv6_disabled,
+        set_local_address_policy, set_outbound_dscp, set_outbound_freebind,
+        set_outbound_interface_cache_ttl, unpin_outbound_interface,
+        watch_outbound_interface,
+    },
     profile,
 };
 use common::{auth, http::new_http_client, mmdb};
@@ -55,7 +71,58 @@ mod config;
 mod proxy;
 mod session;
 
+/// Internals measured by the criterion benchmarks in `benches/`
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    #[cfg(target_os = "linux")]
+    pub use crate::proxy::utils::udp_offload::{gso_supported, send_segments};
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    pub use crate::{
+        app::dispatcher::TrackCopy,
+        common::io::{uring_bidirectional, zero_copy_bidirectional},
+    };
+    pub use crate::{
+        app::net::{OutboundInterface, get_interface_by_ip},
+        proxy::utils::{ConnectOptions, new_tcp_stream},
+    };
+}
+
+/// Outbound protocols implemented by embedders. A protocol is registered by
+/// name with [`plugin::register_outbound`] before the config is loaded, and
+/// proxies of `type: <name>` are then built by its factory. Handlers dial
+/// with [`plugin::new_tcp_stream`] and [`plugin::new_udp_socket`], passing
+/// `&sess.into()` so the interface and routing mark of the session apply.
+///
+/// See `examples/echo_outbound.rs`.
+#[cfg(feature = "plugin")]
+pub mod plugin {
+    pub use crate::{
+        app::{
+            dispatcher::{
+                BoxedChainedDatagram, BoxedChainedStream, ChainedDatagram,
+                ChainedDatagramWrapper, ChainedStream, ChainedStreamWrapper,
+            },
+            dns::{ClashResolver, ThreadSafeDNSResolver},
+        },
+        proxy::{
+            AnyOutboundHandler, ConnectorType, DialWithConnector, OutboundHandler,
+            OutboundType,
+            datagram::{OutboundDatagramImpl, UdpPacket},
+            plugin::{OutboundFactory, register_outbound},
+            utils::{
+                ConnectOptions, RemoteConnector, new_tcp_stream, new_udp_socket,
+            },
+        },
+        session::{Session, SocksAddr},
+    };
+}
+
 use crate::common::{geodata, mmdb::MmdbLookup};
+#[cfg(unix)]
+pub use crate::proxy::utils::{
+    SocketProtector, protect_socket, set_socket_protector,
+};
 pub use config::{
     DNSListen as ClashDNSListen, RuntimeConfig as ClashRuntimeConfig,
     def::{Config as ClashConfigDef, DNS as ClashDNSConfigDef},
@@ -399,15 +466,45 @@ async fn create_components(
     cwd: PathBuf,
     config: InternalConfig,
 ) -> Result<RuntimeComponents> {
-    if config.tun.enable {
-        debug!("tun enabled, initializing default outbound interface");
-        init_net_config(config.tun.so_mark).await;
+    // before anything looks at interfaces or creates sockets
+    set_ipv6_disabled(config.general.ipv6_disabled);
+    set_interface_priority(config.general.interface_priority.clone());
+    set_ip_strategy(config.general.ip_strategy);
+    set_default_ip_version(config.general.ip_version);
+    set_outbound_interface_cache_ttl(config.general.interface_cache_ttl);
+    if config.tun.enable || config.general.interface.is_some() {
+        debug!("initializing default outbound interface");
+        init_net_config(
+            config.tun.so_mark,
+            config.general.interface.as_ref(),
+            config.general.interface_fallback,
+        )
+        .await;
+    } else {
+        unpin_outbound_interface(config.general.interface_fallback);
     }
+    watch_outbound_interface(config.general.interface_watch_interval);
+    set_outbound_freebind(config.general.freebind);
+    set_outbound_dscp(config.general.dscp);
+    set_direct_preserve_source(config.general.direct_preserve_source);
+    set_bogon_policy(config.general.bogon_policy);
+    set_local_address_policy(config.general.local_address_policy);
+    common::io::set_io_uring_relay(
+        config.experimental.as_ref().is_some_and(|e| e.io_uring),
+    );
+    set_tls_session_resumption(config.general.tls_session_resumption);
+    set_handshake_timeout(config.general.handshake_timeout);
+    set_handshake_retries(config.general.handshake_retries);
+    set_tcp_options(config.general.tcp_options.clone());
+    set_udp_options(config.general.udp_options.clone());
+    common::http::set_global_headers(config.general.global_headers.clone());
 
     debug!("initializing cache store");
     let cache_store = profile::ThreadSafeCacheFile::new(
         cwd.join("cache.db").as_path().to_str().unwrap(),
         config.profile.store_selected,
+        config.profile.store_fake_ip,
+        config.profile.store_interface_traffic,
     );
 
     let system_resolver = Arc::new(
@@ -480,10 +577,12 @@ async fn create_components(
                 .collect(),
             config.proxy_providers,
             config.proxy_names,
+            config.general.global_default,
             dns_resolver.clone(),
             cache_store.clone(),
             cwd.to_string_lossy().to_string(),
             config.general.routing_mask,
+            country_mmdb.clone(),
         )
         .await?,
     );
@@ -537,8 +636,14 @@ async fn create_components(
         )
         .await,
     );
+    dns_resolver.attach_rule_providers(router.rule_providers());
 
-    let statistics_manager = StatisticsManager::new();
+    let statistics_manager = StatisticsManager::new(
+        config
+            .profile
+            .store_interface_traffic
+            .then(|| cache_store.clone()),
+    );
 
     debug!("initializing dispatcher");
     let dispatcher = Arc::new(Dispatcher::new(
@@ -548,15 +653,25 @@ async fn create_components(
         config.general.mode,
         statistics_manager.clone(),
         config.experimental.and_then(|e| e.tcp_buffer_size),
+        config.general.udp_fallback,
+        config.general.block_quic,
+        config.general.max_udp_sessions,
     ));
 
     debug!("initializing authenticator");
-    let authenticator = Arc::new(auth::PlainAuthenticator::new(config.users));
+    let authenticator =
+        Arc::new(auth::PlainAuthenticator::new(config.users, config.auth_ban));
 
     debug!("initializing inbound manager");
+    let conn_limiter = ConnectionLimiter::new(config.general.max_connections_per_ip);
     let inbound_manager = Arc::new(
-        InboundManager::new(dispatcher.clone(), authenticator, config.listeners)
-            .await,
+        InboundManager::new(
+            dispatcher.clone(),
+            authenticator,
+            conn_limiter,
+            config.listeners,
+        )
+        .await,
     );
 
     #[cfg(feature = "tun")]
@@ -1,12 +1,20 @@
 use crate::{
     config::internal::proxy::{Hysteria2Obfs, OutboundHysteria2},
-    proxy::hysteria2::{self, Handler, HystOption, SalamanderObfs},
+    proxy::{
+        hysteria2::{self, Handler, HystOption, SalamanderObfs},
+        utils::quic::{
+            CongestionControl, DEFAULT_CONNECTION_RECEIVE_WINDOW,
+            DEFAULT_INITIAL_MTU, DEFAULT_STREAM_RECEIVE_WINDOW,
+        },
+    },
     session::SocksAddr,
 };
+use quinn::VarInt;
 use rand::Rng;
 use std::{
     num::{NonZeroU16, ParseIntError},
     ops::RangeInclusive,
+    time::Duration,
 };
 
 #[derive(Clone)]
@@ -111,6 +119,17 @@ impl TryFrom<OutboundHysteria2> for Handler {
         } else {
             None
         };
+        let keep_alive_interval =
+            Duration::from_millis(value.keep_alive_interval.unwrap_or(10_000));
+        let idle_timeout =
+            Duration::from_millis(value.idle_timeout.unwrap_or(30_000));
+        if keep_alive_interval >= idle_timeout {
+            return Err(crate::Error::InvalidConfig(format!(
+                "hysteria2 keep-alive-interval {keep_alive_interval:?} must be \
+                 smaller than idle-timeout {idle_timeout:?}"
+            )));
+        }
+
         let opts = HystOption {
             name: value.name,
             sni: value.sni.or(addr.domain().map(|s| s.to_owned())),
@@ -127,6 +146,28 @@ impl TryFrom<OutboundHysteria2> for Handler {
             cwnd: value.cwnd,
             udp_mtu: value.udp_mtu,
             disable_mtu_discovery: value.disable_mtu_discovery.unwrap_or(false),
+            initial_mtu: value.initial_mtu.unwrap_or(DEFAULT_INITIAL_MTU),
+            congestion_controller: value
+                .congestion_controller
+                .as_deref()
+                .map(CongestionControl::from)
+                .unwrap_or_default(),
+            stream_receive_window: VarInt::from_u64(
+                value
+                    .stream_receive_window
+                    .unwrap_or(DEFAULT_STREAM_RECEIVE_WINDOW),
+            )
+            .unwrap_or(VarInt::MAX),
+            connection_receive_window: VarInt::from_u64(
+                value
+                    .connection_receive_window
+                    .unwrap_or(DEFAULT_CONNECTION_RECEIVE_WINDOW),
+            )
+            .unwrap_or(VarInt::MAX),
+            gso: value.gso.unwrap_or(true),
+            keep_alive_interval: (!keep_alive_interval.is_zero())
+                .then_some(keep_alive_interval),
+            idle_timeout,
         };
 
         Ok(Handler::new(opts))
@@ -0,0 +1,42 @@
+use crate::{
+    config::internal::proxy::OutboundIcmp,
+    proxy::{
+        HandlerCommonOptions,
+        icmp::{Handler, HandlerOptions},
+    },
+};
+
+impl TryFrom<OutboundIcmp> for Handler {
+    type Error = crate::Error;
+
+    fn try_from(value: OutboundIcmp) -> Result<Self, Self::Error> {
+        (&value).try_into()
+    }
+}
+
+impl TryFrom<&OutboundIcmp> for Handler {
+    type Error = crate::Error;
+
+    fn try_from(s: &OutboundIcmp) -> Result<Self, Self::Error> {
+        if s.password.is_empty() {
+            return Err(crate::Error::InvalidConfig(format!(
+                "icmp outbound {} needs a password",
+                s.name
+            )));
+        }
+        Ok(Handler::new(HandlerOptions {
+            name: s.name.to_owned(),
+            common_opts: HandlerCommonOptions {
+                idle_read_timeout: s
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
+                ip_version: s.ip_version,
+                server_resolver: s.server_resolver,
+                ..Default::default()
+            },
+            server: s.server.to_owned(),
+            password: s.password.to_owned(),
+            udp: s.udp,
+        }))
+    }
+}
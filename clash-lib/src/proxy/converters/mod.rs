@@ -1,4 +1,7 @@
+#[cfg(feature = "hysteria2")]
 pub mod hysteria2;
+#[cfg(all(unix, feature = "icmp"))]
+pub mod icmp;
 #[cfg(feature = "shadowquic")]
 pub mod shadowquic;
 #[cfg(feature = "shadowsocks")]
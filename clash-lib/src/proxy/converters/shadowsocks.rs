@@ -29,6 +29,16 @@ impl TryFrom<&OutboundShadowsocks> for Handler {
             name: s.common_opts.name.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
+                ip_version: s.common_opts.ip_version,
+                server_resolver: s.common_opts.server_resolver,
+                attempt_delay: s
+                    .common_opts
+                    .happy_eyeballs_delay
+                    .map(std::time::Duration::from_millis),
                 ..Default::default()
             },
             server: s.common_opts.server.to_owned(),
@@ -94,6 +104,14 @@ impl TryFrom<&OutboundShadowsocks> for Handler {
             },
             udp: s.udp,
         });
+        // fail at load rather than on every connection for an unsupported
+        // cipher or a 2022 key of the wrong length
+        h.server_config().map_err(|e| {
+            Error::InvalidConfig(format!(
+                "shadowsocks {}: {}",
+                s.common_opts.name, e
+            ))
+        })?;
         Ok(h)
     }
 }
@@ -33,6 +33,16 @@ impl TryFrom<&OutboundSocks5> for Handler {
             name: s.common_opts.name.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
+                ip_version: s.common_opts.ip_version,
+                server_resolver: s.common_opts.server_resolver,
+                attempt_delay: s
+                    .common_opts
+                    .happy_eyeballs_delay
+                    .map(std::time::Duration::from_millis),
                 ..Default::default()
             },
             server: s.common_opts.server.to_owned(),
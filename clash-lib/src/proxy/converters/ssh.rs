@@ -116,6 +116,10 @@ impl TryFrom<&OutboundSsh> for Handler {
             name: s.common_opts.name.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
                 ..Default::default()
             },
             server: s.common_opts.server.to_owned(),
@@ -6,7 +6,11 @@ use crate::{
     config::internal::proxy::OutboundTuic,
     proxy::{
         HandlerCommonOptions,
-        tuic::{Handler, HandlerOptions, types::CongestionControl},
+        tuic::{Handler, HandlerOptions},
+        utils::quic::{
+            CongestionControl, DEFAULT_CONNECTION_RECEIVE_WINDOW,
+            DEFAULT_INITIAL_MTU,
+        },
     },
 };
 
@@ -27,6 +31,11 @@ impl TryFrom<&OutboundTuic> for Handler {
             server: s.common_opts.server.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(Duration::from_secs),
+                server_resolver: s.common_opts.server_resolver,
                 ..Default::default()
             },
             port: s.common_opts.port,
@@ -71,6 +80,13 @@ impl TryFrom<&OutboundTuic> for Handler {
                 s.receive_window.unwrap_or(8 * 1024 * 1024),
             )
             .unwrap_or(VarInt::MAX),
+            receive_window_conn: VarInt::from_u64(
+                s.receive_window_conn
+                    .unwrap_or(DEFAULT_CONNECTION_RECEIVE_WINDOW),
+            )
+            .unwrap_or(VarInt::MAX),
+            initial_mtu: s.initial_mtu.unwrap_or(DEFAULT_INITIAL_MTU),
+            gso: s.gso.unwrap_or(true),
         }))
     }
 }
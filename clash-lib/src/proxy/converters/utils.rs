@@ -1,8 +1,9 @@
 use http::uri::InvalidUri;
+use tracing::warn;
 
 use crate::{
     config::proxy::{CommonConfigOptions, GrpcOpt, H2Opt, WsOpt},
-    proxy::transport::{self, GrpcClient, H2Client, WsClient},
+    proxy::transport::{self, ClientFingerprint, GrpcClient, H2Client, WsClient},
 };
 
 impl TryFrom<(&WsOpt, &CommonConfigOptions)> for WsClient {
@@ -71,3 +72,25 @@ impl TryFrom<(&H2Opt, &CommonConfigOptions)> for H2Client {
         ))
     }
 }
+
+/// The [`ClientFingerprint`] of `client-fingerprint`. Values of other cores
+/// with no counterpart here are warned about and ignored, so that their
+/// configs still load.
+pub(super) fn client_fingerprint(
+    fingerprint: Option<&str>,
+    name: &str,
+) -> Option<ClientFingerprint> {
+    match fingerprint? {
+        "" | "none" => None,
+        "chrome" | "edge" | "android" | "360" | "qq" => {
+            Some(ClientFingerprint::Chrome)
+        }
+        "firefox" => Some(ClientFingerprint::Firefox),
+        "safari" | "ios" => Some(ClientFingerprint::Safari),
+        "random" | "randomized" => Some(ClientFingerprint::Randomized),
+        x => {
+            warn!("client-fingerprint {x} of {name} is not supported, ignoring");
+            None
+        }
+    }
+}
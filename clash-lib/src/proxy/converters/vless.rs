@@ -3,6 +3,7 @@ use crate::{
     config::internal::proxy::OutboundVless,
     proxy::{
         HandlerCommonOptions,
+        converters::utils::client_fingerprint,
         transport::{GrpcClient, H2Client, TlsClient, WsClient},
         vless::{Handler, HandlerOptions},
     },
@@ -22,6 +23,8 @@ impl TryFrom<&OutboundVless> for Handler {
 
     fn try_from(s: &OutboundVless) -> Result<Self, Self::Error> {
         let skip_cert_verify = s.skip_cert_verify.unwrap_or_default();
+        let fingerprint =
+            client_fingerprint(s.client_fingerprint.as_deref(), &s.common_opts.name);
         if skip_cert_verify {
             warn!(
                 "skipping TLS cert verification for {}",
@@ -33,6 +36,16 @@ impl TryFrom<&OutboundVless> for Handler {
             name: s.common_opts.name.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
+                ip_version: s.common_opts.ip_version,
+                server_resolver: s.common_opts.server_resolver,
+                attempt_delay: s
+                    .common_opts
+                    .happy_eyeballs_delay
+                    .map(std::time::Duration::from_millis),
                 ..Default::default()
             },
             server: s.common_opts.server.to_owned(),
@@ -113,7 +126,8 @@ impl TryFrom<&OutboundVless> for Handler {
                             })
                             .transpose()?,
                         None,
-                    );
+                    )
+                    .with_fingerprint(fingerprint);
                     Some(Box::new(client))
                 }
                 false => None,
@@ -25,6 +25,11 @@ impl TryFrom<&OutboundWireguard> for Handler {
             name: s.common_opts.name.to_owned(),
             common_opts: HandlerCommonOptions {
                 connector: s.common_opts.connect_via.clone(),
+                idle_read_timeout: s
+                    .common_opts
+                    .idle_read_timeout
+                    .map(std::time::Duration::from_secs),
+                server_resolver: s.common_opts.server_resolver,
                 ..Default::default()
             },
             server: s.common_opts.server.to_owned(),
@@ -1,15 +1,75 @@
+#[cfg(target_os = "linux")]
+use crate::proxy::utils::udp_offload;
 use crate::{
-    app::dns::ThreadSafeDNSResolver, common::errors::new_io_error,
+    app::{
+        dns::ThreadSafeDNSResolver,
+        net::{OutboundInterface, get_outbound_interface},
+    },
+    common::errors::new_io_error,
     session::SocksAddr,
 };
 use futures::{FutureExt, Sink, Stream, ready};
+use socket2::{MaybeUninitSlice, SockRef};
 use std::{
+    collections::VecDeque,
     fmt::{Debug, Display, Formatter},
     io,
+    mem::MaybeUninit,
+    net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
 };
-use tokio::{io::ReadBuf, net::UdpSocket};
+use tokio::{io::Interest, net::UdpSocket};
+use tracing::warn;
+
+/// The largest UDP payload, over IPv6. Over IPv4 it's 65507 bytes.
+pub const MAX_UDP_PAYLOAD: usize = 65527;
+
+/// Size of the buffers datagrams are received into from sockets, enough for
+/// any datagram
+pub const UDP_RECV_BUFFER_SIZE: usize = MAX_UDP_PAYLOAD;
+
+/// Receives a datagram into `buf` like [`UdpSocket::poll_recv_from`], but
+/// drops, with a warning, those that didn't fit rather than returning them
+/// cut short.
+pub fn poll_recv_from_whole(
+    socket: &UdpSocket,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<(usize, SocketAddr)>> {
+    loop {
+        ready!(socket.poll_recv_ready(cx))?;
+        match socket.try_io(Interest::READABLE, || recv_from_checked(socket, buf)) {
+            Ok((_, src, true)) => {
+                warn!(
+                    "dropping UDP datagram from {} larger than {} bytes",
+                    src,
+                    buf.len()
+                );
+            }
+            Ok((n, src, false)) => return Poll::Ready(Ok((n, src))),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// `recvfrom`, also telling whether the datagram was truncated, i.e.
+/// `MSG_TRUNC` on unix
+fn recv_from_checked(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, bool)> {
+    // Safety: initialized bytes are valid `MaybeUninit<u8>`s, and
+    // `recv_from_vectored` never writes uninitialized ones into the buffer
+    let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+    let (n, flags, src) = SockRef::from(socket)
+        .recv_from_vectored(&mut [MaybeUninitSlice::new(buf)])?;
+    let src = src.as_socket().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "unexpected source address")
+    })?;
+    Ok((n, src, flags.is_truncated()))
+}
 
 #[derive(Clone)]
 pub struct UdpPacket {
@@ -57,6 +117,33 @@ impl UdpPacket {
             dst_addr,
         }
     }
+
+    /// Whether this looks like the QUIC Initial packet of a connection to an
+    /// HTTPS server (RFC 9000 17.2.2, RFC 9369 3.2), i.e. a long header packet
+    /// of type Initial sent to port 443 and padded to the minimum datagram size
+    /// of 1200 bytes.
+    pub fn is_quic_initial(&self) -> bool {
+        const QUIC_V1: u32 = 0x0000_0001;
+        const QUIC_V2: u32 = 0x6b33_43cf;
+
+        if self.dst_addr.port() != 443 || self.data.len() < 1200 {
+            return false;
+        }
+        let first = self.data[0];
+        // long header and fixed bit
+        if first & 0xc0 != 0xc0 {
+            return false;
+        }
+        let version = u32::from_be_bytes(self.data[1..5].try_into().unwrap());
+        let packet_type = (first & 0x30) >> 4;
+        match version {
+            QUIC_V1 => packet_type == 0,
+            QUIC_V2 => packet_type == 1,
+            // drafts
+            v if v >> 8 == 0x00ff_0000 => packet_type == 0,
+            _ => false,
+        }
+    }
 }
 
 #[must_use = "sinks do nothing unless polled"]
@@ -65,18 +152,194 @@ impl UdpPacket {
 pub struct OutboundDatagramImpl {
     inner: UdpSocket,
     resolver: ThreadSafeDNSResolver,
-    flushed: bool,
-    pkt: Option<UdpPacket>,
+    /// packets fed but not sent yet, flushed once [`MAX_PENDING`] are
+    pending: VecDeque<UdpPacket>,
+    /// datagrams received together with GRO, not returned yet
+    received: VecDeque<UdpPacket>,
+    /// zone of the IPv6 link-local destinations, looked up on first use
+    scope_id: Option<u32>,
+    /// runs of equally sized datagrams to the same destination are sent
+    /// with `UDP_SEGMENT`
+    gso: bool,
+    /// received datagrams are coalesced with `UDP_GRO`
+    gro: bool,
 }
 
+/// Most packets [`OutboundDatagramImpl`] holds before sending them, also the
+/// most sent with one `UDP_SEGMENT` call
+const MAX_PENDING: usize = 64;
+
 impl OutboundDatagramImpl {
     pub fn new(udp: UdpSocket, resolver: ThreadSafeDNSResolver) -> Self {
+        #[cfg(target_os = "linux")]
+        let (gso, gro) = (
+            udp_offload::gso_supported(&udp),
+            udp_offload::enable_gro(&udp),
+        );
+        #[cfg(not(target_os = "linux"))]
+        let (gso, gro) = (false, false);
+
         Self {
             inner: udp,
             resolver,
-            flushed: true,
-            pkt: None,
+            pending: VecDeque::new(),
+            received: VecDeque::new(),
+            scope_id: None,
+            gso,
+            gro,
+        }
+    }
+
+    /// Sends to IPv6 link-local destinations without a zone, e.g. those of
+    /// SOCKS5 UDP headers, through the interface the socket is bound to
+    /// rather than the default one.
+    pub fn with_iface(mut self, iface: Option<&OutboundInterface>) -> Self {
+        self.scope_id = iface.map(|x| x.index);
+        self
+    }
+
+    /// Sends the first pending packet, along with those following it in its
+    /// `UDP_SEGMENT` run, and returns how many were sent.
+    fn poll_send_front(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let Self {
+            inner,
+            pending,
+            resolver,
+            scope_id,
+            gso,
+            ..
+        } = self;
+
+        let p = &pending[0];
+        let data = &p.data;
+        let dst = match &p.dst_addr {
+            SocksAddr::Domain(domain, port) => {
+                let domain = domain.to_string();
+                let port = *port;
+                let mut fut = {
+                    if inner.local_addr()?.is_ipv6() {
+                        resolver.resolve(domain.as_str(), false)
+                    } else {
+                        resolver
+                            .resolve_v4(domain.as_str(), false)
+                            .map(|x| x.map(|ip| ip.map(Into::into)))
+                            .boxed()
+                    }
+                };
+                let ip = ready!(
+                    fut.as_mut()
+                        .poll(cx)
+                        .map_err(|_| io::Error::other("resolve domain failed"))
+                )?;
+                if let Some(ip) = ip {
+                    (ip, port).into()
+                } else {
+                    return Poll::Ready(Err(io::Error::other(format!(
+                        "resolve domain failed: {domain}"
+                    ))));
+                }
+            }
+            SocksAddr::Ip(addr) => *addr,
+        };
+        let dst = with_link_local_scope(dst, || {
+            *scope_id.get_or_insert_with(|| {
+                get_outbound_interface()
+                    .map(|x| x.index)
+                    .unwrap_or_default()
+            })
+        });
+
+        #[cfg(target_os = "linux")]
+        if *gso {
+            let run = gso_run(pending);
+            if run > 1 {
+                let buf = pending
+                    .range(..run)
+                    .map(|x| x.data.as_slice())
+                    .collect::<Vec<_>>()
+                    .concat();
+                let segment_size = pending[0].data.len() as u16;
+                match ready!(udp_offload::poll_send_segments(
+                    inner,
+                    cx,
+                    dst,
+                    &buf,
+                    segment_size
+                )) {
+                    Ok(_) => return Poll::Ready(Ok(run)),
+                    Err(e) if udp_offload::is_gso_unsupported(&e) => {
+                        warn!(
+                            "UDP segmentation offload to {} failed, sending \
+                             datagrams one by one: {}",
+                            dst, e
+                        );
+                        *gso = false;
+                    }
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = gso;
+
+        let n = ready!(inner.poll_send_to(cx, data.as_slice(), dst))?;
+        if n == data.len() {
+            Poll::Ready(Ok(1))
+        } else {
+            Poll::Ready(Err(new_io_error(format!(
+                "failed to send all data, only sent {n} bytes"
+            ))))
+        }
+    }
+}
+
+/// How many of the packets at the front of `pending` can be sent with one
+/// `UDP_SEGMENT` call: those to the same address, all as large as the first
+/// except for the last one, which may be smaller.
+#[cfg(target_os = "linux")]
+fn gso_run(pending: &VecDeque<UdpPacket>) -> usize {
+    let Some(first) = pending.front() else {
+        return 0;
+    };
+    let size = first.data.len();
+    if !matches!(first.dst_addr, SocksAddr::Ip(_)) || size > u16::MAX as usize {
+        return 1;
+    }
+
+    let mut total = 0;
+    let mut run = 0;
+    for p in pending.iter().take(udp_offload::MAX_SEGMENTS) {
+        let len = p.data.len();
+        if p.dst_addr != first.dst_addr
+            || len == 0
+            || len > size
+            || total + len > udp_offload::MAX_SEGMENTS_SIZE
+        {
+            break;
+        }
+        total += len;
+        run += 1;
+        if len < size {
+            break;
+        }
+    }
+    run.max(1)
+}
+
+/// Gives `addr` the zone `scope_id()` if it's an IPv6 link-local address
+/// without one, as sending to it, or binding it, fails otherwise.
+pub(crate) fn with_link_local_scope(
+    addr: SocketAddr,
+    scope_id: impl FnOnce() -> u32,
+) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(mut v6)
+            if v6.scope_id() == 0 && v6.ip().is_unicast_link_local() =>
+        {
+            v6.set_scope_id(scope_id());
+            v6.into()
         }
+        _ => addr,
     }
 }
 
@@ -87,86 +350,37 @@ impl Sink<UdpPacket> for OutboundDatagramImpl {
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        if !self.flushed {
-            match self.poll_flush(cx)? {
-                Poll::Ready(()) => {}
-                Poll::Pending => return Poll::Pending,
-            }
+        if self.pending.len() >= MAX_PENDING {
+            ready!(self.poll_flush(cx))?;
         }
 
         Poll::Ready(Ok(()))
     }
 
     fn start_send(self: Pin<&mut Self>, item: UdpPacket) -> Result<(), Self::Error> {
-        let pin = self.get_mut();
-        pin.pkt = Some(item);
-        pin.flushed = false;
+        self.get_mut().pending.push_back(item);
         Ok(())
     }
 
     fn poll_flush(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        if self.flushed {
-            return Poll::Ready(Ok(()));
-        }
-
-        let Self {
-            ref mut inner,
-            ref mut pkt,
-            ref resolver,
-            ..
-        } = *self;
-
-        if pkt.is_some() {
-            let p = pkt.as_ref().unwrap();
-            let dst = &p.dst_addr;
-            let data = &p.data;
-            let dst = match dst {
-                SocksAddr::Domain(domain, port) => {
-                    let domain = domain.to_string();
-                    let port = *port;
-                    let mut fut = {
-                        if inner.local_addr()?.is_ipv6() {
-                            resolver.resolve(domain.as_str(), false)
-                        } else {
-                            resolver
-                                .resolve_v4(domain.as_str(), false)
-                                .map(|x| x.map(|ip| ip.map(Into::into)))
-                                .boxed()
-                        }
-                    };
-                    let ip = ready!(fut.as_mut().poll(cx).map_err(|_| {
-                        io::Error::other("resolve domain failed")
-                    }))?;
-                    if let Some(ip) = ip {
-                        (ip, port).into()
-                    } else {
-                        return Poll::Ready(Err(io::Error::other(format!(
-                            "resolve domain failed: {domain}"
-                        ))));
-                    }
+        let this = self.get_mut();
+        while !this.pending.is_empty() {
+            match ready!(this.poll_send_front(cx)) {
+                Ok(n) => {
+                    this.pending.drain(..n);
                 }
-                SocksAddr::Ip(addr) => *addr,
-            };
-
-            let n = ready!(inner.poll_send_to(cx, data.as_slice(), dst))?;
-            let wrote_all = n == data.len();
-            self.pkt = None;
-            self.flushed = true;
-
-            let res = if wrote_all {
-                Ok(())
-            } else {
-                Err(new_io_error(format!(
-                    "failed to send all data, only sent {n} bytes"
-                )))
-            };
-            Poll::Ready(res)
-        } else {
-            Poll::Ready(Err(io::Error::other("no packet to send")))
+                Err(e) => {
+                    // the packet is dropped, as it'd likely fail again
+                    this.pending.pop_front();
+                    return Poll::Ready(Err(e));
+                }
+            }
         }
+
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(
@@ -181,22 +395,164 @@ impl Stream for OutboundDatagramImpl {
     type Item = UdpPacket;
 
     fn poll_next(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        let Self { ref mut inner, .. } = *self;
-        let mut mem = vec![0u8; 65535];
-        let mut buf = ReadBuf::new(&mut mem);
-        match ready!(inner.poll_recv_from(cx, &mut buf)) {
-            Ok(src) => {
-                let data = buf.filled().to_vec();
-                Poll::Ready(Some(UdpPacket {
-                    data,
-                    src_addr: src.into(),
-                    dst_addr: SocksAddr::any_ipv4(),
-                }))
+        let this = self.get_mut();
+        if let Some(packet) = this.received.pop_front() {
+            return Poll::Ready(Some(packet));
+        }
+
+        let mut mem = vec![0u8; UDP_RECV_BUFFER_SIZE];
+        loop {
+            #[cfg(target_os = "linux")]
+            if this.gro {
+                match ready!(udp_offload::poll_recv_gro(&this.inner, cx, &mut mem)) {
+                    Ok(r) => {
+                        if r.truncated {
+                            warn!(
+                                "dropping UDP datagrams from {} larger than the \
+                                 receive buffer",
+                                r.src
+                            );
+                            continue;
+                        }
+                        let packet = |data: &[u8]| UdpPacket {
+                            data: data.to_vec(),
+                            src_addr: r.src.into(),
+                            dst_addr: SocksAddr::any_ipv4(),
+                        };
+                        if r.len == 0 {
+                            return Poll::Ready(Some(packet(&[])));
+                        }
+                        this.received
+                            .extend(mem[..r.len].chunks(r.stride).map(packet));
+                        return Poll::Ready(this.received.pop_front());
+                    }
+                    Err(_) => return Poll::Ready(None),
+                }
+            }
+
+            match ready!(poll_recv_from_whole(&this.inner, cx, &mut mem)) {
+                Ok((n, src)) => {
+                    return Poll::Ready(Some(UdpPacket {
+                        data: mem[..n].to_vec(),
+                        src_addr: src.into(),
+                        dst_addr: SocksAddr::any_ipv4(),
+                    }));
+                }
+                Err(_) => return Poll::Ready(None),
             }
-            Err(_) => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
+    use futures::future::poll_fn;
+    use tokio::net::UdpSocket;
+
+    use super::{UdpPacket, poll_recv_from_whole, with_link_local_scope};
+    use crate::session::SocksAddr;
+
+    fn packet(port: u16, header: &[u8], len: usize) -> UdpPacket {
+        let mut data = header.to_vec();
+        data.resize(len, 0);
+        UdpPacket::new(
+            data,
+            SocksAddr::any_ipv4(),
+            SocksAddr::Ip(([1, 1, 1, 1], port).into()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_recv_drops_truncated() {
+        let rx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dst = rx.local_addr().unwrap();
+        tx.send_to(&[1; 100], dst).await.unwrap();
+        tx.send_to(&[2; 64], dst).await.unwrap();
+
+        let mut buf = [0u8; 64];
+        let (n, src) = poll_fn(|cx| poll_recv_from_whole(&rx, cx, &mut buf))
+            .await
+            .unwrap();
+        assert_eq!(src, tx.local_addr().unwrap());
+        assert_eq!(&buf[..n], &[2; 64]);
+    }
+
+    #[test]
+    fn test_is_quic_initial() {
+        let v1_initial = [0xc3, 0x00, 0x00, 0x00, 0x01];
+        assert!(packet(443, &v1_initial, 1200).is_quic_initial());
+        assert!(
+            packet(443, &[0xd3, 0x6b, 0x33, 0x43, 0xcf], 1250).is_quic_initial()
+        );
+
+        // other ports, short datagrams and non Initial packets
+        assert!(!packet(8443, &v1_initial, 1200).is_quic_initial());
+        assert!(!packet(443, &v1_initial, 1000).is_quic_initial());
+        assert!(
+            !packet(443, &[0xe3, 0x00, 0x00, 0x00, 0x01], 1200).is_quic_initial()
+        );
+        assert!(
+            !packet(443, &[0x43, 0x00, 0x00, 0x00, 0x01], 1200).is_quic_initial()
+        );
+        assert!(
+            !packet(443, &[0xc3, 0x12, 0x34, 0x56, 0x78], 1200).is_quic_initial()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_gso_run() {
+        use std::collections::VecDeque;
+
+        use super::gso_run;
+
+        let run = |lens: &[usize]| {
+            gso_run(
+                &lens
+                    .iter()
+                    .map(|x| packet(53, &[], *x))
+                    .collect::<VecDeque<_>>(),
+            )
+        };
+        assert_eq!(run(&[]), 0);
+        assert_eq!(run(&[100]), 1);
+        assert_eq!(run(&[100, 100, 100]), 3);
+        // a shorter datagram ends the run
+        assert_eq!(run(&[100, 100, 50, 100]), 3);
+        assert_eq!(run(&[100, 200, 100]), 1);
+        assert_eq!(run(&[1200; 100]), 54);
+
+        let mut other_dst =
+            VecDeque::from([packet(53, &[], 100), packet(53, &[], 100)]);
+        other_dst.push_back(packet(54, &[], 100));
+        assert_eq!(gso_run(&other_dst), 2);
+    }
+
+    #[test]
+    fn test_with_link_local_scope() {
+        let link_local: Ipv6Addr = "fe80::1".parse().unwrap();
+        let scoped = with_link_local_scope((link_local, 5353).into(), || 3);
+        assert_eq!(scoped, SocketAddrV6::new(link_local, 5353, 0, 3).into());
+
+        // an explicit zone is kept
+        let explicit = SocketAddrV6::new(link_local, 5353, 0, 7).into();
+        assert_eq!(with_link_local_scope(explicit, || 3), explicit);
+
+        for addr in [
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 53)),
+            SocketAddr::from(("2001:db8::1".parse::<Ipv6Addr>().unwrap(), 53)),
+            SocketAddr::from(([192, 168, 1, 1], 53)),
+        ] {
+            assert_eq!(
+                with_link_local_scope(addr, || unreachable!("not link-local")),
+                addr
+            );
         }
     }
 }
@@ -72,9 +72,7 @@ impl OutboundHandler for Handler {
 
         let s = new_tcp_stream(
             (remote_ip, sess.destination.port()).into(),
-            sess.iface.as_ref(),
-            #[cfg(target_os = "linux")]
-            sess.so_mark,
+            &sess.into(),
         )
         .await?;
 
@@ -94,15 +92,13 @@ impl OutboundHandler for Handler {
         } else {
             std::net::Ipv6Addr::UNSPECIFIED.into()
         };
-        let d = new_udp_socket(
-            Some((bind_addr, 0).into()),
-            sess.iface.as_ref(),
-            #[cfg(target_os = "linux")]
-            sess.so_mark,
-            family_hint,
-        )
-        .await
-        .map(|x| OutboundDatagramImpl::new(x, resolver))?;
+        let d =
+            new_udp_socket(Some((bind_addr, 0).into()), family_hint, &sess.into())
+                .await
+                .map(|x| {
+                    OutboundDatagramImpl::new(x, resolver)
+                        .with_iface(sess.iface.as_ref())
+                })?;
 
         let d = ChainedDatagramWrapper::new(d);
         d.append_to_chain(self.name()).await;
@@ -124,9 +120,7 @@ impl OutboundHandler for Handler {
                 resolver,
                 sess.destination.host().as_str(),
                 sess.destination.port(),
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
+                &sess.into(),
             )
             .await?;
         let s = ChainedStreamWrapper::new(s);
@@ -141,14 +135,7 @@ impl OutboundHandler for Handler {
         connector: &dyn RemoteConnector,
     ) -> std::io::Result<BoxedChainedDatagram> {
         let d = connector
-            .connect_datagram(
-                resolver,
-                None,
-                sess.destination.clone(),
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
-            )
+            .connect_datagram(resolver, None, sess.destination.clone(), &sess.into())
             .await?;
         let d = ChainedDatagramWrapper::new(d);
         d.append_to_chain(self.name()).await;
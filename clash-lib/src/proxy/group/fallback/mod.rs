@@ -15,7 +15,10 @@ use crate::{
         AnyOutboundHandler, ConnectorType, DialWithConnector, HandlerCommonOptions,
         OutboundHandler, OutboundType,
         group::GroupProxyAPIResponse,
-        utils::{RemoteConnector, provider_helper::get_proxies_from_providers},
+        utils::{
+            RemoteConnector, RetryPolicy,
+            provider_helper::get_proxies_from_providers,
+        },
     },
     session::Session,
 };
@@ -25,6 +28,7 @@ pub struct HandlerOptions {
     pub common_opts: HandlerCommonOptions,
     pub name: String,
     pub udp: bool,
+    pub retry_policy: RetryPolicy,
 }
 
 pub struct Handler {
@@ -68,6 +72,63 @@ impl Handler {
         }
         proxies[0].clone()
     }
+
+    /// All alive proxies in order, or the first one if none is alive.
+    async fn find_alive_proxies(&self, touch: bool) -> Vec<AnyOutboundHandler> {
+        let proxies = self.get_proxies(touch).await;
+        let mut alive = vec![];
+        for proxy in proxies.iter() {
+            if self.proxy_manager.alive(proxy.name()).await {
+                alive.push(proxy.clone());
+            }
+        }
+        if alive.is_empty() {
+            alive.push(proxies[0].clone());
+        }
+        alive
+    }
+
+    /// Connect through the alive proxies in order, moving on to the next one
+    /// as long as the retry policy allows it.
+    async fn connect_stream_with_retry(
+        &self,
+        sess: &Session,
+        resolver: ThreadSafeDNSResolver,
+        connector: Option<&dyn RemoteConnector>,
+    ) -> io::Result<BoxedChainedStream> {
+        let member_sess = self.opts.common_opts.member_session(sess);
+        let sess = member_sess.as_ref();
+        let mut last_err = None;
+        for proxy in self.find_alive_proxies(true).await {
+            debug!("`{}` fallback to `{}`", self.name(), proxy.name());
+            let r = match connector {
+                Some(connector) => {
+                    proxy
+                        .connect_stream_with_connector(
+                            sess,
+                            resolver.clone(),
+                            connector,
+                        )
+                        .await
+                }
+                None => proxy.connect_stream(sess, resolver.clone()).await,
+            };
+            match r {
+                Ok(s) => return Ok(s),
+                Err(e) if self.opts.retry_policy.should_retry(&e) => {
+                    debug!(
+                        "`{}` failed to connect via `{}`: {}, trying next",
+                        self.name(),
+                        proxy.name(),
+                        e
+                    );
+                    last_err = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_err.expect("at least one proxy was tried"))
+    }
 }
 
 impl DialWithConnector for Handler {}
@@ -96,8 +157,7 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedStream> {
-        let proxy = self.find_alive_proxy(true).await;
-        let s = proxy.connect_stream(sess, resolver).await?;
+        let s = self.connect_stream_with_retry(sess, resolver, None).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -110,8 +170,9 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedDatagram> {
+        let sess = self.opts.common_opts.member_session(sess);
         let proxy = self.find_alive_proxy(true).await;
-        let s = proxy.connect_datagram(sess, resolver).await?;
+        let s = proxy.connect_datagram(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -128,9 +189,7 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedStream> {
-        let proxy = self.find_alive_proxy(true).await;
-        proxy
-            .connect_stream_with_connector(sess, resolver, connector)
+        self.connect_stream_with_retry(sess, resolver, Some(connector))
             .await
     }
 
@@ -1,7 +1,7 @@
 use std::{
     io::Cursor,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use futures::future::BoxFuture;
@@ -10,7 +10,8 @@ use public_suffix::{DEFAULT_PROVIDER, EffectiveTLDProvider};
 use tokio::sync::Mutex;
 
 use crate::{
-    app::remote_content_manager::ProxyManager, proxy::AnyOutboundHandler,
+    app::remote_content_manager::ProxyManager,
+    config::internal::proxy::LoadBalanceHashKey, proxy::AnyOutboundHandler,
     session::Session,
 };
 
@@ -33,6 +34,16 @@ fn get_key(sess: &Session) -> String {
     }
 }
 
+fn get_hash_key(sess: &Session, hash_key: LoadBalanceHashKey) -> String {
+    match hash_key {
+        LoadBalanceHashKey::Domain => get_key(sess),
+        LoadBalanceHashKey::Host => sess.destination.host(),
+        LoadBalanceHashKey::HostPort => {
+            format!("{}:{}", sess.destination.host(), sess.destination.port())
+        }
+    }
+}
+
 fn get_key_src_and_dst(sess: &Session) -> String {
     let dst = get_key(sess);
     let src = match &sess.source {
@@ -63,20 +74,91 @@ pub fn strategy_rr() -> StrategyFn {
     })
 }
 
-pub fn strategy_consistent_hashring() -> StrategyFn {
-    let max_retry = 5;
+/// The weight of `proxy` for `key` in the rendezvous hashing
+fn rendezvous_weight(key: &str, proxy: &str) -> u32 {
+    murmur3_32(&mut Cursor::new(format!("{key}\0{proxy}")), 0).unwrap()
+}
+
+/// Rendezvous (highest random weight) hashing over the proxy names: a key
+/// goes to the alive proxy that weighs the most for it, so it only moves when
+/// that proxy leaves the group or goes down, and moves back once it returns.
+/// Keys of the other proxies are left where they are.
+pub fn strategy_consistent_hashring(
+    hash_key: LoadBalanceHashKey,
+    proxy_manager: ProxyManager,
+) -> StrategyFn {
     Box::new(move |proxies, sess| {
-        let key = murmur3_32(&mut Cursor::new(get_key(sess)), 0).unwrap() as u64;
-        let buckets = proxies.len() as i32;
-        for _ in 0..max_retry {
-            let index = jump_hash(key, buckets);
-            if let Some(proxy) = proxies.get(index as usize) {
-                return Box::pin(futures::future::ok(proxy.clone()));
+        let key = get_hash_key(sess, hash_key);
+        let proxy_manager = proxy_manager.clone();
+        Box::pin(async move {
+            let mut ranked = proxies
+                .into_iter()
+                .map(|p| (rendezvous_weight(&key, p.name()), p))
+                .collect::<Vec<_>>();
+            ranked.sort_by(|a, b| b.0.cmp(&a.0));
+
+            for (_, proxy) in &ranked {
+                if proxy_manager.alive(proxy.name()).await {
+                    return Ok(proxy.clone());
+                }
             }
-        }
-        Box::pin(futures::future::err(std::io::Error::other(
-            "no proxy found",
-        )))
+            // all down, stick to the first choice rather than failing
+            ranked
+                .into_iter()
+                .next()
+                .map(|(_, proxy)| proxy)
+                .ok_or_else(|| std::io::Error::other("no proxy found"))
+        })
+    })
+}
+
+/// Destinations remembered by [`strategy_with_affinity`]
+const AFFINITY_CAPACITY: usize = 4096;
+
+/// Sends a destination, keyed by `key`, through the proxy `strategy` picked
+/// for it, until `ttl` has passed since or that proxy is gone or down.
+pub fn strategy_with_affinity(
+    strategy: StrategyFn,
+    key: LoadBalanceHashKey,
+    ttl: Duration,
+    proxy_manager: ProxyManager,
+) -> StrategyFn {
+    let strategy = Arc::new(Mutex::new(strategy));
+    let picked: lru_time_cache::LruCache<String, (String, Instant)> =
+        lru_time_cache::LruCache::with_capacity(AFFINITY_CAPACITY);
+    let picked = Arc::new(Mutex::new(picked));
+    Box::new(move |proxies, sess| {
+        let key = get_hash_key(sess, key);
+        let sess = sess.clone();
+        let strategy = strategy.clone();
+        let picked = picked.clone();
+        let proxy_manager = proxy_manager.clone();
+        Box::pin(async move {
+            let now = Instant::now();
+            let cached = picked
+                .lock()
+                .await
+                .get(&key)
+                .filter(|(_, until)| *until > now)
+                .map(|(name, _)| name.clone());
+            if let Some(name) = cached
+                && let Some(proxy) = proxies.iter().find(|x| x.name() == name)
+                && proxy_manager.alive(&name).await
+            {
+                return Ok(proxy.clone());
+            }
+
+            let pick = {
+                let mut strategy = strategy.lock().await;
+                (*strategy)(proxies, &sess)
+            };
+            let proxy = pick.await?;
+            picked
+                .lock()
+                .await
+                .insert(key, (proxy.name().to_owned(), now + ttl));
+            Ok(proxy)
+        })
     })
 }
 
@@ -187,6 +269,138 @@ mod tests {
         };
     }
 
+    #[tokio::test]
+    async fn test_consistent_hashing() {
+        let resolver = Arc::new(NoopResolver);
+        let proxies = ["a", "b", "c", "d"]
+            .map(|name| {
+                Arc::new(NoopOutboundHandler {
+                    name: name.to_string(),
+                }) as AnyOutboundHandler
+            })
+            .to_vec();
+        let manager = ProxyManager::new(resolver, None);
+        let mut strategy_fn =
+            strategy_consistent_hashring(LoadBalanceHashKey::Host, manager.clone());
+
+        let sess = |i: usize| Session {
+            destination: SocksAddr::Domain(format!("host{i}.example.com"), 443),
+            ..Default::default()
+        };
+        let mut picked = vec![];
+        for i in 0..100 {
+            let a = strategy_fn(proxies.clone(), &sess(i)).await.unwrap();
+            let b = strategy_fn(proxies.clone(), &sess(i)).await.unwrap();
+            assert_eq!(a.name(), b.name());
+            picked.push(a.name().to_owned());
+        }
+
+        // only the keys of the removed proxy move
+        let without_b = proxies
+            .iter()
+            .filter(|p| p.name() != "b")
+            .cloned()
+            .collect::<Vec<_>>();
+        for (i, before) in picked.iter().enumerate() {
+            let after = strategy_fn(without_b.clone(), &sess(i)).await.unwrap();
+            if before != "b" {
+                assert_eq!(after.name(), before);
+            }
+        }
+
+        // same for a proxy going down, until it comes back
+        manager.report_alive("c", false).await;
+        for (i, before) in picked.iter().enumerate() {
+            let after = strategy_fn(proxies.clone(), &sess(i)).await.unwrap();
+            assert_ne!(after.name(), "c");
+            if before != "c" {
+                assert_eq!(after.name(), before);
+            }
+        }
+        manager.report_alive("c", true).await;
+        for (i, before) in picked.iter().enumerate() {
+            let after = strategy_fn(proxies.clone(), &sess(i)).await.unwrap();
+            assert_eq!(after.name(), before);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_affinity() {
+        let resolver = Arc::new(NoopResolver);
+        let proxies = ["a", "b", "c"]
+            .map(|name| {
+                Arc::new(NoopOutboundHandler {
+                    name: name.to_string(),
+                }) as AnyOutboundHandler
+            })
+            .to_vec();
+        let manager = ProxyManager::new(resolver, None);
+        let mut strategy_fn = strategy_with_affinity(
+            strategy_rr(),
+            LoadBalanceHashKey::Host,
+            Duration::from_millis(200),
+            manager.clone(),
+        );
+        let sess = |host: &str, port: u16| Session {
+            destination: SocksAddr::Domain(host.to_owned(), port),
+            ..Default::default()
+        };
+
+        let first = strategy_fn(proxies.clone(), &sess("a.com", 443))
+            .await
+            .unwrap();
+        // other destinations are still spread
+        let other = strategy_fn(proxies.clone(), &sess("b.com", 443))
+            .await
+            .unwrap();
+        assert_ne!(first.name(), other.name());
+        for port in [443, 80] {
+            let again = strategy_fn(proxies.clone(), &sess("a.com", port))
+                .await
+                .unwrap();
+            assert_eq!(again.name(), first.name());
+        }
+
+        // not while the proxy is down
+        manager.report_alive(first.name(), false).await;
+        let moved = strategy_fn(proxies.clone(), &sess("a.com", 443))
+            .await
+            .unwrap();
+        assert_ne!(moved.name(), first.name());
+        manager.report_alive(first.name(), true).await;
+        let again = strategy_fn(proxies.clone(), &sess("a.com", 443))
+            .await
+            .unwrap();
+        assert_eq!(again.name(), moved.name());
+
+        // nor once it expired
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let expired = strategy_fn(proxies.clone(), &sess("a.com", 443))
+            .await
+            .unwrap();
+        assert_ne!(expired.name(), moved.name());
+    }
+
+    #[test]
+    fn test_hash_key() {
+        let sess = Session {
+            destination: SocksAddr::Domain("www.example.com".to_owned(), 443),
+            ..Default::default()
+        };
+        assert_eq!(
+            get_hash_key(&sess, LoadBalanceHashKey::Domain),
+            "example.com"
+        );
+        assert_eq!(
+            get_hash_key(&sess, LoadBalanceHashKey::Host),
+            "www.example.com"
+        );
+        assert_eq!(
+            get_hash_key(&sess, LoadBalanceHashKey::HostPort),
+            "www.example.com:443"
+        );
+    }
+
     #[tokio::test]
     async fn test_sticky_session() {
         let resolver = Arc::new(NoopResolver);
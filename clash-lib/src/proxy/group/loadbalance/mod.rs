@@ -2,11 +2,13 @@ mod helpers;
 
 use async_trait::async_trait;
 use helpers::strategy_sticky_session;
-use std::{io, sync::Arc};
+use std::{io, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 use tracing::debug;
 
-use self::helpers::{StrategyFn, strategy_consistent_hashring, strategy_rr};
+use self::helpers::{
+    StrategyFn, strategy_consistent_hashring, strategy_rr, strategy_with_affinity,
+};
 use crate::{
     app::{
         dispatcher::{BoxedChainedDatagram, BoxedChainedStream},
@@ -15,7 +17,7 @@ use crate::{
             ProxyManager, providers::proxy_provider::ThreadSafeProxyProvider,
         },
     },
-    config::internal::proxy::LoadBalanceStrategy,
+    config::internal::proxy::{LoadBalanceHashKey, LoadBalanceStrategy},
     proxy::{
         AnyOutboundHandler, ConnectorType, DialWithConnector, HandlerCommonOptions,
         OutboundHandler, OutboundType,
@@ -31,6 +33,9 @@ pub struct HandlerOptions {
     pub name: String,
     pub udp: bool,
     pub strategy: LoadBalanceStrategy,
+    pub hash_key: LoadBalanceHashKey,
+    /// what a destination is keyed by, and how long it sticks to a proxy
+    pub affinity: Option<(LoadBalanceHashKey, Duration)>,
 }
 
 struct HandlerInner {
@@ -60,11 +65,19 @@ impl Handler {
         proxy_manager: ProxyManager,
     ) -> Self {
         let strategy_fn = match opts.strategy {
-            LoadBalanceStrategy::ConsistentHashing => strategy_consistent_hashring(),
+            LoadBalanceStrategy::ConsistentHashing => {
+                strategy_consistent_hashring(opts.hash_key, proxy_manager)
+            }
             LoadBalanceStrategy::RoundRobin => strategy_rr(),
             LoadBalanceStrategy::StickySession => {
-                strategy_sticky_session(proxy_manager)
+                strategy_sticky_session(proxy_manager.clone())
+            }
+        };
+        let strategy_fn = match opts.affinity {
+            Some((key, ttl)) => {
+                strategy_with_affinity(strategy_fn, key, ttl, proxy_manager)
             }
+            None => strategy_fn,
         };
 
         Self {
@@ -109,7 +122,8 @@ impl OutboundHandler for Handler {
         let proxy = (self.inner.lock().await.strategy_fn)(proxies, sess).await?;
         debug!("{} use proxy {}", self.name(), proxy.name());
 
-        let s = proxy.connect_stream(sess, resolver).await?;
+        let sess = self.opts.common_opts.member_session(sess);
+        let s = proxy.connect_stream(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -126,7 +140,8 @@ impl OutboundHandler for Handler {
         let proxy = (self.inner.lock().await.strategy_fn)(proxies, sess).await?;
         debug!("{} use proxy {}", self.name(), proxy.name());
 
-        let s = proxy.connect_datagram(sess, resolver).await?;
+        let sess = self.opts.common_opts.member_session(sess);
+        let s = proxy.connect_datagram(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -146,8 +161,9 @@ impl OutboundHandler for Handler {
         let proxies = self.get_proxies(false).await;
         let proxy = (self.inner.lock().await.strategy_fn)(proxies, sess).await?;
         debug!("{} use proxy {}", self.name(), proxy.name());
+        let sess = self.opts.common_opts.member_session(sess);
         proxy
-            .connect_stream_with_connector(sess, resolver, connector)
+            .connect_stream_with_connector(&sess, resolver, connector)
             .await
     }
 
@@ -89,6 +89,8 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedStream> {
+        let member_sess = self.opts.common_opts.member_session(sess);
+        let sess = member_sess.as_ref();
         let proxies: Vec<AnyOutboundHandler> =
             stream::iter(self.get_proxies(true).await).collect().await;
 
@@ -134,6 +136,8 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedDatagram> {
+        let member_sess = self.opts.common_opts.member_session(sess);
+        let sess = member_sess.as_ref();
         let proxies: Vec<AnyOutboundHandler> =
             stream::iter(self.get_proxies(true).await).collect().await;
 
@@ -13,6 +13,7 @@ use crate::{
         dns::ThreadSafeDNSResolver,
         remote_content_manager::providers::proxy_provider::ThreadSafeProxyProvider,
     },
+    common::utils::proxy_name_eq,
     proxy::{
         AnyOutboundHandler, ConnectorType, DialWithConnector, HandlerCommonOptions,
         OutboundHandler, OutboundType,
@@ -67,7 +68,9 @@ impl Handler {
             providers,
             current_selected_index: AtomicU16::new(
                 selected
-                    .and_then(|s| proxies.iter().position(|p| p.name() == s))
+                    .and_then(|s| {
+                        proxies.iter().position(|p| proxy_name_eq(p.name(), &s))
+                    })
                     .unwrap_or(0) as u16,
             )
             .into(),
@@ -99,14 +102,17 @@ impl Handler {
 impl SelectorControl for Handler {
     async fn select(&self, name: &str) -> Result<(), Error> {
         let proxies = get_proxies_from_providers(&self.providers, false).await;
-        if proxies.iter().any(|x| x.name() == name) {
-            self.current_selected_index.store(
-                proxies.iter().position(|p| p.name() == name).unwrap() as u16,
-                std::sync::atomic::Ordering::Relaxed,
-            );
-            Ok(())
-        } else {
-            Err(Error::Operation(format!("proxy {name} not found")))
+        match proxies
+            .iter()
+            .position(|p| p.name() == name)
+            .or_else(|| proxies.iter().position(|p| proxy_name_eq(p.name(), name)))
+        {
+            Some(idx) => {
+                self.current_selected_index
+                    .store(idx as u16, std::sync::atomic::Ordering::Relaxed);
+                Ok(())
+            }
+            None => Err(Error::Operation(format!("proxy {name} not found"))),
         }
     }
 
@@ -148,8 +154,9 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedStream> {
+        let sess = self.opts.common_opts.member_session(sess);
         let selected = self.selected_proxy(true).await;
-        let s = selected.connect_stream(sess, resolver).await?;
+        let s = selected.connect_stream(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -161,8 +168,9 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedDatagram> {
+        let sess = self.opts.common_opts.member_session(sess);
         let selected = self.selected_proxy(true).await;
-        let s = selected.connect_datagram(sess, resolver).await?;
+        let s = selected.connect_datagram(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -179,10 +187,11 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedStream> {
+        let sess = self.opts.common_opts.member_session(sess);
         let s = self
             .selected_proxy(true)
             .await
-            .connect_stream_with_connector(sess, resolver, connector)
+            .connect_stream_with_connector(&sess, resolver, connector)
             .await?;
 
         s.append_to_chain(self.name()).await;
@@ -195,9 +204,10 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedDatagram> {
+        let sess = self.opts.common_opts.member_session(sess);
         self.selected_proxy(true)
             .await
-            .connect_datagram_with_connector(sess, resolver, connector)
+            .connect_datagram_with_connector(&sess, resolver, connector)
             .await
     }
 
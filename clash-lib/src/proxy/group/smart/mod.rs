@@ -456,6 +456,7 @@ impl Handler {
     ) -> io::Result<BoxedChainedStream> {
         let site = sess.destination.host();
         let dest_ip = sess.destination.ip().map(|ip| ip.to_string());
+        let member_sess = self.opts.common_opts.member_session(sess);
         let mut tried = HashSet::new();
         let mut retry_delay = 100; // Initial retry delay in ms
 
@@ -488,7 +489,8 @@ impl Handler {
                     tried.insert(name.clone());
 
                     let start = Instant::now();
-                    match proxy.connect_stream(sess, resolver.clone()).await {
+                    match proxy.connect_stream(&member_sess, resolver.clone()).await
+                    {
                         Ok(stream) => {
                             let delay = start.elapsed().as_secs_f64() * 1000.0;
 
@@ -691,7 +693,8 @@ impl OutboundHandler for Handler {
         // For UDP we use the best proxy without retries for simplicity
         if let Some(proxy) = self.pick_smart(sess).await {
             debug!("{} use proxy {} (smart)", self.name(), proxy.name());
-            let s = proxy.connect_datagram(sess, resolver).await?;
+            let sess = self.opts.common_opts.member_session(sess);
+            let s = proxy.connect_datagram(&sess, resolver).await?;
 
             s.append_to_chain(self.name()).await;
 
@@ -713,8 +716,9 @@ impl OutboundHandler for Handler {
     ) -> io::Result<BoxedChainedStream> {
         if let Some(proxy) = self.pick_smart(sess).await {
             debug!("{} use proxy {} (smart)", self.name(), proxy.name());
+            let sess = self.opts.common_opts.member_session(sess);
             proxy
-                .connect_stream_with_connector(sess, resolver, connector)
+                .connect_stream_with_connector(&sess, resolver, connector)
                 .await
         } else {
             Err(io::Error::other("no available proxy in smart group"))
@@ -821,6 +825,8 @@ mod tests {
         let cache_store = ThreadSafeCacheFile::new(
             cache_path.to_str().expect("Cache path is not valid UTF-8"),
             false,
+            false,
+            false,
         );
 
         let resolver = SystemResolver::new(false).map_err(|e| {
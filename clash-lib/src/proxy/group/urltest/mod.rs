@@ -1,7 +1,13 @@
-use std::{io, sync::atomic::AtomicU16, time::Duration};
+use std::{
+    io,
+    sync::atomic::AtomicU16,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
-use tracing::trace;
+use futures::future::join_all;
+use tokio::sync::Mutex;
+use tracing::{debug, trace};
 
 use crate::{
     app::{
@@ -11,6 +17,7 @@ use crate::{
             ProxyManager, providers::proxy_provider::ThreadSafeProxyProvider,
         },
     },
+    common::mmdb::MmdbLookup,
     proxy::{
         AnyOutboundHandler, ConnectorType, DialWithConnector, HandlerCommonOptions,
         OutboundHandler, OutboundType,
@@ -27,6 +34,17 @@ pub struct HandlerOptions {
     pub udp: bool,
 }
 
+/// Restricts the selection to proxies whose exit IP is located in `country`.
+pub struct ExpectedCountry {
+    /// ISO country code, compared case-insensitively
+    pub country: String,
+    /// URL that echoes the caller's IP address in plain text
+    pub ip_echo_url: String,
+    /// How often the exit IPs are probed again, zero to probe only once
+    pub interval: Duration,
+    pub mmdb: MmdbLookup,
+}
+
 pub struct Handler {
     opts: HandlerOptions,
     tolerance: u16,
@@ -34,6 +52,9 @@ pub struct Handler {
     providers: Vec<ThreadSafeProxyProvider>,
     proxy_manager: ProxyManager,
     fastest_proxy_index: AtomicU16,
+
+    expected_country: Option<ExpectedCountry>,
+    last_exit_probe: Mutex<Option<Instant>>,
 }
 
 impl std::fmt::Debug for Handler {
@@ -57,9 +78,16 @@ impl Handler {
             providers,
             proxy_manager,
             fastest_proxy_index: AtomicU16::new(0),
+            expected_country: None,
+            last_exit_probe: Mutex::new(None),
         }
     }
 
+    pub fn expected_country(mut self, expected: ExpectedCountry) -> Self {
+        self.expected_country = Some(expected);
+        self
+    }
+
     async fn get_proxies(&self, touch: bool) -> Vec<AnyOutboundHandler> {
         get_proxies_from_providers(&self.providers, touch).await
     }
@@ -68,6 +96,7 @@ impl Handler {
         let proxy_manager = self.proxy_manager.clone();
 
         let proxies = self.get_proxies(touch).await;
+        let proxies = self.filter_by_country(proxies).await;
         let mut fastest = proxies
             .first()
             .unwrap_or_else(|| panic!("no proxy found for {}", self.name()));
@@ -128,6 +157,68 @@ impl Handler {
 
         fastest.clone()
     }
+
+    /// Keep only the proxies exiting in the expected country, or all of them
+    /// if none does (or none could be probed). The first call waits for the
+    /// exit IPs to be probed, so that no proxy is picked before they're
+    /// known, later probes run in the background every `interval`.
+    async fn filter_by_country(
+        &self,
+        proxies: Vec<AnyOutboundHandler>,
+    ) -> Vec<AnyOutboundHandler> {
+        let Some(expected) = &self.expected_country else {
+            return proxies;
+        };
+
+        {
+            let mut last_probe = self.last_exit_probe.lock().await;
+            let first = last_probe.is_none();
+            if last_probe.is_none_or(|x| {
+                !expected.interval.is_zero() && x.elapsed() >= expected.interval
+            }) {
+                *last_probe = Some(Instant::now());
+
+                let proxy_manager = self.proxy_manager.clone();
+                let url = expected.ip_echo_url.clone();
+                let proxies = proxies.clone();
+                let probe = async move {
+                    join_all(proxies.into_iter().map(|proxy| {
+                        proxy_manager.probe_exit_ip(proxy, &url, None)
+                    }))
+                    .await;
+                };
+                // the lock is held, concurrent callers wait for it too
+                if first {
+                    probe.await;
+                } else {
+                    tokio::spawn(probe);
+                }
+            }
+        }
+
+        let mut matched = vec![];
+        for proxy in proxies.iter() {
+            let Some(ip) = self.proxy_manager.exit_ip(proxy.name()).await else {
+                continue;
+            };
+            if expected.mmdb.lookup_country(ip).is_ok_and(|x| {
+                x.country_code.eq_ignore_ascii_case(&expected.country)
+            }) {
+                matched.push(proxy.clone());
+            }
+        }
+
+        if matched.is_empty() {
+            debug!(
+                "`{}` no proxy exits in {}, using all proxies",
+                self.name(),
+                expected.country
+            );
+            proxies
+        } else {
+            matched
+        }
+    }
 }
 
 impl DialWithConnector for Handler {}
@@ -155,8 +246,9 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedStream> {
+        let sess = self.opts.common_opts.member_session(sess);
         let fastest = self.fastest(false).await;
-        let s = fastest.connect_stream(sess, resolver).await?;
+        let s = fastest.connect_stream(&sess, resolver).await?;
 
         s.append_to_chain(self.name()).await;
 
@@ -169,8 +261,9 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> io::Result<BoxedChainedDatagram> {
+        let sess = self.opts.common_opts.member_session(sess);
         let fastest = self.fastest(false).await;
-        let d = fastest.connect_datagram(sess, resolver).await?;
+        let d = fastest.connect_datagram(&sess, resolver).await?;
 
         d.append_to_chain(self.name()).await;
 
@@ -187,10 +280,11 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedStream> {
+        let sess = self.opts.common_opts.member_session(sess);
         let s = self
             .fastest(true)
             .await
-            .connect_stream_with_connector(sess, resolver, connector)
+            .connect_stream_with_connector(&sess, resolver, connector)
             .await?;
 
         s.append_to_chain(self.name()).await;
@@ -203,9 +297,10 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedDatagram> {
+        let sess = self.opts.common_opts.member_session(sess);
         self.fastest(true)
             .await
-            .connect_datagram_with_connector(sess, resolver, connector)
+            .connect_datagram_with_connector(&sess, resolver, connector)
             .await
     }
 
@@ -232,3 +327,140 @@ impl GroupProxyAPIResponse for Handler {
         self.opts.common_opts.icon.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use tokio::sync::RwLock;
+
+    use super::{ExpectedCountry, Handler, HandlerOptions};
+    use crate::{
+        app::{dns::MockClashResolver, remote_content_manager::ProxyManager},
+        common::mmdb::{MmdbLookupCountry, MockMmdbLookupTrait},
+        proxy::{
+            OutboundHandler,
+            mocks::{MockDummyOutboundHandler, MockDummyProxyProvider},
+        },
+    };
+
+    const US: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+    const JP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1));
+
+    /// A url-test group over the proxies `us` and `jp` expecting `country`
+    fn url_test(country: &str, proxy_manager: ProxyManager) -> Handler {
+        url_test_probed(country, proxy_manager, Arc::default())
+    }
+
+    /// Like [`url_test`], counting the exit IP probes in `probes`
+    fn url_test_probed(
+        country: &str,
+        proxy_manager: ProxyManager,
+        probes: Arc<AtomicUsize>,
+    ) -> Handler {
+        let mut provider = MockDummyProxyProvider::new();
+        provider.expect_proxies().returning(move || {
+            ["us", "jp"]
+                .into_iter()
+                .map(|name| {
+                    let mut proxy = MockDummyOutboundHandler::new();
+                    proxy.expect_name().return_const(name.to_owned());
+                    // the exit IP probes
+                    let probes = probes.clone();
+                    proxy.expect_connect_stream().returning(move |_, _| {
+                        probes.fetch_add(1, Ordering::Relaxed);
+                        Err(std::io::Error::other("not probed in tests"))
+                    });
+                    Arc::new(proxy) as _
+                })
+                .collect()
+        });
+
+        let mut mmdb = MockMmdbLookupTrait::new();
+        mmdb.expect_lookup_country().returning(|ip| {
+            Ok(MmdbLookupCountry {
+                country_code: if ip == US { "US" } else { "JP" }.to_owned(),
+            })
+        });
+
+        Handler::new(
+            HandlerOptions {
+                name: "auto".to_owned(),
+                ..Default::default()
+            },
+            0,
+            vec![Arc::new(RwLock::new(provider))],
+            proxy_manager,
+        )
+        .expected_country(ExpectedCountry {
+            country: country.to_owned(),
+            ip_echo_url: "http://127.0.0.1:1/".to_owned(),
+            interval: Duration::ZERO,
+            mmdb: Arc::new(mmdb),
+        })
+    }
+
+    fn proxy_manager() -> ProxyManager {
+        ProxyManager::new(Arc::new(MockClashResolver::new()), None)
+    }
+
+    #[tokio::test]
+    async fn test_expected_country() {
+        let proxy_manager = proxy_manager();
+        proxy_manager.report_exit_ip("us", US).await;
+        proxy_manager.report_exit_ip("jp", JP).await;
+
+        // compared case-insensitively
+        let handler = url_test("jp", proxy_manager.clone());
+        assert_eq!(handler.fastest(false).await.name(), "jp");
+
+        let handler = url_test("US", proxy_manager);
+        assert_eq!(handler.fastest(false).await.name(), "us");
+    }
+
+    #[tokio::test]
+    async fn test_expected_country_unknown_exit_ip() {
+        let proxy_manager = proxy_manager();
+        let handler = url_test("JP", proxy_manager.clone());
+        // the probes failed, all proxies are candidates
+        assert_eq!(handler.fastest(false).await.name(), "us");
+
+        // only the exit IP of `us` is known, and it does not match
+        proxy_manager.report_exit_ip("us", US).await;
+        assert_eq!(handler.fastest(false).await.name(), "us");
+
+        proxy_manager.report_exit_ip("jp", JP).await;
+        assert_eq!(handler.fastest(false).await.name(), "jp");
+    }
+
+    #[tokio::test]
+    async fn test_expected_country_waits_for_first_probe() {
+        let probes = Arc::new(AtomicUsize::new(0));
+        let handler = url_test_probed("JP", proxy_manager(), probes.clone());
+
+        handler.fastest(false).await;
+        assert_eq!(probes.load(Ordering::Relaxed), 2);
+
+        // probed only once
+        handler.fastest(false).await;
+        tokio::task::yield_now().await;
+        assert_eq!(probes.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expected_country_no_match() {
+        let proxy_manager = proxy_manager();
+        proxy_manager.report_exit_ip("us", US).await;
+        proxy_manager.report_exit_ip("jp", JP).await;
+
+        let handler = url_test("DE", proxy_manager);
+        assert_eq!(handler.fastest(false).await.name(), "us");
+    }
+}
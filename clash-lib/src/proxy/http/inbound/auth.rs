@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use base64::Engine;
 
 use bytes::Bytes;
@@ -31,6 +33,7 @@ fn decode_basic_proxy_authorization(cred: &str) -> Option<(String, String)> {
 /// returns a auth required response on auth failure
 pub fn authenticate_req(
     req: &Request<hyper::body::Incoming>,
+    src: IpAddr,
     authenticator: ThreadSafeAuthenticator,
 ) -> Option<Response<BoxBody<Bytes, std::io::Error>>> {
     let auth_resp = Response::builder()
@@ -54,10 +57,9 @@ pub fn authenticate_req(
             Some(auth_resp)
         }
         Some((user, pass)) => {
-            if authenticator.authenticate(&user, &pass) {
+            if authenticator.authenticate(src, &user, &pass) {
                 None // Authenticated successfully
             } else {
-                warn!("proxy authentication failed for user: {}", user);
                 Some(auth_resp)
             }
         }
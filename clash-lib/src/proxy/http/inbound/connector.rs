@@ -20,6 +20,7 @@ use super::proxy::maybe_socks_addr;
 #[derive(Clone)]
 pub struct Connector {
     src: SocketAddr,
+    local: Option<SocketAddr>,
     dispatcher: Arc<Dispatcher>,
     fw_mark: Option<u32>,
 }
@@ -27,11 +28,13 @@ pub struct Connector {
 impl Connector {
     pub fn new(
         src: SocketAddr,
+        local: Option<SocketAddr>,
         dispatcher: Arc<Dispatcher>,
         fw_mark: Option<u32>,
     ) -> Self {
         Self {
             src,
+            local,
             dispatcher,
             fw_mark,
         }
@@ -60,6 +63,7 @@ impl tower::Service<Uri> for Connector {
 
     fn call(&mut self, url: Uri) -> Self::Future {
         let src = self.src;
+        let local = self.local;
         let dispatcher = self.dispatcher.clone();
 
         let destination = maybe_socks_addr(&url);
@@ -71,6 +75,7 @@ impl tower::Service<Uri> for Connector {
                 network: Network::Tcp,
                 typ: Type::Http,
                 source: src,
+                local_addr: local,
                 destination: destination
                     .ok_or(ProxyError::InvalidUrl(url.to_string()))?,
                 so_mark: fw_mark,
@@ -4,17 +4,21 @@ mod proxy;
 
 use crate::{
     Dispatcher,
+    app::inbound::conn_limit::ThreadSafeConnectionLimiter,
     common::{auth::ThreadSafeAuthenticator, errors::new_io_error},
     proxy::{
         inbound::InboundHandlerTrait,
-        utils::{ToCanonical, apply_tcp_options, try_create_dualstack_tcplistener},
+        utils::{
+            ToCanonical, apply_tcp_options, tcp_options,
+            try_create_dualstack_tcplistener,
+        },
     },
 };
 use async_trait::async_trait;
 use hyper_util::rt::TokioIo;
 pub use proxy::handle as handle_http;
 use std::{net::SocketAddr, sync::Arc};
-use tracing::warn;
+use tracing::{debug, warn};
 
 #[derive(Clone)]
 pub struct HttpInbound {
@@ -22,7 +26,12 @@ pub struct HttpInbound {
     allow_lan: bool,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
 }
 
 impl Drop for HttpInbound {
@@ -37,14 +46,20 @@ impl HttpInbound {
         allow_lan: bool,
         dispatcher: Arc<Dispatcher>,
         authenticator: ThreadSafeAuthenticator,
+        conn_limiter: ThreadSafeConnectionLimiter,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
     ) -> Self {
         Self {
             addr,
             allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         }
     }
 }
@@ -60,7 +75,8 @@ impl InboundHandlerTrait for HttpInbound {
     }
 
     async fn listen_tcp(&self) -> std::io::Result<()> {
-        let listener = try_create_dualstack_tcplistener(self.addr)?;
+        let listener =
+            try_create_dualstack_tcplistener(self.addr, self.tfo, self.reuse_port)?;
 
         loop {
             let (socket, _) = listener.accept().await?;
@@ -73,15 +89,29 @@ impl InboundHandlerTrait for HttpInbound {
                 continue;
             }
 
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
 
+            if self.authenticator.banned(src_addr.ip()) {
+                debug!("Connection from banned {} refused", src_addr);
+                continue;
+            }
+
+            let Some(guard) = self.conn_limiter.try_acquire(src_addr.ip()) else {
+                debug!("Too many connections from {}, rejecting", src_addr);
+                self.conn_limiter.reject_http(socket);
+                continue;
+            };
+
+            let socket_local = socket.local_addr().ok();
             let dispatcher = self.dispatcher.clone();
             let author = self.authenticator.clone();
             let fw_mark = self.fw_mark;
             tokio::spawn(async move {
+                let _guard = guard;
                 proxy::handle(
                     TokioIo::new(Box::new(socket)),
                     src_addr,
+                    socket_local,
                     dispatcher,
                     author,
                     fw_mark,
@@ -17,7 +17,10 @@ use tracing::{instrument, warn};
 
 use crate::{
     app::dispatcher::Dispatcher,
-    common::{auth::ThreadSafeAuthenticator, errors::map_io_error},
+    common::{
+        auth::ThreadSafeAuthenticator, errors::map_io_error,
+        http::merge_global_headers,
+    },
     proxy::{AnyStream, ProxyError},
     session::{Network, Session, SocksAddr, Type},
 };
@@ -45,12 +48,13 @@ pub fn maybe_socks_addr(r: &Uri) -> Option<SocksAddr> {
 async fn proxy(
     req: Request<hyper::body::Incoming>,
     src: SocketAddr,
+    local: Option<SocketAddr>,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
     fw_mark: Option<u32>,
 ) -> Result<Response<BoxBody<Bytes, std::io::Error>>, ProxyError> {
     if authenticator.enabled()
-        && let Some(res) = authenticate_req(&req, authenticator)
+        && let Some(res) = authenticate_req(&req, src.ip(), authenticator)
     {
         return Ok(res);
     }
@@ -58,7 +62,7 @@ async fn proxy(
     let client = Client::builder(TokioExecutor::new())
         .http1_title_case_headers(true)
         .http1_preserve_header_case(true)
-        .build(Connector::new(src, dispatcher.clone(), fw_mark));
+        .build(Connector::new(src, local, dispatcher.clone(), fw_mark));
 
     // TODO: handle other upgrades: https://github.com/hyperium/hyper/blob/master/examples/upgrades.rs
     if req.method() == Method::CONNECT {
@@ -71,6 +75,7 @@ async fn proxy(
                                 network: Network::Tcp,
                                 typ: Type::HttpConnect,
                                 source: src,
+                                local_addr: local,
                                 destination: addr,
                                 so_mark: fw_mark,
 
@@ -100,6 +105,8 @@ async fn proxy(
                 .unwrap()),
         }
     } else {
+        let mut req = req;
+        merge_global_headers(req.headers_mut());
         match client
             .request(req)
             .map_err(|x| ProxyError::General(x.to_string()))
@@ -119,6 +126,7 @@ async fn proxy(
 
 struct ProxyService {
     src: SocketAddr,
+    local: Option<SocketAddr>,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
     fw_mark: Option<u32>,
@@ -133,6 +141,7 @@ impl hyper::service::Service<Request<hyper::body::Incoming>> for ProxyService {
         Box::pin(proxy(
             req,
             self.src,
+            self.local,
             self.dispatcher.clone(),
             self.authenticator.clone(),
             self.fw_mark,
@@ -144,6 +153,7 @@ impl hyper::service::Service<Request<hyper::body::Incoming>> for ProxyService {
 pub async fn handle(
     stream: TokioIo<AnyStream>,
     src: SocketAddr,
+    local: Option<SocketAddr>,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
     fw_mark: Option<u32>,
@@ -155,6 +165,7 @@ pub async fn handle(
             stream,
             ProxyService {
                 src,
+                local,
                 dispatcher,
                 authenticator,
                 fw_mark,
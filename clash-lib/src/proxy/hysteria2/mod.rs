@@ -11,8 +11,9 @@ use self::{
 };
 use super::{
     ConnectorType, DialWithConnector, OutboundHandler, OutboundType,
-    converters::hysteria2::PortGenerator, datagram::UdpPacket,
-    utils::new_udp_socket,
+    converters::hysteria2::PortGenerator,
+    datagram::UdpPacket,
+    utils::{new_udp_socket, quic::CongestionControl, with_handshake_timeout},
 };
 use crate::{
     app::{
@@ -22,7 +23,7 @@ use crate::{
         },
         dns::ThreadSafeDNSResolver,
     },
-    common::tls::DefaultTlsVerifier,
+    common::tls::{DefaultTlsVerifier, client_resumption},
     session::{Session, SocksAddr},
 };
 use anyhow::anyhow;
@@ -32,7 +33,7 @@ use futures::{SinkExt, StreamExt};
 use h3::client::SendRequest;
 use h3_quinn::OpenStreams;
 use quinn::{
-    ClientConfig, Connection, TokioRuntime, crypto::rustls::QuicClientConfig,
+    ClientConfig, Connection, TokioRuntime, VarInt, crypto::rustls::QuicClientConfig,
 };
 use quinn_proto::TransportConfig;
 use rustls::ClientConfig as RustlsClientConfig;
@@ -79,10 +80,21 @@ pub struct HystOption {
     pub ca: Option<PathBuf>,
     pub udp_mtu: Option<u32>,
     pub disable_mtu_discovery: bool,
+    pub initial_mtu: u16,
+    pub congestion_controller: CongestionControl,
+    pub stream_receive_window: VarInt,
+    pub connection_receive_window: VarInt,
+    /// UDP segmentation offload, Linux only
+    pub gso: bool,
     #[allow(dead_code)]
     pub ca_str: Option<String>,
     #[allow(dead_code)]
     pub cwnd: Option<u64>,
+    /// PINGs are sent on the idle connection this often, `None` disables
+    /// them
+    pub keep_alive_interval: Option<std::time::Duration>,
+    /// the connection is closed when nothing is acknowledged for this long
+    pub idle_timeout: std::time::Duration,
 }
 
 enum CcRx {
@@ -122,9 +134,6 @@ impl Debug for Handler {
 }
 
 impl Handler {
-    const DEFAULT_MAX_IDLE_TIMEOUT: std::time::Duration =
-        std::time::Duration::from_secs(300);
-
     pub fn new(opts: HystOption) -> Self {
         if opts.ca.is_some() {
             warn!("hysteria2 does not support ca yet");
@@ -142,6 +151,7 @@ impl Handler {
         } else {
             opts.alpn.iter().map(|x| x.as_bytes().to_vec()).collect()
         };
+        tls_config.resumption = client_resumption();
 
         let mut transport = TransportConfig::default();
         if opts.disable_mtu_discovery {
@@ -150,10 +160,14 @@ impl Handler {
         }
         // TODO
         // transport.congestion_controller_factory(DynCongestion);
-        transport.max_idle_timeout(Some(
-            Self::DEFAULT_MAX_IDLE_TIMEOUT.try_into().unwrap(),
-        ));
-        transport.keep_alive_interval(Some(std::time::Duration::from_secs(10)));
+        transport
+            .initial_mtu(opts.initial_mtu)
+            .congestion_controller_factory(opts.congestion_controller.factory())
+            .stream_receive_window(opts.stream_receive_window)
+            .receive_window(opts.connection_receive_window)
+            .enable_segmentation_offload(opts.gso);
+        transport.max_idle_timeout(opts.idle_timeout.try_into().ok());
+        transport.keep_alive_interval(opts.keep_alive_interval);
 
         let quic_config: QuicClientConfig = tls_config.try_into().unwrap();
         let mut client_config = ClientConfig::new(Arc::new(quic_config));
@@ -193,14 +207,7 @@ impl Handler {
         // Here maybe we should use a AsyncUdpSocket which implement salamander obfs
         // and port hopping
         let create_socket = || async {
-            new_udp_socket(
-                None,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
-                Some(server_socket_addr),
-            )
-            .await
+            new_udp_socket(None, Some(server_socket_addr), &sess.into()).await
         };
 
         let mut ep = if let Some(obfs) = self.opts.obfs.as_ref() {
@@ -245,10 +252,14 @@ impl Handler {
 
         ep.set_default_client_config(self.client_config.clone());
 
-        let session = ep
-            .connect(server_socket_addr, self.opts.sni.as_deref().unwrap_or(""))?
-            .await?;
-        let (guard, _rx, udp) = Self::auth(&session, &self.opts.passwd).await?;
+        let (session, (guard, _rx, udp)) = with_handshake_timeout(async {
+            let session = ep
+                .connect(server_socket_addr, self.opts.sni.as_deref().unwrap_or(""))?
+                .await?;
+            let auth = Self::auth(&session, &self.opts.passwd).await?;
+            anyhow::Ok((session, auth))
+        })
+        .await?;
         *self.support_udp.write().unwrap() = udp;
         // todo set congestion controller according to cc_rx
 
@@ -662,7 +673,14 @@ mod tests {
             ca_str: None,
             cwnd: None,
             udp_mtu: None,
+            initial_mtu: 1252,
+            congestion_controller: CongestionControl::Bbr,
+            stream_receive_window: VarInt::from_u32(8 * 1024 * 1024),
+            connection_receive_window: VarInt::from_u32(20 * 1024 * 1024),
+            gso: true,
             disable_mtu_discovery: false,
+            keep_alive_interval: Some(std::time::Duration::from_secs(10)),
+            idle_timeout: std::time::Duration::from_secs(30),
         };
 
         let handler = Arc::new(Handler::new(opts));
@@ -0,0 +1,155 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::BytesMut;
+use futures::{Sink, Stream};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::{debug, trace};
+
+use super::{
+    link::Link,
+    packet::{Frame, Kind, open_payload},
+    stream::{IDLE_TIMEOUT, POLL_INTERVAL},
+};
+use crate::{proxy::datagram::UdpPacket, session::SocksAddr};
+
+/// Datagrams that wouldn't fit in an echo within the usual MTU are dropped
+const MAX_DATAGRAM: usize = 1200;
+
+/// Opens a UDP association through the tunnel. Datagrams are sent as they
+/// come, with no retransmission.
+pub async fn connect(link: Link, password: &str) -> io::Result<IcmpDatagram> {
+    link.open(open_payload(password, link.conn(), None)).await?;
+
+    let (in_tx, in_rx) = mpsc::channel(64);
+    let (out_tx, out_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        if let Err(e) = relay(&link, out_rx, in_tx).await {
+            debug!("icmp tunnel association {} closed: {e}", link.conn());
+        }
+        link.send(&Frame::new(Kind::Reset, link.conn())).await.ok();
+    });
+    Ok(IcmpDatagram {
+        rx: in_rx,
+        tx: out_tx,
+    })
+}
+
+async fn relay(
+    link: &Link,
+    mut outgoing: mpsc::UnboundedReceiver<UdpPacket>,
+    incoming: mpsc::Sender<UdpPacket>,
+) -> io::Result<()> {
+    let (mut last_sent, mut last_heard) = (Instant::now(), Instant::now());
+    let mut tick = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            pkt = outgoing.recv() => {
+                let Some(pkt) = pkt else {
+                    return Ok(());
+                };
+                if pkt.data.len() > MAX_DATAGRAM {
+                    trace!("dropping {} bytes datagram to {}", pkt.data.len(), pkt.dst_addr);
+                    continue;
+                }
+                let mut payload = BytesMut::with_capacity(pkt.dst_addr.size() + pkt.data.len());
+                pkt.dst_addr.write_buf(&mut payload);
+                payload.extend_from_slice(&pkt.data);
+                let mut frame = Frame::new(Kind::Datagram, link.conn());
+                frame.payload = payload.to_vec();
+                link.send(&frame).await?;
+                last_sent = Instant::now();
+            }
+            frame = link.recv() => {
+                let frame = frame?;
+                last_heard = Instant::now();
+                match frame.kind {
+                    Kind::Datagram => {
+                        let src = SocksAddr::peek_read(&frame.payload)?;
+                        let pkt = UdpPacket {
+                            data: frame.payload[src.size()..].to_vec(),
+                            src_addr: src,
+                            dst_addr: SocksAddr::any_ipv4(),
+                        };
+                        if incoming.send(pkt).await.is_err() {
+                            return Ok(());
+                        }
+                        // there may be more queued on the server
+                        link.send(&link.poll(0)).await?;
+                        last_sent = Instant::now();
+                    }
+                    Kind::Reset => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionReset,
+                            "reset by the icmp tunnel server",
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            _ = tick.tick() => {
+                let now = Instant::now();
+                if now - last_heard > IDLE_TIMEOUT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "icmp tunnel server stopped answering",
+                    ));
+                }
+                if now - last_sent >= POLL_INTERVAL {
+                    link.send(&link.poll(0)).await?;
+                    last_sent = now;
+                }
+            }
+        }
+    }
+}
+
+pub struct IcmpDatagram {
+    rx: mpsc::Receiver<UdpPacket>,
+    tx: mpsc::UnboundedSender<UdpPacket>,
+}
+
+impl Stream for IcmpDatagram {
+    type Item = UdpPacket;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+impl Sink<UdpPacket> for IcmpDatagram {
+    type Error = io::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: UdpPacket) -> Result<(), Self::Error> {
+        self.tx.send(item).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "icmp tunnel closed")
+        })
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    io,
+    mem::MaybeUninit,
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+
+use socket2::SockAddr;
+use tokio::io::{Interest, unix::AsyncFd};
+use tracing::trace;
+
+use super::packet::{Frame, Kind, decode_reply, encode_request};
+use crate::proxy::utils::{ConnectOptions, new_icmp_socket};
+
+/// How long to wait for a frame to be acknowledged before sending it again
+pub const RTO: Duration = Duration::from_millis(500);
+
+/// The largest packet read, frames are kept within the path MTU by both
+/// ends, as fragmented ICMP rarely makes it through
+const MAX_PACKET: usize = 4096;
+
+/// The raw socket of one connection through the tunnel. Every raw ICMP
+/// socket sees all the ICMP traffic of the host, packets from other hosts
+/// are skipped, and frames of other connections by their `conn`.
+pub struct Link {
+    socket: AsyncFd<socket2::Socket>,
+    server: SockAddr,
+    v6: bool,
+    conn: u32,
+    ident: u16,
+    echo_seq: AtomicU16,
+}
+
+impl Link {
+    pub async fn new(server: IpAddr, opts: &ConnectOptions<'_>) -> io::Result<Self> {
+        let socket = new_icmp_socket(server, opts).await?;
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+            server: SocketAddr::new(server, 0).into(),
+            v6: server.is_ipv6(),
+            conn: rand::random(),
+            ident: rand::random(),
+            echo_seq: AtomicU16::new(0),
+        })
+    }
+
+    pub fn conn(&self) -> u32 {
+        self.conn
+    }
+
+    pub async fn send(&self, frame: &Frame) -> io::Result<()> {
+        let seq = self.echo_seq.fetch_add(1, Ordering::Relaxed);
+        let packet = encode_request(self.v6, self.ident, seq, frame);
+        self.socket
+            .async_io(Interest::WRITABLE, |s| s.send_to(&packet, &self.server))
+            .await?;
+        Ok(())
+    }
+
+    /// The next frame from the server for this connection
+    pub async fn recv(&self) -> io::Result<Frame> {
+        let mut buf = [0u8; MAX_PACKET];
+        loop {
+            let (n, src) = self
+                .socket
+                .async_io(Interest::READABLE, |s| {
+                    // Safety: initialized bytes are valid `MaybeUninit<u8>`s,
+                    // and `recv_from` never writes uninitialized ones
+                    s.recv_from(unsafe {
+                        &mut *(&mut buf[..] as *mut [u8] as *mut [MaybeUninit<u8>])
+                    })
+                })
+                .await?;
+            if src.as_socket().map(|x| x.ip())
+                != self.server.as_socket().map(|x| x.ip())
+            {
+                trace!("skipping packet from {:?}", src.as_socket());
+                continue;
+            }
+            match decode_reply(self.v6, &buf[..n]) {
+                Some(frame) if frame.conn == self.conn => return Ok(frame),
+                Some(frame) => trace!("skipping frame of conn {}", frame.conn),
+                None => {}
+            }
+        }
+    }
+
+    /// Sends `Open` until the server answers it
+    pub async fn open(&self, payload: Vec<u8>) -> io::Result<()> {
+        let mut frame = Frame::new(Kind::Open, self.conn);
+        frame.payload = payload;
+        let mut resend = tokio::time::interval(RTO);
+        loop {
+            tokio::select! {
+                _ = resend.tick() => self.send(&frame).await?,
+                reply = self.recv() => match reply?.kind {
+                    Kind::Open => return Ok(()),
+                    Kind::Reset => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionRefused,
+                            "refused by the icmp tunnel server",
+                        ));
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// A `Poll` acknowledging everything before `ack`
+    pub fn poll(&self, ack: u32) -> Frame {
+        let mut frame = Frame::new(Kind::Poll, self.conn);
+        frame.ack = ack;
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::proxy::icmp::packet::{FLAG_SERVER, encode_reply};
+
+    /// Sends a reply carrying `frame` from `src` to 127.0.0.1
+    fn send_reply(src: Ipv4Addr, frame: &Frame) -> io::Result<()> {
+        let socket = socket2::Socket::new(
+            socket2::Domain::IPV4,
+            socket2::Type::RAW,
+            Some(socket2::Protocol::ICMPV4),
+        )?;
+        socket.bind(&SocketAddr::from((src, 0)).into())?;
+        let packet = encode_reply(false, 0, 0, frame);
+        socket
+            .send_to(&packet, &SocketAddr::from((Ipv4Addr::LOCALHOST, 0)).into())?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recv_skips_other_hosts() {
+        let server = Ipv4Addr::new(127, 0, 0, 2);
+        let Ok(link) = Link::new(server.into(), &ConnectOptions::default()).await
+        else {
+            eprintln!("skipping: raw sockets denied");
+            return;
+        };
+
+        // the frame is right, only the source gives a spoofed one away
+        for (src, seq) in [(Ipv4Addr::new(127, 0, 0, 3), 1), (server, 2)] {
+            let mut frame = Frame::new(Kind::Poll, link.conn());
+            frame.flags |= FLAG_SERVER;
+            frame.seq = seq;
+            send_reply(src, &frame).unwrap();
+        }
+        let frame = tokio::time::timeout(Duration::from_secs(1), link.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.seq, 2);
+    }
+}
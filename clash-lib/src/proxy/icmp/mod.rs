@@ -0,0 +1,271 @@
+//! An experimental last resort for networks that let nothing but ping
+//! through, in the vein of ptunnel: connections and datagrams are carried
+//! in the payload of ICMP echo requests, and the server answers in echo
+//! replies. See `packet.rs` for the wire format.
+//!
+//! The server can only send when replying, so the client keeps polling it
+//! while a connection is open, and the data of connections is acknowledged
+//! and retransmitted as ICMP may be dropped anywhere. Nothing is encrypted,
+//! and only the opening of a connection is authenticated, tunnel TLS or
+//! another proxy through it for privacy and integrity.
+//!
+//! It needs a raw socket, i.e. root or CAP_NET_RAW, and a server speaking
+//! the protocol, with the kernel's own echo replies turned off, e.g.
+//! `net.ipv4.icmp_echo_ignore_all = 1`. Unix only, with the `icmp` feature:
+//!
+//! ```yaml
+//! proxies:
+//!   - name: ping-tunnel
+//!     type: icmp
+//!     server: tunnel.example.com
+//!     password: secret
+//! ```
+
+mod datagram;
+mod link;
+mod packet;
+#[cfg(test)]
+mod responder;
+mod stream;
+
+use std::{fmt::Debug, io, net::IpAddr, time::Duration};
+
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::{
+    app::{
+        dispatcher::{
+            BoxedChainedDatagram, BoxedChainedStream, ChainedDatagram,
+            ChainedDatagramWrapper, ChainedStream, ChainedStreamWrapper,
+        },
+        dns::ThreadSafeDNSResolver,
+    },
+    common::errors::new_io_error,
+    session::Session,
+};
+
+use super::{
+    ConnectorType, DialWithConnector, HandlerCommonOptions, OutboundHandler,
+    OutboundType, utils::with_handshake_timeout,
+};
+use link::Link;
+
+#[derive(Default)]
+pub struct HandlerOptions {
+    pub name: String,
+    pub common_opts: HandlerCommonOptions,
+    pub server: String,
+    pub password: String,
+    pub udp: bool,
+}
+
+pub struct Handler {
+    opts: HandlerOptions,
+}
+
+impl Debug for Handler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Icmp")
+            .field("name", &self.opts.name)
+            .finish()
+    }
+}
+
+impl Handler {
+    pub fn new(opts: HandlerOptions) -> Self {
+        Self { opts }
+    }
+
+    async fn server_ip(
+        &self,
+        resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<IpAddr> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
+        resolver
+            .resolve(&self.opts.server, false)
+            .await
+            .map_err(|e| new_io_error(e.to_string().as_str()))?
+            .ok_or_else(|| {
+                new_io_error(format!("can't resolve {}", self.opts.server).as_str())
+            })
+    }
+}
+
+// ICMP can't be relayed through another proxy
+impl DialWithConnector for Handler {}
+
+#[async_trait]
+impl OutboundHandler for Handler {
+    fn name(&self) -> &str {
+        &self.opts.name
+    }
+
+    fn proto(&self) -> OutboundType {
+        OutboundType::Icmp
+    }
+
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
+    async fn support_udp(&self) -> bool {
+        self.opts.udp
+    }
+
+    async fn support_connector(&self) -> ConnectorType {
+        ConnectorType::None
+    }
+
+    async fn connect_stream(
+        &self,
+        sess: &Session,
+        resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<BoxedChainedStream> {
+        let server = self.server_ip(resolver).await?;
+        let link = Link::new(server, &sess.into()).await?;
+        debug!("{:?} opening {} to {}", self, link.conn(), sess.destination);
+
+        let s = with_handshake_timeout(stream::connect(
+            link,
+            &self.opts.password,
+            &sess.destination,
+        ))
+        .await?;
+
+        let s = ChainedStreamWrapper::new(s);
+        s.append_to_chain(self.name()).await;
+        Ok(Box::new(s))
+    }
+
+    async fn connect_datagram(
+        &self,
+        sess: &Session,
+        resolver: ThreadSafeDNSResolver,
+    ) -> io::Result<BoxedChainedDatagram> {
+        let server = self.server_ip(resolver).await?;
+        let link = Link::new(server, &sess.into()).await?;
+        debug!("{:?} opening association {}", self, link.conn());
+
+        let d = with_handshake_timeout(datagram::connect(link, &self.opts.password))
+            .await?;
+
+        let d = ChainedDatagramWrapper::new(d);
+        d.append_to_chain(self.name()).await;
+        Ok(Box::new(d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, UdpSocket},
+    };
+
+    use super::*;
+    use crate::{
+        app::dns::MockClashResolver, proxy::datagram::UdpPacket, session::SocksAddr,
+    };
+
+    fn handler(password: &str) -> Handler {
+        Handler::new(HandlerOptions {
+            name: "icmp".to_owned(),
+            server: "127.0.0.1".to_owned(),
+            password: password.to_owned(),
+            udp: true,
+            ..Default::default()
+        })
+    }
+
+    fn resolver() -> ThreadSafeDNSResolver {
+        let mut resolver = MockClashResolver::new();
+        resolver
+            .expect_resolve()
+            .returning(|_, _| Ok(Some([127, 0, 0, 1].into())));
+        Arc::new(resolver)
+    }
+
+    #[tokio::test]
+    async fn test_stream_roundtrip() {
+        if !responder::start() {
+            return;
+        }
+        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sess = Session {
+            destination: echo.local_addr().unwrap().into(),
+            ..Default::default()
+        };
+        tokio::spawn(async move {
+            let (s, _) = echo.accept().await.unwrap();
+            let (mut r, mut w) = s.into_split();
+            tokio::io::copy(&mut r, &mut w).await.unwrap();
+        });
+
+        let mut s = handler(responder::PASSWORD)
+            .connect_stream(&sess, resolver())
+            .await
+            .unwrap();
+        // several segments each way
+        let data = (0..4 * stream::SEGMENT_SIZE + 7)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        s.write_all(&data).await.unwrap();
+        s.shutdown().await.unwrap();
+
+        let mut echoed = vec![];
+        s.read_to_end(&mut echoed).await.unwrap();
+        assert_eq!(echoed, data);
+    }
+
+    #[tokio::test]
+    async fn test_datagram_roundtrip() {
+        if !responder::start() {
+            return;
+        }
+        let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let echo_addr = echo.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            while let Ok((n, src)) = echo.recv_from(&mut buf).await {
+                echo.send_to(&buf[..n], src).await.unwrap();
+            }
+        });
+
+        let mut d = handler(responder::PASSWORD)
+            .connect_datagram(&Session::default(), resolver())
+            .await
+            .unwrap();
+        for data in [&b"ping"[..], b"pong"] {
+            d.send(UdpPacket {
+                data: data.to_vec(),
+                dst_addr: echo_addr.into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+            let pkt = d.next().await.unwrap();
+            assert_eq!(pkt.data, data);
+            assert_eq!(pkt.src_addr, SocksAddr::from(echo_addr));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password() {
+        if !responder::start() {
+            return;
+        }
+        let sess = Session {
+            destination: SocksAddr::Domain("example.com".to_owned(), 443),
+            ..Default::default()
+        };
+        let err = handler("wrong")
+            .connect_stream(&sess, resolver())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
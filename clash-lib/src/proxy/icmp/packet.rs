@@ -0,0 +1,301 @@
+//! Every packet is an ICMP echo, a request from the client or a reply from
+//! the server, whose payload is a frame:
+//!
+//! ```text
+//! +-------+-------+------+------+-----+-----+---------+
+//! | magic | flags | kind | conn | seq | ack | payload |
+//! +-------+-------+------+------+-----+-----+---------+
+//! |   4   |   1   |  1   |  4   |  4  |  4  |    *    |
+//! +-------+-------+------+------+-----+-----+---------+
+//! ```
+//!
+//! - `magic` is `ctun`
+//! - `flags` has [`FLAG_SERVER`] set on frames from the server, so that echoes
+//!   of requests by the server's kernel are told apart
+//! - `conn` is picked by the client for each connection
+//! - `seq` numbers the `Data` and `Close` frames of a connection, from 0
+//! - `ack` is the next `seq` the sender expects from its peer
+//!
+//! The payload of `Open` is a 16 byte tag, the first half of
+//! HMAC-SHA256(password, conn || rest), followed by the rest: 0 and the
+//! destination as a SOCKS address for TCP, 1 for UDP. That of `Datagram` is
+//! the SOCKS address of the destination, or source from the server, then
+//! the UDP payload.
+//!
+//! Only `Open` is authenticated. The frames that follow are told apart by
+//! `conn` alone, so whoever sees them on the path can inject into or reset
+//! the connection. `Open` has no nonce or timestamp either, a captured one
+//! can be replayed to have the server open the same connection again.
+
+use bytes::{Buf, BufMut, BytesMut};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::session::SocksAddr;
+
+pub const MAGIC: [u8; 4] = *b"ctun";
+pub const FLAG_SERVER: u8 = 0x80;
+pub const HEADER_LEN: usize = 18;
+pub const TAG_LEN: usize = 16;
+
+const ICMP_HEADER_LEN: usize = 8;
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    /// Opens a connection, answered with an `Open` once it's established
+    Open     = 1,
+    Data     = 2,
+    /// The sender won't send anything more, ordered like `Data`
+    Close    = 3,
+    /// Carries nothing but `ack`, and gives the server a request to reply
+    /// to with whatever it has queued
+    Poll     = 4,
+    Datagram = 5,
+    /// Tears down a connection: the server doesn't know it or failed to open
+    /// it, or the client gave up on it
+    Reset    = 6,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Kind::Open,
+            2 => Kind::Data,
+            3 => Kind::Close,
+            4 => Kind::Poll,
+            5 => Kind::Datagram,
+            6 => Kind::Reset,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub flags: u8,
+    pub kind: Kind,
+    pub conn: u32,
+    pub seq: u32,
+    pub ack: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: Kind, conn: u32) -> Self {
+        Self {
+            flags: 0,
+            kind,
+            conn,
+            seq: 0,
+            ack: 0,
+            payload: vec![],
+        }
+    }
+
+    fn write_buf(&self, buf: &mut BytesMut) {
+        buf.put_slice(&MAGIC);
+        buf.put_u8(self.flags);
+        buf.put_u8(self.kind as u8);
+        buf.put_u32(self.conn);
+        buf.put_u32(self.seq);
+        buf.put_u32(self.ack);
+        buf.put_slice(&self.payload);
+    }
+
+    fn parse(mut buf: &[u8]) -> Option<Self> {
+        if buf.len() < HEADER_LEN || buf[..4] != MAGIC {
+            return None;
+        }
+        buf.advance(4);
+        Some(Self {
+            flags: buf.get_u8(),
+            kind: buf.get_u8().try_into().ok()?,
+            conn: buf.get_u32(),
+            seq: buf.get_u32(),
+            ack: buf.get_u32(),
+            payload: buf.to_vec(),
+        })
+    }
+}
+
+/// The payload of an `Open` frame for a connection to `dst`, or a UDP
+/// association without one
+pub fn open_payload(password: &str, conn: u32, dst: Option<&SocksAddr>) -> Vec<u8> {
+    let mut rest = BytesMut::new();
+    match dst {
+        Some(dst) => {
+            rest.put_u8(0);
+            dst.write_buf(&mut rest);
+        }
+        None => rest.put_u8(1),
+    }
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(password.as_bytes())
+        .expect("HMAC takes keys of any length");
+    mac.update(&conn.to_be_bytes());
+    mac.update(&rest);
+    let mut payload = mac.finalize().into_bytes()[..TAG_LEN].to_vec();
+    payload.extend_from_slice(&rest);
+    payload
+}
+
+/// An ICMP echo request carrying `frame`. The checksum of ICMPv6 is left to
+/// the kernel, which covers the IPv6 pseudo header.
+pub fn encode_request(v6: bool, ident: u16, seq: u16, frame: &Frame) -> Vec<u8> {
+    encode(
+        if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 },
+        v6,
+        ident,
+        seq,
+        frame,
+    )
+}
+
+fn encode(typ: u8, v6: bool, ident: u16, seq: u16, frame: &Frame) -> Vec<u8> {
+    let mut buf = BytesMut::with_capacity(ICMP_HEADER_LEN + HEADER_LEN);
+    buf.put_u8(typ);
+    buf.put_u8(0);
+    buf.put_u16(0);
+    buf.put_u16(ident);
+    buf.put_u16(seq);
+    frame.write_buf(&mut buf);
+    if !v6 {
+        let sum = checksum(&buf);
+        buf[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    buf.to_vec()
+}
+
+/// Parses a packet read from a raw ICMP socket, which starts with the IP
+/// header for IPv4 but not for IPv6, as a frame from the server.
+pub fn decode_reply(v6: bool, buf: &[u8]) -> Option<Frame> {
+    let icmp = if v6 {
+        buf
+    } else {
+        let ihl = (*buf.first()? & 0x0f) as usize * 4;
+        buf.get(ihl..)?
+    };
+    let typ = *icmp.first()?;
+    if typ != if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 } {
+        return None;
+    }
+    let frame = Frame::parse(icmp.get(ICMP_HEADER_LEN..)?)?;
+    (frame.flags & FLAG_SERVER != 0).then_some(frame)
+}
+
+/// Parses a packet read from a raw ICMP socket as an echo request carrying
+/// a frame, along with the ident and sequence number its reply echoes back,
+/// as the server does
+#[cfg(test)]
+pub fn decode_request(v6: bool, buf: &[u8]) -> Option<(u16, u16, Frame)> {
+    let icmp = if v6 {
+        buf
+    } else {
+        let ihl = (*buf.first()? & 0x0f) as usize * 4;
+        buf.get(ihl..)?
+    };
+    if *icmp.first()? != if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 } {
+        return None;
+    }
+    let ident = u16::from_be_bytes(icmp.get(4..6)?.try_into().ok()?);
+    let seq = u16::from_be_bytes(icmp.get(6..8)?.try_into().ok()?);
+    Some((ident, seq, Frame::parse(icmp.get(ICMP_HEADER_LEN..)?)?))
+}
+
+/// An ICMP echo reply carrying `frame`, as sent by the server
+#[cfg(test)]
+pub fn encode_reply(v6: bool, ident: u16, seq: u16, frame: &Frame) -> Vec<u8> {
+    encode(
+        if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 },
+        v6,
+        ident,
+        seq,
+        frame,
+    )
+}
+
+/// The Internet checksum of RFC 1071
+fn checksum(buf: &[u8]) -> u16 {
+    let mut sum = buf
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_header() -> Vec<u8> {
+        let mut header = vec![0u8; 20];
+        header[0] = 0x45;
+        header
+    }
+
+    #[test]
+    fn test_reply_roundtrip() {
+        let frame = Frame {
+            flags: FLAG_SERVER,
+            kind: Kind::Data,
+            conn: 0xdead_beef,
+            seq: 7,
+            ack: 3,
+            payload: b"hello".to_vec(),
+        };
+        let icmp = encode(ECHO_REPLY_V4, false, 1, 2, &frame);
+        assert_eq!(checksum(&icmp), 0);
+
+        let mut packet = ipv4_header();
+        packet.extend(&icmp);
+        assert_eq!(decode_reply(false, &packet), Some(frame.clone()));
+
+        let icmp = encode(ECHO_REPLY_V6, true, 1, 2, &frame);
+        assert_eq!(decode_reply(true, &icmp), Some(frame));
+    }
+
+    #[test]
+    fn test_ignores_echoed_requests() {
+        let frame = Frame::new(Kind::Poll, 1);
+        // the server's kernel answering a request itself
+        let mut packet = ipv4_header();
+        packet.extend(encode(ECHO_REPLY_V4, false, 1, 2, &frame));
+        assert_eq!(decode_reply(false, &packet), None);
+
+        let mut packet = ipv4_header();
+        packet.extend(encode_request(false, 1, 2, &frame));
+        assert_eq!(decode_reply(false, &packet), None);
+
+        let mut packet = ipv4_header();
+        packet.extend(b"\x00\x00\x00\x00\x00\x01\x00\x02not a frame at all");
+        assert_eq!(decode_reply(false, &packet), None);
+    }
+
+    #[test]
+    fn test_open_payload() {
+        let dst = SocksAddr::Domain("example.com".to_owned(), 443);
+        let payload = open_payload("password", 1, Some(&dst));
+        assert_eq!(payload[TAG_LEN], 0);
+        assert_eq!(SocksAddr::peek_read(&payload[TAG_LEN + 1..]).unwrap(), dst);
+
+        assert_ne!(
+            payload[..TAG_LEN],
+            open_payload("other", 1, Some(&dst))[..TAG_LEN]
+        );
+        assert_ne!(
+            payload[..TAG_LEN],
+            open_payload("password", 2, Some(&dst))[..TAG_LEN]
+        );
+        assert_eq!(open_payload("password", 1, None)[TAG_LEN..], [1]);
+    }
+}
@@ -0,0 +1,231 @@
+//! The server side of the tunnel for tests, answering the echo requests of
+//! clients on 127.0.0.1 and connecting out to their destinations. It reads
+//! every ICMP packet of the host like the clients do, so there's one for
+//! the whole test binary, running on a thread of its own.
+//!
+//! Frames of a connection are sent one at a time, the first unacknowledged
+//! one in reply to every request until it's acknowledged.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read},
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, LazyLock},
+};
+
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, Interest, unix::AsyncFd},
+    net::{TcpStream, UdpSocket, tcp::OwnedWriteHalf},
+    sync::mpsc,
+};
+
+use super::{
+    packet::{
+        FLAG_SERVER, Frame, Kind, TAG_LEN, decode_request, encode_reply,
+        open_payload,
+    },
+    stream::{SEGMENT_SIZE, seq_before},
+};
+use crate::session::SocksAddr;
+
+pub const PASSWORD: &str = "ping-tunnel";
+
+enum Conn {
+    Tcp {
+        write: OwnedWriteHalf,
+        /// Read from the destination, empty once it's closed
+        read: mpsc::Receiver<Vec<u8>>,
+        recv_next: u32,
+        next_seq: u32,
+        unacked: VecDeque<Frame>,
+    },
+    Udp {
+        socket: Arc<UdpSocket>,
+        read: mpsc::Receiver<(SocketAddr, Vec<u8>)>,
+    },
+}
+
+static STARTED: LazyLock<bool> = LazyLock::new(|| {
+    let socket = match socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::RAW,
+        Some(socket2::Protocol::ICMPV4),
+    ) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("no icmp tunnel responder: {e}");
+            return false;
+        }
+    };
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run(socket))
+            .unwrap();
+    });
+    true
+});
+
+/// Starts the responder if it isn't running, returns false when raw sockets
+/// are denied, i.e. without root or CAP_NET_RAW
+pub fn start() -> bool {
+    *STARTED
+}
+
+async fn run(socket: socket2::Socket) -> io::Result<()> {
+    socket.set_nonblocking(true)?;
+    let socket = AsyncFd::new(socket)?;
+    let mut conns = HashMap::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket
+            .async_io(Interest::READABLE, |mut s| s.read(&mut buf))
+            .await?;
+        let Some((ident, seq, frame)) = decode_request(false, &buf[..n]) else {
+            continue;
+        };
+        let client = Ipv4Addr::new(buf[12], buf[13], buf[14], buf[15]);
+        if let Some(mut reply) = handle(&mut conns, frame).await {
+            reply.flags |= FLAG_SERVER;
+            let packet = encode_reply(false, ident, seq, &reply);
+            let client = SocketAddr::from((client, 0)).into();
+            socket
+                .async_io(Interest::WRITABLE, |s| s.send_to(&packet, &client))
+                .await?;
+        }
+    }
+}
+
+async fn handle(conns: &mut HashMap<u32, Conn>, frame: Frame) -> Option<Frame> {
+    if frame.kind == Kind::Open {
+        if !conns.contains_key(&frame.conn) {
+            match open(&frame).await {
+                Some(conn) => conns.insert(frame.conn, conn),
+                None => return Some(Frame::new(Kind::Reset, frame.conn)),
+            };
+        }
+        return Some(Frame::new(Kind::Open, frame.conn));
+    }
+    if frame.kind == Kind::Reset {
+        conns.remove(&frame.conn);
+        return None;
+    }
+    let Some(conn) = conns.get_mut(&frame.conn) else {
+        return Some(Frame::new(Kind::Reset, frame.conn));
+    };
+
+    let mut reply = Frame::new(Kind::Poll, frame.conn);
+    match conn {
+        Conn::Tcp {
+            write,
+            read,
+            recv_next,
+            next_seq,
+            unacked,
+        } => {
+            if matches!(frame.kind, Kind::Data | Kind::Close)
+                && frame.seq == *recv_next
+            {
+                *recv_next = recv_next.wrapping_add(1);
+                if frame.kind == Kind::Data {
+                    write.write_all(&frame.payload).await.ok()?;
+                } else {
+                    write.shutdown().await.ok()?;
+                }
+            }
+            while unacked
+                .front()
+                .is_some_and(|f| seq_before(f.seq, frame.ack))
+            {
+                unacked.pop_front();
+            }
+            if unacked.is_empty()
+                && let Ok(data) = read.try_recv()
+            {
+                let kind = if data.is_empty() {
+                    Kind::Close
+                } else {
+                    Kind::Data
+                };
+                let mut next = Frame::new(kind, frame.conn);
+                next.seq = *next_seq;
+                next.payload = data;
+                *next_seq = next_seq.wrapping_add(1);
+                unacked.push_back(next);
+            }
+            if let Some(next) = unacked.front() {
+                reply = next.clone();
+            }
+            reply.ack = *recv_next;
+        }
+        Conn::Udp { socket, read } => {
+            if frame.kind == Kind::Datagram {
+                let dst = SocksAddr::peek_read(&frame.payload).ok()?;
+                socket
+                    .send_to(&frame.payload[dst.size()..], dst.to_string())
+                    .await
+                    .ok()?;
+            }
+            if let Ok((src, data)) = read.try_recv() {
+                let src = SocksAddr::from(src);
+                let mut payload = BytesMut::new();
+                src.write_buf(&mut payload);
+                payload.extend_from_slice(&data);
+                reply = Frame::new(Kind::Datagram, frame.conn);
+                reply.payload = payload.to_vec();
+            }
+        }
+    }
+    Some(reply)
+}
+
+/// Checks the tag of an `Open` and connects to its destination
+async fn open(frame: &Frame) -> Option<Conn> {
+    let dst = match frame.payload.get(TAG_LEN)? {
+        0 => Some(SocksAddr::peek_read(&frame.payload[TAG_LEN + 1..]).ok()?),
+        _ => None,
+    };
+    if open_payload(PASSWORD, frame.conn, dst.as_ref()) != frame.payload {
+        return None;
+    }
+
+    let (tx, rx) = mpsc::channel(64);
+    match dst {
+        Some(dst) => {
+            let (mut read, write) =
+                TcpStream::connect(dst.to_string()).await.ok()?.into_split();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; SEGMENT_SIZE];
+                loop {
+                    let n = read.read(&mut buf).await.unwrap_or(0);
+                    if tx.send(buf[..n].to_vec()).await.is_err() || n == 0 {
+                        break;
+                    }
+                }
+            });
+            Some(Conn::Tcp {
+                write,
+                read: rx,
+                recv_next: 0,
+                next_seq: 0,
+                unacked: VecDeque::new(),
+            })
+        }
+        None => {
+            let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.ok()?);
+            let reader = socket.clone();
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 2048];
+                while let Ok((n, src)) = reader.recv_from(&mut buf).await {
+                    if tx.send((src, buf[..n].to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            Some(Conn::Udp { socket, read: rx })
+        }
+    }
+}
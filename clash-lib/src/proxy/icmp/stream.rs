@@ -0,0 +1,152 @@
+use std::{collections::VecDeque, io, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    time::Instant,
+};
+use tracing::debug;
+
+use super::{
+    link::{Link, RTO},
+    packet::{Frame, Kind, open_payload},
+};
+use crate::session::SocksAddr;
+
+/// Kept small so that the whole echo fits in any path MTU
+pub const SEGMENT_SIZE: usize = 1024;
+/// How many frames may be waiting for an acknowledgement
+const WINDOW: usize = 32;
+/// How often the server is polled when there's nothing else to send, as it
+/// can only send when replying
+pub const POLL_INTERVAL: Duration = Duration::from_millis(50);
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Opens a connection to `dst` through the tunnel, relayed by a task
+/// retransmitting until the server acknowledges
+pub async fn connect(
+    link: Link,
+    password: &str,
+    dst: &SocksAddr,
+) -> io::Result<DuplexStream> {
+    link.open(open_payload(password, link.conn(), Some(dst)))
+        .await?;
+
+    let (local, remote) = tokio::io::duplex(BUFFER_SIZE);
+    tokio::spawn(async move {
+        if let Err(e) = relay(&link, remote).await {
+            debug!("icmp tunnel connection {} closed: {e}", link.conn());
+            link.send(&Frame::new(Kind::Reset, link.conn())).await.ok();
+        }
+    });
+    Ok(local)
+}
+
+struct Sent {
+    frame: Frame,
+    at: Instant,
+}
+
+/// Whether `a` comes before `b`, with wrapping sequence numbers
+pub fn seq_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+async fn relay(link: &Link, mut local: DuplexStream) -> io::Result<()> {
+    let mut next_seq = 0u32;
+    let mut recv_next = 0u32;
+    let mut unacked = VecDeque::<Sent>::new();
+    let (mut local_eof, mut remote_eof) = (false, false);
+    let (mut last_sent, mut last_heard) = (Instant::now(), Instant::now());
+    let mut buf = vec![0u8; SEGMENT_SIZE];
+    let mut tick = tokio::time::interval(POLL_INTERVAL);
+
+    while !(local_eof && remote_eof && unacked.is_empty()) {
+        tokio::select! {
+            n = local.read(&mut buf), if !local_eof && unacked.len() < WINDOW => {
+                let n = n?;
+                local_eof = n == 0;
+                let kind = if local_eof { Kind::Close } else { Kind::Data };
+                let mut frame = Frame::new(kind, link.conn());
+                frame.seq = next_seq;
+                frame.ack = recv_next;
+                frame.payload = buf[..n].to_vec();
+                next_seq = next_seq.wrapping_add(1);
+
+                link.send(&frame).await?;
+                last_sent = Instant::now();
+                unacked.push_back(Sent { frame, at: last_sent });
+            }
+            frame = link.recv() => {
+                let frame = frame?;
+                last_heard = Instant::now();
+                while unacked
+                    .front()
+                    .is_some_and(|s| seq_before(s.frame.seq, frame.ack))
+                {
+                    unacked.pop_front();
+                }
+
+                match frame.kind {
+                    Kind::Data | Kind::Close => {
+                        // anything out of order is dropped, and resent by the
+                        // server once it times out
+                        if frame.seq == recv_next && !remote_eof {
+                            recv_next = recv_next.wrapping_add(1);
+                            if frame.kind == Kind::Close {
+                                remote_eof = true;
+                                local.shutdown().await?;
+                            } else {
+                                local.write_all(&frame.payload).await?;
+                            }
+                        }
+                        // acknowledges, and lets the server send the next one
+                        link.send(&link.poll(recv_next)).await?;
+                        last_sent = Instant::now();
+                    }
+                    Kind::Reset => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionReset,
+                            "reset by the icmp tunnel server",
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            _ = tick.tick() => {
+                let now = Instant::now();
+                if now - last_heard > IDLE_TIMEOUT {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "icmp tunnel server stopped answering",
+                    ));
+                }
+                for sent in unacked.iter_mut().filter(|s| now - s.at >= RTO) {
+                    sent.frame.ack = recv_next;
+                    link.send(&sent.frame).await?;
+                    sent.at = now;
+                    last_sent = now;
+                }
+                if now - last_sent >= POLL_INTERVAL {
+                    link.send(&link.poll(recv_next)).await?;
+                    last_sent = now;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seq_before() {
+        assert!(seq_before(1, 2));
+        assert!(!seq_before(2, 2));
+        assert!(!seq_before(3, 2));
+        assert!(seq_before(u32::MAX, 0));
+        assert!(!seq_before(0, u32::MAX));
+    }
+}
@@ -1,23 +1,34 @@
 use crate::{
     Dispatcher,
+    app::inbound::conn_limit::ThreadSafeConnectionLimiter,
     common::auth::ThreadSafeAuthenticator,
     proxy::utils::{ToCanonical, try_create_dualstack_tcplistener},
     session::{Network, Session},
 };
 
-use super::{http, inbound::InboundHandlerTrait, socks, utils::apply_tcp_options};
+use super::{
+    http,
+    inbound::InboundHandlerTrait,
+    socks,
+    utils::{apply_tcp_options, tcp_options},
+};
 use crate::common::errors::new_io_error;
 use async_trait::async_trait;
 use hyper_util::rt::TokioIo;
 use std::{net::SocketAddr, sync::Arc};
-use tracing::warn;
+use tracing::{debug, warn};
 
 pub struct MixedInbound {
     addr: SocketAddr,
     allow_lan: bool,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
 }
 
 impl Drop for MixedInbound {
@@ -32,14 +43,20 @@ impl MixedInbound {
         allow_lan: bool,
         dispatcher: Arc<Dispatcher>,
         authenticator: ThreadSafeAuthenticator,
+        conn_limiter: ThreadSafeConnectionLimiter,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
     ) -> Self {
         Self {
             addr,
             allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         }
     }
 }
@@ -55,7 +72,8 @@ impl InboundHandlerTrait for MixedInbound {
     }
 
     async fn listen_tcp(&self) -> std::io::Result<()> {
-        let listener = try_create_dualstack_tcplistener(self.addr)?;
+        let listener =
+            try_create_dualstack_tcplistener(self.addr, self.tfo, self.reuse_port)?;
 
         loop {
             let (socket, _) = match listener.accept().await {
@@ -78,7 +96,12 @@ impl InboundHandlerTrait for MixedInbound {
                 warn!("Connection from {} is not allowed", src_addr);
                 continue;
             }
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
+
+            if self.authenticator.banned(src_addr.ip()) {
+                debug!("Connection from banned {} refused", src_addr);
+                continue;
+            }
 
             let mut p = [0; 1];
             let n = match socket.peek(&mut p).await {
@@ -96,6 +119,15 @@ impl InboundHandlerTrait for MixedInbound {
                 continue;
             }
 
+            let Some(guard) = self.conn_limiter.try_acquire(src_addr.ip()) else {
+                debug!("Too many connections from {}, rejecting", src_addr);
+                match p[0] {
+                    socks::SOCKS5_VERSION => self.conn_limiter.reject_socks5(socket),
+                    _ => self.conn_limiter.reject_http(socket),
+                };
+                continue;
+            };
+
             let dispatcher = self.dispatcher.clone();
             let authenticator = self.authenticator.clone();
             let fw_mark = self.fw_mark;
@@ -105,11 +137,13 @@ impl InboundHandlerTrait for MixedInbound {
                     let mut sess = Session {
                         network: Network::Tcp,
                         source: socket.peer_addr()?.to_canonical(),
+                        local_addr: socket.local_addr().ok(),
                         so_mark: fw_mark,
                         ..Default::default()
                     };
 
                     tokio::spawn(async move {
+                        let _guard = guard;
                         socks::inbound::handle_tcp(
                             &mut sess,
                             socket,
@@ -122,12 +156,15 @@ impl InboundHandlerTrait for MixedInbound {
 
                 _ => {
                     let src = socket.peer_addr()?.to_canonical();
+                    let local = socket.local_addr().ok();
                     let dispatcher = dispatcher.clone();
                     let authenticator = authenticator.clone();
                     tokio::spawn(async move {
+                        let _guard = guard;
                         http::handle_http(
                             TokioIo::new(Box::new(socket) as _),
                             src,
+                            local,
                             dispatcher,
                             authenticator,
                             fw_mark,
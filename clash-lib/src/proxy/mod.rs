@@ -16,6 +16,7 @@ use std::{
     fmt::{Debug, Display},
     io,
     sync::Arc,
+    time::Duration,
 };
 
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -26,6 +27,8 @@ pub mod direct;
 pub mod reject;
 
 pub mod http;
+#[cfg(all(unix, feature = "icmp"))]
+pub mod icmp;
 pub mod mixed;
 #[cfg(all(target_os = "linux", feature = "tproxy"))]
 pub mod tproxy;
@@ -36,6 +39,7 @@ pub mod redir;
 pub(crate) mod datagram;
 
 pub mod converters;
+#[cfg(feature = "hysteria2")]
 pub mod hysteria2;
 #[cfg(feature = "shadowquic")]
 pub mod shadowquic;
@@ -63,6 +67,8 @@ pub use group::{fallback, loadbalance, relay, selector, urltest};
 mod common;
 pub mod inbound;
 mod options;
+#[cfg(feature = "plugin")]
+pub mod plugin;
 mod transport;
 pub mod tunnel;
 
@@ -130,6 +136,7 @@ pub enum OutboundType {
     Hysteria2,
     Ssh,
     ShadowQuic,
+    Icmp,
 
     #[serde(rename = "URLTest")]
     UrlTest,
@@ -141,6 +148,10 @@ pub enum OutboundType {
 
     Direct,
     Reject,
+
+    /// see [`plugin::register_outbound`]
+    #[cfg(feature = "plugin")]
+    Plugin,
 }
 
 impl Display for OutboundType {
@@ -157,6 +168,7 @@ impl Display for OutboundType {
             OutboundType::Hysteria2 => write!(f, "Hysteria2"),
             OutboundType::Ssh => write!(f, "ssh"),
             OutboundType::ShadowQuic => write!(f, "ShadowQuic"),
+            OutboundType::Icmp => write!(f, "Icmp"),
 
             OutboundType::UrlTest => write!(f, "URLTest"),
             OutboundType::Selector => write!(f, "Selector"),
@@ -167,6 +179,9 @@ impl Display for OutboundType {
 
             OutboundType::Direct => write!(f, "Direct"),
             OutboundType::Reject => write!(f, "Reject"),
+
+            #[cfg(feature = "plugin")]
+            OutboundType::Plugin => write!(f, "Plugin"),
         }
     }
 }
@@ -234,6 +249,13 @@ pub trait OutboundHandler: Sync + Send + Unpin + DialWithConnector + Debug {
     fn try_as_group_handler(&self) -> Option<&dyn GroupProxyAPIResponse> {
         None
     }
+
+    /// How long a TCP connection through this handler may go without reading
+    /// anything after sending data before it's closed, see
+    /// [`crate::common::io::IdleReadWatchdog`]
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        None
+    }
 }
 pub type AnyOutboundHandler = Arc<dyn OutboundHandler>;
 
@@ -1,6 +1,158 @@
+use std::{borrow::Cow, time::Duration};
+
+use tracing::warn;
+
+use crate::{
+    app::{
+        dns::{ThreadSafeDNSResolver, with_ip_version, with_server_resolver},
+        net::find_interface,
+    },
+    config::def::{IpVersion, ServerResolver},
+    proxy::utils::ConnectOptions,
+    session::Session,
+};
+
 #[derive(Default, Debug, Clone)]
 pub struct HandlerCommonOptions {
     pub connector: Option<String>,
     pub icon: Option<String>,
     pub url: Option<String>,
+    /// see [`super::OutboundHandler::idle_read_timeout`]
+    pub idle_read_timeout: Option<Duration>,
+    /// the address families the server address is resolved to
+    pub ip_version: Option<IpVersion>,
+    /// the resolver the server address is looked up with
+    pub server_resolver: Option<ServerResolver>,
+    /// the head start of each connection attempt to the server, the
+    /// global `happy-eyeballs-delay` unless set
+    pub attempt_delay: Option<Duration>,
+    /// groups only, the interface, by name or address, the members dial from
+    pub interface_name: Option<String>,
+    /// groups only, the SO_MARK the members dial with
+    pub routing_mark: Option<u32>,
+    /// groups only, the DSCP value the members dial with
+    pub dscp: Option<u8>,
+}
+
+impl HandlerCommonOptions {
+    /// The resolver to look the server address up with, out of the one the
+    /// connection was handed, per `server-resolver` and `ip-version`.
+    /// Destinations are still resolved with the one handed over, if at all.
+    pub fn server_resolver(
+        &self,
+        resolver: ThreadSafeDNSResolver,
+    ) -> ThreadSafeDNSResolver {
+        with_ip_version(
+            with_server_resolver(resolver, self.server_resolver),
+            self.ip_version,
+        )
+    }
+
+    /// The options to dial the server with for `sess`, with this proxy's
+    /// `happy-eyeballs-delay`, if any
+    pub fn connect_options<'a>(&self, sess: &'a Session) -> ConnectOptions<'a> {
+        let opts = ConnectOptions::from(sess);
+        match self.attempt_delay {
+            Some(delay) => opts.attempt_delay(delay),
+            None => opts,
+        }
+    }
+
+    /// The session a group hands to its members, with the group's
+    /// `interface-name`, `routing-mark` and `dscp` in place of the global
+    /// defaults.
+    /// Whatever the matched rule picked is left alone, while a nested group
+    /// applies its own on top.
+    pub fn member_session<'a>(&self, sess: &'a Session) -> Cow<'a, Session> {
+        let iface = self
+            .interface_name
+            .as_deref()
+            .filter(|_| !sess.iface_from_rule);
+        let mark = self.routing_mark.filter(|_| !sess.so_mark_from_rule);
+        let dscp = self.dscp.filter(|_| !sess.dscp_from_rule);
+        if iface.is_none() && mark.is_none() && dscp.is_none() {
+            return Cow::Borrowed(sess);
+        }
+
+        let mut sess = sess.clone();
+        if let Some(name) = iface {
+            match find_interface(name) {
+                Some(iface) => sess.iface = Some(iface),
+                None => warn!("interface {} of the proxy group not found", name),
+            }
+        }
+        if mark.is_some() {
+            sess.so_mark = mark;
+        }
+        if dscp.is_some() {
+            sess.dscp = dscp;
+        }
+        Cow::Owned(sess)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_member_session_routing_mark() {
+        let group = HandlerCommonOptions {
+            routing_mark: Some(100),
+            ..Default::default()
+        };
+
+        let sess = Session::default();
+        assert_eq!(group.member_session(&sess).so_mark, Some(100));
+
+        // a nested group replaces the mark of the outer one
+        let outer = Session {
+            so_mark: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(group.member_session(&outer).so_mark, Some(100));
+
+        let from_rule = Session {
+            so_mark: Some(200),
+            so_mark_from_rule: true,
+            ..Default::default()
+        };
+        assert!(matches!(group.member_session(&from_rule), Cow::Borrowed(_)));
+        assert_eq!(group.member_session(&from_rule).so_mark, Some(200));
+
+        let none = HandlerCommonOptions::default();
+        assert!(matches!(none.member_session(&sess), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_connect_options_attempt_delay() {
+        let sess = Session::default();
+        let global = HandlerCommonOptions::default().connect_options(&sess);
+        assert_eq!(
+            global.attempt_delay,
+            ConnectOptions::default().attempt_delay
+        );
+
+        let opts = HandlerCommonOptions {
+            attempt_delay: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(opts.connect_options(&sess).attempt_delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_member_session_dscp() {
+        let group = HandlerCommonOptions {
+            dscp: Some(46),
+            ..Default::default()
+        };
+        assert_eq!(group.member_session(&Session::default()).dscp, Some(46));
+
+        let from_rule = Session {
+            dscp: Some(8),
+            dscp_from_rule: true,
+            ..Default::default()
+        };
+        assert_eq!(group.member_session(&from_rule).dscp, Some(8));
+    }
 }
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use serde_yaml::Value;
+
+use crate::{Error, proxy::AnyOutboundHandler};
+
+/// Builds an outbound handler from its entry in `proxies` or in a proxy
+/// provider, with every key of the entry, including `name` and `type`.
+pub type OutboundFactory = dyn Fn(HashMap<String, Value>) -> crate::Result<AnyOutboundHandler>
+    + Send
+    + Sync;
+
+/// The `type`s of the builtin proxies, which can't be registered
+const BUILTIN_TYPES: &[&str] = &[
+    "direct",
+    "reject",
+    "ss",
+    "socks5",
+    "trojan",
+    "vmess",
+    "vless",
+    "wireguard",
+    "tor",
+    "tuic",
+    "hysteria2",
+    "ssh",
+    "shadowquic",
+];
+
+static OUTBOUND_FACTORIES: LazyLock<RwLock<HashMap<String, Arc<OutboundFactory>>>> =
+    LazyLock::new(Default::default);
+
+/// Registers an outbound protocol, which proxies then use with
+/// `type: <proto>`. Must be called before the config is loaded.
+/// Fails if `proto` is a builtin protocol or already registered.
+pub fn register_outbound<F>(proto: &str, factory: F) -> crate::Result<()>
+where
+    F: Fn(HashMap<String, Value>) -> crate::Result<AnyOutboundHandler>
+        + Send
+        + Sync
+        + 'static,
+{
+    if BUILTIN_TYPES.contains(&proto) {
+        return Err(Error::InvalidConfig(format!(
+            "outbound protocol {proto} is builtin"
+        )));
+    }
+    let mut factories = OUTBOUND_FACTORIES.write().unwrap();
+    if factories.contains_key(proto) {
+        return Err(Error::InvalidConfig(format!(
+            "outbound protocol {proto} is already registered"
+        )));
+    }
+    factories.insert(proto.to_owned(), Arc::new(factory));
+    Ok(())
+}
+
+pub(crate) fn is_registered(proto: &str) -> bool {
+    OUTBOUND_FACTORIES.read().unwrap().contains_key(proto)
+}
+
+pub(crate) fn build_outbound(
+    proto: &str,
+    config: HashMap<String, Value>,
+) -> crate::Result<AnyOutboundHandler> {
+    let factory = OUTBOUND_FACTORIES
+        .read()
+        .unwrap()
+        .get(proto)
+        .cloned()
+        .ok_or_else(|| {
+            Error::InvalidConfig(format!("unknown outbound protocol {proto}"))
+        })?;
+    factory(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::direct;
+
+    #[test]
+    fn test_register_outbound() {
+        let factory = |config: HashMap<String, Value>| {
+            let name = config["name"].as_str().unwrap_or_default();
+            Ok(Arc::new(direct::Handler::new(name)) as AnyOutboundHandler)
+        };
+        assert!(register_outbound("vmess", factory).is_err());
+        register_outbound("test-plugin", factory).unwrap();
+        assert!(register_outbound("test-plugin", factory).is_err());
+        assert!(is_registered("test-plugin"));
+
+        let config = HashMap::from([
+            ("name".to_owned(), Value::from("p")),
+            ("type".to_owned(), Value::from("test-plugin")),
+        ]);
+        assert!(build_outbound("test-plugin", config.clone()).is_ok());
+        assert!(build_outbound("unknown", config).is_err());
+    }
+}
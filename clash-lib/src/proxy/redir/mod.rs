@@ -3,7 +3,8 @@ use crate::{
     app::dispatcher::Dispatcher,
     common::errors::new_io_error,
     proxy::utils::{
-        ToCanonical, apply_tcp_options, try_create_dualstack_tcplistener,
+        ToCanonical, apply_tcp_options, tcp_options,
+        try_create_dualstack_tcplistener,
     },
     session::{Network, Session, Type},
 };
@@ -18,6 +19,10 @@ pub struct RedirInbound {
     allow_lan: bool,
     dispatcher: Arc<Dispatcher>,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
 }
 
 impl Drop for RedirInbound {
@@ -32,12 +37,16 @@ impl RedirInbound {
         allow_lan: bool,
         dispatcher: Arc<Dispatcher>,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
     ) -> Self {
         Self {
             addr,
             allow_lan,
             dispatcher,
             fw_mark,
+            tfo,
+            reuse_port,
         }
     }
 }
@@ -53,7 +62,8 @@ impl InboundHandlerTrait for RedirInbound {
     }
 
     async fn listen_tcp(&self) -> std::io::Result<()> {
-        let listener = try_create_dualstack_tcplistener(self.addr)?;
+        let listener =
+            try_create_dualstack_tcplistener(self.addr, self.tfo, self.reuse_port)?;
 
         loop {
             let (socket, _) = listener.accept().await?;
@@ -66,7 +76,7 @@ impl InboundHandlerTrait for RedirInbound {
                 continue;
             }
 
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
 
             // get redirect traffic original destination
             let orig_dst = get_original_destination_addr(&socket)?.to_canonical();
@@ -76,6 +86,7 @@ impl InboundHandlerTrait for RedirInbound {
                 typ: Type::Redir,
                 source: src_addr,
                 destination: orig_dst.into(),
+                local_addr: socket.local_addr().ok(),
                 so_mark: self.fw_mark,
                 ..Default::default()
             };
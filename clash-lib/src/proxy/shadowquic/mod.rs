@@ -93,14 +93,9 @@ impl Handler {
                 } else {
                     "[::]:0".parse().unwrap()
                 };
-                let socket = new_udp_socket(
-                    Some(bind_addr),
-                    sess.iface.as_ref(),
-                    #[cfg(target_os = "linux")]
-                    sess.so_mark,
-                    Some(bind_addr),
-                )
-                .await?;
+                let socket =
+                    new_udp_socket(Some(bind_addr), Some(bind_addr), &sess.into())
+                        .await?;
 
                 let mut ep = ShadowQuicClient::new_with_socket(
                     self.opts.clone(),
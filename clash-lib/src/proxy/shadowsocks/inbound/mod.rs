@@ -7,8 +7,8 @@ use crate::{
         inbound::InboundHandlerTrait,
         shadowsocks::{inbound::datagram::InboundShadowsocksDatagram, map_cipher},
         utils::{
-            ToCanonical, apply_tcp_options, new_udp_socket,
-            try_create_dualstack_tcplistener,
+            ConnectOptions, ToCanonical, apply_tcp_options, new_udp_socket,
+            tcp_options, try_create_dualstack_tcplistener,
         },
     },
     session::{Network, Session, SocksAddr, Type},
@@ -30,6 +30,8 @@ pub struct ShadowsocksInbound {
     #[allow(unused)]
     authenticator: ThreadSafeAuthenticator,
     fw_mark: Option<u32>,
+    tfo: Option<u32>,
+    reuse_port: bool,
 
     udp_closer: Arc<tokio::sync::Mutex<Option<tokio::sync::oneshot::Sender<u8>>>>,
 }
@@ -49,6 +51,10 @@ pub struct InboundOptions {
     pub dispatcher: Arc<Dispatcher>,
     pub authenticator: ThreadSafeAuthenticator,
     pub fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    pub tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    pub reuse_port: bool,
 }
 
 impl ShadowsocksInbound {
@@ -62,6 +68,8 @@ impl ShadowsocksInbound {
             dispatcher: opts.dispatcher,
             authenticator: opts.authenticator,
             fw_mark: opts.fw_mark,
+            tfo: opts.tfo,
+            reuse_port: opts.reuse_port,
             udp_closer: Default::default(),
         }
     }
@@ -99,7 +107,8 @@ impl InboundHandlerTrait for ShadowsocksInbound {
         //
         // config.set_user_manager(user_manager);
 
-        let listener = try_create_dualstack_tcplistener(self.addr)?;
+        let listener =
+            try_create_dualstack_tcplistener(self.addr, self.tfo, self.reuse_port)?;
 
         let ss_listener = shadowsocks::relay::tcprelay::ProxyListener::from_listener(
             context,
@@ -143,7 +152,8 @@ impl InboundHandlerTrait for ShadowsocksInbound {
 
             debug!("Shadowsocks TCP connection target: {:?}", target);
 
-            if apply_tcp_options(socket.get_ref()).is_err() {
+            if apply_tcp_options(socket.get_ref(), &tcp_options().keepalive).is_err()
+            {
                 warn!("Failed to apply TCP options to Shadowsocks socket");
                 continue;
             };
@@ -152,6 +162,7 @@ impl InboundHandlerTrait for ShadowsocksInbound {
                 network: Network::Tcp,
                 typ: Type::Shadowsocks,
                 source: src_addr.to_canonical(),
+                local_addr: socket.get_ref().local_addr().ok(),
                 so_mark: self.fw_mark,
                 destination: match target {
                     Address::SocketAddress(addr) => SocksAddr::Ip(addr),
@@ -182,9 +193,10 @@ impl InboundHandlerTrait for ShadowsocksInbound {
         let socket = new_udp_socket(
             Some(self.addr),
             None,
-            #[cfg(target_os = "linux")]
-            self.fw_mark,
-            None,
+            &ConnectOptions::default()
+                .so_mark(self.fw_mark)
+                .reuse_address(true)
+                .reuse_port(self.reuse_port),
         )
         .await?;
 
@@ -202,6 +214,7 @@ impl InboundHandlerTrait for ShadowsocksInbound {
             network: Network::Udp,
             typ: Type::Shadowsocks,
             source: self.addr,
+            local_addr: Some(self.addr),
             so_mark: self.fw_mark,
             iface: None, // No interface for Shadowsocks UDP
             ..Default::default()
@@ -4,11 +4,18 @@ use std::io;
 pub mod inbound;
 pub mod outbound;
 
+/// The cipher names accepted by [`map_cipher`], for error messages
+const SUPPORTED_CIPHERS: &str = "aes-128-gcm, aes-256-gcm, chacha20-ietf-poly1305, \
+                                 2022-blake3-aes-128-gcm, 2022-blake3-aes-256-gcm, \
+                                 2022-blake3-chacha20-ietf-poly1305, rc4-md5";
+
 pub(crate) fn map_cipher(cipher: &str) -> std::io::Result<CipherKind> {
     match cipher {
         "aes-128-gcm" => Ok(CipherKind::AES_128_GCM),
         "aes-256-gcm" => Ok(CipherKind::AES_256_GCM),
-        "chacha20-ietf-poly1305" => Ok(CipherKind::CHACHA20_POLY1305),
+        "chacha20-ietf-poly1305" | "chacha20-poly1305" => {
+            Ok(CipherKind::CHACHA20_POLY1305)
+        }
 
         "2022-blake3-aes-128-gcm" => Ok(CipherKind::AEAD2022_BLAKE3_AES_128_GCM),
         "2022-blake3-aes-256-gcm" => Ok(CipherKind::AEAD2022_BLAKE3_AES_256_GCM),
@@ -17,6 +24,34 @@ pub(crate) fn map_cipher(cipher: &str) -> std::io::Result<CipherKind> {
         }
 
         "rc4-md5" => Ok(CipherKind::SS_RC4_MD5),
-        _ => Err(io::Error::other("unsupported cipher")),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unsupported cipher {cipher}, supported ciphers are: \
+                 {SUPPORTED_CIPHERS}"
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shadowsocks::crypto::CipherKind;
+
+    use super::map_cipher;
+
+    #[test]
+    fn test_map_cipher() {
+        assert_eq!(
+            map_cipher("chacha20-poly1305").unwrap(),
+            CipherKind::CHACHA20_POLY1305
+        );
+        assert_eq!(
+            map_cipher("2022-blake3-aes-256-gcm").unwrap(),
+            CipherKind::AEAD2022_BLAKE3_AES_256_GCM
+        );
+
+        let err = map_cipher("aes-256-cfb").unwrap_err();
+        assert!(err.to_string().contains("unsupported cipher aes-256-cfb"));
     }
 }
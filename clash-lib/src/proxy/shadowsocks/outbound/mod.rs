@@ -21,7 +21,7 @@ use crate::{
         OutboundHandler, OutboundType,
         shadowsocks::map_cipher,
         transport::Sip003Plugin,
-        utils::{GLOBAL_DIRECT_CONNECTOR, RemoteConnector},
+        utils::{GLOBAL_DIRECT_CONNECTOR, RemoteConnector, with_handshake_timeout},
     },
     session::Session,
 };
@@ -30,7 +30,7 @@ use shadowsocks::{
     ProxyClientStream, ProxySocket, ServerConfig, config::ServerType,
     context::Context, relay::udprelay::proxy_socket::UdpSocketType,
 };
-use std::{fmt::Debug, io, sync::Arc};
+use std::{fmt::Debug, io, sync::Arc, time::Duration};
 use tracing::debug;
 
 pub struct HandlerOptions {
@@ -92,7 +92,7 @@ impl Handler {
         Ok(Box::new(ShadowSocksStream(stream)))
     }
 
-    fn server_config(&self) -> Result<ServerConfig, io::Error> {
+    pub(crate) fn server_config(&self) -> Result<ServerConfig, io::Error> {
         ServerConfig::new(
             (self.opts.server.to_owned(), self.opts.port),
             self.opts.password.to_owned(),
@@ -112,6 +112,10 @@ impl OutboundHandler for Handler {
         OutboundType::Shadowsocks
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         self.opts.udp
     }
@@ -170,18 +174,18 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedStream> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         let stream = connector
             .connect_stream(
                 resolver.clone(),
                 self.opts.server.as_str(),
                 self.opts.port,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
+                &self.opts.common_opts.connect_options(sess),
             )
             .await?;
 
-        let s = self.proxy_stream(stream, sess, resolver).await?;
+        let s = with_handshake_timeout(self.proxy_stream(stream, sess, resolver))
+            .await?;
         let chained = ChainedStreamWrapper::new(s);
         chained.append_to_chain(self.name()).await;
         Ok(Box::new(chained))
@@ -193,6 +197,7 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedDatagram> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         let ctx = Context::new_shared(ServerType::Local);
         let cfg = self.server_config()?;
 
@@ -201,9 +206,7 @@ impl OutboundHandler for Handler {
                 resolver.clone(),
                 None,
                 (self.opts.server.clone(), self.opts.port).try_into()?,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
+                &sess.into(),
             )
             .await?;
 
@@ -262,11 +265,19 @@ mod tests {
     const SHADOW_TLS_PASSWORD: &str = "password";
 
     async fn get_ss_runner(port: u16) -> anyhow::Result<DockerTestRunner> {
+        get_ss_runner_with_cipher(port, CIPHER, PASSWORD).await
+    }
+
+    async fn get_ss_runner_with_cipher(
+        port: u16,
+        cipher: &str,
+        password: &str,
+    ) -> anyhow::Result<DockerTestRunner> {
         let host = format!("0.0.0.0:{}", port);
         DockerTestRunnerBuilder::new()
             .image(IMAGE_SS_RUST)
             .entrypoint(&["ssserver"])
-            .cmd(&["-s", &host, "-m", CIPHER, "-k", PASSWORD, "-U", "-vvv"])
+            .cmd(&["-s", &host, "-m", cipher, "-k", password, "-U", "-vvv"])
             .build()
             .await
     }
@@ -309,6 +320,50 @@ mod tests {
             .await
     }
 
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_ss_aead_ciphers() -> anyhow::Result<()> {
+        initialize();
+        // 2022 ciphers take a base64 key of the cipher's key length
+        let ciphers = [
+            ("aes-128-gcm", PASSWORD),
+            ("chacha20-ietf-poly1305", PASSWORD),
+            ("2022-blake3-aes-128-gcm", "6kM4mNQ0Bd8rpfDzeCmYgg=="),
+            (
+                "2022-blake3-aes-256-gcm",
+                "NCrnD0cnGsSqbsO2SOaXNKJpKjvM1zE8eJcMtHwtfvs=",
+            ),
+            (
+                "2022-blake3-chacha20-ietf-poly1305",
+                "NCrnD0cnGsSqbsO2SOaXNKJpKjvM1zE8eJcMtHwtfvs=",
+            ),
+        ];
+        for (cipher, password) in ciphers {
+            let opts = HandlerOptions {
+                name: "test-ss".to_owned(),
+                common_opts: Default::default(),
+                server: LOCAL_ADDR.to_owned(),
+                port: 10002,
+                password: password.to_owned(),
+                cipher: cipher.to_owned(),
+                plugin: Default::default(),
+                udp: false,
+            };
+            let port = opts.port;
+            let handler = Arc::new(Handler::new(opts));
+            handler
+                .register_connector(GLOBAL_DIRECT_CONNECTOR.clone())
+                .await;
+            run_test_suites_and_cleanup(
+                handler,
+                get_ss_runner_with_cipher(port, cipher, password).await?,
+                Suite::all(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial_test::serial]
     async fn test_ss_plain() -> anyhow::Result<()> {
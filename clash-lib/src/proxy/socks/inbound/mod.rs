@@ -3,10 +3,14 @@ mod stream;
 
 use crate::{
     Dispatcher,
+    app::inbound::conn_limit::ThreadSafeConnectionLimiter,
     common::auth::ThreadSafeAuthenticator,
     proxy::{
         inbound::InboundHandlerTrait,
-        utils::{ToCanonical, apply_tcp_options, try_create_dualstack_tcplistener},
+        utils::{
+            ToCanonical, apply_tcp_options, tcp_options,
+            try_create_dualstack_tcplistener,
+        },
     },
     session::{Network, Session, Type},
 };
@@ -14,7 +18,7 @@ use crate::{
 use async_trait::async_trait;
 use std::{net::SocketAddr, sync::Arc};
 pub use stream::handle_tcp;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use crate::common::errors::new_io_error;
 pub use datagram::Socks5UDPCodec;
@@ -24,7 +28,12 @@ pub struct SocksInbound {
     allow_lan: bool,
     dispatcher: Arc<Dispatcher>,
     authenticator: ThreadSafeAuthenticator,
+    conn_limiter: ThreadSafeConnectionLimiter,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
 }
 
 impl Drop for SocksInbound {
@@ -39,14 +48,20 @@ impl SocksInbound {
         allow_lan: bool,
         dispatcher: Arc<Dispatcher>,
         authenticator: ThreadSafeAuthenticator,
+        conn_limiter: ThreadSafeConnectionLimiter,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
     ) -> Self {
         Self {
             addr,
             allow_lan,
             dispatcher,
             authenticator,
+            conn_limiter,
             fw_mark,
+            tfo,
+            reuse_port,
         }
     }
 }
@@ -62,7 +77,8 @@ impl InboundHandlerTrait for SocksInbound {
     }
 
     async fn listen_tcp(&self) -> std::io::Result<()> {
-        let listener = try_create_dualstack_tcplistener(self.addr)?;
+        let listener =
+            try_create_dualstack_tcplistener(self.addr, self.tfo, self.reuse_port)?;
 
         loop {
             let (socket, _) = listener.accept().await?;
@@ -73,12 +89,24 @@ impl InboundHandlerTrait for SocksInbound {
                 warn!("Connection from {} is not allowed", src_addr);
                 continue;
             }
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
+
+            if self.authenticator.banned(src_addr.ip()) {
+                debug!("Connection from banned {} refused", src_addr);
+                continue;
+            }
+
+            let Some(guard) = self.conn_limiter.try_acquire(src_addr.ip()) else {
+                debug!("Too many connections from {}, rejecting", src_addr);
+                self.conn_limiter.reject_socks5(socket);
+                continue;
+            };
 
             let mut sess = Session {
                 network: Network::Tcp,
                 typ: Type::Socks5,
                 source: socket.peer_addr()?.to_canonical(),
+                local_addr: socket.local_addr().ok(),
                 so_mark: self.fw_mark,
 
                 ..Default::default()
@@ -88,6 +116,7 @@ impl InboundHandlerTrait for SocksInbound {
             let authenticator = self.authenticator.clone();
 
             tokio::spawn(async move {
+                let _guard = guard;
                 handle_tcp(&mut sess, socket, dispatcher, authenticator).await
             });
         }
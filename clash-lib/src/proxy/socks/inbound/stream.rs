@@ -7,7 +7,7 @@ use crate::{
             inbound::{Socks5UDPCodec, datagram::InboundUdp},
             socks5::{auth_methods, response_code, socks_command},
         },
-        utils::new_udp_socket,
+        utils::{ConnectOptions, new_udp_socket},
     },
     session::{Network, Session, SocksAddr, Type},
 };
@@ -84,7 +84,7 @@ pub async fn handle_tcp(
                 str::from_utf8_unchecked(buf.to_owned().as_ref()).to_owned()
             };
 
-            match authenticator.authenticate(&user, &pass) {
+            match authenticator.authenticate(sess.source.ip(), &user, &pass) {
                 // +----+--------+
                 // |VER | STATUS |
                 // +----+--------+
@@ -141,14 +141,9 @@ pub async fn handle_tcp(
         }
         socks_command::UDP_ASSOCIATE => {
             let udp_addr = SocketAddr::new(s.local_addr()?.ip(), 0);
-            let udp_inbound = new_udp_socket(
-                Some(udp_addr),
-                None,
-                #[cfg(target_os = "linux")]
-                None,
-                None,
-            )
-            .await?;
+            let udp_inbound =
+                new_udp_socket(Some(udp_addr), None, &ConnectOptions::default())
+                    .await?;
 
             trace!(
                 "Got a UDP_ASSOCIATE request from {}, UDP assigned at {}",
@@ -171,6 +166,9 @@ pub async fn handle_tcp(
             let sess = Session {
                 network: Network::Udp,
                 typ: Type::Socks5,
+                // that of the TCP connection, as the listener of the UDP
+                // socket is this very association
+                local_addr: sess.local_addr,
                 so_mark,
                 iface,
                 ..Default::default()
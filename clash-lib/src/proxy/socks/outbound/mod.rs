@@ -1,6 +1,6 @@
 mod datagram;
 
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 
 use crate::{
     app::{
@@ -16,7 +16,10 @@ use crate::{
         AnyStream, ConnectorType, DialWithConnector, HandlerCommonOptions,
         OutboundHandler, OutboundType,
         transport::Transport,
-        utils::{GLOBAL_DIRECT_CONNECTOR, RemoteConnector, new_udp_socket},
+        utils::{
+            GLOBAL_DIRECT_CONNECTOR, RemoteConnector, new_udp_socket,
+            with_handshake_timeout,
+        },
     },
     session::Session,
 };
@@ -127,14 +130,9 @@ impl Handler {
         let bind_port = bind_addr.port();
         trace!("bind address resolved to {}:{}", bind_ip, bind_port);
 
-        let udp_socket = new_udp_socket(
-            None,
-            sess.iface.as_ref(),
-            #[cfg(target_os = "linux")]
-            sess.so_mark,
-            Some((bind_ip, bind_port).into()),
-        )
-        .await?;
+        let udp_socket =
+            new_udp_socket(None, Some((bind_ip, bind_port).into()), &sess.into())
+                .await?;
 
         Ok(Socks5Datagram::new(
             s,
@@ -154,6 +152,10 @@ impl OutboundHandler for Handler {
         OutboundType::Socks5
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         self.opts.udp
     }
@@ -214,18 +216,17 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> std::io::Result<BoxedChainedStream> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         let s = connector
             .connect_stream(
                 resolver,
                 self.opts.server.as_str(),
                 self.opts.port,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
+                &self.opts.common_opts.connect_options(sess),
             )
             .await?;
 
-        let s = self.inner_connect_stream(s, sess).await?;
+        let s = with_handshake_timeout(self.inner_connect_stream(s, sess)).await?;
 
         let s = ChainedStreamWrapper::new(s);
         s.append_to_chain(self.name()).await;
@@ -238,18 +239,19 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> std::io::Result<BoxedChainedDatagram> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         let s = connector
             .connect_stream(
                 resolver.clone(),
                 self.opts.server.as_str(),
                 self.opts.port,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
+                &self.opts.common_opts.connect_options(sess),
             )
             .await?;
 
-        let d = self.inner_connect_datagram(s, sess, resolver).await?;
+        let d =
+            with_handshake_timeout(self.inner_connect_datagram(s, sess, resolver))
+                .await?;
 
         let d = ChainedDatagramWrapper::new(d);
         d.append_to_chain(self.name()).await;
@@ -1,7 +1,11 @@
 use bytes::{BufMut, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use crate::{common::errors::new_io_error, proxy::AnyStream, session::SocksAddr};
+use crate::{
+    common::errors::new_io_error,
+    proxy::{AnyStream, utils::UpstreamError},
+    session::SocksAddr,
+};
 
 pub const SOCKS5_VERSION: u8 = 0x05;
 
@@ -32,18 +36,6 @@ pub(crate) mod response_code {
     // pub const ADDR_TYPE_NOT_SUPPORTED: u8 = 0x08;
 }
 
-const ERROR_CODE_LOOKUP: &[&str] = &[
-    "succeeded",
-    "general SOCKS server failure",
-    "connection not allowed by ruleset",
-    "network unreachable",
-    "host unreachable",
-    "connection refused",
-    "TTL expired",
-    "command not supported",
-    "address type not supported",
-];
-
 pub(crate) async fn client_handshake(
     s: &mut AnyStream,
     addr: &SocksAddr,
@@ -88,7 +80,7 @@ pub(crate) async fn client_handshake(
         s.read_exact(&mut buf[..2]).await?;
 
         if buf[1] != response_code::SUCCEEDED {
-            return Err(new_io_error("SOCKS5 authentication failed"));
+            return Err(UpstreamError::Socks5AuthFailed.into());
         }
     } else if method != auth_methods::NO_AUTH {
         return Err(new_io_error("unsupported SOCKS5 authentication method"));
@@ -118,17 +110,7 @@ pub(crate) async fn client_handshake(
     }
 
     if buf[1] != response_code::SUCCEEDED {
-        return Err(new_io_error(
-            format!(
-                "SOCKS5 request failed with {}",
-                if buf[1] < ERROR_CODE_LOOKUP.len() as u8 {
-                    ERROR_CODE_LOOKUP[buf[1] as usize]
-                } else {
-                    "unknown error"
-                }
-            )
-            .as_str(),
-        ));
+        return Err(UpstreamError::Socks5Reply(buf[1]).into());
     }
 
     SocksAddr::read_from(s).await
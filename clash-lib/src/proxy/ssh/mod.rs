@@ -28,7 +28,8 @@ use crate::{
 
 use super::{
     ConnectorType, DialWithConnector, HandlerCommonOptions, OutboundHandler,
-    OutboundType, ProxyStream, utils::RemoteConnector,
+    OutboundType, ProxyStream,
+    utils::{RemoteConnector, with_handshake_timeout},
 };
 
 /// Wrapper for `ChannelStream` for `Debug` trait
@@ -149,6 +150,10 @@ impl OutboundHandler for Handler {
         OutboundType::Ssh
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         false
     }
@@ -191,24 +196,30 @@ impl OutboundHandler for Handler {
         });
         let sh = connector::Client { server_public_key };
 
-        // TODO: adding fw_mark
-        let mut session =
-            client::connect(config, (self.opts.server.as_str(), self.opts.port), sh)
-                .await
-                .map_err(io::Error::other)?;
-
-        auth0(&mut session, &self.opts).await?;
-
         let dst = sess.destination.clone();
-        let channel = session
-            .channel_open_direct_tcpip(
-                dst.host(),
-                sess.destination.port() as _,
-                "0.0.0.0",
-                0,
+        let channel = with_handshake_timeout(async {
+            // TODO: adding fw_mark
+            let mut session = client::connect(
+                config,
+                (self.opts.server.as_str(), self.opts.port),
+                sh,
             )
             .await
             .map_err(io::Error::other)?;
+
+            auth0(&mut session, &self.opts).await?;
+
+            session
+                .channel_open_direct_tcpip(
+                    dst.host(),
+                    sess.destination.port() as _,
+                    "0.0.0.0",
+                    0,
+                )
+                .await
+                .map_err(io::Error::other)
+        })
+        .await?;
         let s = Box::new(ChannelStreamWrapper {
             inner: channel.into_stream(),
         });
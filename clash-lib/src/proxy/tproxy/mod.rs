@@ -1,20 +1,20 @@
 use super::{inbound::InboundHandlerTrait, tun::TunDatagram};
 use crate::{
     app::dispatcher::Dispatcher,
+    config::internal::listener::SourceNatMode,
     proxy::{
         datagram::UdpPacket,
-        utils::{ToCanonical, apply_tcp_options, try_create_dualstack_socket},
+        utils::{
+            ToCanonical, apply_tcp_options, set_tcp_fastopen, tcp_options,
+            try_create_dualstack_socket,
+        },
     },
     session::{Network, Session, Type},
 };
 
 use async_trait::async_trait;
 use std::{
-    collections::{HashMap, hash_map},
-    io,
-    net::SocketAddr,
-    os::fd::AsRawFd,
-    sync::Arc,
+    collections::HashMap, io, net::SocketAddr, os::fd::AsRawFd, sync::Arc,
     time::Duration,
 };
 use tokio::{net::TcpListener, time::Instant};
@@ -26,6 +26,11 @@ pub struct TproxyInbound {
     allow_lan: bool,
     dispatcher: Arc<Dispatcher>,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
+    udp_source_nat: SourceNatMode,
 }
 
 impl Drop for TproxyInbound {
@@ -40,12 +45,18 @@ impl TproxyInbound {
         allow_lan: bool,
         dispatcher: Arc<Dispatcher>,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
+        udp_source_nat: SourceNatMode,
     ) -> Self {
         Self {
             addr,
             allow_lan,
             dispatcher,
             fw_mark,
+            tfo,
+            reuse_port,
+            udp_source_nat,
         }
     }
 }
@@ -68,8 +79,14 @@ impl InboundHandlerTrait for TproxyInbound {
             // IPV6 doesn't require this
             socket.set_ip_transparent_v4(true)?;
         }
+        if self.reuse_port {
+            socket.set_reuse_port(true)?;
+        }
         socket.set_nonblocking(true)?;
         socket.bind(&self.addr.into())?;
+        if let Some(queue) = self.tfo {
+            set_tcp_fastopen(&socket, queue);
+        }
         socket.listen(1024)?;
 
         let listener = TcpListener::from_std(socket.into())?;
@@ -87,7 +104,7 @@ impl InboundHandlerTrait for TproxyInbound {
             // src_addr,listener.local_addr()?);     continue;
             // }
 
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
 
             // local_addr is getsockname
             let orig_dst = socket.local_addr()?.to_canonical();
@@ -143,13 +160,21 @@ impl InboundHandlerTrait for TproxyInbound {
         handle_inbound_datagram(
             self.allow_lan,
             self.fw_mark,
+            self.udp_source_nat,
             Arc::new(listener),
             self.dispatcher.clone(),
         )
         .await
     }
 }
-fn bind_nonlocal_socket(src_addr: SocketAddr) -> io::Result<UdpSocket> {
+
+/// A socket bound to `src_addr`, which isn't a local address, to send
+/// replies that appear to come from the original destination. Replies carry
+/// `fw_mark` so the rules marking local traffic for tproxy skip them.
+fn bind_nonlocal_socket(
+    src_addr: SocketAddr,
+    fw_mark: Option<u32>,
+) -> io::Result<UdpSocket> {
     let domain = if src_addr.is_ipv4() {
         socket2::Domain::IPV4
     } else {
@@ -162,6 +187,9 @@ fn bind_nonlocal_socket(src_addr: SocketAddr) -> io::Result<UdpSocket> {
     } else {
         set_ip_transparent_v6(&socket)?;
     }
+    if let Some(mark) = fw_mark {
+        socket.set_mark(mark)?;
+    }
     socket.set_nonblocking(true)?;
     socket.set_reuse_address(true)?;
     socket.bind(&src_addr.into())?;
@@ -173,6 +201,7 @@ fn bind_nonlocal_socket(src_addr: SocketAddr) -> io::Result<UdpSocket> {
 async fn handle_inbound_datagram(
     _allow_lan: bool,
     fw_mark: Option<u32>,
+    source_nat: SourceNatMode,
     socket: Arc<unix_udp_sock::UdpSocket>,
     dispatcher: Arc<Dispatcher>,
 ) -> std::io::Result<()> {
@@ -199,7 +228,8 @@ async fn handle_inbound_datagram(
         .await;
 
     // dispatcher -> tproxy
-    let fut1 = tokio::spawn(handle_packet_from_dispatcher(l_rx));
+    let fut1 =
+        tokio::spawn(handle_packet_from_dispatcher(l_rx, fw_mark, source_nat));
 
     // tproxy -> dispatcher
     let fut2 = tokio::spawn(async move {
@@ -281,6 +311,8 @@ fn set_ip_recv_orig_dstaddr(
 
 async fn handle_packet_from_dispatcher(
     mut l_rx: tokio::sync::mpsc::Receiver<UdpPacket>,
+    fw_mark: Option<u32>,
+    source_nat: SourceNatMode,
 ) {
     let mut responder_map = HashMap::<SocketAddr, (Arc<UdpSocket>, Instant)>::new();
     let mut sleep = Box::pin(tokio::time::sleep(Duration::from_secs(60)));
@@ -296,13 +328,20 @@ async fn handle_packet_from_dispatcher(
                 // remote -> local
                 let now = Instant::now();
                 let src_addr = pkt.src_addr.must_into_socket_addr();
-                let responder = match responder_map.entry(src_addr) {
-                    hash_map::Entry::Occupied(mut occupied_entry) => {
-                        occupied_entry.get_mut().1 = now;
-                        occupied_entry.get().0.clone()
+                let cached = match source_nat {
+                    SourceNatMode::Preserve => {
+                        responder_map.get_mut(&src_addr).map(|(socket, last)| {
+                            *last = now;
+                            socket.clone()
+                        })
                     }
-                    hash_map::Entry::Vacant(vacant_entry) => {
-                        let socket = match bind_nonlocal_socket(src_addr) {
+                    // bound for this reply only, dropped once it's sent
+                    SourceNatMode::PerPacket => None,
+                };
+                let responder = match cached {
+                    Some(x) => x,
+                    None => {
+                        let socket = match bind_nonlocal_socket(src_addr, fw_mark) {
                             Ok(x) => Arc::new(x),
                             Err(e) => {
                                 tracing::error!(
@@ -312,7 +351,9 @@ async fn handle_packet_from_dispatcher(
                                 continue;
                             }
                         };
-                        vacant_entry.insert((socket.clone(), now));
+                        if source_nat == SourceNatMode::Preserve {
+                            responder_map.insert(src_addr, (socket.clone(), now));
+                        }
                         socket
                     }
                 };
@@ -374,3 +415,34 @@ fn set_socket_option(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::bind_nonlocal_socket;
+
+    // See udp_test.sh for the routing a full tproxy round trip needs
+    #[tokio::test]
+    #[ignore = "requires CAP_NET_ADMIN"]
+    async fn test_reply_from_original_destination() {
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let orig_dst: SocketAddr = (Ipv4Addr::new(203, 0, 113, 1), 53).into();
+
+        let responder = bind_nonlocal_socket(orig_dst, None).unwrap();
+        // a second reply socket on the same destination, as `per-packet`
+        // binds while `preserve` may still hold one
+        let other = bind_nonlocal_socket(orig_dst, None).unwrap();
+        for socket in [&responder, &other] {
+            socket
+                .send_to(b"reply", client.local_addr().unwrap())
+                .await
+                .unwrap();
+
+            let mut buf = [0; 16];
+            let (n, from) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"reply");
+            assert_eq!(from, orig_dst);
+        }
+    }
+}
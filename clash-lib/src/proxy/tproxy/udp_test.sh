@@ -0,0 +1,102 @@
+#!/bin/bash
+
+# UDP tproxy round trip through network namespaces, to check that replies
+# reach the client from the original destination.
+#
+#   client (10.99.0.2) --- gateway running clash-rs --- server (10.98.0.2)
+#
+# The client sends to the server's echo, the gateway captures it with
+# TPROXY and proxies it DIRECT. The reply is sent back from a socket bound
+# with IP_TRANSPARENT to 10.98.0.2:5300, which the client only accepts
+# because it's connected to that address.
+#
+# What tproxy UDP needs on the gateway:
+# - `ip rule add fwmark 0x1 table 803` and
+#   `ip route add local 0.0.0.0/0 dev lo table 803`, so marked packets are
+#   delivered locally whatever their destination
+# - a mangle PREROUTING `-j TPROXY --on-port <tproxy-port> --tproxy-mark 0x1`
+#   rule for the traffic to capture
+# - CAP_NET_ADMIN for clash-rs, to set IP_TRANSPARENT on the listener and on
+#   the reply sockets
+# - with `-m socket --transparent` rules, `udp-source-nat: per-packet`, as a
+#   reply socket left bound on the destination matches the client's next
+#   packets and swallows them
+#
+# Usage: sudo ./udp_test.sh path/to/clash-rs [preserve|per-packet]
+# Needs iproute2, iptables and socat.
+
+set -euo pipefail
+
+CLASH=${1:?path to the clash-rs binary}
+MODE=${2:-preserve}
+TPROXY_PORT=7893
+
+WORK=$(mktemp -d)
+
+cleanup() {
+    [ -n "${CLASH_PID:-}" ] && kill "${CLASH_PID}" 2>/dev/null || true
+    [ -n "${ECHO_PID:-}" ] && kill "${ECHO_PID}" 2>/dev/null || true
+    for ns in tp-client tp-gw tp-server; do
+        ip netns del "${ns}" 2>/dev/null || true
+    done
+    rm -rf "${WORK}"
+}
+trap cleanup EXIT
+
+for ns in tp-client tp-gw tp-server; do
+    ip netns add "${ns}"
+    ip -n "${ns}" link set lo up
+done
+
+ip link add tp-c0 netns tp-client type veth peer name tp-c1 netns tp-gw
+ip link add tp-s0 netns tp-server type veth peer name tp-s1 netns tp-gw
+
+ip -n tp-client addr add 10.99.0.2/24 dev tp-c0
+ip -n tp-client link set tp-c0 up
+ip -n tp-client route add default via 10.99.0.1
+
+ip -n tp-server addr add 10.98.0.2/24 dev tp-s0
+ip -n tp-server link set tp-s0 up
+ip -n tp-server route add default via 10.98.0.1
+
+ip -n tp-gw addr add 10.99.0.1/24 dev tp-c1
+ip -n tp-gw link set tp-c1 up
+ip -n tp-gw addr add 10.98.0.1/24 dev tp-s1
+ip -n tp-gw link set tp-s1 up
+ip netns exec tp-gw sysctl -qw net.ipv4.ip_forward=1
+
+ip -n tp-gw rule add fwmark 0x1 table 803
+ip -n tp-gw route add local 0.0.0.0/0 dev lo table 803
+ip netns exec tp-gw iptables -t mangle -A PREROUTING -i tp-c1 -p udp \
+    -j TPROXY --on-port ${TPROXY_PORT} --tproxy-mark 0x1/0x1
+
+cat >"${WORK}/config.yaml" <<EOF
+allow-lan: true
+bind-address: "*"
+log-level: debug
+listeners:
+  - name: tproxy-test
+    type: tproxy
+    listen: 0.0.0.0
+    allow-lan: true
+    port: ${TPROXY_PORT}
+    udp-source-nat: ${MODE}
+rules:
+  - MATCH,DIRECT
+EOF
+
+ip netns exec tp-server socat UDP-LISTEN:5300,fork EXEC:cat &
+ECHO_PID=$!
+ip netns exec tp-gw "${CLASH}" -d "${WORK}" -c "${WORK}/config.yaml" &
+CLASH_PID=$!
+sleep 2
+
+for i in 1 2 3; do
+    got=$(echo "ping ${i}" | ip netns exec tp-client \
+        timeout 3 socat - UDP:10.98.0.2:5300 || true)
+    if [ "${got}" != "ping ${i}" ]; then
+        echo "FAIL: reply ${i} got '${got}'"
+        exit 1
+    fi
+done
+echo "OK: replies came back from 10.98.0.2:5300 (${MODE})"
@@ -9,7 +9,10 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::error;
 
 use super::Transport;
-use crate::{common::errors::map_io_error, proxy::AnyStream};
+use crate::{
+    common::{errors::map_io_error, http::merge_global_headers},
+    proxy::AnyStream,
+};
 
 pub struct Client {
     pub hosts: Vec<String>,
@@ -54,6 +57,9 @@ impl Client {
                 request = request.header(k, v);
             }
         }
+        if let Some(headers) = request.headers_mut() {
+            merge_global_headers(headers);
+        }
 
         Ok(request.body(()).expect("build req"))
     }
@@ -12,7 +12,7 @@ pub use h2::Client as H2Client;
 pub use shadow_tls::Client as Shadowtls;
 pub use simple_obfs::*;
 pub use sip003::Plugin as Sip003Plugin;
-pub use tls::Client as TlsClient;
+pub use tls::{Client as TlsClient, ClientFingerprint};
 pub use v2ray::{V2RayOBFSOption, V2rayWsClient};
 pub use ws::Client as WsClient;
 
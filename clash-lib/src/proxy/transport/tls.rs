@@ -1,12 +1,17 @@
 use async_trait::async_trait;
+use rand::seq::IndexedRandom;
+use rustls::crypto::CryptoProvider;
 use serde::Serialize;
-use std::{io, sync::Arc};
+use std::{
+    io,
+    sync::{Arc, OnceLock},
+};
 
 use super::Transport;
 use crate::{
     common::{
         errors::map_io_error,
-        tls::{DefaultTlsVerifier, GLOBAL_ROOT_STORE},
+        tls::{DefaultTlsVerifier, GLOBAL_ROOT_STORE, client_resumption},
     },
     proxy::AnyStream,
 };
@@ -29,6 +34,66 @@ pub struct Client {
     pub sni: String,
     pub alpn: Option<Vec<String>>,
     pub expected_alpn: Option<String>,
+    /// built on first use and shared by all connections, which lets later
+    /// handshakes resume the sessions cached in it
+    tls_config: OnceLock<Arc<rustls::ClientConfig>>,
+    /// `client-fingerprint`
+    fingerprint: Option<ClientFingerprint>,
+}
+
+/// Whose order of cipher suites and key exchange groups the ClientHello
+/// offers, of those rustls has. Only that order follows the browser: the
+/// extensions and the rest of the hello are still rustls' own, so this
+/// doesn't pass for the browser with JA3/JA4 style fingerprinting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClientFingerprint {
+    Chrome,
+    Firefox,
+    Safari,
+    /// One of the others, picked anew on every dial
+    Randomized,
+}
+
+/// The IANA codes of cipher suites and of key exchange groups, in the order
+/// a browser offers them
+struct Offer {
+    suites: &'static [u16],
+    groups: &'static [u16],
+}
+
+static CHROME: Offer = Offer {
+    suites: &[
+        0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8,
+    ],
+    groups: &[0x11ec, 0x001d, 0x0017, 0x0018],
+};
+
+static FIREFOX: Offer = Offer {
+    suites: &[
+        0x1301, 0x1303, 0x1302, 0xc02b, 0xc02f, 0xcca9, 0xcca8, 0xc02c, 0xc030,
+    ],
+    groups: &[0x11ec, 0x001d, 0x0017, 0x0018, 0x0019],
+};
+
+static SAFARI: Offer = Offer {
+    suites: &[
+        0x1301, 0x1302, 0x1303, 0xc02c, 0xc02b, 0xcca9, 0xc030, 0xc02f, 0xcca8,
+    ],
+    groups: &[0x001d, 0x0017, 0x0018, 0x0019],
+};
+
+impl ClientFingerprint {
+    fn offer(self) -> &'static Offer {
+        match self {
+            ClientFingerprint::Chrome => &CHROME,
+            ClientFingerprint::Firefox => &FIREFOX,
+            ClientFingerprint::Safari => &SAFARI,
+            ClientFingerprint::Randomized => [&CHROME, &FIREFOX, &SAFARI]
+                .choose(&mut rand::rng())
+                .copied()
+                .unwrap(),
+        }
+    }
 }
 
 impl Client {
@@ -43,16 +108,60 @@ impl Client {
             sni,
             alpn,
             expected_alpn,
+            tls_config: OnceLock::new(),
+            fingerprint: None,
         }
     }
-}
 
-#[async_trait]
-impl Transport for Client {
-    async fn proxy_stream(&self, stream: AnyStream) -> io::Result<AnyStream> {
-        let mut tls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(GLOBAL_ROOT_STORE.clone())
-            .with_no_client_auth();
+    /// Offer the cipher suites and key exchange groups in the order of
+    /// `fingerprint`, rather than rustls'
+    pub fn with_fingerprint(
+        mut self,
+        fingerprint: Option<ClientFingerprint>,
+    ) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    fn tls_config(&self) -> Arc<rustls::ClientConfig> {
+        let shared = self
+            .tls_config
+            .get_or_init(|| {
+                let tls_config = rustls::ClientConfig::builder()
+                    .with_root_certificates(GLOBAL_ROOT_STORE.clone())
+                    .with_no_client_auth();
+                Arc::new(self.setup(tls_config, client_resumption()))
+            })
+            .clone();
+        let Some(fingerprint) = self.fingerprint else {
+            return shared;
+        };
+
+        let Some(provider) = CryptoProvider::get_default() else {
+            return shared;
+        };
+        match rustls::ClientConfig::builder_with_provider(Arc::new(
+            ordered_provider(provider, fingerprint.offer()),
+        ))
+        .with_safe_default_protocol_versions()
+        {
+            Ok(builder) => {
+                let tls_config = builder
+                    .with_root_certificates(GLOBAL_ROOT_STORE.clone())
+                    .with_no_client_auth();
+                // share the session cache, the order of the offer doesn't
+                // matter to resumption
+                Arc::new(self.setup(tls_config, shared.resumption.clone()))
+            }
+            Err(_) => shared,
+        }
+    }
+
+    fn setup(
+        &self,
+        mut tls_config: rustls::ClientConfig,
+        resumption: rustls::client::Resumption,
+    ) -> rustls::ClientConfig {
         tls_config.alpn_protocols = self
             .alpn
             .clone()
@@ -60,6 +169,7 @@ impl Transport for Client {
             .into_iter()
             .map(|x| x.as_bytes().to_vec())
             .collect();
+        tls_config.resumption = resumption;
 
         tls_config.dangerous().set_certificate_verifier(Arc::new(
             DefaultTlsVerifier::new(None, self.skip_cert_verify),
@@ -68,8 +178,38 @@ impl Transport for Client {
         if std::env::var("SSLKEYLOGFILE").is_ok() {
             tls_config.key_log = Arc::new(rustls::KeyLogFile::new());
         }
+        tls_config
+    }
+}
+
+/// A copy of `provider` offering the cipher suites and key exchange groups
+/// of `offer` it has, in that order. Either is left as it is if it would
+/// end up empty.
+fn ordered_provider(provider: &CryptoProvider, offer: &Offer) -> CryptoProvider {
+    fn order<T: Clone>(items: &mut Vec<T>, codes: &[u16], code: impl Fn(&T) -> u16) {
+        let ordered: Vec<_> = codes
+            .iter()
+            .filter_map(|c| items.iter().find(|x| code(x) == *c).cloned())
+            .collect();
+        if !ordered.is_empty() {
+            *items = ordered;
+        }
+    }
+
+    let mut provider = provider.clone();
+    order(&mut provider.cipher_suites, offer.suites, |x| {
+        u16::from(x.suite())
+    });
+    order(&mut provider.kx_groups, offer.groups, |x| {
+        u16::from(x.name())
+    });
+    provider
+}
 
-        let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config));
+#[async_trait]
+impl Transport for Client {
+    async fn proxy_stream(&self, stream: AnyStream) -> io::Result<AnyStream> {
+        let connector = tokio_rustls::TlsConnector::from(self.tls_config());
         let dns_name =
             rustls::pki_types::ServerName::try_from(self.sni.as_str().to_owned())
                 .map_err(map_io_error)?;
@@ -90,3 +230,58 @@ impl Transport for Client {
         c.map(|x| Box::new(x) as _)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rustls::crypto::CryptoProvider;
+
+    use super::{CHROME, ClientFingerprint, FIREFOX, SAFARI, ordered_provider};
+
+    #[test]
+    fn test_ordered_provider() {
+        crate::setup_default_crypto_provider();
+        let provider = CryptoProvider::get_default().unwrap();
+
+        for offer in [&CHROME, &FIREFOX, &SAFARI] {
+            let ordered = ordered_provider(provider, offer);
+
+            // a subsequence of the offer, of what the provider has
+            let suites: Vec<_> = ordered
+                .cipher_suites
+                .iter()
+                .map(|x| u16::from(x.suite()))
+                .collect();
+            let expected: Vec<_> = offer
+                .suites
+                .iter()
+                .copied()
+                .filter(|c| {
+                    provider
+                        .cipher_suites
+                        .iter()
+                        .any(|x| u16::from(x.suite()) == *c)
+                })
+                .collect();
+            assert_eq!(suites, expected);
+            assert!(!suites.is_empty());
+
+            let groups: Vec<_> = ordered
+                .kx_groups
+                .iter()
+                .map(|x| u16::from(x.name()))
+                .collect();
+            let mut it = offer.groups.iter();
+            assert!(groups.iter().all(|g| it.any(|c| c == g)));
+            assert!(!groups.is_empty());
+        }
+
+        for _ in 0..16 {
+            let offer = ClientFingerprint::Randomized.offer();
+            assert!(
+                [&CHROME, &FIREFOX, &SAFARI]
+                    .iter()
+                    .any(|x| std::ptr::eq(*x, offer))
+            );
+        }
+    }
+}
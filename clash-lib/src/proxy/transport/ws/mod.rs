@@ -7,7 +7,10 @@ use tokio_tungstenite::{
 };
 
 use super::Transport;
-use crate::{common::errors::map_io_error, proxy::AnyStream};
+use crate::{
+    common::{errors::map_io_error, http::merge_global_headers},
+    proxy::AnyStream,
+};
 
 mod websocket;
 mod websocket_early_data;
@@ -57,6 +60,9 @@ impl Client {
         for (k, v) in self.headers.iter() {
             request = request.header(k.as_str(), v.as_str());
         }
+        if let Some(headers) = request.headers_mut() {
+            merge_global_headers(headers);
+        }
         if self.max_early_data > 0 {
             // we will replace this field later
             request = request.header(self.early_data_header_name.as_str(), "xxoo");
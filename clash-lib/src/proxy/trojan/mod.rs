@@ -1,4 +1,4 @@
-use std::{io, sync::Arc};
+use std::{io, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use bytes::{BufMut, BytesMut};
@@ -25,7 +25,10 @@ use super::{
     AnyStream, ConnectorType, DialWithConnector, HandlerCommonOptions,
     OutboundHandler, OutboundType,
     transport::Transport,
-    utils::{GLOBAL_DIRECT_CONNECTOR, RemoteConnector},
+    utils::{
+        GLOBAL_DIRECT_CONNECTOR, RemoteConnector, with_handshake_retries,
+        with_handshake_timeout,
+    },
 };
 
 mod datagram;
@@ -110,6 +113,10 @@ impl OutboundHandler for Handler {
         OutboundType::Trojan
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         self.opts.udp
     }
@@ -168,18 +175,20 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedStream> {
-        let stream = connector
-            .connect_stream(
-                resolver,
-                self.opts.server.as_str(),
-                self.opts.port,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
-            )
-            .await?;
-
-        let s = self.inner_proxy_stream(stream, sess, false).await?;
+        let resolver = self.opts.common_opts.server_resolver(resolver);
+        let s = with_handshake_retries(|| async {
+            let stream = connector
+                .connect_stream(
+                    resolver.clone(),
+                    self.opts.server.as_str(),
+                    self.opts.port,
+                    &self.opts.common_opts.connect_options(sess),
+                )
+                .await?;
+            with_handshake_timeout(self.inner_proxy_stream(stream, sess, false))
+                .await
+        })
+        .await?;
         let chained = ChainedStreamWrapper::new(s);
         chained.append_to_chain(self.name()).await;
         Ok(Box::new(chained))
@@ -191,18 +200,19 @@ impl OutboundHandler for Handler {
         resolver: ThreadSafeDNSResolver,
         connector: &dyn RemoteConnector,
     ) -> io::Result<BoxedChainedDatagram> {
-        let stream = connector
-            .connect_stream(
-                resolver,
-                self.opts.server.as_str(),
-                self.opts.port,
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
-            )
-            .await?;
-
-        let stream = self.inner_proxy_stream(stream, sess, true).await?;
+        let resolver = self.opts.common_opts.server_resolver(resolver);
+        let stream = with_handshake_retries(|| async {
+            let stream = connector
+                .connect_stream(
+                    resolver.clone(),
+                    self.opts.server.as_str(),
+                    self.opts.port,
+                    &self.opts.common_opts.connect_options(sess),
+                )
+                .await?;
+            with_handshake_timeout(self.inner_proxy_stream(stream, sess, true)).await
+        })
+        .await?;
 
         let d = OutboundDatagramTrojan::new(stream, sess.destination.clone());
 
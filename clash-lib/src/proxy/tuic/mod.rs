@@ -4,17 +4,16 @@ mod handle_task;
 pub(crate) mod types;
 
 use crate::{
-    common::tls::DefaultTlsVerifier,
-    proxy::{tuic::types::SocketAdderTrans, utils::new_udp_socket},
+    common::tls::{DefaultTlsVerifier, client_resumption},
+    proxy::{
+        tuic::types::SocketAdderTrans,
+        utils::{ConnectOptions, new_udp_socket, quic::CongestionControl},
+    },
 };
 use anyhow::Result;
 use async_trait::async_trait;
 
-use quinn::{
-    EndpointConfig, TokioRuntime,
-    congestion::{BbrConfig, NewRenoConfig},
-    crypto::rustls::QuicClientConfig,
-};
+use quinn::{EndpointConfig, TokioRuntime, crypto::rustls::QuicClientConfig};
 use tracing::debug;
 
 use std::{
@@ -46,11 +45,11 @@ use crate::{
 use crate::session::SocksAddr as ClashSocksAddr;
 use quinn::{
     ClientConfig as QuinnConfig, Endpoint as QuinnEndpoint,
-    TransportConfig as QuinnTransportConfig, VarInt, congestion::CubicConfig,
+    TransportConfig as QuinnTransportConfig, VarInt,
 };
 use tokio::sync::{Mutex as AsyncMutex, OnceCell};
 
-use self::types::{CongestionControl, TuicConnection, UdpRelayMode, UdpSession};
+use self::types::{TuicConnection, UdpRelayMode, UdpSession};
 
 use super::{
     ConnectorType, HandlerCommonOptions, OutboundHandler, OutboundType,
@@ -77,9 +76,12 @@ pub struct HandlerOptions {
     pub gc_lifetime: Duration,
     pub send_window: u64,
     pub receive_window: VarInt,
+    pub receive_window_conn: VarInt,
+    pub initial_mtu: u16,
+    /// UDP segmentation offload, Linux only
+    pub gso: bool,
     pub skip_cert_verify: bool,
 
-    #[allow(dead_code)]
     pub common_opts: HandlerCommonOptions,
 
     /// not used
@@ -118,6 +120,10 @@ impl OutboundHandler for Handler {
         OutboundType::Tuic
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         true
     }
@@ -127,6 +133,7 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> std::io::Result<BoxedChainedStream> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         self.do_connect_stream(sess, resolver).await.map_err(|e| {
             tracing::error!("{:?}", e);
             std::io::Error::other(e.to_string())
@@ -138,6 +145,7 @@ impl OutboundHandler for Handler {
         sess: &Session,
         resolver: ThreadSafeDNSResolver,
     ) -> std::io::Result<BoxedChainedDatagram> {
+        let resolver = self.opts.common_opts.server_resolver(resolver);
         self.do_connect_datagram(sess, resolver).await.map_err(|e| {
             tracing::error!("{:?}", e);
             std::io::Error::other(e.to_string())
@@ -178,6 +186,7 @@ impl Handler {
         crypto.alpn_protocols.clone_from(&opts.alpn);
         crypto.enable_early_data = true;
         crypto.enable_sni = !opts.disable_sni;
+        crypto.resumption = client_resumption();
 
         let mut quinn_config =
             QuinnConfig::new(Arc::new(QuicClientConfig::try_from(crypto)?));
@@ -187,15 +196,11 @@ impl Handler {
             .max_concurrent_uni_streams(opts.max_open_stream)
             .send_window(opts.send_window)
             .stream_receive_window(opts.receive_window)
+            .receive_window(opts.receive_window_conn)
+            .initial_mtu(opts.initial_mtu)
+            .enable_segmentation_offload(opts.gso)
+            .congestion_controller_factory(opts.congestion_controller.factory())
             .max_idle_timeout(Some(opts.idle_timeout.try_into().unwrap()));
-        match opts.congestion_controller {
-            CongestionControl::Cubic => transport_config
-                .congestion_controller_factory(Arc::new(CubicConfig::default())),
-            CongestionControl::NewReno => transport_config
-                .congestion_controller_factory(Arc::new(NewRenoConfig::default())),
-            CongestionControl::Bbr => transport_config
-                .congestion_controller_factory(Arc::new(BbrConfig::default())),
-        };
 
         quinn_config.transport_config(Arc::new(transport_config));
 
@@ -204,19 +209,15 @@ impl Handler {
             if resolver.ipv6() {
                 new_udp_socket(
                     Some((Ipv6Addr::UNSPECIFIED, 0).into()),
-                    sess.iface.as_ref(),
-                    #[cfg(target_os = "linux")]
-                    sess.so_mark,
                     None,
+                    &sess.into(),
                 )
                 .await?
             } else {
                 new_udp_socket(
                     Some((Ipv4Addr::UNSPECIFIED, 0).into()),
                     None,
-                    #[cfg(target_os = "linux")]
-                    sess.so_mark,
-                    None,
+                    &ConnectOptions::default().so_mark(sess.so_mark),
                 )
                 .await?
             }
@@ -425,6 +426,9 @@ mod tests {
             gc_lifetime: Duration::from_millis(15000),
             send_window: 8 * 1024 * 1024 * 2,
             receive_window: VarInt::from_u64(8 * 1024 * 1024)?,
+            receive_window_conn: VarInt::from_u64(20 * 1024 * 1024)?,
+            initial_mtu: 1252,
+            gso: true,
         })
     }
 
@@ -1,6 +1,9 @@
 use crate::{
     app::{dns::ThreadSafeDNSResolver, net::DEFAULT_OUTBOUND_INTERFACE},
-    proxy::{datagram::UdpPacket, utils::new_udp_socket},
+    proxy::{
+        datagram::UdpPacket,
+        utils::{ConnectOptions, new_udp_socket},
+    },
     session::SocksAddr as ClashSocksAddr,
 };
 use anyhow::{Result, anyhow};
@@ -46,13 +49,11 @@ impl TuicEndpoint {
                 let socket = {
                     let iface = DEFAULT_OUTBOUND_INTERFACE.read().await;
                     new_udp_socket(
-                        None,
-                        iface.as_ref(),
-                        #[cfg(target_os = "linux")]
                         None,
                         self.server
                             .ip
                             .map(|ip| SocketAddr::new(ip, self.server.port)),
+                        &ConnectOptions::default().iface(iface.as_ref()),
                     )
                     .await?
                 };
@@ -249,32 +250,6 @@ impl From<&str> for UdpRelayMode {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-pub enum CongestionControl {
-    Cubic,
-    NewReno,
-    #[default]
-    Bbr,
-}
-impl From<&str> for CongestionControl {
-    fn from(s: &str) -> Self {
-        if s.eq_ignore_ascii_case("cubic") {
-            Self::Cubic
-        } else if s.eq_ignore_ascii_case("new_reno")
-            || s.eq_ignore_ascii_case("newreno")
-        {
-            Self::NewReno
-        } else if s.eq_ignore_ascii_case("bbr") {
-            Self::Bbr
-        } else {
-            tracing::warn!(
-                "Unknown congestion controller {s}. Use default controller"
-            );
-            Self::default()
-        }
-    }
-}
-
 pub trait SocketAdderTrans {
     fn into_tuic(self) -> tuic::Address;
 }
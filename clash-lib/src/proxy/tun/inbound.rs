@@ -1,12 +1,13 @@
 use crate::{
     Error, Runner,
     app::{dispatcher::Dispatcher, dns::ThreadSafeDNSResolver},
-    config::internal::config::TunConfig,
+    config::{def::TunStack, internal::config::TunConfig},
     defer,
     proxy::tun::{
         datagram::handle_inbound_datagram,
         routes::{self},
         stream::handle_inbound_stream,
+        system::{self, Route, SystemStack},
     },
 };
 use futures::{SinkExt, StreamExt};
@@ -82,6 +83,7 @@ pub fn get_runner(
         }
     };
 
+    let from_fd = tun_init_config.fd.is_some();
     let tun = if let Some(fd) = tun_init_config.fd {
         #[cfg(target_family = "unix")]
         {
@@ -158,6 +160,37 @@ pub fn get_runner(
         }
     };
 
+    let system_stack = match cfg.stack {
+        TunStack::Gvisor => None,
+        TunStack::Auto
+            if from_fd
+                || !cfg!(any(target_os = "linux", target_os = "macos"))
+                || system::portal(cfg.gateway.into()).is_none() =>
+        {
+            None
+        }
+        TunStack::Auto | TunStack::System => {
+            match SystemStack::new(cfg.gateway, cfg.gateway_v6) {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    warn!(
+                        "failed to set up the tun system stack: {e}, using gvisor"
+                    );
+                    None
+                }
+            }
+        }
+    };
+    let (system_stack, system_listeners) = system_stack.unzip();
+    info!(
+        "tun stack: {}",
+        if system_stack.is_some() {
+            "system"
+        } else {
+            "gvisor"
+        }
+    );
+
     let (stack, mut tcp_listener, udp_socket) = watfaq_netstack::NetStack::new();
 
     Ok(Some(Box::pin(async move {
@@ -182,22 +215,28 @@ pub fn get_runner(
         let (mut tun_sink, mut tun_stream) = framed.split::<bytes::Bytes>();
         let (mut stack_sink, mut stack_stream) = stack.split();
 
+        // packets rewritten by the system stack, back to the tun
+        let (nat_tx, mut nat_rx) = tokio::sync::mpsc::channel::<bytes::Bytes>(1024);
+
         let mut futs: Vec<Runner> = vec![];
 
         // dispatcher -> stack -> tun
         futs.push(Box::pin(async move {
-            while let Some(pkt) = stack_stream.next().await {
-                match pkt {
-                    Ok(pkt) => {
-                        if let Err(e) = tun_sink.send(pkt.into_bytes()).await {
-                            error!("failed to send pkt to tun: {}", e);
+            loop {
+                let pkt = tokio::select! {
+                    pkt = stack_stream.next() => match pkt {
+                        Some(Ok(pkt)) => pkt.into_bytes(),
+                        Some(Err(e)) => {
+                            error!("tun stack error: {}", e);
                             break;
                         }
-                    }
-                    Err(e) => {
-                        error!("tun stack error: {}", e);
-                        break;
-                    }
+                        None => break,
+                    },
+                    Some(pkt) = nat_rx.recv() => pkt,
+                };
+                if let Err(e) = tun_sink.send(pkt).await {
+                    error!("failed to send pkt to tun: {}", e);
+                    break;
                 }
             }
 
@@ -205,10 +244,23 @@ pub fn get_runner(
         }));
 
         // tun -> stack -> dispatcher
+        let system = system_stack.clone();
         futs.push(Box::pin(async move {
             while let Some(pkt) = tun_stream.next().await {
                 match pkt {
-                    Ok(pkt) => {
+                    Ok(mut pkt) => {
+                        if let Some(system) = &system {
+                            match system.rewrite(&mut pkt) {
+                                Route::Stack => {}
+                                Route::Tun => {
+                                    if nat_tx.send(pkt.freeze()).await.is_err() {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                Route::Drop => continue,
+                            }
+                        }
                         if let Err(e) =
                             stack_sink.send(watfaq_netstack::Packet::new(pkt)).await
                         {
@@ -235,12 +287,56 @@ pub fn get_runner(
                     stream.remote_addr()
                 );
 
-                tokio::spawn(handle_inbound_stream(stream, dsp.clone(), so_mark));
+                let (source, destination) =
+                    (stream.local_addr(), stream.remote_addr());
+                tokio::spawn(handle_inbound_stream(
+                    stream,
+                    source,
+                    destination,
+                    dsp.clone(),
+                    so_mark,
+                ));
             }
 
             Err(Error::Operation("tun stopped unexpectedly 2".to_string()))
         }));
 
+        if let Some(system) = system_stack {
+            for listener in system_listeners.into_iter().flatten() {
+                let system = system.clone();
+                let dsp = dispatcher.clone();
+                futs.push(Box::pin(async move {
+                    loop {
+                        let (stream, peer) = match listener.accept().await {
+                            Ok(x) => x,
+                            Err(e) => {
+                                error!("tun system stack accept error: {}", e);
+                                break;
+                            }
+                        };
+                        let Some((source, destination)) = system.original(peer)
+                        else {
+                            debug!("tun system stack: unknown connection {}", peer);
+                            continue;
+                        };
+                        debug!(
+                            "new tun TCP connection: {} -> {}",
+                            source, destination
+                        );
+                        tokio::spawn(handle_inbound_stream(
+                            stream,
+                            source,
+                            destination,
+                            dsp.clone(),
+                            so_mark,
+                        ));
+                    }
+
+                    Err(Error::Operation("tun stopped unexpectedly 4".to_string()))
+                }));
+            }
+        }
+
         futs.push(Box::pin(async move {
             handle_inbound_datagram(
                 udp_socket,
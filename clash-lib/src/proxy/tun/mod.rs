@@ -3,6 +3,7 @@ pub mod inbound;
 pub use inbound::get_runner as get_tun_runner;
 mod routes;
 mod stream;
+mod system;
 
 #[cfg(target_os = "linux")] // for tproxy
 pub use datagram::TunDatagram;
@@ -1,23 +1,25 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tracing::debug;
 
 use crate::{
     app::{dispatcher::Dispatcher, net::DEFAULT_OUTBOUND_INTERFACE},
+    proxy::ClientStream,
     session::{Network, Session, Type},
 };
 
 pub(crate) async fn handle_inbound_stream(
-    stream: watfaq_netstack::TcpStream,
-
+    stream: impl ClientStream,
+    source: SocketAddr,
+    destination: SocketAddr,
     dispatcher: Arc<Dispatcher>,
     so_mark: Option<u32>,
 ) {
     let sess = Session {
         network: Network::Tcp,
         typ: Type::Tun,
-        source: stream.local_addr(),
-        destination: stream.remote_addr().into(),
+        source,
+        destination: destination.into(),
         iface: DEFAULT_OUTBOUND_INTERFACE
             .read()
             .await
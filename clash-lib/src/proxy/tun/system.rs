@@ -0,0 +1,364 @@
+//! The `system` stack. TCP packets read from the tun are rewritten to come
+//! from a portal address in the tun subnet and go to a listener bound to the
+//! tun address, and written back to the tun, so the OS terminates the
+//! connection on the listener. Its replies to the portal are rewritten back
+//! to the original addresses. Everything else goes to the userspace stack.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use smoltcp::wire::{
+    IpAddress, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet, TcpPacket,
+};
+use tokio::net::TcpListener;
+
+/// Ports the portal connects to the listener from
+const NAT_PORTS: std::ops::RangeInclusive<u16> = 1024..=65535;
+
+/// A mapping idle for longer may be reused for a new connection when the
+/// ports run out
+const NAT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What to do with a packet read from the tun
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    /// not TCP, or of an IP version without a listener
+    Stack,
+    /// rewritten, write it back to the tun
+    Tun,
+    Drop,
+}
+
+struct Conn {
+    src: SocketAddr,
+    dst: SocketAddr,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct Nat {
+    ports: HashMap<(SocketAddr, SocketAddr), u16>,
+    conns: HashMap<u16, Conn>,
+    next: u16,
+}
+
+impl Nat {
+    fn port_for(
+        &mut self,
+        src: SocketAddr,
+        dst: SocketAddr,
+        now: Instant,
+    ) -> Option<u16> {
+        if let Some(port) = self.ports.get(&(src, dst)) {
+            self.conns.get_mut(port)?.last_seen = now;
+            return Some(*port);
+        }
+
+        for _ in NAT_PORTS {
+            let port = self.next.max(*NAT_PORTS.start());
+            self.next = port.checked_add(1).unwrap_or(*NAT_PORTS.start());
+            match self.conns.get(&port) {
+                Some(conn)
+                    if now.duration_since(conn.last_seen) < NAT_IDLE_TIMEOUT =>
+                {
+                    continue;
+                }
+                Some(conn) => {
+                    self.ports.remove(&(conn.src, conn.dst));
+                }
+                None => {}
+            }
+            self.ports.insert((src, dst), port);
+            self.conns.insert(
+                port,
+                Conn {
+                    src,
+                    dst,
+                    last_seen: now,
+                },
+            );
+            return Some(port);
+        }
+        None
+    }
+
+    fn conn(&mut self, port: u16, now: Instant) -> Option<(SocketAddr, SocketAddr)> {
+        let conn = self.conns.get_mut(&port)?;
+        conn.last_seen = now;
+        Some((conn.src, conn.dst))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Endpoint {
+    /// the tun address the listener is bound to
+    gateway: IpAddr,
+    /// the address connections to the listener come from
+    portal: IpAddr,
+    port: u16,
+}
+
+/// The address after the gateway, if it's in the subnet and isn't its
+/// broadcast address
+pub fn portal(net: IpNet) -> Option<IpAddr> {
+    let portal = match net {
+        IpNet::V4(net) => IpAddr::V4(net.addr().to_bits().checked_add(1)?.into()),
+        IpNet::V6(net) => IpAddr::V6(net.addr().to_bits().checked_add(1)?.into()),
+    };
+    (net.contains(&portal) && portal != net.broadcast()).then_some(portal)
+}
+
+#[derive(Clone)]
+pub struct SystemStack {
+    v4: Option<Endpoint>,
+    v6: Option<Endpoint>,
+    nat: Arc<Mutex<Nat>>,
+}
+
+impl SystemStack {
+    /// Binds the listeners on the tun addresses. Fails if the subnets have
+    /// no room for a portal or the addresses can't be bound, e.g. as the
+    /// tun has others.
+    pub fn new(
+        gateway: Ipv4Net,
+        gateway_v6: Option<Ipv6Net>,
+    ) -> io::Result<(Self, Vec<TcpListener>)> {
+        let mut listeners = vec![];
+        let mut bind = |net: IpNet| -> io::Result<Endpoint> {
+            let portal = portal(net).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no room for a portal address in {net}"),
+                )
+            })?;
+            let listener = std::net::TcpListener::bind((net.addr(), 0))?;
+            listener.set_nonblocking(true)?;
+            let port = listener.local_addr()?.port();
+            listeners.push(TcpListener::from_std(listener)?);
+            Ok(Endpoint {
+                gateway: net.addr(),
+                portal,
+                port,
+            })
+        };
+        let v4 = bind(gateway.into())?;
+        // without a portal, IPv6 TCP stays with the userspace stack
+        let v6 = gateway_v6
+            .filter(|x| portal((*x).into()).is_some())
+            .map(|x| bind(x.into()))
+            .transpose()?;
+        Ok((
+            Self {
+                v4: Some(v4),
+                v6,
+                nat: Default::default(),
+            },
+            listeners,
+        ))
+    }
+
+    /// The addresses of the connection a listener accepted from `peer`
+    /// was made with, source first
+    pub fn original(&self, peer: SocketAddr) -> Option<(SocketAddr, SocketAddr)> {
+        self.nat.lock().unwrap().conn(peer.port(), Instant::now())
+    }
+
+    /// Rewrites a packet read from the tun in place
+    pub fn rewrite(&self, pkt: &mut [u8]) -> Route {
+        match IpVersion::of_packet(pkt) {
+            Ok(IpVersion::Ipv4) => self.rewrite_v4(pkt),
+            Ok(IpVersion::Ipv6) => self.rewrite_v6(pkt),
+            Err(_) => Route::Drop,
+        }
+    }
+
+    fn rewrite_v4(&self, pkt: &mut [u8]) -> Route {
+        let Some(ep) = self.v4 else {
+            return Route::Stack;
+        };
+        let Ok(mut ip) = Ipv4Packet::new_checked(pkt) else {
+            return Route::Drop;
+        };
+        if ip.next_header() != IpProtocol::Tcp
+            || ip.more_frags()
+            || ip.frag_offset() != 0
+        {
+            return Route::Stack;
+        }
+        let (src, dst) = (IpAddr::V4(ip.src_addr()), IpAddr::V4(ip.dst_addr()));
+        let Some((src, dst)) = self.rewrite_tcp(ep, src, dst, ip.payload_mut())
+        else {
+            return Route::Drop;
+        };
+        let (IpAddr::V4(src), IpAddr::V4(dst)) = (src.ip(), dst.ip()) else {
+            return Route::Drop;
+        };
+        ip.set_src_addr(src);
+        ip.set_dst_addr(dst);
+        ip.fill_checksum();
+        Route::Tun
+    }
+
+    fn rewrite_v6(&self, pkt: &mut [u8]) -> Route {
+        let Some(ep) = self.v6 else {
+            return Route::Stack;
+        };
+        let Ok(mut ip) = Ipv6Packet::new_checked(pkt) else {
+            return Route::Drop;
+        };
+        // TCP behind extension headers is left to the userspace stack
+        if ip.next_header() != IpProtocol::Tcp {
+            return Route::Stack;
+        }
+        let (src, dst) = (IpAddr::V6(ip.src_addr()), IpAddr::V6(ip.dst_addr()));
+        let Some((src, dst)) = self.rewrite_tcp(ep, src, dst, ip.payload_mut())
+        else {
+            return Route::Drop;
+        };
+        let (IpAddr::V6(src), IpAddr::V6(dst)) = (src.ip(), dst.ip()) else {
+            return Route::Drop;
+        };
+        ip.set_src_addr(src);
+        ip.set_dst_addr(dst);
+        Route::Tun
+    }
+
+    /// Rewrites the ports of the TCP segment, returning the addresses the
+    /// IP header is to be rewritten to
+    fn rewrite_tcp(
+        &self,
+        ep: Endpoint,
+        src_ip: IpAddr,
+        dst_ip: IpAddr,
+        segment: &mut [u8],
+    ) -> Option<(SocketAddr, SocketAddr)> {
+        let mut tcp = TcpPacket::new_checked(segment).ok()?;
+        let src = SocketAddr::new(src_ip, tcp.src_port());
+        let dst = SocketAddr::new(dst_ip, tcp.dst_port());
+
+        let now = Instant::now();
+        let mut nat = self.nat.lock().unwrap();
+        let (src, dst) = if src.ip() == ep.gateway && src.port() == ep.port {
+            // the listener replying to the portal
+            if dst.ip() != ep.portal {
+                return None;
+            }
+            let (orig_src, orig_dst) = nat.conn(dst.port(), now)?;
+            (orig_dst, orig_src)
+        } else {
+            let port = nat.port_for(src, dst, now)?;
+            (
+                SocketAddr::new(ep.portal, port),
+                SocketAddr::new(ep.gateway, ep.port),
+            )
+        };
+        drop(nat);
+
+        tcp.set_src_port(src.port());
+        tcp.set_dst_port(dst.port());
+        tcp.fill_checksum(&IpAddress::from(src.ip()), &IpAddress::from(dst.ip()));
+        Some((src, dst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    /// A SYN from `src` to `dst`
+    fn segment(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+        let (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) = (src.ip(), dst.ip()) else {
+            unreachable!()
+        };
+        let mut buf = vec![0u8; 40];
+        let mut ip = Ipv4Packet::new_unchecked(&mut buf[..]);
+        ip.set_version(4);
+        ip.set_header_len(20);
+        ip.set_total_len(40);
+        ip.set_hop_limit(64);
+        ip.set_next_header(IpProtocol::Tcp);
+        ip.set_src_addr(src_ip);
+        ip.set_dst_addr(dst_ip);
+        ip.fill_checksum();
+
+        let mut tcp = TcpPacket::new_unchecked(ip.payload_mut());
+        tcp.set_src_port(src.port());
+        tcp.set_dst_port(dst.port());
+        tcp.set_header_len(20);
+        tcp.set_syn(true);
+        tcp.set_window_len(1024);
+        tcp.fill_checksum(&src_ip.into(), &dst_ip.into());
+        buf
+    }
+
+    fn addrs(pkt: &[u8]) -> (SocketAddr, SocketAddr) {
+        let ip = Ipv4Packet::new_checked(pkt).unwrap();
+        assert!(ip.verify_checksum());
+        let (src, dst) = (ip.src_addr(), ip.dst_addr());
+        let tcp = TcpPacket::new_checked(ip.payload()).unwrap();
+        assert!(tcp.verify_checksum(&src.into(), &dst.into()));
+        (
+            SocketAddr::new(src.into(), tcp.src_port()),
+            SocketAddr::new(dst.into(), tcp.dst_port()),
+        )
+    }
+
+    #[test]
+    fn test_portal() {
+        assert_eq!(
+            portal("198.18.0.1/24".parse().unwrap()),
+            Some("198.18.0.2".parse().unwrap())
+        );
+        assert_eq!(portal("198.18.0.254/24".parse().unwrap()), None);
+        assert_eq!(portal("198.19.0.1/32".parse().unwrap()), None);
+        assert_eq!(
+            portal("2001:fac::1/64".parse().unwrap()),
+            Some("2001:fac::2".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let gateway = IpAddr::V4(Ipv4Addr::new(198, 18, 0, 1));
+        let portal = IpAddr::V4(Ipv4Addr::new(198, 18, 0, 2));
+        let stack = SystemStack {
+            v4: Some(Endpoint {
+                gateway,
+                portal,
+                port: 7890,
+            }),
+            v6: None,
+            nat: Default::default(),
+        };
+        let app: SocketAddr = "198.18.0.1:50000".parse().unwrap();
+        let remote: SocketAddr = "1.1.1.1:443".parse().unwrap();
+
+        let mut pkt = segment(app, remote);
+        assert_eq!(stack.rewrite(&mut pkt), Route::Tun);
+        let (src, dst) = addrs(&pkt);
+        assert_eq!(src.ip(), portal);
+        assert_eq!(dst, SocketAddr::new(gateway, 7890));
+        assert_eq!(stack.original(src), Some((app, remote)));
+
+        // the same connection keeps its port
+        let mut again = segment(app, remote);
+        stack.rewrite(&mut again);
+        assert_eq!(addrs(&again).0, src);
+
+        let mut reply = segment(dst, src);
+        assert_eq!(stack.rewrite(&mut reply), Route::Tun);
+        assert_eq!(addrs(&reply), (remote, app));
+
+        // a reply to a port without a connection
+        let mut stray = segment(dst, SocketAddr::new(portal, src.port() + 1));
+        assert_eq!(stack.rewrite(&mut stray), Route::Drop);
+    }
+}
@@ -15,11 +15,13 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use tokio::{io::ReadBuf, net::UdpSocket};
+use tokio::net::UdpSocket;
 use tracing::{info, warn};
 
 use super::{
-    datagram::UdpPacket, inbound::InboundHandlerTrait, utils::apply_tcp_options,
+    datagram::{UDP_RECV_BUFFER_SIZE, UdpPacket, poll_recv_from_whole},
+    inbound::InboundHandlerTrait,
+    utils::{apply_tcp_options, tcp_options},
 };
 
 #[derive(Clone)]
@@ -29,6 +31,10 @@ pub struct TunnelInbound {
     network: Vec<String>,
     target: SocksAddr,
     fw_mark: Option<u32>,
+    /// TCP Fast Open queue length, if enabled
+    tfo: Option<u32>,
+    /// SO_REUSEPORT on the listening sockets
+    reuse_port: bool,
 }
 
 impl Drop for TunnelInbound {
@@ -44,6 +50,8 @@ impl TunnelInbound {
         network: Vec<String>,
         target: String,
         fw_mark: Option<u32>,
+        tfo: Option<u32>,
+        reuse_port: bool,
     ) -> crate::Result<Self> {
         Ok(Self {
             listen: addr,
@@ -51,6 +59,8 @@ impl TunnelInbound {
             network,
             target: SocksAddr::from_str(&target)?,
             fw_mark,
+            tfo,
+            reuse_port,
         })
     }
 }
@@ -73,12 +83,16 @@ impl InboundHandlerTrait for TunnelInbound {
             "[Tunnel-TCP] listening on {}, remote: {}",
             self.listen, self.target
         );
-        let listener = try_create_dualstack_tcplistener(self.listen)?;
+        let listener = try_create_dualstack_tcplistener(
+            self.listen,
+            self.tfo,
+            self.reuse_port,
+        )?;
 
         loop {
             let (socket, src_addr) = listener.accept().await?;
 
-            apply_tcp_options(&socket)?;
+            apply_tcp_options(&socket, &tcp_options().keepalive)?;
 
             let dispatcher = self.dispatcher.clone();
             let sess = Session {
@@ -86,6 +100,7 @@ impl InboundHandlerTrait for TunnelInbound {
                 typ: Type::Tunnel,
                 source: src_addr.to_canonical(),
                 destination: self.target.clone(),
+                local_addr: socket.local_addr().ok(),
                 so_mark: self.fw_mark,
                 ..Default::default()
             };
@@ -109,6 +124,7 @@ impl InboundHandlerTrait for TunnelInbound {
             network: Network::Udp,
             typ: Type::Tunnel,
             destination: self.target.clone(),
+            local_addr: socket.local_addr().ok(),
             ..Default::default()
         };
         let inbound = UdpSession::new(socket, self.target.clone());
@@ -134,7 +150,7 @@ impl UdpSession {
         Self {
             socket,
             dst_addr,
-            read_buf: Vec::with_capacity(65507),
+            read_buf: vec![0u8; UDP_RECV_BUFFER_SIZE],
             send_buf: None,
         }
     }
@@ -223,14 +239,9 @@ impl Stream for UdpSession {
         cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
         let this = self.deref_mut();
-        let socket = &this.socket;
-        this.read_buf.resize(this.read_buf.capacity(), 0);
-        let mut buf = ReadBuf::new(&mut this.read_buf);
-        dbg!(buf.initialized().len());
-        buf.clear();
-        match socket.poll_recv_from(cx, &mut buf) {
-            Poll::Ready(Ok(src_addr)) => {
-                let data = buf.filled().to_vec();
+        match poll_recv_from_whole(&this.socket, cx, &mut this.read_buf) {
+            Poll::Ready(Ok((n, src_addr))) => {
+                let data = this.read_buf[..n].to_vec();
                 let dst_addr = this.dst_addr.clone();
                 let src_addr = SocksAddr::from(src_addr);
                 Poll::Ready(Some(UdpPacket {
@@ -239,14 +250,8 @@ impl Stream for UdpSession {
                     dst_addr,
                 }))
             }
-            Poll::Ready(Err(e)) => {
-                if e.kind() == io::ErrorKind::WouldBlock {
-                    Poll::Pending
-                } else {
-                    // FIXME
-                    Poll::Ready(None)
-                }
-            }
+            // FIXME
+            Poll::Ready(Err(_)) => Poll::Ready(None),
             Poll::Pending => Poll::Pending,
         }
     }
@@ -0,0 +1,310 @@
+use std::{ops::RangeInclusive, sync::RwLock, time::Duration};
+
+use crate::{
+    app::net::{OutboundInterface, outbound_dscp, outbound_freebind},
+    session::Session,
+};
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// As recommended by RFC 8305
+const DEFAULT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+tokio::task_local! {
+    static CONNECT_TIMEOUT: Duration;
+}
+
+/// Runs `f` with `timeout` as the default [`ConnectOptions::connect_timeout`]
+/// of the sockets it creates on its task, instead of `connect-timeout`.
+/// Outbounds dial through [`super::RemoteConnector`]s that don't see the
+/// session, so this reaches them all, e.g. for health checks to fail fast on
+/// dead nodes.
+pub async fn with_connect_timeout<F: Future>(timeout: Duration, f: F) -> F::Output {
+    CONNECT_TIMEOUT.scope(timeout, f).await
+}
+
+/// Keepalive of accepted inbound connections, see [`apply_tcp_options`]
+///
+/// [`apply_tcp_options`]: super::apply_tcp_options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpKeepaliveConfig {
+    /// Idle time before the first probe
+    pub time: Duration,
+    /// Time between unanswered probes
+    pub interval: Duration,
+    /// Unanswered probes before the connection is dropped, ignored on
+    /// Windows
+    pub retries: u32,
+    /// Turns keepalive off altogether when false
+    pub enabled: bool,
+}
+
+impl TcpKeepaliveConfig {
+    const DEFAULT: Self = Self {
+        time: Duration::from_secs(10),
+        interval: Duration::from_secs(1),
+        retries: 3,
+        enabled: true,
+    };
+}
+
+impl Default for TcpKeepaliveConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// TCP options applied to every proxied connection, see `tcp-keepalive`
+/// and `connect-timeout`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpOptions {
+    pub keepalive: TcpKeepaliveConfig,
+    /// TCP_NODELAY of outbound connections, see [`ConnectOptions::nodelay`]
+    pub nodelay: bool,
+    /// The default of [`ConnectOptions::connect_timeout`]
+    pub connect_timeout: Duration,
+    /// The default of [`ConnectOptions::fast_open`], `outbound-tfo`
+    pub fast_open: bool,
+    /// The default of [`ConnectOptions::attempt_delay`],
+    /// `happy-eyeballs-delay`
+    pub attempt_delay: Duration,
+}
+
+impl TcpOptions {
+    const DEFAULT: Self = Self {
+        keepalive: TcpKeepaliveConfig::DEFAULT,
+        nodelay: true,
+        connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        fast_open: false,
+        attempt_delay: DEFAULT_ATTEMPT_DELAY,
+    };
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static TCP_OPTIONS: RwLock<TcpOptions> = RwLock::new(TcpOptions::DEFAULT);
+
+pub fn set_tcp_options(options: TcpOptions) {
+    *TCP_OPTIONS.write().unwrap() = options;
+}
+
+pub fn tcp_options() -> TcpOptions {
+    TCP_OPTIONS.read().unwrap().clone()
+}
+
+/// UDP options applied to every outbound UDP socket, see `udp-send-buffer`
+/// and `udp-recv-buffer`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UdpOptions {
+    /// The default of [`ConnectOptions::send_buffer`]
+    pub send_buffer: Option<usize>,
+    /// The default of [`ConnectOptions::recv_buffer`]
+    pub recv_buffer: Option<usize>,
+}
+
+static UDP_OPTIONS: RwLock<UdpOptions> = RwLock::new(UdpOptions {
+    send_buffer: None,
+    recv_buffer: None,
+});
+
+pub fn set_udp_options(options: UdpOptions) {
+    *UDP_OPTIONS.write().unwrap() = options;
+}
+
+pub fn udp_options() -> UdpOptions {
+    UDP_OPTIONS.read().unwrap().clone()
+}
+
+/// Socket level options for outbound sockets created by [`new_tcp_stream`]
+/// and [`new_udp_socket`].
+///
+/// Platform specific options are always present and silently ignored on
+/// platforms that don't support them.
+///
+/// [`new_tcp_stream`]: super::new_tcp_stream
+/// [`new_udp_socket`]: super::new_udp_socket
+#[derive(Debug, Clone)]
+pub struct ConnectOptions<'a> {
+    /// The interface to bind the socket to
+    pub iface: Option<&'a OutboundInterface>,
+    /// SO_MARK on Linux, the routing table (SO_SETFIB) on FreeBSD, ignored
+    /// with a warning elsewhere
+    pub so_mark: Option<u32>,
+    /// The DSCP bits of `IP_TOS`/`IPV6_TCLASS`, Unix only, `dscp` unless
+    /// set
+    pub dscp: Option<u8>,
+    /// IP_FREEBIND, Linux only
+    pub freebind: bool,
+    /// TCP only, the maximum time to wait for the connection to establish,
+    /// `connect-timeout` unless set or overridden by [`with_connect_timeout`]
+    pub connect_timeout: Duration,
+    /// TCP only, TCP_NODELAY
+    pub nodelay: bool,
+    /// TCP only, SO_KEEPALIVE
+    pub keepalive: bool,
+    /// TCP only, TCP_FASTOPEN_CONNECT, Linux only. The connection is
+    /// established with the first write, which goes with the SYN.
+    pub fast_open: bool,
+    /// TCP only, how long an attempt of [`new_tcp_stream_racing`] gets
+    /// before the next one starts, `happy-eyeballs-delay` unless set
+    ///
+    /// [`new_tcp_stream_racing`]: super::new_tcp_stream_racing
+    pub attempt_delay: Duration,
+    /// SO_REUSEADDR, off for outbound connections
+    pub reuse_address: bool,
+    /// SO_REUSEPORT, off for outbound connections. Ignored on Windows,
+    /// which has no such option.
+    pub reuse_port: bool,
+    /// UDP only, SO_SNDBUF in bytes, the OS default unless set
+    pub send_buffer: Option<usize>,
+    /// UDP only, SO_RCVBUF in bytes, the OS default unless set
+    pub recv_buffer: Option<usize>,
+    /// UDP only, the local ports to pick from, the first free one, when no
+    /// port is asked for. For peers and firewalls that only take traffic
+    /// from a known band of source ports.
+    pub source_ports: Option<RangeInclusive<u16>>,
+}
+
+impl Default for ConnectOptions<'_> {
+    fn default() -> Self {
+        Self {
+            iface: None,
+            so_mark: None,
+            dscp: outbound_dscp(),
+            freebind: outbound_freebind(),
+            connect_timeout: CONNECT_TIMEOUT
+                .try_with(|timeout| *timeout)
+                .unwrap_or_else(|_| tcp_options().connect_timeout),
+            nodelay: tcp_options().nodelay,
+            keepalive: true,
+            fast_open: tcp_options().fast_open,
+            attempt_delay: tcp_options().attempt_delay,
+            reuse_address: false,
+            reuse_port: false,
+            send_buffer: udp_options().send_buffer,
+            recv_buffer: udp_options().recv_buffer,
+            source_ports: None,
+        }
+    }
+}
+
+impl<'a> ConnectOptions<'a> {
+    /// Options for the common case: bind to an interface and/or set a
+    /// packet mark, everything else is default.
+    pub fn new(iface: Option<&'a OutboundInterface>, so_mark: Option<u32>) -> Self {
+        Self::default().iface(iface).so_mark(so_mark)
+    }
+
+    pub fn iface(mut self, iface: Option<&'a OutboundInterface>) -> Self {
+        self.iface = iface;
+        self
+    }
+
+    pub fn so_mark(mut self, so_mark: Option<u32>) -> Self {
+        self.so_mark = so_mark;
+        self
+    }
+
+    pub fn dscp(mut self, dscp: Option<u8>) -> Self {
+        self.dscp = dscp;
+        self
+    }
+
+    pub fn freebind(mut self, freebind: bool) -> Self {
+        self.freebind = freebind;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn keepalive(mut self, keepalive: bool) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    pub fn fast_open(mut self, fast_open: bool) -> Self {
+        self.fast_open = fast_open;
+        self
+    }
+
+    pub fn attempt_delay(mut self, delay: Duration) -> Self {
+        self.attempt_delay = delay;
+        self
+    }
+
+    pub fn reuse_address(mut self, reuse_address: bool) -> Self {
+        self.reuse_address = reuse_address;
+        self
+    }
+
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    pub fn send_buffer(mut self, size: Option<usize>) -> Self {
+        self.send_buffer = size;
+        self
+    }
+
+    pub fn recv_buffer(mut self, size: Option<usize>) -> Self {
+        self.recv_buffer = size;
+        self
+    }
+
+    pub fn source_ports(mut self, ports: Option<RangeInclusive<u16>>) -> Self {
+        self.source_ports = ports;
+        self
+    }
+}
+
+impl<'a> From<&'a Session> for ConnectOptions<'a> {
+    fn from(sess: &'a Session) -> Self {
+        let opts = Self::new(sess.iface.as_ref(), sess.so_mark);
+        match sess.dscp {
+            Some(dscp) => opts.dscp(Some(dscp)),
+            None => opts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::proxy::utils::new_tcp_stream;
+
+    #[tokio::test]
+    async fn test_with_connect_timeout() {
+        assert_eq!(
+            ConnectOptions::default().connect_timeout,
+            DEFAULT_CONNECT_TIMEOUT
+        );
+
+        let timeout = Duration::from_millis(200);
+        let start = Instant::now();
+        let (opts, res) = with_connect_timeout(timeout, async {
+            // unroutable, the SYN goes unanswered
+            let opts = ConnectOptions::default();
+            let res = new_tcp_stream(([10, 255, 255, 1], 9).into(), &opts).await;
+            (opts.connect_timeout, res)
+        })
+        .await;
+        assert_eq!(opts, timeout);
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < DEFAULT_CONNECT_TIMEOUT / 2);
+    }
+}
@@ -3,9 +3,21 @@ pub mod test_utils;
 
 mod platform;
 
+mod connect_options;
 pub mod provider_helper;
 mod proxy_connector;
+#[cfg(any(feature = "tuic", feature = "hysteria2"))]
+pub mod quic;
 mod socket_helpers;
+#[cfg(unix)]
+mod socket_protector;
+#[cfg(target_os = "linux")]
+pub mod udp_offload;
+mod upstream_error;
 
+pub use connect_options::*;
 pub use proxy_connector::*;
 pub use socket_helpers::*;
+#[cfg(unix)]
+pub use socket_protector::*;
+pub use upstream_error::*;
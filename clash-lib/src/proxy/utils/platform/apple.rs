@@ -1,18 +1,23 @@
-use std::io;
+use std::{ffi::CString, io};
 
 use tracing::warn;
 
 use crate::app::net::OutboundInterface;
 
+/// Binds with IP_BOUND_IF/IPV6_BOUND_IF, which take the index of the
+/// interface. The index is looked up by name when it's unknown.
 pub(crate) fn must_bind_socket_on_interface(
     socket: &socket2::Socket,
     iface: &OutboundInterface,
     family: socket2::Domain,
 ) -> io::Result<()> {
-    let index = iface.index;
+    let index = match iface.index {
+        0 => index_by_name(&iface.name),
+        index => index,
+    };
     if index == 0 {
         warn!(
-            "OutboundInterface index is 0, skipping binding to interface {}",
+            "no index found for interface {}, skipping binding to it",
             iface.name
         );
         return Ok(());
@@ -30,3 +35,11 @@ pub(crate) fn must_bind_socket_on_interface(
         )),
     }
 }
+
+/// `if_nametoindex`, 0 if there's no such interface
+fn index_by_name(name: &str) -> u32 {
+    match CString::new(name) {
+        Ok(name) => unsafe { libc::if_nametoindex(name.as_ptr()) },
+        Err(_) => 0,
+    }
+}
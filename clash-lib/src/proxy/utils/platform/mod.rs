@@ -15,8 +15,34 @@ pub(crate) mod unix;
     target_os = "freebsd",
     target_os = "android"
 ))]
-pub(crate) use unix::must_bind_socket_on_interface;
+pub(crate) use unix::{apply_fwmark, must_bind_socket_on_interface};
 #[cfg(windows)]
 pub(crate) mod win;
 #[cfg(windows)]
 pub(crate) use win::must_bind_socket_on_interface;
+
+/// Routing marks can't be expressed here, e.g. macOS has no SO_MARK, so
+/// the mark is ignored with a warning, once. Binding to an interface is
+/// the way to pick the route on those systems.
+#[cfg(not(any(
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "android"
+)))]
+pub(crate) fn apply_fwmark(
+    _socket: &socket2::Socket,
+    mark: u32,
+) -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "routing mark {mark} ignored, not supported on {}, bind to an \
+             interface instead",
+            std::env::consts::OS
+        );
+    }
+    Ok(())
+}
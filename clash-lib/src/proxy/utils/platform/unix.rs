@@ -2,6 +2,35 @@ use std::io;
 
 use crate::app::net::OutboundInterface;
 
+/// Marks the packets of `socket` for policy routing: SO_MARK on Linux, or
+/// SO_SETFIB on FreeBSD, where the mark is the number of the routing table
+pub(crate) fn apply_fwmark(socket: &socket2::Socket, mark: u32) -> io::Result<()> {
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    {
+        socket.set_mark(mark)
+    }
+    #[cfg(target_os = "freebsd")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let fib = mark as libc::c_int;
+        let rv = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_SETFIB,
+                &fib as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if rv == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
 pub(crate) fn must_bind_socket_on_interface(
     #[allow(unused_variables)] socket: &socket2::Socket,
     iface: &OutboundInterface,
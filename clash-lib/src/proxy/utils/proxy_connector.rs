@@ -6,7 +6,7 @@ use std::{
 };
 use tracing::trace;
 
-use super::{new_tcp_stream, new_udp_socket};
+use super::{ConnectOptions, new_tcp_stream, new_udp_socket};
 use crate::{
     app::{
         dispatcher::{
@@ -14,7 +14,6 @@ use crate::{
             ChainedStreamWrapper,
         },
         dns::ThreadSafeDNSResolver,
-        net::OutboundInterface,
     },
     common::errors::new_io_error,
     proxy::{
@@ -32,8 +31,7 @@ pub trait RemoteConnector: Send + Sync + Debug {
         resolver: ThreadSafeDNSResolver,
         address: &str,
         port: u16,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] packet_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyStream>;
 
     async fn connect_datagram(
@@ -41,8 +39,7 @@ pub trait RemoteConnector: Send + Sync + Debug {
         resolver: ThreadSafeDNSResolver,
         src: Option<SocketAddr>,
         destination: SocksAddr,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] packet_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyOutboundDatagram>;
 }
 
@@ -69,8 +66,7 @@ impl RemoteConnector for DirectConnector {
         resolver: ThreadSafeDNSResolver,
         address: &str,
         port: u16,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] so_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyStream> {
         let dial_addr = resolver
             .resolve(address, false)
@@ -78,14 +74,9 @@ impl RemoteConnector for DirectConnector {
             .map_err(|v| new_io_error(format!("can't resolve dns: {v}")))?
             .ok_or(new_io_error("no dns result"))?;
 
-        new_tcp_stream(
-            (dial_addr, port).into(),
-            iface,
-            #[cfg(target_os = "linux")]
-            so_mark,
-        )
-        .await
-        .map(|x| Box::new(x) as _)
+        new_tcp_stream((dial_addr, port).into(), opts)
+            .await
+            .map(|x| Box::new(x) as _)
     }
 
     async fn connect_datagram(
@@ -93,20 +84,17 @@ impl RemoteConnector for DirectConnector {
         resolver: ThreadSafeDNSResolver,
         src: Option<SocketAddr>,
         destination: SocksAddr,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] so_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyOutboundDatagram> {
         let dgram = new_udp_socket(
             src,
-            iface,
-            #[cfg(target_os = "linux")]
-            so_mark,
             destination
                 .ip()
                 .map(|ip| SocketAddr::new(ip, destination.port())),
+            opts,
         )
         .await
-        .map(|x| OutboundDatagramImpl::new(x, resolver))?;
+        .map(|x| OutboundDatagramImpl::new(x, resolver).with_iface(opts.iface))?;
 
         let dgram = ChainedDatagramWrapper::new(dgram);
         Ok(Box::new(dgram))
@@ -143,16 +131,15 @@ impl RemoteConnector for ProxyConnector {
         resolver: ThreadSafeDNSResolver,
         address: &str,
         port: u16,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] so_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyStream> {
         let sess = Session {
             network: Network::Tcp,
             typ: Type::Ignore,
             destination: SocksAddr::Domain(address.to_owned(), port),
-            iface: iface.cloned(),
-            #[cfg(target_os = "linux")]
-            so_mark,
+            iface: opts.iface.cloned(),
+            so_mark: opts.so_mark,
+            dscp: opts.dscp,
             ..Default::default()
         };
 
@@ -178,16 +165,15 @@ impl RemoteConnector for ProxyConnector {
         resolver: ThreadSafeDNSResolver,
         _src: Option<SocketAddr>,
         destination: SocksAddr,
-        iface: Option<&OutboundInterface>,
-        #[cfg(target_os = "linux")] so_mark: Option<u32>,
+        opts: &ConnectOptions<'_>,
     ) -> std::io::Result<AnyOutboundDatagram> {
         let sess = Session {
             network: Network::Udp,
             typ: Type::Ignore,
-            iface: iface.cloned(),
+            iface: opts.iface.cloned(),
             destination: destination.clone(),
-            #[cfg(target_os = "linux")]
-            so_mark,
+            so_mark: opts.so_mark,
+            dscp: opts.dscp,
             ..Default::default()
         };
         let s = self
@@ -204,3 +190,154 @@ impl RemoteConnector for ProxyConnector {
         Ok(Box::new(stream))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::{SinkExt, StreamExt};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, UdpSocket},
+    };
+
+    use super::*;
+    use crate::{
+        app::dns::MockClashResolver,
+        config::internal::proxy::PROXY_DIRECT,
+        proxy::{datagram::UdpPacket, direct},
+    };
+
+    fn resolver() -> ThreadSafeDNSResolver {
+        let mut resolver = MockClashResolver::new();
+        resolver
+            .expect_resolve()
+            .returning(|_, _| Ok(Some([127, 0, 0, 1].into())));
+        Arc::new(resolver)
+    }
+
+    /// Records the options it's asked to dial with and fails
+    #[derive(Debug)]
+    struct Recorder(Arc<Mutex<Vec<(String, Option<u32>, Option<u8>)>>>);
+
+    impl Recorder {
+        fn record(&self, dst: String, opts: &ConnectOptions<'_>) -> std::io::Error {
+            self.0.lock().unwrap().push((dst, opts.so_mark, opts.dscp));
+            new_io_error("recorded")
+        }
+    }
+
+    #[async_trait]
+    impl RemoteConnector for Recorder {
+        async fn connect_stream(
+            &self,
+            _resolver: ThreadSafeDNSResolver,
+            address: &str,
+            port: u16,
+            opts: &ConnectOptions<'_>,
+        ) -> std::io::Result<AnyStream> {
+            Err(self.record(format!("{address}:{port}"), opts))
+        }
+
+        async fn connect_datagram(
+            &self,
+            _resolver: ThreadSafeDNSResolver,
+            _src: Option<SocketAddr>,
+            destination: SocksAddr,
+            opts: &ConnectOptions<'_>,
+        ) -> std::io::Result<AnyOutboundDatagram> {
+            Err(self.record(destination.to_string(), opts))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_direct_connector_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4];
+            s.read_exact(&mut buf).await.unwrap();
+            s.write_all(&buf).await.unwrap();
+        });
+
+        let mut s = DirectConnector::new()
+            .connect_stream(
+                resolver(),
+                "localhost",
+                port,
+                &ConnectOptions::default(),
+            )
+            .await
+            .unwrap();
+        s.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        s.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_direct_connector_datagram() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let mut d = DirectConnector::new()
+            .connect_datagram(
+                resolver(),
+                None,
+                server_addr.into(),
+                &ConnectOptions::default(),
+            )
+            .await
+            .unwrap();
+        d.send(UdpPacket {
+            data: b"ping".to_vec(),
+            dst_addr: server_addr.into(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut buf = [0; 16];
+        let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"ping");
+        server.send_to(b"pong", peer).await.unwrap();
+
+        let pkt = d.next().await.unwrap();
+        assert_eq!(pkt.data, b"pong");
+        assert_eq!(pkt.src_addr, SocksAddr::from(server_addr));
+    }
+
+    #[tokio::test]
+    async fn test_proxy_connector_passes_options() {
+        let recorded = Arc::new(Mutex::new(vec![]));
+        let connector = ProxyConnector::new(
+            Arc::new(direct::Handler::new(PROXY_DIRECT)),
+            Box::new(Recorder(recorded.clone())),
+        );
+        let opts = ConnectOptions::default().so_mark(Some(0x42)).dscp(Some(46));
+
+        connector
+            .connect_stream(resolver(), "example.com", 443, &opts)
+            .await
+            .unwrap_err();
+        connector
+            .connect_datagram(
+                resolver(),
+                None,
+                SocketAddr::from(([1, 1, 1, 1], 53)).into(),
+                &opts,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            vec![
+                ("example.com:443".to_owned(), Some(0x42), Some(46)),
+                ("1.1.1.1:53".to_owned(), Some(0x42), Some(46)),
+            ]
+        );
+    }
+}
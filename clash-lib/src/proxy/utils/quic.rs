@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use quinn::congestion::{BbrConfig, ControllerFactory, CubicConfig, NewRenoConfig};
+
+/// Size in bytes of the first QUIC packets, before path MTU discovery
+/// raises it, the same as quic-go's
+pub const DEFAULT_INITIAL_MTU: u16 = 1252;
+/// Receive windows of the Go implementations, 8MiB per stream and 20MiB per
+/// connection
+pub const DEFAULT_STREAM_RECEIVE_WINDOW: u64 = 8 * 1024 * 1024;
+pub const DEFAULT_CONNECTION_RECEIVE_WINDOW: u64 =
+    DEFAULT_STREAM_RECEIVE_WINDOW * 5 / 2;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionControl {
+    Cubic,
+    NewReno,
+    #[default]
+    Bbr,
+}
+
+impl CongestionControl {
+    pub fn factory(&self) -> Arc<dyn ControllerFactory + Send + Sync> {
+        match self {
+            Self::Cubic => Arc::new(CubicConfig::default()),
+            Self::NewReno => Arc::new(NewRenoConfig::default()),
+            Self::Bbr => Arc::new(BbrConfig::default()),
+        }
+    }
+}
+
+impl From<&str> for CongestionControl {
+    fn from(s: &str) -> Self {
+        if s.eq_ignore_ascii_case("cubic") {
+            Self::Cubic
+        } else if s.eq_ignore_ascii_case("new_reno")
+            || s.eq_ignore_ascii_case("newreno")
+        {
+            Self::NewReno
+        } else if s.eq_ignore_ascii_case("bbr") {
+            Self::Bbr
+        } else {
+            tracing::warn!(
+                "Unknown congestion controller {s}. Use default controller"
+            );
+            Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_congestion_control_from_str() {
+        assert_eq!(CongestionControl::from("CUBIC"), CongestionControl::Cubic);
+        assert_eq!(
+            CongestionControl::from("newreno"),
+            CongestionControl::NewReno
+        );
+        assert_eq!(
+            CongestionControl::from("new_reno"),
+            CongestionControl::NewReno
+        );
+        assert_eq!(CongestionControl::from("unknown"), CongestionControl::Bbr);
+    }
+}
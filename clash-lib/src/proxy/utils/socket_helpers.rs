@@ -1,46 +1,83 @@
-use super::platform::must_bind_socket_on_interface;
+#[cfg(unix)]
+use super::protect_socket_async;
+use super::{
+    ConnectOptions, TcpKeepaliveConfig,
+    platform::{apply_fwmark, must_bind_socket_on_interface},
+};
 use crate::{
-    app::{dns::ThreadSafeDNSResolver, net::OutboundInterface},
+    app::{
+        dns::ThreadSafeDNSResolver,
+        net::{
+            OutboundInterface, bogon_policy, ipv6_disabled, is_bogon,
+            missing_outbound_interface,
+        },
+    },
+    config::def::BogonPolicy,
+    proxy::datagram::with_link_local_scope,
     session::Session,
 };
 
-use futures::io;
+use futures::{StreamExt, io, stream::FuturesUnordered};
 use socket2::TcpKeepalive;
-use std::{net::SocketAddr, time::Duration};
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    ops::RangeInclusive,
+};
 use tokio::{
     net::{TcpListener, TcpSocket, TcpStream, UdpSocket},
     time::timeout,
 };
-use tracing::{debug, error, instrument, trace};
+use tracing::{debug, error, instrument, trace, warn};
 
-pub fn apply_tcp_options(s: &TcpStream) -> std::io::Result<()> {
-    #[cfg(not(target_os = "windows"))]
-    {
-        let s = socket2::SockRef::from(s);
-        s.set_tcp_keepalive(
-            &TcpKeepalive::new()
-                .with_time(Duration::from_secs(10))
-                .with_interval(Duration::from_secs(1))
-                .with_retries(3),
-        )
+/// Sets the keepalive `opts` on an accepted inbound connection, usually
+/// those of `tcp-keepalive` from [`super::tcp_options`]
+pub fn apply_tcp_options(
+    s: &TcpStream,
+    opts: &TcpKeepaliveConfig,
+) -> std::io::Result<()> {
+    let sock = socket2::SockRef::from(s);
+    if !opts.enabled {
+        return sock.set_keepalive(false);
     }
+    let keepalive = TcpKeepalive::new()
+        .with_time(opts.time)
+        .with_interval(opts.interval);
+    #[cfg(not(target_os = "windows"))]
+    let keepalive = keepalive.with_retries(opts.retries);
     #[cfg(target_os = "windows")]
-    {
-        let s = socket2::SockRef::from(s);
-        s.set_tcp_keepalive(
-            &TcpKeepalive::new()
-                .with_time(Duration::from_secs(10))
-                .with_interval(Duration::from_secs(1)),
-        )
-    }
+    debug!(
+        "ignoring keepalive retries {}, not supported on windows",
+        opts.retries
+    );
+    sock.set_tcp_keepalive(&keepalive)
 }
 
-#[instrument(skip(so_mark))]
+fn ipv6_disabled_error(addr: SocketAddr) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!("ipv6 is disabled, not connecting to {addr}"),
+    )
+}
+
+#[instrument(skip(opts))]
 pub async fn new_tcp_stream(
     endpoint: SocketAddr,
-    iface: Option<&OutboundInterface>,
-    #[cfg(target_os = "linux")] so_mark: Option<u32>,
+    opts: &ConnectOptions<'_>,
 ) -> std::io::Result<TcpStream> {
+    if is_bogon(&endpoint.ip()) && bogon_policy() == BogonPolicy::Block {
+        warn!("blocked connection to bogon address {}", endpoint);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("connection to bogon address {endpoint} is blocked"),
+        ));
+    }
+    if endpoint.is_ipv6() && ipv6_disabled() {
+        return Err(ipv6_disabled_error(endpoint));
+    }
+
     let (socket, family) = match endpoint {
         SocketAddr::V4(_) => (
             socket2::Socket::new(
@@ -60,41 +97,189 @@ pub async fn new_tcp_stream(
         ),
     };
     debug!("created tcp socket");
+    #[cfg(unix)]
+    protect_socket_async(socket.as_raw_fd()).await?;
+    set_reuse(&socket, opts.reuse_address, opts.reuse_port)?;
+
+    if opts.iface.is_none() {
+        refuse_unbound()?;
+    }
 
-    if !cfg!(target_os = "android")
-        && let Some(iface) = iface
+    #[cfg(target_os = "linux")]
+    if wants_freebind(opts) {
+        set_freebind(&socket, family)?;
+    }
+
+    if let Some(iface) = opts.iface
+        && should_bind_interface(iface)
     {
-        must_bind_socket_on_interface(&socket, iface, family)?;
+        bind_device(&socket, iface, family)?;
+        bind_interface_addr(&socket, iface, family)?;
         trace!("tcp socket bound to interface: {socket:?}");
     }
 
-    #[cfg(not(target_os = "android"))]
-    #[cfg(target_os = "linux")]
-    if let Some(so_mark) = so_mark {
-        socket.set_mark(so_mark)?;
+    if let Some(so_mark) = opts.so_mark {
+        apply_fwmark(&socket, so_mark)?;
     }
 
-    socket.set_keepalive(true)?;
-    socket.set_tcp_nodelay(true)?;
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    if let Some(dscp) = opts.dscp {
+        set_dscp(&socket, family, dscp)?;
+    }
+
+    socket.set_keepalive(opts.keepalive)?;
+    socket.set_tcp_nodelay(opts.nodelay)?;
+    if opts.fast_open {
+        set_tcp_fastopen_connect(&socket);
+    }
     socket.set_nonblocking(true)?;
 
+    // told apart from a refused connection by its kind
     timeout(
-        Duration::from_secs(10),
+        opts.connect_timeout,
         TcpSocket::from_std_stream(socket.into()).connect(endpoint),
     )
-    .await?
+    .await
+    .map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!(
+                "connect to {endpoint} timed out after {:?}",
+                opts.connect_timeout
+            ),
+        )
+    })?
 }
 
-#[instrument(skip(so_mark))]
+/// Connects to the first of `addrs` to accept, Happy Eyeballs style (RFC
+/// 8305), so that a black-holed family doesn't hold the connection for the
+/// whole connect timeout.
+///
+/// The address families are interleaved, starting with the family of the
+/// first address. Each attempt starts once the previous one failed, or
+/// after [`ConnectOptions::attempt_delay`], with the same `opts`. The attempts
+/// still pending once one connects are dropped, closing their sockets.
+/// Once all failed, the error lists the error of each address.
+pub async fn new_tcp_stream_racing(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    opts: &ConnectOptions<'_>,
+) -> std::io::Result<TcpStream> {
+    let mut addrs = interleave_families(addrs.into_iter().collect()).into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut errors = Vec::new();
+
+    loop {
+        if let Some(addr) = addrs.next() {
+            attempts.push(async move { (addr, new_tcp_stream(addr, opts).await) });
+        }
+        let delay = tokio::time::sleep(opts.attempt_delay);
+        tokio::pin!(delay);
+
+        loop {
+            tokio::select! {
+                Some(rv) = attempts.next() => match rv {
+                    (_, Ok(stream)) => return Ok(stream),
+                    (addr, Err(e)) => {
+                        debug!("connection attempt to {addr} failed: {e}");
+                        errors.push((addr, e));
+                        if addrs.len() > 0 {
+                            break;
+                        }
+                    }
+                },
+                _ = &mut delay, if addrs.len() > 0 => break,
+                else => return Err(connect_errors(errors)),
+            }
+        }
+    }
+}
+
+/// One error for the failed attempts of [`new_tcp_stream_racing`], of the
+/// kind of the last one, e.g. for timeouts to be told apart
+fn connect_errors(mut errors: Vec<(SocketAddr, std::io::Error)>) -> std::io::Error {
+    match errors.len() {
+        0 => std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "no address to connect to",
+        ),
+        1 => errors.pop().unwrap().1,
+        n => {
+            let kind = errors[n - 1].1.kind();
+            let all = errors
+                .iter()
+                .map(|(addr, e)| format!("{addr}: {e}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            std::io::Error::new(kind, format!("all {n} addresses failed: {all}"))
+        }
+    }
+}
+
+/// Connects to the first of the A and AAAA results `addrs` of an endpoint
+/// to accept, like [`new_tcp_stream_racing`] but always starting with IPv6,
+/// which so gets a [`ConnectOptions::attempt_delay`] head start over IPv4 as
+/// RFC 8305 recommends, whatever order the lookups finished in.
+pub async fn new_tcp_stream_happy_eyeballs(
+    addrs: &[SocketAddr],
+    opts: &ConnectOptions<'_>,
+) -> std::io::Result<TcpStream> {
+    new_tcp_stream_racing(ipv6_first(addrs), opts).await
+}
+
+/// Moves the first IPv6 address in front, keeping the rest in order
+fn ipv6_first(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let mut addrs = addrs.to_vec();
+    if let Some(i) = addrs.iter().position(|x| x.is_ipv6()) {
+        addrs[..=i].rotate_right(1);
+    }
+    addrs
+}
+
+/// Alternates IPv4 and IPv6 addresses, starting with the family of the
+/// first, keeping the order within each family
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first_v6) = addrs.first().map(|x| x.is_ipv6()) else {
+        return addrs;
+    };
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) =
+        addrs.into_iter().partition(|x| x.is_ipv6() == first_v6);
+    let mut rv = Vec::with_capacity(first.len() + second.len());
+    while !first.is_empty() || !second.is_empty() {
+        rv.extend(first.pop_front());
+        rv.extend(second.pop_front());
+    }
+    rv
+}
+
+#[instrument(skip(opts))]
 pub async fn new_udp_socket(
     src: Option<SocketAddr>,
-    iface: Option<&OutboundInterface>,
-    #[cfg(target_os = "linux")] so_mark: Option<u32>,
     // Optional family hint for the socket.
     // If not provided, the family will be determined based on the source
     // address or interface.
     family_hint: Option<std::net::SocketAddr>,
+    opts: &ConnectOptions<'_>,
 ) -> std::io::Result<UdpSocket> {
+    let iface = opts.iface;
+    if iface.is_none() && src.is_none() {
+        refuse_unbound()?;
+    }
+    let src = match src {
+        // the wildcard is only asked for to get a dual stack socket
+        Some(src) if src.is_ipv6() && ipv6_disabled() => {
+            if !src.ip().is_unspecified() {
+                return Err(ipv6_disabled_error(src));
+            }
+            Some(SocketAddr::from((Ipv4Addr::UNSPECIFIED, src.port())))
+        }
+        src => src,
+    };
+    if let Some(hint) = family_hint
+        && hint.is_ipv6()
+        && ipv6_disabled()
+    {
+        return Err(ipv6_disabled_error(hint));
+    }
     // Determine the socket family based on the source address or interface
     // logic:
     // - If family_hint is provided, use it.
@@ -123,46 +308,389 @@ pub async fn new_udp_socket(
         ),
     };
     debug!("created udp socket");
+    #[cfg(unix)]
+    protect_socket_async(socket.as_raw_fd()).await?;
 
-    if !cfg!(target_os = "android") {
-        match (src, iface) {
+    #[cfg(target_os = "linux")]
+    if wants_freebind(opts) {
+        set_freebind(&socket, family)?;
+    }
+
+    set_reuse(&socket, opts.reuse_address, opts.reuse_port)?;
+
+    let bound_iface = iface.filter(|x| should_bind_interface(x));
+    if !cfg!(target_os = "android") || bound_iface.is_some() {
+        match (src, bound_iface) {
             (_, Some(iface)) => {
-                must_bind_socket_on_interface(&socket, iface, family).inspect_err(
-                    |x| {
-                        error!("failed to bind socket to interface: {}", x);
-                    },
-                )?;
-                // binding is not necessary for linux but is required on windows
-                // Without binding local_addr can't be obtained by system call
-                // which is required on quinn.
-                #[cfg(target_os = "windows")]
-                if let Some(addr) = src {
-                    socket.bind(&socket2::SockAddr::from(addr))?;
+                bind_device(&socket, iface, family).inspect_err(|x| {
+                    error!("failed to bind socket to interface: {}", x);
+                })?;
+                if let Some(ports) = &opts.source_ports {
+                    let ip = iface
+                        .bind_addr
+                        .filter(|x| x.is_ipv6() == (family == socket2::Domain::IPV6))
+                        .or(src.map(|x| x.ip()))
+                        .unwrap_or_else(|| unspecified_addr(family));
+                    let addr = with_link_local_scope(SocketAddr::new(ip, 0), || {
+                        iface.index
+                    });
+                    bind_port_in_range(&socket, addr, ports)?;
+                } else if !bind_interface_addr(&socket, iface, family)? {
+                    // binding is not necessary for linux but is required on
+                    // windows Without binding local_addr can't be obtained by
+                    // system call which is required on quinn.
+                    #[cfg(target_os = "windows")]
+                    if let Some(addr) = src {
+                        socket.bind(&socket2::SockAddr::from(addr))?;
+                    }
                 }
 
                 trace!(iface = ?iface, "udp socket bound: {socket:?}");
             }
             (Some(src), None) => {
-                socket.bind(&src.into())?;
+                match &opts.source_ports {
+                    Some(ports) if src.port() == 0 => {
+                        bind_port_in_range(&socket, src, ports)?
+                    }
+                    _ => socket.bind(&src.into())?,
+                }
                 trace!(src = ?src, "udp socket bound: {socket:?}");
             }
-            (None, None) => {
-                trace!("udp socket not bound to any specific address: {socket:?}");
-            }
+            (None, None) => match &opts.source_ports {
+                Some(ports) => {
+                    let addr = SocketAddr::new(unspecified_addr(family), 0);
+                    bind_port_in_range(&socket, addr, ports)?;
+                    trace!("udp socket bound in {ports:?}: {socket:?}");
+                }
+                None => {
+                    trace!(
+                        "udp socket not bound to any specific address: {socket:?}"
+                    );
+                }
+            },
         }
     }
 
-    #[cfg(target_os = "linux")]
-    if let Some(so_mark) = so_mark {
-        socket.set_mark(so_mark)?;
+    if let Some(so_mark) = opts.so_mark {
+        apply_fwmark(&socket, so_mark)?;
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    if let Some(dscp) = opts.dscp {
+        set_dscp(&socket, family, dscp)?;
     }
 
+    set_buffer_sizes(&socket, opts)?;
     socket.set_broadcast(true)?;
     socket.set_nonblocking(true)?;
 
     UdpSocket::from_std(socket.into())
 }
 
+/// Creates a non-blocking raw ICMP socket of the family of `server`. Raw
+/// sockets need root or CAP_NET_RAW, the error says so when they're denied.
+#[instrument(skip(opts))]
+pub async fn new_icmp_socket(
+    server: IpAddr,
+    opts: &ConnectOptions<'_>,
+) -> std::io::Result<socket2::Socket> {
+    let iface = opts.iface;
+    if iface.is_none() {
+        refuse_unbound()?;
+    }
+    if server.is_ipv6() && ipv6_disabled() {
+        return Err(ipv6_disabled_error(SocketAddr::new(server, 0)));
+    }
+    let (family, protocol) = match server {
+        IpAddr::V4(_) => (socket2::Domain::IPV4, socket2::Protocol::ICMPV4),
+        IpAddr::V6(_) => (socket2::Domain::IPV6, socket2::Protocol::ICMPV6),
+    };
+    let socket = socket2::Socket::new(family, socket2::Type::RAW, Some(protocol))
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!(
+                    "raw ICMP sockets need root or CAP_NET_RAW, e.g. `setcap \
+                     cap_net_raw+ep` on the binary: {e}"
+                ),
+            ),
+            _ => e,
+        })?;
+    debug!("created icmp socket");
+    #[cfg(unix)]
+    protect_socket_async(socket.as_raw_fd()).await?;
+
+    #[cfg(target_os = "linux")]
+    if wants_freebind(opts) {
+        set_freebind(&socket, family)?;
+    }
+
+    if let Some(iface) = iface
+        && should_bind_interface(iface)
+    {
+        bind_device(&socket, iface, family).inspect_err(|x| {
+            error!("failed to bind socket to interface: {}", x);
+        })?;
+        bind_interface_addr(&socket, iface, family)?;
+    }
+
+    if let Some(so_mark) = opts.so_mark {
+        apply_fwmark(&socket, so_mark)?;
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    if let Some(dscp) = opts.dscp {
+        set_dscp(&socket, family, dscp)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Enables TCP Fast Open on a socket about to listen, so that clients can
+/// send data along with their SYN. `queue` is the maximum number of pending
+/// TFO requests, it can't be set on macOS. Failures are logged only, the
+/// listener works without TFO.
+pub fn set_tcp_fastopen(socket: &socket2::Socket, queue: u32) {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+    {
+        #[cfg(target_os = "macos")]
+        let value: libc::c_int = {
+            let _ = queue;
+            1
+        };
+        #[cfg(not(target_os = "macos"))]
+        let value = queue.min(libc::c_int::MAX as u32) as libc::c_int;
+
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_FASTOPEN,
+                &value as *const _ as *const _,
+                std::mem::size_of_val(&value) as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            warn!(
+                "failed to enable TCP Fast Open on listener: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos"
+    )))]
+    {
+        let _ = (socket, queue);
+        warn!("TCP Fast Open on listeners is not supported on this platform");
+    }
+}
+
+/// Enables TCP Fast Open on a socket about to connect: the connect returns
+/// right away, and the first write goes with the SYN, along with the cookie
+/// of the server if there is one. Failures are logged only, the connection
+/// is made without TFO.
+fn set_tcp_fastopen_connect(socket: &socket2::Socket) {
+    #[cfg(target_os = "linux")]
+    {
+        let value: libc::c_int = 1;
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_FASTOPEN_CONNECT,
+                &value as *const _ as *const _,
+                std::mem::size_of_val(&value) as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            debug!(
+                "failed to enable TCP Fast Open on outbound socket: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = socket;
+        trace!(
+            "TCP Fast Open on outbound sockets is not supported on this platform"
+        );
+    }
+}
+
+/// Fails when sockets must go through an outbound interface that could not
+/// be found, instead of silently using the default routes.
+fn refuse_unbound() -> std::io::Result<()> {
+    match missing_outbound_interface() {
+        Some(iface) => Err(std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            format!("outbound interface {iface} is unavailable"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// SO_REUSEADDR and SO_REUSEPORT, to be set before binding.
+fn set_reuse(
+    socket: &socket2::Socket,
+    reuse_address: bool,
+    reuse_port: bool,
+) -> std::io::Result<()> {
+    if reuse_address {
+        socket.set_reuse_address(true)?;
+    }
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+    if reuse_port {
+        trace!("SO_REUSEPORT is not supported on this platform, ignored");
+    }
+    Ok(())
+}
+
+/// Binds to `addr` on the first port of `ports` not in use, with an error of
+/// kind `AddrInUse` once there's none left
+fn bind_port_in_range(
+    socket: &socket2::Socket,
+    mut addr: SocketAddr,
+    ports: &RangeInclusive<u16>,
+) -> std::io::Result<()> {
+    for port in ports.clone() {
+        addr.set_port(port);
+        match socket.bind(&addr.into()) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AddrInUse,
+        format!(
+            "no free port in {}-{} on {}",
+            ports.start(),
+            ports.end(),
+            addr.ip()
+        ),
+    ))
+}
+
+fn unspecified_addr(family: socket2::Domain) -> IpAddr {
+    if family == socket2::Domain::IPV6 {
+        Ipv6Addr::UNSPECIFIED.into()
+    } else {
+        Ipv4Addr::UNSPECIFIED.into()
+    }
+}
+
+/// SO_SNDBUF and SO_RCVBUF, when set. The kernel may grant a different
+/// size, e.g. Linux doubles it and caps it at `wmem_max`/`rmem_max`.
+fn set_buffer_sizes(
+    socket: &socket2::Socket,
+    opts: &ConnectOptions<'_>,
+) -> std::io::Result<()> {
+    if let Some(size) = opts.send_buffer {
+        socket.set_send_buffer_size(size)?;
+        debug!(
+            "udp send buffer size {size} requested, {} granted",
+            socket.send_buffer_size()?
+        );
+    }
+    if let Some(size) = opts.recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        debug!(
+            "udp recv buffer size {size} requested, {} granted",
+            socket.recv_buffer_size()?
+        );
+    }
+    Ok(())
+}
+
+/// Whether to bind a socket to `iface`, which is always done except on
+/// Android. There, sockets are protected first with the
+/// [`SocketProtector`](super::SocketProtector) if one is set, i.e.
+/// `VpnService.protect()`, which lets the system route them out of the VPN,
+/// so only an interface asked for by name is bound to, with
+/// SO_BINDTODEVICE, after the socket was protected.
+fn should_bind_interface(iface: &OutboundInterface) -> bool {
+    !cfg!(target_os = "android") || iface.by_name
+}
+
+/// Binds the socket to the device of `iface`, unless it's a
+/// [`freebind_source`](crate::app::net::freebind_source) without one.
+fn bind_device(
+    socket: &socket2::Socket,
+    iface: &OutboundInterface,
+    family: socket2::Domain,
+) -> std::io::Result<()> {
+    if iface.freebind {
+        return Ok(());
+    }
+    must_bind_socket_on_interface(socket, iface, family)
+}
+
+/// Whether to set `IP_FREEBIND`, which a source address no interface has
+/// needs to be bound to.
+#[cfg(target_os = "linux")]
+fn wants_freebind(opts: &ConnectOptions<'_>) -> bool {
+    opts.freebind || opts.iface.is_some_and(|x| x.freebind)
+}
+
+/// Binds the address `iface` was picked by, if it's of the socket's family,
+/// so that traffic leaves from that address rather than the primary one of
+/// the interface. Returns whether the socket was bound.
+fn bind_interface_addr(
+    socket: &socket2::Socket,
+    iface: &OutboundInterface,
+    family: socket2::Domain,
+) -> std::io::Result<bool> {
+    match iface.bind_addr {
+        Some(ip) if ip.is_ipv6() == (family == socket2::Domain::IPV6) => {
+            // link-local addresses need the zone of their interface
+            let addr = with_link_local_scope(SocketAddr::new(ip, 0), || iface.index);
+            socket.bind(&addr.into())?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Set the DSCP bits, i.e. the upper 6 bits, of `IP_TOS`/`IPV6_TCLASS`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn set_dscp(
+    socket: &socket2::Socket,
+    family: socket2::Domain,
+    dscp: u8,
+) -> std::io::Result<()> {
+    let tos = u32::from(dscp) << 2;
+    if family == socket2::Domain::IPV6 {
+        socket.set_tclass_v6(tos)
+    } else {
+        socket.set_tos_v4(tos)
+    }
+}
+
+/// Set `IP_FREEBIND`/`IPV6_FREEBIND` so that the socket may bind to a source
+/// address not present on the host.
+/// Must be called before `bind`.
+#[cfg(target_os = "linux")]
+fn set_freebind(
+    socket: &socket2::Socket,
+    family: socket2::Domain,
+) -> std::io::Result<()> {
+    if family == socket2::Domain::IPV6 {
+        socket.set_freebind_v6(true)
+    } else {
+        socket.set_freebind_v4(true)
+    }
+    .inspect_err(|e| error!("failed to set IP_FREEBIND: {e}"))
+}
+
 pub async fn family_hint_for_session(
     sess: &Session,
     resolver: &ThreadSafeDNSResolver,
@@ -222,18 +750,345 @@ pub fn try_create_dualstack_socket(
     Ok((socket, dualstack))
 }
 
+/// `tfo` enables TCP Fast Open with the given queue length, `reuse_port`
+/// lets other sockets listen on the same port, ignored on Windows
 pub fn try_create_dualstack_tcplistener(
     addr: SocketAddr,
+    tfo: Option<u32>,
+    reuse_port: bool,
 ) -> io::Result<TcpListener> {
     let (socket, _dualstack) =
         try_create_dualstack_socket(addr, socket2::Type::STREAM)?;
 
     socket.set_nonblocking(true)?;
     // For fast restart avoid Address In Use Error
-    socket.set_reuse_address(true)?;
+    set_reuse(&socket, true, reuse_port)?;
     socket.bind(&addr.into())?;
+    if let Some(queue) = tfo {
+        set_tcp_fastopen(&socket, queue);
+    }
     socket.listen(1024)?;
 
     let listener = TcpListener::from_std(socket.into())?;
     Ok(listener)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv6Addr, SocketAddr},
+        time::{Duration, Instant},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time::timeout,
+    };
+
+    use super::{
+        ConnectOptions, bind_interface_addr, interleave_families, ipv6_first,
+        new_tcp_stream, new_tcp_stream_happy_eyeballs, new_tcp_stream_racing,
+        new_udp_socket, try_create_dualstack_tcplistener,
+    };
+    use crate::app::net::Interface;
+
+    #[test]
+    fn test_interleave_families() {
+        let addrs: Vec<SocketAddr> = vec![
+            "[2001:db8::1]:443".parse().unwrap(),
+            "[2001:db8::2]:443".parse().unwrap(),
+            "[2001:db8::3]:443".parse().unwrap(),
+            "192.0.2.1:443".parse().unwrap(),
+        ];
+        assert_eq!(
+            interleave_families(addrs.clone()),
+            vec![addrs[0], addrs[3], addrs[1], addrs[2]]
+        );
+        assert!(interleave_families(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_ipv6_first() {
+        let addrs: Vec<SocketAddr> = vec![
+            "192.0.2.1:443".parse().unwrap(),
+            "192.0.2.2:443".parse().unwrap(),
+            "[2001:db8::1]:443".parse().unwrap(),
+            "[2001:db8::2]:443".parse().unwrap(),
+        ];
+        assert_eq!(
+            interleave_families(ipv6_first(&addrs)),
+            vec![addrs[2], addrs[0], addrs[3], addrs[1]]
+        );
+        assert_eq!(ipv6_first(&addrs[..2]), addrs[..2]);
+    }
+
+    #[test]
+    fn test_bind_link_local_with_scope() {
+        use network_interface::{Addr, NetworkInterface, NetworkInterfaceConfig};
+
+        let Some((name, ip)) = NetworkInterface::show()
+            .unwrap_or_default()
+            .into_iter()
+            .find_map(|iface| {
+                let ip = iface.addr.iter().find_map(|addr| match addr {
+                    Addr::V6(v6) if v6.ip.is_unicast_link_local() => Some(v6.ip),
+                    _ => None,
+                })?;
+                Some((iface.name, ip))
+            })
+        else {
+            // no link-local address to bind on this host
+            return;
+        };
+
+        let iface = format!("{ip}%{name}")
+            .parse::<Interface>()
+            .unwrap()
+            .resolve()
+            .expect("the interface owns the address");
+        let socket =
+            socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::DGRAM, None)
+                .unwrap();
+        assert!(
+            bind_interface_addr(&socket, &iface, socket2::Domain::IPV6).unwrap()
+        );
+
+        let local = socket.local_addr().unwrap().as_socket_ipv6().unwrap();
+        assert_eq!(*local.ip(), ip);
+        assert_eq!(local.scope_id(), iface.index);
+    }
+
+    #[tokio::test]
+    async fn test_racing_past_unroutable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let good = listener.local_addr().unwrap();
+        // non-routable, the SYN goes unanswered or fails right away
+        let unroutable: SocketAddr = "10.255.255.1:9".parse().unwrap();
+
+        let opts = ConnectOptions::default();
+        let started = Instant::now();
+        let stream = new_tcp_stream_racing(vec![unroutable, good], &opts)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < opts.attempt_delay * 2);
+        assert_eq!(stream.peer_addr().unwrap(), good);
+
+        assert!(
+            new_tcp_stream_racing(vec![], &ConnectOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_racing_reports_every_address() {
+        // ports nothing listens on anymore
+        let mut closed = vec![];
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            closed.push(listener.local_addr().unwrap());
+        }
+
+        let e = new_tcp_stream_racing(closed.clone(), &ConnectOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::ConnectionRefused);
+        for addr in closed {
+            assert!(e.to_string().contains(&addr.to_string()));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_dscp() {
+        let opts = ConnectOptions::default().dscp(Some(46));
+        let tos = 46 << 2;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = new_tcp_stream(listener.local_addr().unwrap(), &opts)
+            .await
+            .unwrap();
+        assert_eq!(socket2::SockRef::from(&stream).tos_v4().unwrap(), tos);
+
+        // IPv6 sockets take IPV6_TCLASS rather than IP_TOS
+        let socket = new_udp_socket(None, Some("[::1]:53".parse().unwrap()), &opts)
+            .await
+            .unwrap();
+        assert_eq!(socket2::SockRef::from(&socket).tclass_v6().unwrap(), tos);
+    }
+
+    #[tokio::test]
+    async fn test_udp_source_ports() {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let port =
+            |socket: &tokio::net::UdpSocket| socket.local_addr().unwrap().port();
+
+        let taken = new_udp_socket(Some(any), None, &ConnectOptions::default())
+            .await
+            .unwrap();
+        let opts = ConnectOptions::default()
+            .source_ports(Some(port(&taken)..=port(&taken)));
+        let e = new_udp_socket(Some(any), None, &opts).await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AddrInUse);
+
+        let freed = port(
+            &new_udp_socket(Some(any), None, &ConnectOptions::default())
+                .await
+                .unwrap(),
+        );
+        let opts = ConnectOptions::default().source_ports(Some(freed..=freed));
+        let socket = new_udp_socket(Some(any), None, &opts).await.unwrap();
+        assert_eq!(port(&socket), freed);
+    }
+
+    #[tokio::test]
+    async fn test_udp_buffer_sizes() {
+        let opts = ConnectOptions::default()
+            .send_buffer(Some(65536))
+            .recv_buffer(Some(65536));
+        let socket =
+            new_udp_socket(Some("127.0.0.1:0".parse().unwrap()), None, &opts)
+                .await
+                .unwrap();
+        let socket = socket2::SockRef::from(&socket);
+        assert!(socket.send_buffer_size().unwrap() >= 65536);
+        assert!(socket.recv_buffer_size().unwrap() >= 65536);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_reuse_port() {
+        let opts = ConnectOptions::default().reuse_port(true);
+        let first =
+            new_udp_socket(Some("127.0.0.1:0".parse().unwrap()), None, &opts)
+                .await
+                .unwrap();
+        let addr = first.local_addr().unwrap();
+        new_udp_socket(Some(addr), None, &opts).await.unwrap();
+
+        // off by default
+        assert!(
+            new_udp_socket(Some(addr), None, &ConnectOptions::default())
+                .await
+                .is_err()
+        );
+
+        let listener = try_create_dualstack_tcplistener(
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            true,
+        )
+        .unwrap();
+        try_create_dualstack_tcplistener(listener.local_addr().unwrap(), None, true)
+            .unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_freebind_non_local_source() {
+        // TEST-NET-2, not configured on any interface
+        let ip = "198.51.100.7".parse().unwrap();
+        let source = crate::app::net::freebind_source(ip);
+
+        let opts = ConnectOptions::default().iface(Some(&source));
+        let socket = new_udp_socket(None, None, &opts).await.unwrap();
+        assert_eq!(socket.local_addr().unwrap().ip(), ip);
+
+        let opts = ConnectOptions::default().freebind(true);
+        new_udp_socket(Some(SocketAddr::new(ip, 0)), None, &opts)
+            .await
+            .unwrap();
+        assert!(
+            new_udp_socket(
+                Some(SocketAddr::new(ip, 0)),
+                None,
+                &ConnectOptions::default()
+            )
+            .await
+            .is_err()
+        );
+
+        // the source binds, whatever becomes of the connection
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let opts = ConnectOptions::default()
+            .iface(Some(&source))
+            .connect_timeout(Duration::from_millis(200));
+        if let Err(e) = new_tcp_stream(listener.local_addr().unwrap(), &opts).await {
+            assert_ne!(e.kind(), std::io::ErrorKind::AddrNotAvailable);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_fast_open() {
+        let listener = try_create_dualstack_tcplistener(
+            "127.0.0.1:0".parse().unwrap(),
+            Some(16),
+            false,
+        )
+        .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let opts = ConnectOptions::default().fast_open(true);
+
+        // the first connection gets the cookie, the second sends data with
+        // its SYN, where the host allows it
+        for _ in 0..2 {
+            let mut client = new_tcp_stream(addr, &opts).await.unwrap();
+            client.write_all(b"hello").await.unwrap();
+
+            let (mut server, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        }
+    }
+
+    /// A listener on `[::1]` that never accepts, with its accept queue
+    /// filled up so that further connects hang, or `None` without IPv6
+    /// loopback or where a full queue doesn't stall connects
+    async fn stalled_ipv6_listener()
+    -> Option<(socket2::Socket, Vec<TcpStream>, SocketAddr)> {
+        let socket =
+            socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::STREAM, None)
+                .ok()?;
+        socket
+            .bind(&SocketAddr::from((Ipv6Addr::LOCALHOST, 0)).into())
+            .ok()?;
+        socket.listen(0).ok()?;
+        let addr = socket.local_addr().ok()?.as_socket()?;
+
+        let mut queued = Vec::new();
+        while queued.len() < 16 {
+            match timeout(Duration::from_millis(100), TcpStream::connect(addr)).await
+            {
+                Ok(Ok(stream)) => queued.push(stream),
+                Ok(Err(_)) => return None,
+                Err(_) => return Some((socket, queued, addr)),
+            }
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn test_happy_eyeballs_falls_back_to_ipv4() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let good = listener.local_addr().unwrap();
+        let Some((_listener, _queued, v6)) = stalled_ipv6_listener().await else {
+            eprintln!("no stalled IPv6 listener, skipping");
+            return;
+        };
+
+        for delay in [Duration::from_millis(250), Duration::from_millis(600)] {
+            let opts = ConnectOptions::default().attempt_delay(delay);
+            let started = Instant::now();
+            let stream = new_tcp_stream_happy_eyeballs(&[good, v6], &opts)
+                .await
+                .unwrap();
+            let elapsed = started.elapsed();
+            assert_eq!(stream.peer_addr().unwrap(), good);
+            // IPv4 is only tried once IPv6 had its head start
+            assert!(elapsed >= delay);
+            assert!(elapsed < delay * 3);
+        }
+    }
+}
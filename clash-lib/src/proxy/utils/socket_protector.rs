@@ -0,0 +1,74 @@
+//! A hook for the host app to exempt the sockets of outbound connections
+//! from its own VPN, e.g. with `VpnService.protect()` on Android, so that
+//! they don't loop back into the TUN device.
+
+use std::{
+    io,
+    os::fd::RawFd,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait SocketProtector: Send + Sync {
+    fn protect(&self, fd: RawFd) -> io::Result<()>;
+
+    /// Called on the async socket creation paths, override when
+    /// protecting may block, e.g. on a JNI round trip, to do it off the
+    /// runtime's worker threads
+    async fn protect_async(&self, fd: RawFd) -> io::Result<()> {
+        self.protect(fd)
+    }
+}
+
+static SOCKET_PROTECTOR: RwLock<Option<Arc<dyn SocketProtector>>> =
+    RwLock::new(None);
+
+pub fn set_socket_protector(protector: Option<Arc<dyn SocketProtector>>) {
+    *SOCKET_PROTECTOR.write().unwrap() = protector;
+}
+
+fn socket_protector() -> Option<Arc<dyn SocketProtector>> {
+    SOCKET_PROTECTOR.read().unwrap().clone()
+}
+
+/// Protects `fd` with the protector set, if any
+pub fn protect_socket(fd: RawFd) -> io::Result<()> {
+    match socket_protector() {
+        Some(protector) => protector.protect(fd),
+        None => Ok(()),
+    }
+}
+
+/// Like [`protect_socket`], with [`SocketProtector::protect_async`]
+pub async fn protect_socket_async(fd: RawFd) -> io::Result<()> {
+    match socket_protector() {
+        Some(protector) => protector.protect_async(fd).await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    struct Recorder(Mutex<Vec<RawFd>>);
+
+    #[async_trait]
+    impl SocketProtector for Recorder {
+        fn protect(&self, fd: RawFd) -> io::Result<()> {
+            self.0.lock().unwrap().push(fd);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protect_async_defaults_to_protect() {
+        let recorder = Arc::new(Recorder(Mutex::new(vec![])));
+        recorder.protect_async(7).await.unwrap();
+        assert_eq!(*recorder.0.lock().unwrap(), [7]);
+    }
+}
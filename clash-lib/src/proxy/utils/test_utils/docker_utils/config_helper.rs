@@ -56,6 +56,8 @@ pub async fn build_dns_resolver() -> anyhow::Result<Arc<dyn ClashResolver>> {
     let cache_store = profile::ThreadSafeCacheFile::new(
         root.join("cache.db").as_path().to_str().unwrap(),
         config.profile.store_selected,
+        config.profile.store_fake_ip,
+        config.profile.store_interface_traffic,
     );
 
     let dns_resolver = Arc::new(
@@ -0,0 +1,260 @@
+//! UDP segmentation offload on Linux. With `UDP_SEGMENT` a run of
+//! datagrams of the same size to the same destination is handed to the
+//! kernel in one `sendmsg` and split by it, or by the NIC. With `UDP_GRO`
+//! the kernel hands back a run of datagrams from the same source in one
+//! `recvmsg`, with the size they're to be split at.
+
+use std::{
+    io,
+    mem::{self, MaybeUninit},
+    net::SocketAddr,
+    os::fd::{AsFd, AsRawFd},
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, ready};
+use socket2::SockAddr;
+use tokio::{io::Interest, net::UdpSocket};
+
+/// Most datagrams the kernel takes in one `UDP_SEGMENT` send
+pub const MAX_SEGMENTS: usize = 64;
+
+/// Largest total size of a `UDP_SEGMENT` send
+pub const MAX_SEGMENTS_SIZE: usize = 65000;
+
+fn setsockopt_udp(fd: &impl AsFd, opt: libc::c_int, value: libc::c_int) -> bool {
+    let res = unsafe {
+        libc::setsockopt(
+            fd.as_fd().as_raw_fd(),
+            libc::SOL_UDP,
+            opt,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    res == 0
+}
+
+/// Whether the kernel takes `UDP_SEGMENT` sends on the socket
+pub fn gso_supported(fd: &impl AsFd) -> bool {
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of_val(&value) as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            fd.as_fd().as_raw_fd(),
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    res == 0
+}
+
+/// Asks the kernel to coalesce received datagrams, which
+/// [`poll_recv_gro`] then returns together. False if it's unsupported.
+pub fn enable_gro(fd: &impl AsFd) -> bool {
+    setsockopt_udp(fd, libc::UDP_GRO, 1)
+}
+
+/// Whether a failed `UDP_SEGMENT` send means the path doesn't support it,
+/// e.g. a NIC without checksum offload, so the datagrams should be sent one
+/// by one from now on.
+pub fn is_gso_unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EIO | libc::EINVAL | libc::EOPNOTSUPP)
+    )
+}
+
+/// Sends `buf` to `dst` as datagrams of `segment_size` bytes, the last one
+/// possibly shorter.
+pub fn poll_send_segments(
+    socket: &UdpSocket,
+    cx: &mut Context<'_>,
+    dst: SocketAddr,
+    buf: &[u8],
+    segment_size: u16,
+) -> Poll<io::Result<usize>> {
+    let dst = SockAddr::from(dst);
+    loop {
+        ready!(socket.poll_send_ready(cx))?;
+        match socket.try_io(Interest::WRITABLE, || {
+            send_segments_raw(socket, &dst, buf, segment_size)
+        }) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            res => return Poll::Ready(res),
+        }
+    }
+}
+
+/// See [`poll_send_segments`]
+pub async fn send_segments(
+    socket: &UdpSocket,
+    dst: SocketAddr,
+    buf: &[u8],
+    segment_size: u16,
+) -> io::Result<usize> {
+    poll_fn(|cx| poll_send_segments(socket, cx, dst, buf, segment_size)).await
+}
+
+fn send_segments_raw(
+    socket: &UdpSocket,
+    dst: &SockAddr,
+    buf: &[u8],
+    segment_size: u16,
+) -> io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // room for a single cmsg carrying a u16, aligned as cmsghdr
+    let mut control = [0u64; 4];
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<u16>() as u32) } as usize;
+    debug_assert!(space <= mem::size_of_val(&control));
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = dst.as_ptr() as *mut libc::c_void;
+    msg.msg_namelen = dst.len();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_UDP;
+        (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment_size);
+    }
+
+    let n = unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// A receive with [`poll_recv_gro`]
+pub struct GroRecv {
+    pub len: usize,
+    pub src: SocketAddr,
+    /// size of the coalesced datagrams, the last one possibly shorter.
+    /// Equal to `len` if only one was received.
+    pub stride: usize,
+    /// the datagram didn't fit in the buffer
+    pub truncated: bool,
+}
+
+/// Receives one or, with [`enable_gro`], several coalesced datagrams from
+/// the same source into `buf`.
+pub fn poll_recv_gro(
+    socket: &UdpSocket,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<GroRecv>> {
+    loop {
+        ready!(socket.poll_recv_ready(cx))?;
+        match socket.try_io(Interest::READABLE, || recv_gro_raw(socket, buf)) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            res => return Poll::Ready(res),
+        }
+    }
+}
+
+fn recv_gro_raw(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<GroRecv> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [MaybeUninit::<u64>::uninit(); 8];
+
+    let ((n, flags, stride), src) = unsafe {
+        SockAddr::try_init(|storage, len| {
+            let mut msg: libc::msghdr = mem::zeroed();
+            msg.msg_name = storage.cast();
+            msg.msg_namelen = *len;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+
+            let n = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            *len = msg.msg_namelen;
+
+            let mut stride = None;
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP
+                    && (*cmsg).cmsg_type == libc::UDP_GRO
+                {
+                    stride = Some(std::ptr::read_unaligned(
+                        libc::CMSG_DATA(cmsg) as *const libc::c_int
+                    ) as usize);
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            Ok((n as usize, msg.msg_flags, stride))
+        })?
+    };
+
+    let src = src.as_socket().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "unexpected source address")
+    })?;
+    Ok(GroRecv {
+        len: n,
+        src,
+        stride: stride.filter(|x| *x > 0).unwrap_or(n),
+        truncated: flags & libc::MSG_TRUNC != 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_segments_loopback() {
+        let rx = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let tx = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        if !gso_supported(&tx) {
+            eprintln!("skipping: UDP_SEGMENT unsupported");
+            return;
+        }
+        let gro = enable_gro(&rx);
+
+        let buf = (0..250u8).collect::<Vec<_>>();
+        let n = send_segments(&tx, rx.local_addr().unwrap(), &buf, 100)
+            .await
+            .unwrap();
+        assert_eq!(n, buf.len());
+
+        let mut received = vec![];
+        let mut mem = vec![0u8; 65536];
+        while received.len() < 3 {
+            let r = poll_fn(|cx| poll_recv_gro(&rx, cx, &mut mem))
+                .await
+                .unwrap();
+            assert!(!r.truncated);
+            assert_eq!(r.src, tx.local_addr().unwrap());
+            if !gro {
+                assert_eq!(r.stride, r.len);
+            }
+            received.extend(mem[..r.len].chunks(r.stride).map(<[u8]>::to_vec));
+        }
+        assert_eq!(
+            received,
+            vec![
+                buf[..100].to_vec(),
+                buf[100..200].to_vec(),
+                buf[200..].to_vec()
+            ]
+        );
+    }
+}
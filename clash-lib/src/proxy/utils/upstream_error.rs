@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tracing::debug;
+
+/// Key of `retry-on` matching failures that aren't an [`UpstreamError`],
+/// e.g. the proxy server being unreachable.
+const OTHER: &str = "other";
+
+const SOCKS5_REPLIES: &[(&str, &str)] = &[
+    ("succeeded", "succeeded"),
+    ("general-failure", "general SOCKS server failure"),
+    ("not-allowed", "connection not allowed by ruleset"),
+    ("network-unreachable", "network unreachable"),
+    ("host-unreachable", "host unreachable"),
+    ("connection-refused", "connection refused"),
+    ("ttl-expired", "TTL expired"),
+    ("command-not-supported", "command not supported"),
+    ("address-type-not-supported", "address type not supported"),
+];
+
+/// A handshake failure reported by an upstream proxy server.
+///
+/// Outbounds return it wrapped in an `io::Error`, so that groups can tell
+/// why a member failed, see [`RetryPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpstreamError {
+    /// The SOCKS5 server rejected the username/password
+    Socks5AuthFailed,
+    /// The SOCKS5 server replied to the request with this error code
+    Socks5Reply(u8),
+    /// The server accepted the connection but didn't complete the TLS or
+    /// protocol handshake within `handshake-timeout`
+    HandshakeTimeout,
+}
+
+impl UpstreamError {
+    pub fn from_io_error(e: &io::Error) -> Option<Self> {
+        e.get_ref()?.downcast_ref::<Self>().copied()
+    }
+
+    /// The name of this error in `retry-on`
+    pub fn key(&self) -> String {
+        match self {
+            UpstreamError::HandshakeTimeout => "handshake-timeout".to_owned(),
+            UpstreamError::Socks5AuthFailed => "socks5-auth-failed".to_owned(),
+            UpstreamError::Socks5Reply(code) => format!(
+                "socks5-{}",
+                SOCKS5_REPLIES
+                    .get(*code as usize)
+                    .map(|(key, _)| *key)
+                    .unwrap_or("unknown")
+            ),
+        }
+    }
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpstreamError::HandshakeTimeout => {
+                write!(f, "proxy handshake timed out")
+            }
+            UpstreamError::Socks5AuthFailed => {
+                write!(f, "SOCKS5 authentication failed")
+            }
+            UpstreamError::Socks5Reply(code) => write!(
+                f,
+                "SOCKS5 request failed with {}",
+                SOCKS5_REPLIES
+                    .get(*code as usize)
+                    .map(|(_, desc)| *desc)
+                    .unwrap_or("unknown error")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+impl From<UpstreamError> for io::Error {
+    fn from(e: UpstreamError) -> Self {
+        match e {
+            UpstreamError::HandshakeTimeout => {
+                io::Error::new(io::ErrorKind::TimedOut, e)
+            }
+            _ => io::Error::other(e),
+        }
+    }
+}
+
+static HANDSHAKE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(10_000);
+
+/// How long outbounds wait for the TLS and protocol handshake once connected
+/// to the server, `None` for no limit
+pub fn set_handshake_timeout(timeout: Option<Duration>) {
+    HANDSHAKE_TIMEOUT_MS.store(
+        timeout.map(|x| x.as_millis() as u64).unwrap_or_default(),
+        Ordering::Relaxed,
+    );
+}
+
+/// Fails `handshake` with [`UpstreamError::HandshakeTimeout`] if it doesn't
+/// finish within `handshake-timeout`, so that a server that accepts
+/// connections but never answers doesn't hold them until keep-alive gives up
+pub async fn with_handshake_timeout<T, E: From<io::Error>>(
+    handshake: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let timeout = match HANDSHAKE_TIMEOUT_MS.load(Ordering::Relaxed) {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+    handshake_within(timeout, handshake).await
+}
+
+async fn handshake_within<T, E: From<io::Error>>(
+    timeout: Option<Duration>,
+    handshake: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match timeout {
+        None => handshake.await,
+        Some(timeout) => tokio::time::timeout(timeout, handshake)
+            .await
+            .unwrap_or_else(|_| {
+                Err(io::Error::from(UpstreamError::HandshakeTimeout).into())
+            }),
+    }
+}
+
+static HANDSHAKE_RETRIES: AtomicU32 = AtomicU32::new(0);
+
+/// How many more times outbounds dial the server when the handshake fails
+/// with a transient error
+pub fn set_handshake_retries(retries: u32) {
+    HANDSHAKE_RETRIES.store(retries, Ordering::Relaxed);
+}
+
+/// Whether `e` is the connection getting cut during the handshake, which
+/// flaky or meddling networks do now and then, rather than the server
+/// refusing it. Certificate and protocol errors aren't, a retry would fail
+/// the same way.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Runs `dial`, which connects to the server and handshakes over the new
+/// connection, up to `handshake-retries` more times while it fails with a
+/// transient error. Other errors, and the last transient one, are returned
+/// for groups to fail over on.
+pub async fn with_handshake_retries<T, F>(dial: impl FnMut() -> F) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    retry_transient(HANDSHAKE_RETRIES.load(Ordering::Relaxed), dial).await
+}
+
+async fn retry_transient<T, F>(
+    retries: u32,
+    mut dial: impl FnMut() -> F,
+) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match dial().await {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                debug!("handshake failed: {e}, retrying ({attempt}/{retries})");
+            }
+            rv => return rv,
+        }
+    }
+}
+
+/// Decides whether a group should try its next member after one failed to
+/// connect.
+///
+/// Every failure is retried except those known to be caused by the
+/// configuration of the member itself, such as rejected credentials. Both
+/// can be overridden per error, keyed by [`UpstreamError::key`] or `other`.
+#[derive(Debug, Clone, Default)]
+pub struct RetryPolicy {
+    overrides: HashMap<String, bool>,
+}
+
+impl RetryPolicy {
+    pub fn new(overrides: HashMap<String, bool>) -> Result<Self, String> {
+        if let Some(key) = overrides.keys().find(|x| !Self::is_valid_key(x)) {
+            return Err(format!("unknown upstream error `{key}`"));
+        }
+        Ok(Self { overrides })
+    }
+
+    pub fn should_retry(&self, e: &io::Error) -> bool {
+        let (key, default) = match UpstreamError::from_io_error(e) {
+            Some(UpstreamError::Socks5AuthFailed) => {
+                (UpstreamError::Socks5AuthFailed.key(), false)
+            }
+            Some(e) => (e.key(), true),
+            None => (OTHER.to_owned(), true),
+        };
+        self.overrides.get(&key).copied().unwrap_or(default)
+    }
+
+    fn is_valid_key(key: &str) -> bool {
+        key == OTHER
+            || key == "handshake-timeout"
+            || key == "socks5-auth-failed"
+            || key == "socks5-unknown"
+            || key.strip_prefix("socks5-").is_some_and(|reply| {
+                SOCKS5_REPLIES.iter().skip(1).any(|(x, _)| *x == reply)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io,
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use super::{RetryPolicy, UpstreamError, handshake_within, retry_transient};
+
+    #[test]
+    fn test_upstream_error_roundtrip() {
+        let e: io::Error = UpstreamError::Socks5Reply(4).into();
+        assert_eq!(
+            UpstreamError::from_io_error(&e),
+            Some(UpstreamError::Socks5Reply(4))
+        );
+        assert_eq!(e.to_string(), "SOCKS5 request failed with host unreachable");
+        assert_eq!(
+            UpstreamError::Socks5Reply(4).key(),
+            "socks5-host-unreachable"
+        );
+        assert_eq!(UpstreamError::Socks5Reply(42).key(), "socks5-unknown");
+        assert!(UpstreamError::from_io_error(&io::Error::other("boom")).is_none());
+
+        let e: io::Error = UpstreamError::HandshakeTimeout.into();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(
+            UpstreamError::from_io_error(&e),
+            Some(UpstreamError::HandshakeTimeout)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        let timeout = Some(Duration::from_millis(50));
+        let e = handshake_within(timeout, std::future::pending::<io::Result<()>>())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            UpstreamError::from_io_error(&e),
+            Some(UpstreamError::HandshakeTimeout)
+        );
+        assert!(RetryPolicy::default().should_retry(&e));
+        assert_eq!(
+            handshake_within(timeout, async { io::Result::Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handshake_retries() {
+        let dials = AtomicU32::new(0);
+        let flaky = || async {
+            match dials.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(io::ErrorKind::ConnectionReset.into()),
+                1 => Err(io::ErrorKind::UnexpectedEof.into()),
+                n => io::Result::Ok(n),
+            }
+        };
+        assert_eq!(retry_transient(2, flaky).await.unwrap(), 2);
+
+        dials.store(0, Ordering::Relaxed);
+        let e = retry_transient(1, flaky).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(dials.load(Ordering::Relaxed), 2);
+
+        dials.store(0, Ordering::Relaxed);
+        let bad_cert = || async {
+            dials.fetch_add(1, Ordering::Relaxed);
+            io::Result::<()>::Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid peer certificate: UnknownIssuer",
+            ))
+        };
+        assert!(retry_transient(3, bad_cert).await.is_err());
+        assert_eq!(dials.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let default = RetryPolicy::default();
+        assert!(default.should_retry(&UpstreamError::Socks5Reply(4).into()));
+        assert!(!default.should_retry(&UpstreamError::Socks5AuthFailed.into()));
+        assert!(default.should_retry(&io::ErrorKind::TimedOut.into()));
+
+        let policy = RetryPolicy::new(HashMap::from([
+            ("socks5-host-unreachable".to_owned(), false),
+            ("socks5-auth-failed".to_owned(), true),
+            ("other".to_owned(), false),
+        ]))
+        .unwrap();
+        assert!(!policy.should_retry(&UpstreamError::Socks5Reply(4).into()));
+        assert!(policy.should_retry(&UpstreamError::Socks5Reply(1).into()));
+        assert!(policy.should_retry(&UpstreamError::Socks5AuthFailed.into()));
+        assert!(!policy.should_retry(&io::ErrorKind::TimedOut.into()));
+
+        assert!(
+            RetryPolicy::new(HashMap::from([("http-503".to_owned(), true)]))
+                .is_err()
+        );
+        assert!(
+            RetryPolicy::new(HashMap::from([("socks5-succeeded".to_owned(), true)]))
+                .is_err()
+        );
+    }
+}
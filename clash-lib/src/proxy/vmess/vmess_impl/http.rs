@@ -5,7 +5,7 @@ use futures::ready;
 use tokio::io::{AsyncRead, AsyncWrite, BufStream, ReadBuf};
 
 use crate::{
-    common::{errors::map_io_error, utils},
+    common::{errors::map_io_error, http::with_global_headers, utils},
     proxy::AnyStream,
 };
 
@@ -24,7 +24,7 @@ impl HttpConfig {
             stream,
             self.host.clone(),
             path,
-            self.headers.clone(),
+            with_global_headers(&self.headers),
         )))
     }
 }
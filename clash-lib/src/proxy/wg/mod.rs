@@ -24,6 +24,7 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::Arc,
+    time::Duration,
 };
 use tokio::sync::OnceCell;
 
@@ -99,7 +100,10 @@ impl Handler {
             .get_or_try_init(|| async {
                 let recv_pair = tokio::sync::mpsc::channel(1024);
                 let send_pair = tokio::sync::mpsc::channel(1024);
-                let server_ip = resolver
+                let server_ip = self
+                    .opts
+                    .common_opts
+                    .server_resolver(resolver.clone())
                     .resolve(&self.opts.server, false)
                     .await
                     .map_err(map_io_error)?
@@ -233,6 +237,10 @@ impl OutboundHandler for Handler {
         OutboundType::WireGuard
     }
 
+    fn idle_read_timeout(&self) -> Option<Duration> {
+        self.opts.common_opts.idle_read_timeout
+    }
+
     async fn support_udp(&self) -> bool {
         self.opts.udp
     }
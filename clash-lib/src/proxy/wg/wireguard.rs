@@ -98,14 +98,7 @@ impl WireguardTunnel {
 
         let connector = connector.unwrap_or(GLOBAL_DIRECT_CONNECTOR.clone());
         let udp = connector
-            .connect_datagram(
-                resolver,
-                None,
-                remote_endpoint.into(),
-                sess.iface.as_ref(),
-                #[cfg(target_os = "linux")]
-                sess.so_mark,
-            )
+            .connect_datagram(resolver, None, remote_endpoint.into(), &sess.into())
             .await?;
 
         let (tx, rx) = udp.split();
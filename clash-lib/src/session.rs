@@ -1,4 +1,4 @@
-use crate::app::net::OutboundInterface;
+use crate::{app::net::OutboundInterface, config::def::IpVersion};
 use anyhow::anyhow;
 use bytes::{Buf, BufMut};
 use erased_serde::Serialize as ESerialize;
@@ -407,14 +407,36 @@ pub struct Session {
     pub source: SocketAddr,
     /// The proxy target address of a proxy connection.
     pub destination: SocksAddr,
+    /// The local address an inbound connection arrived on, for listeners
+    /// that accept connections addressed to themselves.
+    pub local_addr: Option<SocketAddr>,
     /// The locally resolved IP address of the destination domain.
     pub resolved_ip: Option<IpAddr>,
     /// The packet mark SO_MARK
     pub so_mark: Option<u32>,
     /// The bind interface
     pub iface: Option<OutboundInterface>,
+    /// Whether `iface` was picked by the matched rule, which outbound groups
+    /// then leave alone
+    #[serde(skip)]
+    pub iface_from_rule: bool,
+    /// Whether `so_mark` was picked by the matched rule, see `iface_from_rule`
+    #[serde(skip)]
+    pub so_mark_from_rule: bool,
+    /// The DSCP value of outgoing packets
+    pub dscp: Option<u8>,
+    /// Whether `dscp` was picked by the matched rule, see `iface_from_rule`
+    #[serde(skip)]
+    pub dscp_from_rule: bool,
+    /// The address families the destination may be resolved to, set by the
+    /// matched rule
+    pub ip_version: Option<IpVersion>,
+    /// The ALPN protocols offered in the TLS ClientHello, when sniffed
+    pub alpn: Option<Vec<String>>,
     /// The ASN of the destination IP address. Only for display.
     pub asn: Option<String>,
+    /// The tag of the matched rule. Only for display.
+    pub tag: Option<String>,
     /// Traffic statistics for intelligent proxy selection
     pub traffic_stats: Option<crate::app::remote_content_manager::TrafficStats>,
 }
@@ -443,6 +465,7 @@ impl Session {
         );
         rv.insert("host".to_string(), Box::new(self.destination.host()) as _);
         rv.insert("asn".to_string(), Box::new(self.asn.clone()) as _);
+        rv.insert("tag".to_string(), Box::new(self.tag.clone()) as _);
         rv.insert(
             "traffic_stats".to_string(),
             Box::new(self.traffic_stats.clone()) as _,
@@ -458,10 +481,18 @@ impl Default for Session {
             typ: Type::Http,
             source: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
             destination: SocksAddr::any_ipv4(),
+            local_addr: None,
             resolved_ip: None,
             so_mark: None,
             iface: None,
+            iface_from_rule: false,
+            so_mark_from_rule: false,
+            dscp: None,
+            dscp_from_rule: false,
+            ip_version: None,
+            alpn: None,
             asn: None,
+            tag: None,
             traffic_stats: None,
         }
     }
@@ -486,9 +517,14 @@ impl Debug for Session {
             .field("network", &self.network)
             .field("source", &self.source)
             .field("destination", &self.destination)
+            .field("local_addr", &self.local_addr)
             .field("packet_mark", &self.so_mark)
             .field("iface", &self.iface)
+            .field("dscp", &self.dscp)
+            .field("ip_version", &self.ip_version)
+            .field("alpn", &self.alpn)
             .field("asn", &self.asn)
+            .field("tag", &self.tag)
             .finish()
     }
 }
@@ -500,10 +536,17 @@ impl Clone for Session {
             typ: self.typ,
             source: self.source,
             destination: self.destination.clone(),
+            local_addr: self.local_addr,
             resolved_ip: self.resolved_ip,
             so_mark: self.so_mark,
             iface: self.iface.as_ref().cloned(),
+            iface_from_rule: self.iface_from_rule,
+            so_mark_from_rule: self.so_mark_from_rule,
+            dscp: self.dscp,
+            dscp_from_rule: self.dscp_from_rule,
+            ip_version: self.ip_version,
             asn: self.asn.clone(),
+            tag: self.tag.clone(),
             traffic_stats: self.traffic_stats.clone(),
         }
     }